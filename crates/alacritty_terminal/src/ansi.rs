@@ -3,6 +3,7 @@
 use std::convert::TryFrom;
 use std::fmt::Write;
 use std::path::Path;
+use std::sync::LazyLock;
 use std::time::{
     Duration,
     Instant,
@@ -117,6 +118,27 @@ fn parse_number(input: &[u8]) -> Option<u8> {
     Some(num)
 }
 
+/// The name of this machine, which the working directory reported by OSC 7 must be on.
+static HOSTNAME: LazyLock<Option<String>> = LazyLock::new(|| whoami::fallible::hostname().ok());
+
+/// Parse the path of a `file://host/path` URL, as reported by OSC 7. Only URLs without a host or
+/// with the host of this machine are accepted, since the path of a remote shell, e.g. over ssh,
+/// does not exist here.
+fn parse_file_url(input: &[u8], hostname: Option<&str>) -> Option<String> {
+    let url = input.strip_prefix(b"file://")?;
+    let (host, path) = url.split_at(url.iter().position(|b| *b == b'/')?);
+    let is_local = host.is_empty()
+        || host.eq_ignore_ascii_case(b"localhost")
+        || hostname.is_some_and(|hostname| host.eq_ignore_ascii_case(hostname.as_bytes()));
+    if !is_local {
+        return None;
+    }
+    percent_encoding::percent_decode(path)
+        .decode_utf8()
+        .ok()
+        .map(|path| path.into_owned())
+}
+
 /// Internal state for VTE processor.
 #[derive(Debug, Default)]
 struct ProcessorState {
@@ -524,6 +546,12 @@ pub trait Handler {
     /// Fig OSCUnlock OSC
     fn osc_unlock(&mut self, _: &str) {}
 
+    /// FinalTerm semantic prompt OSC 133
+    fn semantic_prompt(&mut self, _: SemanticPromptMark) {}
+
+    /// Working directory OSC 7
+    fn working_directory(&mut self, _: &Path) {}
+
     /// Unhandled `execute` fallthrough
     fn unhandled_execute(&mut self, _byte: u8) -> HandledStatus {
         HandledStatus::Unhandled
@@ -572,6 +600,23 @@ pub trait Handler {
     }
 }
 
+/// Semantic prompt marks sent with OSC 133, as defined by FinalTerm and supported by most
+/// terminals and shells.
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum SemanticPromptMark {
+    /// `A`: the prompt is about to be printed.
+    PromptStart,
+
+    /// `B`: the prompt was printed and the user is entering a command.
+    CommandStart,
+
+    /// `C`: the command was submitted and is about to run.
+    CommandExecuted,
+
+    /// `D`: the command finished, with its exit code when reported.
+    CommandFinished(Option<i32>),
+}
+
 /// Terminal cursor configuration.
 #[derive(Default, Debug, Eq, PartialEq, Copy, Clone, Hash)]
 pub struct CursorStyle {
@@ -1171,6 +1216,28 @@ where
             // Reset text cursor color.
             b"112" => self.handler.reset_color(NamedColor::Cursor as usize),
 
+            // Report the working directory as a `file://` URL.
+            b"7" if params.len() >= 2 => match parse_file_url(&params[1..].join(&b';'), HOSTNAME.as_deref()) {
+                Some(path) => self.handler.working_directory(Path::new(&path)),
+                None => unhandled!(),
+            },
+
+            // FinalTerm semantic prompt marks, optionally followed by options such as the exit code.
+            b"133" => match params.get(1).copied() {
+                Some(b"A") => self.handler.semantic_prompt(SemanticPromptMark::PromptStart),
+                Some(b"B") => self.handler.semantic_prompt(SemanticPromptMark::CommandStart),
+                Some(b"C") => self.handler.semantic_prompt(SemanticPromptMark::CommandExecuted),
+                Some(b"D") => {
+                    let exit_code = params
+                        .get(2)
+                        .and_then(|code| str::from_utf8(code).ok())
+                        .and_then(|code| code.parse::<i32>().ok());
+                    self.handler
+                        .semantic_prompt(SemanticPromptMark::CommandFinished(exit_code));
+                },
+                _ => unhandled!(),
+            },
+
             // feeg
             b"697" => {
                 if let Some(fig_osc) = params.get(1) {
@@ -1686,6 +1753,8 @@ mod tests {
         index: CharsetIndex,
         charset: StandardCharset,
         attr: Option<Attr>,
+        semantic_prompt: Vec<SemanticPromptMark>,
+        working_directory: Option<std::path::PathBuf>,
     }
 
     impl Handler for MockHandler {
@@ -1705,6 +1774,14 @@ mod tests {
         fn reset_state(&mut self) {
             *self = Self::default();
         }
+
+        fn semantic_prompt(&mut self, mark: SemanticPromptMark) {
+            self.semantic_prompt.push(mark);
+        }
+
+        fn working_directory(&mut self, directory: &Path) {
+            self.working_directory = Some(directory.to_path_buf());
+        }
     }
 
     impl Default for MockHandler {
@@ -1713,6 +1790,8 @@ mod tests {
                 index: CharsetIndex::G0,
                 charset: StandardCharset::Ascii,
                 attr: None,
+                semantic_prompt: Vec::new(),
+                working_directory: None,
             }
         }
     }
@@ -1890,6 +1969,56 @@ mod tests {
         assert_eq!(xparse_color(b"#f"), None);
     }
 
+    #[test]
+    fn parse_semantic_prompt() {
+        static BYTES: &[u8] = b"\x1b]133;D;1\x07\x1b]133;A\x07$ \x1b]133;B\x07ls\x1b]133;C;aid=1\x1b\\\x1b]133;D\x07";
+        let mut parser = Processor::new();
+        let mut handler = MockHandler::default();
+
+        for byte in BYTES {
+            parser.advance(&mut handler, *byte);
+        }
+
+        assert_eq!(handler.semantic_prompt, vec![
+            SemanticPromptMark::CommandFinished(Some(1)),
+            SemanticPromptMark::PromptStart,
+            SemanticPromptMark::CommandStart,
+            SemanticPromptMark::CommandExecuted,
+            SemanticPromptMark::CommandFinished(None),
+        ]);
+    }
+
+    #[test]
+    fn parse_working_directory() {
+        static BYTES: &[u8] = b"\x1b]7;file://localhost/home/user/my%20dir;1\x07";
+        let mut parser = Processor::new();
+        let mut handler = MockHandler::default();
+
+        for byte in BYTES {
+            parser.advance(&mut handler, *byte);
+        }
+
+        assert_eq!(
+            handler.working_directory.as_deref(),
+            Some(Path::new("/home/user/my dir;1"))
+        );
+    }
+
+    #[test]
+    fn parse_file_urls() {
+        let hostname = Some("my-host");
+        assert_eq!(parse_file_url(b"file:///tmp", hostname), Some("/tmp".into()));
+        assert_eq!(
+            parse_file_url(b"file://localhost/tmp/%C3%A9", hostname),
+            Some("/tmp/\u{e9}".into())
+        );
+        assert_eq!(parse_file_url(b"file://My-Host/tmp", hostname), Some("/tmp".into()));
+        assert_eq!(parse_file_url(b"file://remote/tmp", hostname), None);
+        assert_eq!(parse_file_url(b"file://my-host/tmp", None), None);
+        assert_eq!(parse_file_url(b"file://host", hostname), None);
+        assert_eq!(parse_file_url(b"/tmp", hostname), None);
+    }
+
     #[test]
     fn parse_invalid_number() {
         assert_eq!(parse_number(b"1abc"), None);
@@ -37,6 +37,7 @@ use crate::ansi::{
     Color,
     Handler,
     NamedColor,
+    SemanticPromptMark,
     StandardCharset,
 };
 use crate::event::{
@@ -169,6 +170,8 @@ pub struct CommandInfo {
     pub end_time: Option<SystemTime>,
     pub username: Option<String>,
     pub exit_code: Option<i32>,
+    /// What the command printed, when output capture is enabled
+    pub output: Option<String>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -203,6 +206,8 @@ pub struct ShellState {
     pub preexec: bool,
     /// Position of start of cmd
     pub cmd_cursor: Option<Point>,
+    /// Position of start of the output of the running command, when output capture is enabled
+    pub output_cursor: Option<Point>,
     /// Fish suggestion color
     pub fish_suggestion_color: Option<shell_color::SuggestionColor>,
     /// Zsh autosuggestion color
@@ -219,6 +224,10 @@ pub struct ShellState {
     pub fig_log_level: Option<String>,
     /// OSC Lock
     pub osc_lock: bool,
+    /// If the current prompt has been reported by our shell integration, in which case the
+    /// standard OSC 133 and OSC 7 sequences are ignored so the two integrations don't report each
+    /// event twice
+    pub has_seen_fig_osc: bool,
 }
 
 impl ShellState {
@@ -291,6 +300,12 @@ pub struct Term<T> {
     /// Delay and manually trigger end_prompt/new_cmd on windows.
     windows_delay_end_prompt: bool,
 
+    /// Name of the shell assumed when it only reports its prompt with OSC 133.
+    default_shell: Option<String>,
+
+    /// Record the output of commands in their [`CommandInfo`].
+    capture_command_output: bool,
+
     /// Delayed events that should eventually be manually triggered.
     delayed_events: Vec<DelayedEvent>,
 }
@@ -333,6 +348,8 @@ impl<T> Term<T> {
             title_stack: Vec::new(),
             shell_state,
             windows_delay_end_prompt: false,
+            default_shell: None,
+            capture_command_output: false,
             delayed_events: Vec::new(),
         }
     }
@@ -530,6 +547,9 @@ impl<T> Term<T> {
         if let Some(ref mut cursor) = self.shell_state.cmd_cursor {
             cursor.line -= lines as i32;
         }
+        if let Some(ref mut cursor) = self.shell_state.output_cursor {
+            cursor.line -= lines as i32;
+        }
 
         lines = min(lines, (self.scroll_region.end - self.scroll_region.start).0 as usize);
 
@@ -793,6 +813,55 @@ impl<T> Term<T> {
         self.windows_delay_end_prompt = delay_end_prompt;
     }
 
+    /// Set the name of the shell that is assumed when the shell does not report it, i.e. when it
+    /// reports its prompt with OSC 133 instead of our shell integration.
+    pub fn set_default_shell(&mut self, shell: impl Into<String>) {
+        self.default_shell = Some(shell.into());
+    }
+
+    /// Record what each command prints in its [`CommandInfo`]. Only the lines still in the grid
+    /// when the command finishes can be recorded.
+    pub fn set_capture_command_output(&mut self, capture: bool) {
+        self.capture_command_output = capture;
+    }
+
+    /// Store the text printed since the running command started in its [`CommandInfo`].
+    fn capture_command_output(&mut self) {
+        let Some(output_cursor) = self.shell_state.output_cursor.take() else {
+            return;
+        };
+
+        let start = if output_cursor.line < self.topmost_line() {
+            Point::new(self.topmost_line(), Column(0))
+        } else {
+            output_cursor
+        };
+        let end = self.grid.cursor.point;
+        if start >= end {
+            return;
+        }
+
+        let output = self.bounds_to_string(start, end);
+        let output = output.trim_end();
+        trace!("Command output: {} bytes", output.len());
+        if let Some(command) = &mut self.shell_state.command_info {
+            if !output.is_empty() {
+                command.output = Some(output.to_owned());
+            }
+        }
+    }
+
+    fn start_prompt_internal(&mut self) {
+        if self.shell_state.osc_lock {
+            return;
+        }
+        trace!("Fig start prompt");
+        self.capture_command_output();
+        self.shell_state.has_seen_prompt = true;
+
+        self.grid.cursor.template.fig_flags.insert(FigFlags::IN_PROMPT);
+    }
+
     fn end_prompt_internal(&mut self, force: bool) {
         if self.windows_delay_end_prompt && !force {
             self.delayed_events.push(DelayedEvent::EndPrompt);
@@ -1723,18 +1792,14 @@ impl<T: EventListener> Handler for Term<T> {
 
     #[inline]
     fn new_cmd(&mut self, session_id: &str) {
+        self.shell_state.has_seen_fig_osc = true;
         self.new_cmd_internal(false, Some(session_id));
     }
 
     #[inline]
     fn start_prompt(&mut self) {
-        if self.shell_state.osc_lock {
-            return;
-        }
-        trace!("Fig start prompt");
-        self.shell_state.has_seen_prompt = true;
-
-        self.grid.cursor.template.fig_flags.insert(FigFlags::IN_PROMPT);
+        self.shell_state.has_seen_fig_osc = true;
+        self.start_prompt_internal();
     }
 
     #[inline]
@@ -1752,6 +1817,10 @@ impl<T: EventListener> Handler for Term<T> {
         }
         trace!("Fig PreExec");
         self.shell_state.preexec = true;
+        // The command may be a shell without our integration, e.g. over ssh, whose prompts are
+        // only reported with the standard sequences. Ours reports the next prompt again if it
+        // is still running.
+        self.shell_state.has_seen_fig_osc = false;
         self.event_proxy.send_event(Event::PreExec, &self.shell_state);
         trace!("PreExec event sent");
 
@@ -1768,7 +1837,11 @@ impl<T: EventListener> Handler for Term<T> {
             username: context.username.clone(),
             exit_code: None,
             end_time: None,
+            output: None,
         });
+        if self.capture_command_output {
+            self.shell_state.output_cursor = Some(self.grid.cursor.point);
+        }
     }
 
     #[inline]
@@ -1806,6 +1879,7 @@ impl<T: EventListener> Handler for Term<T> {
             return;
         }
         trace!("Fig exit code: {exit_code}");
+        self.capture_command_output();
         if let Some(command) = &mut self.shell_state.command_info {
             command.exit_code = Some(exit_code);
             command.end_time = Some(std::time::SystemTime::now());
@@ -1918,6 +1992,39 @@ impl<T: EventListener> Handler for Term<T> {
         self.event_proxy.log_level_event(Some(fig_log_level));
     }
 
+    #[inline]
+    fn semantic_prompt(&mut self, mark: SemanticPromptMark) {
+        if self.shell_state.has_seen_fig_osc {
+            return;
+        }
+        trace!("Semantic prompt: {mark:?}");
+        match mark {
+            SemanticPromptMark::PromptStart => {
+                if self.shell_state.local_context.shell.is_none() {
+                    if let Some(shell) = self.default_shell.clone() {
+                        self.shell(&shell);
+                    }
+                }
+                self.start_prompt_internal();
+            },
+            SemanticPromptMark::CommandStart => {
+                self.end_prompt();
+                self.new_cmd_internal(false, None);
+            },
+            SemanticPromptMark::CommandExecuted => self.pre_exec(),
+            SemanticPromptMark::CommandFinished(Some(exit_code)) => self.exit_code(exit_code),
+            SemanticPromptMark::CommandFinished(None) => {},
+        }
+    }
+
+    #[inline]
+    fn working_directory(&mut self, directory: &std::path::Path) {
+        if self.shell_state.has_seen_fig_osc {
+            return;
+        }
+        self.dir(directory);
+    }
+
     #[inline]
     fn osc_lock(&mut self, session_id: &str) {
         if let Some(local_session_id) = &self.shell_state.local_context.session_id {
@@ -2115,6 +2222,7 @@ mod tests {
         self,
         CharsetIndex,
         Handler,
+        SemanticPromptMark,
         StandardCharset,
     };
     use crate::event::VoidListener;
@@ -2335,4 +2443,85 @@ mod tests {
         assert_eq!(term.history_size(), 15);
         assert_eq!(term.grid.cursor.point, Point::new(Line(4), Column(0)));
     }
+
+    #[test]
+    fn semantic_prompt_updates_shell_state() {
+        let size = SizeInfo::new(10, 20);
+        let mut term = Term::new_test(size, VoidListener, 10_000);
+        term.set_default_shell("bash");
+
+        term.semantic_prompt(SemanticPromptMark::PromptStart);
+        assert!(term.shell_state.has_seen_prompt);
+        assert_eq!(term.shell_state.local_context.shell.as_deref(), Some("bash"));
+
+        term.semantic_prompt(SemanticPromptMark::CommandStart);
+        assert!(term.shell_state.cmd_cursor.is_some());
+        assert!(!term.shell_state.preexec);
+
+        term.semantic_prompt(SemanticPromptMark::CommandExecuted);
+        assert!(term.shell_state.preexec);
+
+        term.semantic_prompt(SemanticPromptMark::CommandFinished(Some(2)));
+        let command_info = term.shell_state.command_info.as_ref().unwrap();
+        assert_eq!(command_info.exit_code, Some(2));
+        assert!(command_info.end_time.is_some());
+    }
+
+    #[test]
+    fn fig_osc_takes_precedence_over_semantic_prompt() {
+        let size = SizeInfo::new(10, 20);
+        let mut term = Term::new_test(size, VoidListener, 10_000);
+
+        term.start_prompt();
+        term.end_prompt();
+        term.new_cmd("test-session-123");
+        assert!(term.shell_state.has_seen_fig_osc);
+
+        term.semantic_prompt(SemanticPromptMark::CommandExecuted);
+        assert!(!term.shell_state.preexec);
+
+        term.pre_exec();
+        assert!(term.shell_state.preexec);
+        assert!(!term.shell_state.has_seen_fig_osc);
+
+        // The prompt of a nested shell only reporting the standard sequences is followed
+        term.semantic_prompt(SemanticPromptMark::PromptStart);
+        term.semantic_prompt(SemanticPromptMark::CommandStart);
+        assert!(!term.shell_state.preexec);
+
+        term.start_prompt();
+        assert!(term.shell_state.has_seen_fig_osc);
+    }
+
+    #[test]
+    fn capture_command_output() {
+        let size = SizeInfo::new(10, 20);
+        let mut term = Term::new_test(size, VoidListener, 10_000);
+        term.set_capture_command_output(true);
+
+        fn print(term: &mut Term<VoidListener>, text: &str) {
+            for c in text.chars() {
+                match c {
+                    '\n' => {
+                        term.newline();
+                        term.carriage_return();
+                    },
+                    c => term.input(c),
+                }
+            }
+        }
+
+        term.start_prompt();
+        print(&mut term, "$ ");
+        term.end_prompt();
+        term.new_cmd("test-session-123");
+        print(&mut term, "ls\n");
+        term.pre_exec();
+        print(&mut term, "a.txt\nb.txt\n");
+        term.exit_code(0);
+
+        let command_info = term.shell_state.command_info.as_ref().unwrap();
+        assert_eq!(command_info.output.as_deref(), Some("a.txt\nb.txt"));
+        assert!(term.shell_state.output_cursor.is_none());
+    }
 }
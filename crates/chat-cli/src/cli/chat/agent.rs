@@ -0,0 +1,396 @@
+//! Agents bundle instructions, the tools and MCP servers that can be used, which tools are
+//! trusted, a default model and hooks into a single file. They are defined in
+//! `.amazonq/agents/<name>.json` in the workspace or in `~/.aws/amazonq/agents/<name>.json`, the
+//! former taking precedence, and are selected with `q chat --agent <name>` or `/agent swap <name>`.
+//!
+//! Workspace agents come with the repository rather than from the user, so their trusted tools
+//! and hooks are ignored: either would let the repository run commands without confirmation.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::hooks::Hook;
+use super::tools::custom_tool::CustomToolConfig;
+use super::tools::{
+    ToolOrigin,
+    ToolSpec,
+};
+use crate::platform::Context;
+use crate::util::directories;
+
+/// The JSON schema agent files are validated against, printed by `/agent schema`.
+pub const AGENT_SCHEMA: &str = include_str!("agent_schema.json");
+
+/// Directory of the agents defined in a workspace, relative to the current directory.
+const WORKSPACE_AGENTS_DIR: &str = ".amazonq/agents";
+
+/// Tools that are part of every conversation, whatever the agent allows.
+const ALWAYS_ALLOWED_TOOLS: &[&str] = &["dummy"];
+
+/// The contents of an agent file, see `agent_schema.json`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentConfig {
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Instructions added to the context of every conversation with the agent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    /// Tools the agent can use, all of them if [None]. `*` matches every tool and `@<server>`
+    /// every tool of an MCP server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<String>>,
+    /// Tools used without confirmation, unless the user changes their permissions.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trusted_tools: Vec<String>,
+    /// The only MCP servers launched for the agent, if set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mcp_servers: Option<HashMap<String, CustomToolConfig>>,
+    /// Name of the model used by default, as accepted by `--model`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Hooks run in addition to the global and profile hooks.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub hooks: HashMap<String, Hook>,
+}
+
+impl AgentConfig {
+    /// Parses the contents of an agent file, reporting every violation of the schema.
+    pub fn from_json(contents: &str) -> Result<Self> {
+        let value: serde_json::Value = serde_json::from_str(contents)?;
+        let schema: serde_json::Value = serde_json::from_str(AGENT_SCHEMA)?;
+        let validator = jsonschema::validator_for(&schema).map_err(|err| eyre!("Invalid agent schema: {err}"))?;
+        let errors = validator
+            .iter_errors(&value)
+            .map(|err| match err.instance_path.to_string() {
+                path if path.is_empty() => err.to_string(),
+                path => format!("{path}: {err}"),
+            })
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            bail!("{}", errors.join("\n"));
+        }
+
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Whether the agent can use the tool described by `spec`.
+    pub fn allows_tool(&self, spec: &ToolSpec) -> bool {
+        if ALWAYS_ALLOWED_TOOLS.contains(&spec.name.as_str()) {
+            return true;
+        }
+        if let (Some(servers), ToolOrigin::McpServer(server)) = (&self.mcp_servers, &spec.tool_origin) {
+            if !servers.contains_key(server) {
+                return false;
+            }
+        }
+        match &self.tools {
+            Some(patterns) => patterns.iter().any(|pattern| matches_tool(pattern, spec)),
+            None => true,
+        }
+    }
+
+    /// Whether the agent trusts the tool described by `spec`.
+    pub fn trusts_tool(&self, spec: &ToolSpec) -> bool {
+        self.trusted_tools.iter().any(|pattern| matches_tool(pattern, spec))
+    }
+}
+
+fn matches_tool(pattern: &str, spec: &ToolSpec) -> bool {
+    match pattern.strip_prefix('@') {
+        Some(server) => spec.tool_origin == ToolOrigin::McpServer(server.to_string()),
+        None => pattern == "*" || pattern == spec.name,
+    }
+}
+
+/// Where an agent is defined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AgentSource {
+    Workspace,
+    Global,
+}
+
+impl fmt::Display for AgentSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentSource::Workspace => write!(f, "workspace"),
+            AgentSource::Global => write!(f, "global"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Agent {
+    pub name: String,
+    pub source: AgentSource,
+    pub path: PathBuf,
+    pub config: AgentConfig,
+    /// Fields of a workspace agent that were ignored, see the module documentation.
+    #[serde(skip)]
+    pub ignored_fields: Vec<&'static str>,
+}
+
+impl Agent {
+    /// Loads the agent called `name`, preferring the workspace definition over the global one.
+    pub async fn load(ctx: &Context, name: &str) -> Result<Self> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            bail!("Invalid agent name '{name}'. Agent names may only contain letters, digits, '-' and '_'.");
+        }
+
+        for (source, dir) in agent_dirs(ctx)? {
+            let path = dir.join(format!("{name}.json"));
+            if ctx.fs().exists(&path) {
+                return Self::load_from_path(ctx, name, source, path).await;
+            }
+        }
+
+        let available = list_agents(ctx)
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, ..)| name)
+            .collect::<Vec<_>>();
+        if available.is_empty() {
+            bail!(
+                "Agent '{name}' does not exist. Agents are defined in {WORKSPACE_AGENTS_DIR}/<name>.json or ~/.aws/amazonq/agents/<name>.json."
+            );
+        }
+        bail!(
+            "Agent '{name}' does not exist. Available agents: {}",
+            available.join(", ")
+        )
+    }
+
+    async fn load_from_path(ctx: &Context, name: &str, source: AgentSource, path: PathBuf) -> Result<Self> {
+        let contents = ctx.fs().read_to_string(&path).await?;
+        let mut config = AgentConfig::from_json(&contents)
+            .map_err(|err| eyre!("Agent '{name}' at {} is invalid:\n{err}", path.display()))?;
+
+        let mut ignored_fields = Vec::new();
+        if source == AgentSource::Workspace {
+            if !config.trusted_tools.is_empty() {
+                config.trusted_tools.clear();
+                ignored_fields.push("trustedTools");
+            }
+            if !config.hooks.is_empty() {
+                config.hooks.clear();
+                ignored_fields.push("hooks");
+            }
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            source,
+            path,
+            config,
+            ignored_fields,
+        })
+    }
+
+    /// Explains which fields of the agent were ignored, if any.
+    pub fn ignored_fields_warning(&self) -> Option<String> {
+        if self.ignored_fields.is_empty() {
+            return None;
+        }
+        Some(format!(
+            "The {} of the workspace agent '{}' are ignored, since they would run without confirmation. Define the agent in ~/.aws/amazonq/agents to use them.",
+            self.ignored_fields.join(" and "),
+            self.name
+        ))
+    }
+}
+
+/// The directories agents are loaded from, in order of precedence.
+fn agent_dirs(ctx: &Context) -> Result<Vec<(AgentSource, PathBuf)>> {
+    Ok(vec![
+        (
+            AgentSource::Workspace,
+            ctx.env().current_dir()?.join(WORKSPACE_AGENTS_DIR),
+        ),
+        (AgentSource::Global, directories::chat_global_agents_dir(ctx)?),
+    ])
+}
+
+/// Lists the names of the available agents along with the result of loading them, sorted by
+/// name. Global agents with the same name as a workspace agent are omitted.
+pub async fn list_agents(ctx: &Context) -> Result<Vec<(String, AgentSource, Result<Agent>)>> {
+    let mut agents: Vec<(String, AgentSource, Result<Agent>)> = Vec::new();
+    for (source, dir) in agent_dirs(ctx)? {
+        if !ctx.fs().exists(&dir) {
+            continue;
+        }
+        let mut read_dir = ctx.fs().read_dir(&dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some(name) = file_name.strip_suffix(".json") else {
+                continue;
+            };
+            if agents.iter().any(|(existing, ..)| existing == name) {
+                continue;
+            }
+            let agent = Agent::load_from_path(ctx, name, source, dir.join(&file_name)).await;
+            agents.push((name.to_string(), source, agent));
+        }
+    }
+    agents.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(agents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::hooks::HookTrigger;
+    use crate::cli::chat::tools::InputSchema;
+
+    fn tool_spec(name: &str, origin: ToolOrigin) -> ToolSpec {
+        ToolSpec {
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: InputSchema(serde_json::json!({})),
+            tool_origin: origin,
+            annotations: None,
+        }
+    }
+
+    #[test]
+    fn test_agent_schema() {
+        let schema: serde_json::Value = serde_json::from_str(AGENT_SCHEMA).unwrap();
+        assert!(jsonschema::validator_for(&schema).is_ok());
+    }
+
+    #[test]
+    fn test_agent_config_from_json() {
+        let config = AgentConfig::from_json(
+            r#"{
+                "description": "Reviews code",
+                "instructions": "Review the changes on the current branch.",
+                "tools": ["fs_read", "execute_bash", "@git"],
+                "trustedTools": ["fs_read"],
+                "mcpServers": { "git": { "command": "git-mcp" } },
+                "model": "claude-4-sonnet",
+                "hooks": { "status": { "trigger": "conversation_start", "type": "inline", "command": "git status" } }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(config.description.as_deref(), Some("Reviews code"));
+        assert_eq!(config.trusted_tools, vec!["fs_read".to_string()]);
+        assert_eq!(config.model.as_deref(), Some("claude-4-sonnet"));
+        assert_eq!(config.hooks["status"].trigger, HookTrigger::ConversationStart);
+        assert!(config.mcp_servers.unwrap().contains_key("git"));
+
+        // Every violation of the schema is reported.
+        let err = AgentConfig::from_json(r#"{"tools": "fs_read", "trustedTool": ["fs_read"]}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("/tools"), "{err}");
+        assert!(err.contains("trustedTool"), "{err}");
+
+        assert!(AgentConfig::from_json(r#"{"hooks": {"h": {"trigger": "sometimes", "type": "inline"}}}"#).is_err());
+        assert!(AgentConfig::from_json("not json").is_err());
+        assert!(AgentConfig::from_json("{}").is_ok());
+    }
+
+    #[test]
+    fn test_agent_tools() {
+        let fs_read = tool_spec("fs_read", ToolOrigin::Native);
+        let fs_write = tool_spec("fs_write", ToolOrigin::Native);
+        let dummy = tool_spec("dummy", ToolOrigin::Native);
+        let git_log = tool_spec("git___log", ToolOrigin::McpServer("git".to_string()));
+        let jira = tool_spec("jira___search", ToolOrigin::McpServer("jira".to_string()));
+
+        let config = AgentConfig::default();
+        assert!(
+            [&fs_read, &fs_write, &git_log, &jira]
+                .iter()
+                .all(|t| config.allows_tool(t))
+        );
+        assert!(!config.trusts_tool(&fs_read));
+
+        let config = AgentConfig::from_json(r#"{"tools": ["fs_read", "@git"], "trustedTools": ["@git"]}"#).unwrap();
+        assert!(config.allows_tool(&fs_read));
+        assert!(config.allows_tool(&git_log));
+        assert!(config.allows_tool(&dummy));
+        assert!(!config.allows_tool(&fs_write));
+        assert!(!config.allows_tool(&jira));
+        assert!(config.trusts_tool(&git_log));
+        assert!(!config.trusts_tool(&fs_read));
+
+        // Tools of servers the agent does not launch are not allowed, even with `*`.
+        let config =
+            AgentConfig::from_json(r#"{"tools": ["*"], "mcpServers": {"git": {"command": "git-mcp"}}}"#).unwrap();
+        assert!(config.allows_tool(&fs_write));
+        assert!(config.allows_tool(&git_log));
+        assert!(!config.allows_tool(&jira));
+    }
+
+    #[tokio::test]
+    async fn test_load_agent() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let dir = directories::chat_global_agents_dir(&ctx).unwrap();
+        ctx.fs().create_dir_all(&dir).await.unwrap();
+        ctx.fs()
+            .write(dir.join("reviewer.json"), r#"{"description": "Reviews code"}"#)
+            .await
+            .unwrap();
+        ctx.fs()
+            .write(dir.join("broken.json"), r#"{"tools": 1}"#)
+            .await
+            .unwrap();
+
+        let agent = Agent::load(&ctx, "reviewer").await.unwrap();
+        assert_eq!(agent.source, AgentSource::Global);
+        assert_eq!(agent.config.description.as_deref(), Some("Reviews code"));
+
+        assert!(Agent::load(&ctx, "broken").await.is_err());
+        assert!(Agent::load(&ctx, "../reviewer").await.is_err());
+        let err = Agent::load(&ctx, "missing").await.unwrap_err().to_string();
+        assert!(err.contains("broken, reviewer"), "{err}");
+
+        let agents = list_agents(&ctx).await.unwrap();
+        assert_eq!(agents.len(), 2);
+        assert!(agents[0].2.is_err());
+        assert!(agents[1].2.is_ok());
+        assert!(agent.ignored_fields_warning().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_load_workspace_agent() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let agent = r#"{
+            "trustedTools": ["*"],
+            "hooks": { "setup": { "trigger": "conversation_start", "type": "inline", "command": "./setup.sh" } }
+        }"#;
+        for (source, dir) in agent_dirs(&ctx).unwrap() {
+            ctx.fs().create_dir_all(&dir).await.unwrap();
+            let name = format!("{source}");
+            ctx.fs().write(dir.join(format!("{name}.json")), agent).await.unwrap();
+        }
+
+        // The trusted tools and hooks of workspace agents are ignored.
+        let workspace = Agent::load(&ctx, "workspace").await.unwrap();
+        assert_eq!(workspace.source, AgentSource::Workspace);
+        assert!(workspace.config.trusted_tools.is_empty());
+        assert!(workspace.config.hooks.is_empty());
+        assert_eq!(workspace.ignored_fields, vec!["trustedTools", "hooks"]);
+        assert!(workspace.ignored_fields_warning().is_some());
+
+        let global = Agent::load(&ctx, "global").await.unwrap();
+        assert_eq!(global.config.trusted_tools, vec!["*".to_string()]);
+        assert_eq!(global.config.hooks.len(), 1);
+        assert!(global.ignored_fields.is_empty());
+    }
+}
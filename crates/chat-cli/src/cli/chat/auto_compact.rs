@@ -0,0 +1,133 @@
+//! Automatic management of the context window.
+//!
+//! Unless disabled in a profile, once a conversation uses more than a configured share of the
+//! model's context window, large tool results outside of the most recent turns are elided first.
+//! If that does not free enough space, the oldest turns are summarized while the most recent turns
+//! are kept verbatim. When disabled, a warning is shown once the context window is full instead.
+
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::token_counter::TokenCounter;
+use super::util::truncate_safe;
+
+/// The auto compaction section of a profile's context.json, e.g.
+/// `{"autoCompact": {"enabled": true, "thresholdPercent": 80, "keepRecentTurns": 4,
+/// "maxToolResultTokens": 2000}}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AutoCompactConfig {
+    /// On unless disabled
+    pub enabled: bool,
+    /// Share of the context window, in percent, above which the conversation is compacted
+    pub threshold_percent: u8,
+    /// Number of most recent turns that are never elided or summarized
+    pub keep_recent_turns: usize,
+    /// Tool results outside of the recent turns using more tokens than this are elided
+    pub max_tool_result_tokens: usize,
+}
+
+impl Default for AutoCompactConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold_percent: 80,
+            keep_recent_turns: 4,
+            max_tool_result_tokens: 2_000,
+        }
+    }
+}
+
+impl AutoCompactConfig {
+    /// The number of tokens above which the conversation is compacted, for a model with the given
+    /// context window size.
+    pub fn threshold_tokens(&self, context_window_size: usize) -> usize {
+        context_window_size * usize::from(self.threshold_percent.min(100)) / 100
+    }
+}
+
+/// What auto compaction has done over the course of a conversation, shown by `/usage`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AutoCompactStats {
+    pub elided_tool_results: usize,
+    pub summarized_turns: usize,
+}
+
+const ELIDED_NOTE_START: &str = "[... ";
+const ELIDED_NOTE_END: &str = " tokens of this tool result were elided to save space in the context window ...]";
+
+/// Replaces everything but the start and the end of a tool result using more than `max_tokens`
+/// tokens with a note. Returns [None] if the content fits or has already been elided.
+pub fn elide_tool_result(content: &str, max_tokens: usize) -> Option<String> {
+    if content.contains(ELIDED_NOTE_END) {
+        return None;
+    }
+    let tokens = TokenCounter::count_tokens(content);
+    if tokens <= max_tokens {
+        return None;
+    }
+
+    // A token is about four bytes, so this keeps about half of `max_tokens` in total.
+    let head = truncate_safe(content, max_tokens);
+    let mut tail_start = content.len().saturating_sub(max_tokens).max(head.len());
+    while !content.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    let tail = &content[tail_start..];
+    let elided = tokens.saturating_sub(TokenCounter::count_tokens(head) + TokenCounter::count_tokens(tail));
+
+    Some(format!(
+        "{head}\n\n{ELIDED_NOTE_START}{elided}{ELIDED_NOTE_END}\n\n{tail}"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_tokens() {
+        let config = AutoCompactConfig::default();
+        assert_eq!(config.threshold_tokens(200_000), 160_000);
+
+        let config = AutoCompactConfig {
+            threshold_percent: 150,
+            ..Default::default()
+        };
+        assert_eq!(config.threshold_tokens(200_000), 200_000);
+    }
+
+    #[test]
+    fn test_auto_compact_config_deser() {
+        let config: AutoCompactConfig = serde_json::from_str(r#"{"keepRecentTurns": 2}"#).unwrap();
+        assert_eq!(config, AutoCompactConfig {
+            keep_recent_turns: 2,
+            ..Default::default()
+        });
+        // Enabled unless turned off.
+        assert!(config.enabled);
+        let config: AutoCompactConfig = serde_json::from_str(r#"{"enabled": false}"#).unwrap();
+        assert!(!config.enabled);
+    }
+
+    #[test]
+    fn test_elide_tool_result() {
+        assert_eq!(elide_tool_result("short output", 100), None);
+
+        let content = (0..2_000).map(|i| format!("line {i}\n")).collect::<Vec<_>>().concat();
+        let elided = elide_tool_result(&content, 100).unwrap();
+        assert!(elided.starts_with("line 0\n"));
+        assert!(elided.ends_with("line 1999\n"));
+        assert!(elided.contains(ELIDED_NOTE_END));
+        assert!(TokenCounter::count_tokens(&elided) < TokenCounter::count_tokens(&content) / 10);
+
+        // Already elided content is left as is.
+        assert_eq!(elide_tool_result(&elided, 10), None);
+
+        // Multibyte characters are not split.
+        let content = "é".repeat(1_000);
+        assert!(elide_tool_result(&content, 101).is_some());
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::{
+    HashSet,
+    VecDeque,
+};
+use std::path::{
+    Path,
+    PathBuf,
+};
+use std::time::{
+    Duration,
+    SystemTime,
+};
+
+use eyre::{
+    Result,
+    bail,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use sha2::{
+    Digest,
+    Sha256,
+};
+use time::OffsetDateTime;
+
+use crate::platform::Context;
+use crate::util::directories::chat_checkpoints_dir;
+
+/// Maximum number of checkpoints kept per conversation. The oldest are dropped first.
+const MAX_CHECKPOINTS: usize = 20;
+
+/// Contents stored more recently than this are not pruned, since the conversations of other
+/// running chat sessions may reference them before being saved.
+pub const PRUNE_MIN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The contents of a file from before it was first modified within a [Checkpoint].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSnapshot {
+    pub path: PathBuf,
+    /// The SHA-256 of the contents, [None] if the file did not exist. The contents themselves are
+    /// stored in [chat_checkpoints_dir] rather than with the conversation, so that saving the
+    /// conversation does not copy every edited file into it.
+    pub contents_hash: Option<String>,
+}
+
+impl FileSnapshot {
+    /// Reads and stores the current contents of `path`. Must be called before the file is
+    /// modified.
+    pub async fn read(ctx: &Context, path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let contents_hash = if ctx.fs().exists(path) {
+            Some(store_contents(ctx, &ctx.fs().read(path).await?).await?)
+        } else {
+            None
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            contents_hash,
+        })
+    }
+
+    /// The contents of the file when it was snapshotted, [None] if it did not exist. Kept as bytes
+    /// so that files that are not UTF-8 are restored as they were.
+    pub async fn contents(&self, ctx: &Context) -> Result<Option<Vec<u8>>> {
+        match &self.contents_hash {
+            Some(hash) => Ok(Some(ctx.fs().read(chat_checkpoints_dir(ctx)?.join(hash)).await?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Stores `contents` in [chat_checkpoints_dir] under their hash, so that the same contents are
+/// only stored once. Returns the hash.
+///
+/// Contents that are already stored are written again, so that [prune_contents] sees them as
+/// recent until the conversation referencing them is saved.
+async fn store_contents(ctx: &Context, contents: &[u8]) -> Result<String> {
+    let hash = hex::encode(Sha256::digest(contents));
+    let dir = chat_checkpoints_dir(ctx)?;
+    ctx.fs().create_dir_all(&dir).await?;
+    ctx.fs().write(dir.join(&hash), contents).await?;
+    Ok(hash)
+}
+
+/// Deletes the contents in [chat_checkpoints_dir] that none of the `saved` conversations
+/// reference anymore, e.g. those of checkpoints dropped past [MAX_CHECKPOINTS], by a fork or a
+/// rewind, or of deleted sessions. Contents stored less than `min_age` ago are kept, since they
+/// may belong to a conversation that has not been saved yet. Returns the number of deleted files.
+pub async fn prune_contents(
+    ctx: &Context,
+    saved: impl IntoIterator<Item = String>,
+    min_age: Duration,
+) -> Result<usize> {
+    /// The part of a saved conversation that references contents.
+    #[derive(Deserialize)]
+    struct SavedCheckpoints {
+        #[serde(default)]
+        checkpoints: CheckpointManager,
+    }
+
+    let mut referenced = HashSet::new();
+    for value in saved {
+        // Nothing is deleted unless every reference is known.
+        let saved: SavedCheckpoints = serde_json::from_str(&value)?;
+        for checkpoint in saved.checkpoints.checkpoints {
+            referenced.extend(checkpoint.files.into_iter().filter_map(|file| file.contents_hash));
+        }
+    }
+
+    let dir = chat_checkpoints_dir(ctx)?;
+    if !ctx.fs().exists(&dir) {
+        return Ok(0);
+    }
+    let mut deleted = 0;
+    let mut entries = ctx.fs().read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_contents = name.len() == 64 && name.bytes().all(|b| b.is_ascii_hexdigit());
+        if !is_contents || referenced.contains(&name) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .await?
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .unwrap_or_default();
+        if age >= min_age {
+            ctx.fs().remove_file(dir.join(&name)).await?;
+            deleted += 1;
+        }
+    }
+    Ok(deleted)
+}
+
+/// The state of every file modified during a single turn of the conversation, taken before the
+/// first modification.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub id: usize,
+    /// The number of the turn, counted by [CheckpointManager::start_turn].
+    #[serde(default)]
+    pub turn: usize,
+    /// The user prompt that started the turn.
+    pub prompt: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    pub files: Vec<FileSnapshot>,
+}
+
+/// Tracks the files modified by `fs_write` so that edits can be rolled back with `/undo` and
+/// `/checkpoint restore`.
+///
+/// Checkpoints are created lazily: [Self::start_turn] only records the prompt, and the checkpoint
+/// itself is created by the first [Self::snapshot] of the turn.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CheckpointManager {
+    checkpoints: VecDeque<Checkpoint>,
+    next_id: usize,
+    /// The number of turns started so far, less the rewound ones.
+    #[serde(default)]
+    turns: usize,
+    /// The prompt of the current turn.
+    current_prompt: Option<String>,
+    /// Id of the checkpoint belonging to the current turn, if a file has been modified yet.
+    current_id: Option<usize>,
+}
+
+impl CheckpointManager {
+    /// Starts a new turn of the conversation. Files modified from now on are restored as part of a
+    /// new checkpoint.
+    pub fn start_turn(&mut self, prompt: impl Into<String>) {
+        self.turns += 1;
+        self.current_prompt = Some(prompt.into());
+        self.current_id = None;
+    }
+
+    /// Drops the checkpoints, as done when the conversation is forked, so that the edits made on
+    /// the branch it was forked from are not reverted from the new branch. Turns keep being
+    /// counted from where they were.
+    pub fn fork(&mut self) {
+        self.checkpoints.clear();
+        self.current_id = None;
+    }
+
+    /// Records the current contents of `path` unless it has already been recorded during this
+    /// turn. Must be called before the file is modified.
+    pub async fn snapshot(&mut self, ctx: &Context, path: impl AsRef<Path>) -> Result<()> {
+        let snapshot = FileSnapshot::read(ctx, path).await?;
+        self.record(snapshot);
+        Ok(())
+    }
+
+    /// Adds a snapshot read with [FileSnapshot::read] to the checkpoint of the current turn,
+    /// unless the file has already been recorded during this turn.
+    pub fn record(&mut self, snapshot: FileSnapshot) {
+        let checkpoint = match self.current_id {
+            Some(id) if self.checkpoints.back().is_some_and(|c| c.id == id) => self.checkpoints.back_mut(),
+            _ => {
+                self.next_id += 1;
+                self.current_id = Some(self.next_id);
+                self.checkpoints.push_back(Checkpoint {
+                    id: self.next_id,
+                    turn: self.turns,
+                    prompt: self.current_prompt.clone().unwrap_or_default(),
+                    created_at: OffsetDateTime::now_utc(),
+                    files: Vec::new(),
+                });
+                while self.checkpoints.len() > MAX_CHECKPOINTS {
+                    self.checkpoints.pop_front();
+                }
+                self.checkpoints.back_mut()
+            },
+        };
+
+        if let Some(checkpoint) = checkpoint {
+            if !checkpoint.files.iter().any(|f| f.path == snapshot.path) {
+                checkpoint.files.push(snapshot);
+            }
+        }
+    }
+
+    /// All checkpoints, oldest first.
+    pub fn checkpoints(&self) -> impl DoubleEndedIterator<Item = &Checkpoint> {
+        self.checkpoints.iter()
+    }
+
+    /// Reverts the edits of the most recent checkpoint. Returns the restored paths.
+    pub async fn undo(&mut self, ctx: &Context) -> Result<Vec<PathBuf>> {
+        match self.checkpoints.back() {
+            Some(checkpoint) => self.restore(ctx, checkpoint.id).await,
+            None => bail!("There are no edits to undo"),
+        }
+    }
+
+    /// Restores every file to its state from before checkpoint `id`, reverting the edits of that
+    /// checkpoint and all later ones. The reverted checkpoints are removed. Returns the restored
+    /// paths.
+    pub async fn restore(&mut self, ctx: &Context, id: usize) -> Result<Vec<PathBuf>> {
+        let Some(index) = self.checkpoints.iter().position(|c| c.id == id) else {
+            bail!("No checkpoint with id {id}");
+        };
+
+        let mut restored = Vec::new();
+        // Later checkpoints are reverted first so that each file ends up with its oldest snapshot.
+        for checkpoint in self.checkpoints.iter().skip(index).rev() {
+            for file in &checkpoint.files {
+                match file.contents(ctx).await? {
+                    Some(contents) => {
+                        if let Some(parent) = file.path.parent() {
+                            ctx.fs().create_dir_all(parent).await?;
+                        }
+                        ctx.fs().write(&file.path, contents).await?;
+                    },
+                    None if ctx.fs().exists(&file.path) => ctx.fs().remove_file(&file.path).await?,
+                    None => (),
+                }
+                if !restored.contains(&file.path) {
+                    restored.push(file.path.clone());
+                }
+            }
+        }
+
+        self.checkpoints.truncate(index);
+        self.current_id = None;
+        Ok(restored)
+    }
+
+    /// Reverts the edits made during the last `turns` turns, as done when they are dropped from
+    /// the conversation. Returns the restored paths.
+    pub async fn rewind(&mut self, ctx: &Context, turns: usize) -> Result<Vec<PathBuf>> {
+        self.turns = self.turns.saturating_sub(turns);
+        self.current_id = None;
+        match self.checkpoints.iter().find(|c| c.turn > self.turns) {
+            Some(checkpoint) => self.restore(ctx, checkpoint.id).await,
+            None => Ok(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::Engine;
+    use base64::engine::general_purpose::STANDARD;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_checkpoints() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let fs = ctx.fs();
+        fs.write("/existing.txt", "one").await.unwrap();
+
+        let mut manager = CheckpointManager::default();
+        manager.start_turn("first");
+        manager.snapshot(&ctx, "/existing.txt").await.unwrap();
+        fs.write("/existing.txt", "two").await.unwrap();
+        // Only the first snapshot within a turn is kept.
+        manager.snapshot(&ctx, "/existing.txt").await.unwrap();
+        fs.write("/existing.txt", "three").await.unwrap();
+
+        manager.start_turn("second");
+        manager.snapshot(&ctx, "/existing.txt").await.unwrap();
+        fs.write("/existing.txt", "four").await.unwrap();
+        manager.snapshot(&ctx, "/new.txt").await.unwrap();
+        fs.write("/new.txt", "new").await.unwrap();
+
+        // Turns without edits do not create checkpoints.
+        manager.start_turn("third");
+
+        let ids = manager
+            .checkpoints()
+            .map(|c| (c.id, c.prompt.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![(1, "first"), (2, "second")]);
+
+        let restored = manager.undo(&ctx).await.unwrap();
+        assert_eq!(restored, vec![
+            PathBuf::from("/existing.txt"),
+            PathBuf::from("/new.txt")
+        ]);
+        assert_eq!(fs.read_to_string("/existing.txt").await.unwrap(), "three");
+        assert!(!fs.exists("/new.txt"));
+
+        manager.start_turn("fourth");
+        manager.snapshot(&ctx, "/existing.txt").await.unwrap();
+        fs.write("/existing.txt", "five").await.unwrap();
+
+        manager.restore(&ctx, 1).await.unwrap();
+        assert_eq!(fs.read_to_string("/existing.txt").await.unwrap(), "one");
+        assert_eq!(manager.checkpoints().count(), 0);
+        assert!(manager.undo(&ctx).await.is_err());
+        assert!(manager.restore(&ctx, 3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_binary() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let fs = ctx.fs();
+        let contents = [0xff, 0xfe, 0x00, b'a', 0x80];
+        fs.write("/file.bin", contents).await.unwrap();
+
+        let mut manager = CheckpointManager::default();
+        manager.start_turn("first");
+        manager.snapshot(&ctx, "/file.bin").await.unwrap();
+        fs.write("/file.bin", "text").await.unwrap();
+
+        // The snapshot survives the conversation being saved and loaded, without its contents being
+        // saved along with it.
+        let saved = serde_json::to_string(&manager).unwrap();
+        assert!(!saved.contains(&STANDARD.encode(contents)));
+        let mut manager: CheckpointManager = serde_json::from_str(&saved).unwrap();
+        manager.undo(&ctx).await.unwrap();
+        assert_eq!(fs.read("/file.bin").await.unwrap(), contents);
+    }
+
+    #[tokio::test]
+    async fn test_prune_contents() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let fs = ctx.fs();
+        let stored = |contents: &str| {
+            chat_checkpoints_dir(&ctx)
+                .unwrap()
+                .join(hex::encode(Sha256::digest(contents)))
+        };
+        fs.write("/file.txt", "initial").await.unwrap();
+
+        let mut manager = CheckpointManager::default();
+        for i in 0..=MAX_CHECKPOINTS {
+            manager.start_turn(i.to_string());
+            manager.snapshot(&ctx, "/file.txt").await.unwrap();
+            fs.write("/file.txt", i.to_string()).await.unwrap();
+        }
+        let saved = serde_json::json!({ "checkpoints": manager }).to_string();
+
+        // Recently stored contents are kept, whether they are referenced or not.
+        assert_eq!(
+            prune_contents(&ctx, [saved.clone()], Duration::from_secs(3600))
+                .await
+                .unwrap(),
+            0
+        );
+        assert!(fs.exists(stored("initial")));
+
+        // Only the contents of the checkpoint evicted past the limit are deleted.
+        assert_eq!(prune_contents(&ctx, [saved], Duration::ZERO).await.unwrap(), 1);
+        assert!(!fs.exists(stored("initial")));
+        assert!(fs.exists(stored("0")));
+        manager.undo(&ctx).await.unwrap();
+        assert_eq!(
+            fs.read_to_string("/file.txt").await.unwrap(),
+            format!("{}", MAX_CHECKPOINTS - 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_record() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let fs = ctx.fs();
+        fs.write("/file.txt", "one").await.unwrap();
+
+        // Snapshots read elsewhere, e.g. by sub-agents, are added to the checkpoint of the turn.
+        let mut manager = CheckpointManager::default();
+        manager.start_turn("first");
+        let snapshot = FileSnapshot::read(&ctx, "/file.txt").await.unwrap();
+        fs.write("/file.txt", "two").await.unwrap();
+        manager.snapshot(&ctx, "/file.txt").await.unwrap();
+        manager.record(snapshot);
+        manager.record(FileSnapshot::read(&ctx, "/new.txt").await.unwrap());
+        fs.write("/new.txt", "new").await.unwrap();
+
+        // The snapshot taken first within the turn is kept.
+        manager.undo(&ctx).await.unwrap();
+        assert_eq!(fs.read_to_string("/file.txt").await.unwrap(), "two");
+        assert!(!fs.exists("/new.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_checkpoints_rewind() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let fs = ctx.fs();
+        fs.write("/file.txt", "one").await.unwrap();
+
+        let mut manager = CheckpointManager::default();
+        for (prompt, contents) in [("first", "two"), ("second", "three")] {
+            manager.start_turn(prompt);
+            manager.snapshot(&ctx, "/file.txt").await.unwrap();
+            fs.write("/file.txt", contents).await.unwrap();
+        }
+        // Turns without edits are rewound as well.
+        manager.start_turn("third");
+
+        assert!(manager.rewind(&ctx, 1).await.unwrap().is_empty());
+        assert_eq!(fs.read_to_string("/file.txt").await.unwrap(), "three");
+        assert_eq!(manager.rewind(&ctx, 1).await.unwrap(), vec![PathBuf::from("/file.txt")]);
+        assert_eq!(fs.read_to_string("/file.txt").await.unwrap(), "two");
+
+        // The turns started after a rewind are counted from where it left off.
+        manager.start_turn("second again");
+        manager.snapshot(&ctx, "/file.txt").await.unwrap();
+        fs.write("/file.txt", "four").await.unwrap();
+        manager.rewind(&ctx, 2).await.unwrap();
+        assert_eq!(fs.read_to_string("/file.txt").await.unwrap(), "one");
+        assert_eq!(manager.checkpoints().count(), 0);
+    }
+}
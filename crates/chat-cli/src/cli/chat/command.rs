@@ -50,6 +50,9 @@ pub enum Command {
     Prompts {
         subcommand: Option<PromptsSubcommand>,
     },
+    Resources {
+        subcommand: Option<ResourcesSubcommand>,
+    },
     Usage,
     Load {
         path: String,
@@ -63,6 +66,35 @@ pub enum Command {
     Subscribe {
         manage: bool,
     },
+    Undo,
+    Checkpoint {
+        subcommand: CheckpointSubcommand,
+    },
+    Knowledge {
+        subcommand: KnowledgeSubcommand,
+    },
+    Fork {
+        name: Option<String>,
+    },
+    Branches,
+    Switch {
+        name: String,
+    },
+    Rewind {
+        turns: usize,
+    },
+    Sessions {
+        search: Option<String>,
+    },
+    SubAgents {
+        id: Option<usize>,
+    },
+    Agent {
+        subcommand: AgentSubcommand,
+    },
+    Mode {
+        subcommand: ModeSubcommand,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -124,12 +156,15 @@ pub enum HooksSubcommand {
     Add {
         name: String,
 
-        #[arg(long, value_parser = ["per_prompt", "conversation_start"])]
+        #[arg(long, value_parser = ["per_prompt", "conversation_start", "pre_tool_use", "post_tool_use", "assistant_response", "session_end"])]
         trigger: String,
 
         #[arg(long, value_parser = clap::value_parser!(String))]
         command: String,
 
+        #[arg(long)]
+        matcher: Option<String>,
+
         #[arg(long)]
         global: bool,
     },
@@ -213,8 +248,10 @@ impl ContextSubcommand {
 
   <em>hooks add [--global] <<name>></em>        <black!>Add a new command context hook</black!>
                                          <black!>--global: Add to global hooks</black!>
-         <em>--trigger <<trigger>></em>           <black!>When to trigger the hook, valid options: `per_prompt` or `conversation_start`</black!>
+         <em>--trigger <<trigger>></em>           <black!>When to trigger the hook, valid options: `per_prompt`, `conversation_start`,</black!>
+                                         <black!>`pre_tool_use`, `post_tool_use`, `assistant_response` or `session_end`</black!>
          <em>--command <<command>></em>             <black!>Shell command to execute</black!>
+         <em>--matcher <<tools>></em>               <black!>Tool names that tool use hooks run for, separated by `|` (default: all)</black!>
 
   <em>hooks rm [--global] <<name>></em>         <black!>Remove an existing context hook</black!>
                                          <black!>--global: Remove from global hooks</black!>
@@ -284,9 +321,14 @@ in global or local profiles.
 {}
 
 <cyan!>Notes</cyan!>
-• Hooks are executed in parallel
+• Hooks are executed in parallel, except tool use hooks which run one after another
 • 'conversation_start' hooks run on the first user prompt and are attached once to the conversation history sent to Amazon Q
 • 'per_prompt' hooks run on each user prompt and are attached to the prompt, but are not stored in conversation history
+• 'pre_tool_use' hooks run before a tool is used. Exiting with code 2 blocks the tool use with stderr as the reason,
+  and printing {{"decision": "block", "reason": "..."}} or {{"tool_input": {{...}}}} blocks or rewrites it
+• 'post_tool_use' hooks run after a tool is used, and their output is added to the tool result
+• 'assistant_response' hooks run when a response finishes and 'session_end' hooks run when the chat ends
+• Hooks receive JSON on stdin describing the event, including the tool name and input for tool use hooks
 "#,
             Self::HOOKS_AVAILABLE_COMMANDS
         )
@@ -337,6 +379,14 @@ impl ToolsSubcommand {
 By default, Amazon Q will ask for your permission to use certain tools. You can control which tools you
 trust so that no confirmation is required. These settings will last only for this session.
 
+Commands run by <em>execute_bash</em> are additionally checked against a command policy. Rules are read from
+<em>~/.aws/amazonq/command_policy.json</em> and <em>~/.aws/amazonq/profiles/<<profile>>/command_policy.json</em>:
+
+  {{"rules": [{{"pattern": "cargo test*", "action": "allow"}}, {{"regex": "^rm\\b", "action": "deny"}}]}}
+
+Every command in a pipeline, chain or subshell is checked. <em>deny</em> rules always apply, <em>ask</em> rules apply
+even to trusted tools, and <em>allow</em> rules let matching commands run without confirmation.
+
 {}
 
 {}"#,
@@ -406,6 +456,232 @@ pub struct PromptsGetParam {
     pub arguments: Option<Vec<String>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourcesSubcommand {
+    List { search_word: Option<String> },
+    Read { get_command: ResourcesGetCommand },
+    Attach { get_command: ResourcesGetCommand },
+    Help,
+}
+
+impl ResourcesSubcommand {
+    const ATTACH_USAGE: &str = "/resources attach [server/]<resource uri or name> [arg...]";
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                                                   <black!>Show an explanation for the resources command</black!>
+  <em>list [search word]</em>                                     <black!>List available resources and resource templates</black!>
+  <em>read <<resource>> [arg]</em>                                  <black!>Print the content of the resource specified</black!>
+  <em>attach <<resource>> [arg]</em>                                <black!>Include the resource specified as context for the next prompt</black!>"};
+    const BASE_COMMAND: &str = color_print::cstr! {"<cyan!>Usage: /resources [SUBCOMMAND]</cyan!>
+
+<cyan!>Description</cyan!>
+  Show the current set of resources and resource templates from the current fleet of mcp servers."};
+    const READ_USAGE: &str = "/resources read [server/]<resource uri or name> [arg...]";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!(
+            "{}\n\n{}\n\n{}",
+            header.as_ref(),
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Resources</magenta,em>
+
+Resources are pieces of data (files, database records, api responses, etc.) exposed by the mcp servers 
+you have installed and configured. Attaching a resource includes its content as context for your next prompt.
+
+A resource can be referred to by its uri or its name. Prefix it with <<server name>>/ when more than one 
+server offers a resource by the same name. Resource templates take arguments, either positionally 
+(in the order they appear in the template) or as <<name>>=<<value>> pairs:
+  <em>/resources attach file:///var/log/app.log</em>
+  <em>/resources attach my_server/table_schema users</em>
+  <em>/resources read repo_file owner=aws path=README.md</em>
+
+{}
+
+{}"#,
+            Self::BASE_COMMAND,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckpointSubcommand {
+    List,
+    Restore { id: usize },
+    Help,
+}
+
+impl CheckpointSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>          <black!>Show an explanation for the checkpoint command</black!>
+  <em>list</em>          <black!>List the checkpoints of this conversation</black!>
+  <em>restore <<id>></em>  <black!>Revert all file edits made since the checkpoint specified</black!>"};
+    const RESTORE_USAGE: &str = "/checkpoint restore <id>";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!("{}\n\n{}", header.as_ref(), Self::AVAILABLE_COMMANDS)
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Checkpoints</magenta,em>
+
+Before a file is edited by Amazon Q, its previous contents are saved to a checkpoint. A checkpoint is 
+created for each of your prompts that results in file edits, and is saved with the conversation.
+
+Use <em>/undo</em> to revert the edits made in response to your last prompt, or 
+<em>/checkpoint restore <<id>></em> to revert all edits made since that checkpoint.
+
+{}"#,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentSubcommand {
+    List,
+    Swap { name: String },
+    Clear,
+    Show,
+    Schema,
+    Help,
+}
+
+impl AgentSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>           <black!>Show an explanation for the agent command</black!>
+  <em>list</em>           <black!>List the available agents</black!>
+  <em>swap <<name>></em>    <black!>Switch to another agent</black!>
+  <em>clear</em>          <black!>Stop using the current agent</black!>
+  <em>show</em>           <black!>Show the definition of the current agent</black!>
+  <em>schema</em>         <black!>Show the JSON schema of agent files</black!>"};
+    const SWAP_USAGE: &str = "/agent swap <name>";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!("{}\n\n{}", header.as_ref(), Self::AVAILABLE_COMMANDS)
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Agents</magenta,em>
+
+An agent bundles instructions, the tools and MCP servers that can be used, the tools that are 
+trusted, a default model and hooks. Agents are defined in <em>.amazonq/agents/<<name>>.json</em> in the 
+current workspace or in <em>~/.aws/amazonq/agents/<<name>>.json</em>, and are selected with 
+<em>q chat --agent <<name>></em> or <em>/agent swap <<name>></em>. The tools trusted by workspace agents 
+and their hooks are ignored, since they come with the workspace rather than from you.
+
+Agent files are validated against the schema shown by <em>/agent schema</em>. MCP servers of an agent 
+are only launched when it is selected with <em>--agent</em>.
+
+{}"#,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModeSubcommand {
+    Toggle,
+    Normal,
+    Plan,
+    Approve,
+    Help,
+}
+
+impl ModeSubcommand {
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>           <black!>Show an explanation for the mode command</black!>
+  <em>normal</em>         <black!>Switch to normal mode, where every tool can be used</black!>
+  <em>plan</em>           <black!>Switch to plan mode, where nothing can be changed</black!>
+  <em>approve</em>        <black!>Switch to normal mode and execute the plan</black!>"};
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!("{}\n\n{}", header.as_ref(), Self::AVAILABLE_COMMANDS)
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Modes</magenta,em>
+
+In plan mode the model can explore the workspace but not change anything: <em>fs_write</em> and MCP 
+tools not annotated as read-only are hidden from the model, and commands or AWS calls that would 
+require confirmation are rejected. The model replies with a plan instead, which <em>/mode approve</em> 
+executes in normal mode.
+
+<em>/mode</em> without a subcommand toggles between the two modes. Start a chat in plan mode with 
+<em>q chat --mode plan</em>.
+
+{}"#,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KnowledgeSubcommand {
+    Show,
+    Add { path: String, name: Option<String> },
+    Remove { name: String },
+    Search { query: String },
+    Help,
+}
+
+impl KnowledgeSubcommand {
+    const ADD_USAGE: &str = "/knowledge add <path> [--name <name>]";
+    const AVAILABLE_COMMANDS: &str = color_print::cstr! {"<cyan!>Available subcommands</cyan!>
+  <em>help</em>                         <black!>Show an explanation for the knowledge command</black!>
+  <em>show</em>                         <black!>List all knowledge bases</black!>
+  <em>add <<path>> [--name <<name>>]</em>  <black!>Index a file or directory, or update its existing index</black!>
+  <em>remove <<name|path>></em>          <black!>Remove a knowledge base</black!>
+  <em>search <<query>></em>              <black!>Search all knowledge bases</black!>"};
+    const REMOVE_USAGE: &str = "/knowledge remove <name|path>";
+    const SEARCH_USAGE: &str = "/knowledge search <query>";
+
+    fn usage_msg(header: impl AsRef<str>) -> String {
+        format!("{}\n\n{}", header.as_ref(), Self::AVAILABLE_COMMANDS)
+    }
+
+    pub fn help_text() -> String {
+        color_print::cformat!(
+            r#"
+<magenta,em>Knowledge bases</magenta,em>
+
+Knowledge bases are semantic indexes of files and directories that Amazon Q can search with the 
+<em>knowledge</em> tool. They are saved to ~/.aws/amazonq/knowledge_bases and kept across sessions, so a 
+repository or documentation folder only needs to be indexed once.
+
+{}
+
+<cyan!>Notes</cyan!>
+• The embedding model is downloaded the first time a knowledge base is used
+• Re-adding a path updates its knowledge base with the latest contents
+• Hidden files are not indexed
+"#,
+            Self::AVAILABLE_COMMANDS
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourcesGetCommand {
+    /// Uri or name of the resource (or resource template), optionally prefixed with the server
+    /// name
+    pub name: String,
+    /// Arguments used to expand a resource template
+    pub arguments: Vec<String>,
+}
+
 impl Command {
     // Check if input is a common single-word command that should use slash prefix
     fn check_common_command(input: &str) -> Option<String> {
@@ -822,6 +1098,42 @@ impl Command {
                         },
                     }
                 },
+                "resources" => {
+                    let subcommand = parts.get(1);
+                    match subcommand {
+                        Some(c) if c.to_lowercase() == "list" => Self::Resources {
+                            subcommand: Some(ResourcesSubcommand::List {
+                                search_word: parts.get(2).map(|v| (*v).to_string()),
+                            }),
+                        },
+                        Some(c) if c.to_lowercase() == "help" => Self::Resources {
+                            subcommand: Some(ResourcesSubcommand::Help),
+                        },
+                        Some(c) if c.to_lowercase() == "read" => {
+                            let get_command = parse_input_to_resources_get_command(&parts[2..].join(" "))
+                                .map_err(|e| format!("{e}\n\nUsage:\n  {}", ResourcesSubcommand::READ_USAGE))?;
+                            Self::Resources {
+                                subcommand: Some(ResourcesSubcommand::Read { get_command }),
+                            }
+                        },
+                        Some(c) if c.to_lowercase() == "attach" => {
+                            let get_command = parse_input_to_resources_get_command(&parts[2..].join(" "))
+                                .map_err(|e| format!("{e}\n\nUsage:\n  {}", ResourcesSubcommand::ATTACH_USAGE))?;
+                            Self::Resources {
+                                subcommand: Some(ResourcesSubcommand::Attach { get_command }),
+                            }
+                        },
+                        Some(other) => {
+                            return Err(ResourcesSubcommand::usage_msg(format!(
+                                "Unknown subcommand '{}'\n",
+                                other
+                            )));
+                        },
+                        None => Self::Resources {
+                            subcommand: Some(ResourcesSubcommand::List { search_word: None }),
+                        },
+                    }
+                },
                 "usage" => Self::Usage,
                 "load" => {
                     let Some(path) = parts.get(1) else {
@@ -848,6 +1160,151 @@ impl Command {
                     let manage = parts.contains(&"--manage");
                     Self::Subscribe { manage }
                 },
+                "undo" => Self::Undo,
+                "fork" => {
+                    let name = parts[1..].join(" ");
+                    Self::Fork {
+                        name: (!name.is_empty()).then_some(name),
+                    }
+                },
+                "branches" => Self::Branches,
+                "switch" => {
+                    let name = parts[1..].join(" ");
+                    if name.is_empty() {
+                        return Err("branch name is required\n\nUsage:\n  /switch <name>".to_string());
+                    }
+                    Self::Switch { name }
+                },
+                "sessions" => {
+                    let search = parts[1..].join(" ");
+                    Self::Sessions {
+                        search: (!search.is_empty()).then_some(search),
+                    }
+                },
+                "subagents" => match parts.get(1).map(|id| id.parse::<usize>()) {
+                    None => Self::SubAgents { id: None },
+                    Some(Ok(id)) => Self::SubAgents { id: Some(id) },
+                    Some(Err(_)) => {
+                        return Err("Invalid /subagents arguments.\n\nUsage:\n  /subagents [id]".to_string());
+                    },
+                },
+                "agent" => {
+                    let subcommand = match parts.get(1).map(|c| c.to_lowercase()).as_deref() {
+                        None | Some("list") => AgentSubcommand::List,
+                        Some("help") => AgentSubcommand::Help,
+                        Some("show") => AgentSubcommand::Show,
+                        Some("schema") => AgentSubcommand::Schema,
+                        Some("clear") => AgentSubcommand::Clear,
+                        Some("swap") => match parts.get(2) {
+                            Some(name) => AgentSubcommand::Swap {
+                                name: (*name).to_string(),
+                            },
+                            None => {
+                                return Err(format!(
+                                    "Invalid /agent arguments.\n\nUsage:\n  {}",
+                                    AgentSubcommand::SWAP_USAGE
+                                ));
+                            },
+                        },
+                        Some(other) => {
+                            return Err(AgentSubcommand::usage_msg(format!("Unknown subcommand '{}'\n", other)));
+                        },
+                    };
+                    Self::Agent { subcommand }
+                },
+                "mode" => {
+                    let subcommand = match parts.get(1).map(|c| c.to_lowercase()).as_deref() {
+                        None => ModeSubcommand::Toggle,
+                        Some("normal") => ModeSubcommand::Normal,
+                        Some("plan") => ModeSubcommand::Plan,
+                        Some("approve") => ModeSubcommand::Approve,
+                        Some("help") => ModeSubcommand::Help,
+                        Some(other) => {
+                            return Err(ModeSubcommand::usage_msg(format!("Unknown subcommand '{}'\n", other)));
+                        },
+                    };
+                    Self::Mode { subcommand }
+                },
+                "rewind" => match parts.get(1).map(|turns| turns.parse::<usize>()) {
+                    None => Self::Rewind { turns: 1 },
+                    Some(Ok(turns)) if turns > 0 => Self::Rewind { turns },
+                    _ => return Err("Invalid /rewind arguments.\n\nUsage:\n  /rewind [turns]".to_string()),
+                },
+                "checkpoint" => {
+                    let subcommand = match parts.get(1).map(|c| c.to_lowercase()).as_deref() {
+                        None | Some("list") => CheckpointSubcommand::List,
+                        Some("help") => CheckpointSubcommand::Help,
+                        Some("restore") => match parts.get(2).map(|id| id.parse::<usize>()) {
+                            Some(Ok(id)) => CheckpointSubcommand::Restore { id },
+                            _ => {
+                                return Err(format!(
+                                    "Invalid /checkpoint arguments.\n\nUsage:\n  {}",
+                                    CheckpointSubcommand::RESTORE_USAGE
+                                ));
+                            },
+                        },
+                        Some(other) => {
+                            return Err(CheckpointSubcommand::usage_msg(format!(
+                                "Unknown subcommand '{}'\n",
+                                other
+                            )));
+                        },
+                    };
+                    Self::Checkpoint { subcommand }
+                },
+                "knowledge" => {
+                    macro_rules! usage_err {
+                        ($usage_str:expr) => {
+                            return Err(format!(
+                                "Invalid /knowledge arguments.\n\nUsage:\n  {}",
+                                $usage_str
+                            ))
+                        };
+                    }
+
+                    let args = match shlex::split(&parts[1..].join(" ")) {
+                        Some(args) => args,
+                        None => return Err("Failed to parse quoted arguments".to_string()),
+                    };
+                    let subcommand = match args.first().map(|c| c.to_lowercase()).as_deref() {
+                        None | Some("show") => KnowledgeSubcommand::Show,
+                        Some("help") => KnowledgeSubcommand::Help,
+                        Some("add") => {
+                            let mut path = None;
+                            let mut name = None;
+                            let mut args = args[1..].iter();
+                            while let Some(arg) = args.next() {
+                                match arg.as_str() {
+                                    "--name" => match args.next() {
+                                        Some(n) => name = Some(n.clone()),
+                                        None => usage_err!(KnowledgeSubcommand::ADD_USAGE),
+                                    },
+                                    _ if path.is_none() => path = Some(arg.clone()),
+                                    _ => usage_err!(KnowledgeSubcommand::ADD_USAGE),
+                                }
+                            }
+                            match path {
+                                Some(path) => KnowledgeSubcommand::Add { path, name },
+                                None => usage_err!(KnowledgeSubcommand::ADD_USAGE),
+                            }
+                        },
+                        Some("remove" | "rm") => match &args[1..] {
+                            [name] => KnowledgeSubcommand::Remove { name: name.clone() },
+                            _ => usage_err!(KnowledgeSubcommand::REMOVE_USAGE),
+                        },
+                        Some("search") => match args[1..].join(" ") {
+                            query if query.trim().is_empty() => usage_err!(KnowledgeSubcommand::SEARCH_USAGE),
+                            query => KnowledgeSubcommand::Search { query },
+                        },
+                        Some(other) => {
+                            return Err(KnowledgeSubcommand::usage_msg(format!(
+                                "Unknown subcommand '{}'\n",
+                                other
+                            )));
+                        },
+                    };
+                    Self::Knowledge { subcommand }
+                },
                 unknown_command => {
                     let looks_like_path = {
                         let after_slash_command_str = parts[1..].join(" ");
@@ -924,6 +1381,14 @@ fn parse_input_to_prompts_get_command(command: &str) -> Result<PromptsGetCommand
     Ok(PromptsGetCommand { orig_input, params })
 }
 
+fn parse_input_to_resources_get_command(command: &str) -> Result<ResourcesGetCommand, String> {
+    let input = shell_words::split(command).map_err(|e| format!("Error splitting command for resources: {:?}", e))?;
+    let mut iter = input.into_iter();
+    let name = iter.next().ok_or("Resource needs to be specified")?;
+    let arguments = iter.collect::<Vec<_>>();
+    Ok(ResourcesGetCommand { name, arguments })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -946,6 +1411,13 @@ mod tests {
                 }
             };
         }
+        macro_rules! resources {
+            ($subcommand:expr) => {
+                Command::Resources {
+                    subcommand: Some($subcommand),
+                }
+            };
+        }
         macro_rules! compact {
             ($prompt:expr, $show_summary:expr) => {
                 Command::Compact {
@@ -1045,7 +1517,20 @@ mod tests {
                         name: "test".to_string(),
                         global: true,
                         trigger: "per_prompt".to_string(),
-                        command: "echo 1".to_string()
+                        command: "echo 1".to_string(),
+                        matcher: None,
+                    })
+                }),
+            ),
+            (
+                "/context hooks add fmt --trigger post_tool_use --command 'cargo fmt' --matcher 'fs_write'",
+                context!(ContextSubcommand::Hooks {
+                    subcommand: Some(HooksSubcommand::Add {
+                        name: "fmt".to_string(),
+                        global: false,
+                        trigger: "post_tool_use".to_string(),
+                        command: "cargo fmt".to_string(),
+                        matcher: Some("fs_write".to_string()),
                     })
                 }),
             ),
@@ -1094,6 +1579,111 @@ mod tests {
                     subcommand: Some(HooksSubcommand::Help)
                 }),
             ),
+            (
+                "/resources",
+                resources!(ResourcesSubcommand::List { search_word: None }),
+            ),
+            (
+                "/resources list logs",
+                resources!(ResourcesSubcommand::List {
+                    search_word: Some("logs".to_string())
+                }),
+            ),
+            (
+                "/resources read file:///var/log/app.log",
+                resources!(ResourcesSubcommand::Read {
+                    get_command: ResourcesGetCommand {
+                        name: "file:///var/log/app.log".to_string(),
+                        arguments: vec![]
+                    }
+                }),
+            ),
+            (
+                "/resources attach db/table_schema users 'name=first last'",
+                resources!(ResourcesSubcommand::Attach {
+                    get_command: ResourcesGetCommand {
+                        name: "db/table_schema".to_string(),
+                        arguments: vec!["users".to_string(), "name=first last".to_string()]
+                    }
+                }),
+            ),
+            ("/undo", Command::Undo),
+            ("/fork", Command::Fork { name: None }),
+            ("/fork experiment", Command::Fork {
+                name: Some("experiment".to_string()),
+            }),
+            ("/fork retry with sqlite", Command::Fork {
+                name: Some("retry with sqlite".to_string()),
+            }),
+            ("/branches", Command::Branches),
+            ("/switch main", Command::Switch {
+                name: "main".to_string(),
+            }),
+            ("/switch retry with sqlite", Command::Switch {
+                name: "retry with sqlite".to_string(),
+            }),
+            ("/rewind", Command::Rewind { turns: 1 }),
+            ("/rewind 3", Command::Rewind { turns: 3 }),
+            ("/sessions", Command::Sessions { search: None }),
+            ("/sessions auth bug", Command::Sessions {
+                search: Some("auth bug".to_string()),
+            }),
+            ("/subagents", Command::SubAgents { id: None }),
+            ("/subagents 2", Command::SubAgents { id: Some(2) }),
+            ("/agent", Command::Agent {
+                subcommand: AgentSubcommand::List,
+            }),
+            ("/agent swap reviewer", Command::Agent {
+                subcommand: AgentSubcommand::Swap {
+                    name: "reviewer".to_string(),
+                },
+            }),
+            ("/agent schema", Command::Agent {
+                subcommand: AgentSubcommand::Schema,
+            }),
+            ("/agent clear", Command::Agent {
+                subcommand: AgentSubcommand::Clear,
+            }),
+            ("/mode", Command::Mode {
+                subcommand: ModeSubcommand::Toggle,
+            }),
+            ("/mode plan", Command::Mode {
+                subcommand: ModeSubcommand::Plan,
+            }),
+            ("/mode approve", Command::Mode {
+                subcommand: ModeSubcommand::Approve,
+            }),
+            ("/checkpoint", Command::Checkpoint {
+                subcommand: CheckpointSubcommand::List,
+            }),
+            ("/checkpoint restore 3", Command::Checkpoint {
+                subcommand: CheckpointSubcommand::Restore { id: 3 },
+            }),
+            ("/knowledge", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Show,
+            }),
+            ("/knowledge add ~/docs", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Add {
+                    path: "~/docs".to_string(),
+                    name: None,
+                },
+            }),
+            ("/knowledge add \"my docs\" --name docs", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Add {
+                    path: "my docs".to_string(),
+                    name: Some("docs".to_string()),
+                },
+            }),
+            ("/knowledge rm docs", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Remove {
+                    name: "docs".to_string(),
+                },
+            }),
+            ("/knowledge search how are users authenticated", Command::Knowledge {
+                subcommand: KnowledgeSubcommand::Search {
+                    query: "how are users authenticated".to_string(),
+                },
+            }),
         ];
 
         for (input, parsed) in tests {
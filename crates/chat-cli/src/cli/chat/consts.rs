@@ -1,5 +1,3 @@
-use super::token_counter::TokenCounter;
-
 // These limits are the internal undocumented values from the service for each item
 
 pub const MAX_CURRENT_WORKING_DIRECTORY_LEN: usize = 256;
@@ -13,16 +11,27 @@ pub const MAX_TOOL_RESPONSE_SIZE: usize = 400_000;
 /// Actual service limit is 600_000
 pub const MAX_USER_MESSAGE_SIZE: usize = 400_000;
 
-/// In tokens
+/// In tokens, used for the default model and models without a known context window size.
+///
+/// The service reports neither the context window of a model nor the tokens used by a request,
+/// so the usage is estimated on the client with [TokenCounter](super::token_counter::TokenCounter).
 pub const CONTEXT_WINDOW_SIZE: usize = 200_000;
 
 pub const CONTEXT_FILES_MAX_SIZE: usize = 150_000;
 
-pub const MAX_CHARS: usize = TokenCounter::token_to_chars(CONTEXT_WINDOW_SIZE); // Character-based warning threshold
-
 pub const DUMMY_TOOL_NAME: &str = "dummy";
 
 pub const MAX_NUMBER_OF_IMAGES_PER_REQUEST: usize = 10;
 
 /// In bytes - 10 MB
 pub const MAX_IMAGE_SIZE: usize = 10 * 1024 * 1024;
+
+/// Maximum number of read-only tools invoked at once, unless set with `chat.toolConcurrency`.
+pub const DEFAULT_TOOL_CONCURRENCY: usize = 4;
+
+/// Number of commands recently run in the terminal that are added to the context when
+/// `history.captureOutput` is enabled.
+pub const SHELL_HISTORY_CONTEXT_LEN: usize = 5;
+
+/// Name of the branch a conversation starts on, before any `/fork`.
+pub const DEFAULT_BRANCH_NAME: &str = "main";
@@ -16,14 +16,24 @@ use serde::{
     Deserialize,
     Serialize,
 };
-use tracing::debug;
+use tracing::{
+    debug,
+    warn,
+};
 
+use super::agent::Agent;
+use super::auto_compact::AutoCompactConfig;
 use super::consts::CONTEXT_FILES_MAX_SIZE;
 use super::hooks::{
     Hook,
     HookExecutor,
+    HookInput,
+    HookTrigger,
+    PreToolUseDecision,
 };
+use super::tools::execute::SandboxConfig;
 use super::util::drop_matched_context_files;
+use crate::mcp_client::Root;
 use crate::platform::Context;
 use crate::util::directories;
 
@@ -38,6 +48,14 @@ pub struct ContextConfig {
 
     /// Map of Hook Name to [`Hook`]. The hook name serves as the hook's ID.
     pub hooks: HashMap<String, Hook>,
+
+    /// Sandbox settings for `execute_bash`. Only read from profile configurations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxConfig>,
+
+    /// Automatic context window management. Only read from profile configurations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_compact: Option<AutoCompactConfig>,
 }
 
 #[allow(dead_code)]
@@ -59,6 +77,11 @@ pub struct ContextManager {
     /// Context configuration for the current profile.
     pub profile_config: ContextConfig,
 
+    /// The agent selected with `--agent` or `/agent swap`, whose instructions are added to the
+    /// context and whose hooks run along with the global and profile hooks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub agent: Option<Agent>,
+
     #[serde(skip)]
     pub hook_executor: HookExecutor,
 }
@@ -96,6 +119,7 @@ impl ContextManager {
             global_config,
             current_profile,
             profile_config,
+            agent: None,
             hook_executor: HookExecutor::new(),
         })
     }
@@ -133,6 +157,13 @@ impl ContextManager {
     pub async fn reload_config(&mut self) -> Result<()> {
         self.global_config = load_global_config(&self.ctx).await?;
         self.profile_config = load_profile_config(&self.ctx, &self.current_profile).await?;
+        if let Some(agent) = self.agent.as_mut() {
+            // Keep the agent as it was if its file has since become invalid.
+            match Agent::load(&self.ctx, &agent.name).await {
+                Ok(reloaded) => *agent = reloaded,
+                Err(err) => warn!(?err, "Failed to reload agent {}", agent.name),
+            }
+        }
         Ok(())
     }
 
@@ -584,31 +615,165 @@ impl ContextManager {
         self.save_config(global).await
     }
 
-    /// Run all the currently enabled hooks from both the global and profile contexts.
+    /// Run all the currently enabled context hooks from both the global and profile contexts.
     /// Skipped hooks (disabled) will not appear in the output.
     /// # Arguments
     /// * `updates` - output stream to write hook run status to if Some, else do nothing if None
     /// # Returns
     /// A vector containing pairs of a [`Hook`] definition and its execution output
     pub async fn run_hooks(&mut self, updates: Option<&mut impl Write>) -> Vec<(Hook, String)> {
-        let mut hooks: Vec<&Hook> = Vec::new();
-
-        // Set internal hook states
-        let configs = [
-            (&mut self.global_config.hooks, true),
-            (&mut self.profile_config.hooks, false),
-        ];
-
-        for (hook_list, is_global) in configs {
-            hooks.extend(hook_list.iter_mut().map(|(name, h)| {
-                h.name = name.to_string();
-                h.is_global = is_global;
-                &*h
-            }));
-        }
+        let hooks = named_hooks(
+            &mut self.global_config.hooks,
+            &mut self.profile_config.hooks,
+            self.agent.as_mut().map(|agent| &mut agent.config.hooks),
+        )
+        .into_iter()
+        .filter(|h| h.trigger.is_context())
+        .collect();
+
+        self.hook_executor.run_hooks(&self.ctx, hooks, updates).await
+    }
+
+    /// Run the [`HookTrigger::PreToolUse`] hooks that match `tool_name`.
+    pub async fn run_pre_tool_use_hooks(
+        &mut self,
+        tool_name: &str,
+        tool_input: &serde_json::Value,
+    ) -> PreToolUseDecision {
+        let hooks = named_hooks(
+            &mut self.global_config.hooks,
+            &mut self.profile_config.hooks,
+            self.agent.as_mut().map(|agent| &mut agent.config.hooks),
+        )
+        .into_iter()
+        .filter(|h| h.trigger == HookTrigger::PreToolUse)
+        .collect();
+
+        self.hook_executor
+            .run_pre_tool_use_hooks(&self.ctx, hooks, tool_name, tool_input)
+            .await
+    }
+
+    /// Run the [`HookTrigger::PostToolUse`] hooks that match `tool_name`.
+    pub async fn run_post_tool_use_hooks(
+        &mut self,
+        tool_name: &str,
+        tool_input: &serde_json::Value,
+        tool_response: serde_json::Value,
+    ) -> Vec<(Hook, String)> {
+        let hooks = named_hooks(
+            &mut self.global_config.hooks,
+            &mut self.profile_config.hooks,
+            self.agent.as_mut().map(|agent| &mut agent.config.hooks),
+        )
+        .into_iter()
+        .filter(|h| h.trigger == HookTrigger::PostToolUse)
+        .collect();
+
+        self.hook_executor
+            .run_post_tool_use_hooks(&self.ctx, hooks, tool_name, tool_input, tool_response)
+            .await
+    }
+
+    /// Run the hooks for the event described by `input`, e.g. [`HookTrigger::SessionEnd`].
+    pub async fn run_event_hooks(&mut self, input: HookInput) {
+        let hooks = named_hooks(
+            &mut self.global_config.hooks,
+            &mut self.profile_config.hooks,
+            self.agent.as_mut().map(|agent| &mut agent.config.hooks),
+        )
+        .into_iter()
+        .filter(|h| h.trigger == input.hook_event_name)
+        .collect();
+
+        self.hook_executor.run_event_hooks(hooks, &input).await;
+    }
+
+    /// The sandbox settings of the current profile, if it has any.
+    pub fn sandbox_config(&self) -> Option<&SandboxConfig> {
+        self.profile_config.sandbox.as_ref()
+    }
+
+    /// The instructions of the current agent, if it has any.
+    pub fn agent_instructions(&self) -> Option<&str> {
+        self.agent
+            .as_ref()
+            .and_then(|agent| agent.config.instructions.as_deref())
+    }
 
-        self.hook_executor.run_hooks(hooks, updates).await
+    /// The auto compaction settings of the current profile, if it has any.
+    pub fn auto_compact_config(&self) -> Option<&AutoCompactConfig> {
+        self.profile_config.auto_compact.as_ref()
     }
+
+    /// Directories to expose to MCP servers as roots: the current working directory followed by
+    /// the directories that context paths point into.
+    pub fn roots(&self) -> Result<Vec<Root>> {
+        let cwd = self.ctx.env().current_dir()?;
+        let paths = self.global_config.paths.iter().chain(self.profile_config.paths.iter());
+        Ok(root_dirs(&self.ctx, cwd, paths)
+            .into_iter()
+            .filter_map(|dir| {
+                let uri = url::Url::from_directory_path(&dir).ok()?.to_string();
+                let name = dir.file_name().map(|n| n.to_string_lossy().to_string());
+                Some(Root { uri, name })
+            })
+            .collect())
+    }
+}
+
+/// All global and profile hooks, with their internal name and scope set.
+fn named_hooks<'a>(
+    global: &'a mut HashMap<String, Hook>,
+    profile: &'a mut HashMap<String, Hook>,
+    agent: Option<&'a mut HashMap<String, Hook>>,
+) -> Vec<&'a Hook> {
+    let mut hooks: Vec<&Hook> = Vec::new();
+    for (hook_list, is_global) in [(Some(global), true), (Some(profile), false), (agent, false)] {
+        let Some(hook_list) = hook_list else {
+            continue;
+        };
+        hooks.extend(hook_list.iter_mut().map(|(name, h)| {
+            h.name = name.to_string();
+            h.is_global = is_global;
+            &*h
+        }));
+    }
+    hooks
+}
+
+/// Reduces context paths to the directories they point into, starting with `cwd`. Globs are
+/// reduced to the directory they are rooted at and files to their parent. Paths that fall under
+/// a directory already included are omitted, as are those that do not resolve to an existing
+/// directory.
+fn root_dirs<'a>(ctx: &Context, cwd: PathBuf, paths: impl IntoIterator<Item = &'a String>) -> Vec<PathBuf> {
+    let mut dirs = vec![cwd.clone()];
+    for path in paths {
+        let expanded = match path.strip_prefix("~/") {
+            Some(rest) => match ctx.env().home() {
+                Some(home) => home.join(rest),
+                None => continue,
+            },
+            None => cwd.join(path),
+        };
+        let mut dir = expanded
+            .components()
+            .take_while(|c| !c.as_os_str().to_string_lossy().contains(['*', '?', '[']))
+            .collect::<PathBuf>();
+        let chrooted = ctx.fs().chroot_path(&dir);
+        if chrooted.is_file() {
+            match dir.parent() {
+                Some(parent) => dir = parent.to_path_buf(),
+                None => continue,
+            }
+        } else if !chrooted.is_dir() {
+            continue;
+        }
+        if !dirs.iter().any(|d| dir.starts_with(d)) {
+            dirs.push(dir);
+        }
+    }
+    dirs
 }
 
 fn profile_dir_path(ctx: &Context, profile_name: &str) -> Result<PathBuf> {
@@ -642,6 +807,8 @@ async fn load_global_config(ctx: &Context) -> Result<ContextConfig> {
                 AMAZONQ_FILENAME.to_string(),
             ],
             hooks: HashMap::new(),
+            sandbox: None,
+            auto_compact: None,
         })
     }
 }
@@ -938,6 +1105,30 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_root_dirs() -> Result<()> {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/workspace/test").await?;
+        ctx.fs().create_dir_all("/elsewhere/docs").await?;
+        ctx.fs().write("/elsewhere/notes.md", "notes").await?;
+
+        let paths = [
+            "test/*.md",
+            "/elsewhere/docs/**/*.md",
+            "/elsewhere/notes.md",
+            "/missing/*.md",
+        ]
+        .map(String::from);
+        let dirs = root_dirs(&ctx, PathBuf::from("/workspace"), paths.iter());
+        assert_eq!(dirs, vec![
+            PathBuf::from("/workspace"),
+            PathBuf::from("/elsewhere/docs"),
+            PathBuf::from("/elsewhere"),
+        ]);
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_add_hook() -> Result<()> {
         let mut manager = create_test_context_manager(None).await?;
@@ -21,9 +21,16 @@ use tracing::{
     warn,
 };
 
+use super::agent::Agent;
+use super::auto_compact::{
+    AutoCompactConfig,
+    AutoCompactStats,
+    elide_tool_result,
+};
+use super::checkpoint::CheckpointManager;
 use super::consts::{
+    DEFAULT_BRANCH_NAME,
     DUMMY_TOOL_NAME,
-    MAX_CHARS,
     MAX_CONVERSATION_STATE_HISTORY_LEN,
     MAX_USER_MESSAGE_SIZE,
 };
@@ -40,11 +47,20 @@ use super::message::{
     UserMessageContent,
     build_env_state,
 };
+use super::mode::{
+    ChatMode,
+    PLAN_MODE_INSTRUCTIONS,
+};
 use super::token_counter::{
-    CharCount,
-    CharCounter,
+    TokenCount,
+    TokenCounted,
+    TokenCounter,
+};
+use super::tool_manager::{
+    ResourceBundle,
+    ToolManager,
 };
-use super::tool_manager::ToolManager;
+use super::tools::delegate::SubAgentTranscript;
 use super::tools::{
     InputSchema,
     QueuedTool,
@@ -70,7 +86,10 @@ use crate::api_client::model::{
     UserInputMessageContext,
 };
 use crate::cli::chat::util::shared_writer::SharedWriter;
-use crate::database::Database;
+use crate::database::{
+    Database,
+    ShellCommand,
+};
 use crate::mcp_client::Prompt;
 use crate::platform::Context;
 
@@ -108,6 +127,37 @@ pub struct ConversationState {
     /// Model explicitly selected by the user in this conversation state via `/model`.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub model: Option<String>,
+    /// Snapshots of the files edited during the conversation, used by `/undo` and `/checkpoint`.
+    #[serde(default)]
+    pub checkpoints: CheckpointManager,
+    /// The length of the transcript when each turn started, see [Self::start_turn].
+    #[serde(default)]
+    turn_transcript_lens: VecDeque<usize>,
+    /// Name of the branch this conversation is on, see `/fork`.
+    #[serde(default = "default_branch_name")]
+    branch: String,
+    /// What automatic context window management has done in this conversation.
+    #[serde(default)]
+    auto_compact_stats: AutoCompactStats,
+    /// Transcripts of the sub-agents run by the `delegate` tool, see `/subagents`.
+    #[serde(default)]
+    sub_agents: Vec<SubAgentTranscript>,
+    /// Whether the model can change anything, see `/mode`.
+    #[serde(default)]
+    mode: ChatMode,
+    /// The commands last run in the terminal the conversation was started from, formatted as
+    /// context, see [Self::set_shell_history].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shell_history: Option<String>,
+}
+
+fn default_branch_name() -> String {
+    DEFAULT_BRANCH_NAME.to_string()
+}
+
+/// Formats a message of the user as shown in the transcript.
+fn user_transcript(message: &str) -> String {
+    format!("> {}", message.replace("\n", "> \n"))
 }
 
 impl ConversationState {
@@ -162,6 +212,13 @@ impl ConversationState {
             latest_summary: None,
             updates,
             model: current_model_id,
+            checkpoints: CheckpointManager::default(),
+            turn_transcript_lens: VecDeque::new(),
+            branch: default_branch_name(),
+            auto_compact_stats: AutoCompactStats::default(),
+            sub_agents: Vec::new(),
+            mode: ChatMode::default(),
+            shell_history: None,
         }
     }
 
@@ -213,6 +270,164 @@ impl ConversationState {
         }
     }
 
+    /// Returns the name of the branch this conversation is on.
+    pub fn branch(&self) -> &str {
+        &self.branch
+    }
+
+    /// Moves this conversation onto a new branch named `name`. The history is kept, but the
+    /// conversation gets a new id so that the two branches are tracked separately from here on.
+    /// Likewise, the new branch only has checkpoints for the files edited on it.
+    pub fn fork(&mut self, name: impl Into<String>) {
+        self.conversation_id = uuid::Uuid::new_v4().to_string();
+        self.branch = name.into();
+        self.next_message = None;
+        self.checkpoints.fork();
+    }
+
+    /// Returns a short title for the conversation, taken from the first prompt in the history.
+    pub fn title(&self) -> Option<String> {
+        self.history
+            .iter()
+            .find_map(|(user, _)| user.prompt())
+            .and_then(|prompt| prompt.lines().find(|line| !line.trim().is_empty()))
+            .map(|line| truncate_safe(line.trim(), 80).to_string())
+    }
+
+    /// Returns the number of tokens sent by the user and received from the assistant over the
+    /// history.
+    pub fn token_totals(&self) -> (TokenCount, TokenCount) {
+        self.history.iter().fold(
+            (TokenCount::default(), TokenCount::default()),
+            |(user_tokens, assistant_tokens), (user, assistant)| {
+                (
+                    user_tokens + user.token_count(),
+                    assistant_tokens + assistant.token_count(),
+                )
+            },
+        )
+    }
+
+    /// Returns the number of prompts from the user in the history.
+    pub fn turn_count(&self) -> usize {
+        self.history.iter().filter(|(user, _)| user.prompt().is_some()).count()
+    }
+
+    /// Starts a turn of the conversation for the user prompt `prompt`, so that its transcript and
+    /// file edits can be dropped along with it by [Self::rewind].
+    pub fn start_turn(&mut self, prompt: String) {
+        // The turn starts with the prompt when it was already added to the transcript.
+        let len = match self.transcript.back() {
+            Some(last) if *last == user_transcript(&prompt) => self.transcript.len() - 1,
+            _ => self.transcript.len(),
+        };
+        if self.turn_transcript_lens.len() >= MAX_CONVERSATION_STATE_HISTORY_LEN {
+            self.turn_transcript_lens.pop_front();
+        }
+        self.turn_transcript_lens.push_back(len);
+        self.checkpoints.start_turn(prompt);
+    }
+
+    /// Drops the last `turns` user prompts from the history, along with the responses and tool
+    /// uses that followed each of them, and their transcript. Returns the number of turns actually
+    /// dropped.
+    pub fn rewind(&mut self, turns: usize) -> usize {
+        self.next_message = None;
+
+        let mut dropped = 0;
+        while dropped < turns {
+            let Some((user, _)) = self.history.pop_back() else {
+                break;
+            };
+            if user.prompt().is_some() {
+                dropped += 1;
+            }
+        }
+
+        let len = self.history.len();
+        self.valid_history_range = (self.valid_history_range.0.min(len), len);
+
+        let kept_turns = self.turn_transcript_lens.len().saturating_sub(dropped);
+        if let Some(&len) = self.turn_transcript_lens.get(kept_turns) {
+            self.transcript.truncate(len);
+        }
+        self.turn_transcript_lens.truncate(kept_turns);
+        dropped
+    }
+
+    /// Returns the number of entries at the end of the history that make up the last `turns`
+    /// turns, where a turn starts with a prompt from the user.
+    fn recent_turns_len(&self, turns: usize) -> usize {
+        if turns == 0 {
+            return 0;
+        }
+        let mut seen = 0;
+        for (i, (user, _)) in self.history.iter().enumerate().rev() {
+            if user.prompt().is_some() {
+                seen += 1;
+                if seen == turns {
+                    return self.history.len() - i;
+                }
+            }
+        }
+        self.history.len()
+    }
+
+    /// Returns the number of entries at the end of the history that are kept verbatim when
+    /// compacting. Unless `keep_recent_turns` is set, only the last entry is kept.
+    fn retained_history_len(&self, keep_recent_turns: Option<usize>) -> usize {
+        match keep_recent_turns {
+            Some(turns) => self.recent_turns_len(turns),
+            None => self.history.len().min(1),
+        }
+    }
+
+    /// Whether there are turns older than the last `keep_recent_turns` turns that can be
+    /// summarized.
+    pub fn can_summarize_oldest_turns(&self, keep_recent_turns: usize) -> bool {
+        self.history.len() > self.recent_turns_len(keep_recent_turns)
+    }
+
+    /// Replaces tool results using more than `max_tokens` tokens outside of the last
+    /// `keep_recent_turns` turns with their start and end. Returns the number of tool results
+    /// elided.
+    pub fn elide_old_tool_results(&mut self, keep_recent_turns: usize, max_tokens: usize) -> usize {
+        let end = self.history.len() - self.recent_turns_len(keep_recent_turns);
+        let mut elided = 0;
+        for (user, _) in self.history.range_mut(..end) {
+            let tool_use_results = match &mut user.content {
+                UserMessageContent::ToolUseResults { tool_use_results }
+                | UserMessageContent::CancelledToolUses { tool_use_results, .. } => tool_use_results,
+                UserMessageContent::Prompt { .. } => continue,
+            };
+            for block in tool_use_results.iter_mut().flat_map(|result| result.content.iter_mut()) {
+                let elided_content = match block {
+                    ToolUseResultBlock::Text(text) => elide_tool_result(text, max_tokens),
+                    ToolUseResultBlock::Json(value) => elide_tool_result(&value.to_string(), max_tokens),
+                };
+                if let Some(content) = elided_content {
+                    *block = ToolUseResultBlock::Text(content);
+                    elided += 1;
+                }
+            }
+        }
+        self.auto_compact_stats.elided_tool_results += elided;
+        elided
+    }
+
+    /// The auto compaction settings of the current profile, or the defaults.
+    pub fn auto_compact_config(&self) -> AutoCompactConfig {
+        self.context_manager
+            .as_ref()
+            .and_then(|cm| cm.auto_compact_config())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn auto_compact_stats(&self) -> AutoCompactStats {
+        self.auto_compact_stats
+    }
+
     /// Appends a collection prompts into history and returns the last message in the collection.
     /// It asserts that the collection ends with a prompt that assumes the role of user.
     pub fn append_prompts(&mut self, mut prompts: VecDeque<Prompt>) -> Option<String> {
@@ -269,15 +484,35 @@ impl ConversationState {
 
     /// Sets the response message according to the currently set [Self::next_message].
     pub fn push_assistant_message(&mut self, message: AssistantMessage, database: &mut Database) {
+        self.append_assistant_message(message);
+
+        if let Ok(cwd) = std::env::current_dir() {
+            database.set_conversation_by_path(cwd, self).ok();
+        }
+    }
+
+    /// Adds the assistant response to the history without persisting the conversation, as done
+    /// for the conversations of sub-agents.
+    pub fn append_assistant_message(&mut self, message: AssistantMessage) {
         debug_assert!(self.next_message.is_some(), "next_message should exist");
         let next_user_message = self.next_message.take().expect("next user message should exist");
 
         self.append_assistant_transcript(&message);
         self.history.push_back((next_user_message, message));
+    }
 
-        if let Ok(cwd) = std::env::current_dir() {
-            database.set_conversation_by_path(cwd, self).ok();
-        }
+    /// Transcripts of the sub-agents run in this conversation, oldest first.
+    pub fn sub_agents(&self) -> &[SubAgentTranscript] {
+        &self.sub_agents
+    }
+
+    /// Id to give to the next sub-agent run in this conversation.
+    pub fn next_sub_agent_id(&self) -> usize {
+        self.sub_agents.last().map_or(1, |transcript| transcript.id + 1)
+    }
+
+    pub fn add_sub_agent_transcripts(&mut self, transcripts: Vec<SubAgentTranscript>) {
+        self.sub_agents.extend(transcripts);
     }
 
     /// Returns the conversation id.
@@ -476,10 +711,14 @@ impl ConversationState {
         }
         self.tool_manager.update().await;
         // TODO: make this more targeted so we don't have to clone the entire list of tools
+        let agent = self.context_manager.as_ref().and_then(|cm| cm.agent.as_ref());
+        let mode = self.mode;
         self.tools = self
             .tool_manager
             .schema
             .values()
+            .filter(|spec| agent.is_none_or(|agent| agent.config.allows_tool(spec)))
+            .filter(|spec| mode.allows_tool(spec))
             .fold(HashMap::<ToolOrigin, Vec<Tool>>::new(), |mut acc, v| {
                 let tool = Tool::ToolSpecification(ToolSpecification {
                     name: v.name.clone(),
@@ -498,6 +737,38 @@ impl ConversationState {
         self.enforce_tool_use_history_invariants();
     }
 
+    /// The current mode of the conversation.
+    pub fn mode(&self) -> ChatMode {
+        self.mode
+    }
+
+    /// Switches to `mode`, updating the tools sent to the model from the next request on.
+    pub async fn set_mode(&mut self, mode: ChatMode) {
+        self.mode = mode;
+        self.update_state(true).await;
+    }
+
+    /// Sets the commands recently run in the terminal, with what they printed, that are sent as
+    /// context so that the user can ask about them without copying their output.
+    pub fn set_shell_history(&mut self, commands: &[ShellCommand]) {
+        self.shell_history = format_shell_history(commands);
+    }
+
+    /// The agent selected for this conversation, if any.
+    pub fn agent(&self) -> Option<&Agent> {
+        self.context_manager.as_ref().and_then(|cm| cm.agent.as_ref())
+    }
+
+    /// Selects the agent whose instructions, tools and hooks are used from the next request on.
+    pub async fn set_agent(&mut self, agent: Option<Agent>) -> eyre::Result<()> {
+        let Some(context_manager) = self.context_manager.as_mut() else {
+            eyre::bail!("Agents cannot be used since the context manager failed to initialize");
+        };
+        context_manager.agent = agent;
+        self.update_state(true).await;
+        Ok(())
+    }
+
     /// Returns a conversation state representation which reflects the exact conversation to send
     /// back to the model.
     pub async fn backend_conversation_state(&mut self, run_hooks: bool, quiet: bool) -> BackendConversationState<'_> {
@@ -506,6 +777,7 @@ impl ConversationState {
 
         // Run hooks and add to conversation start and next user message.
         let mut conversation_start_context = None;
+        let mut hook_results = Vec::new();
         if let (true, Some(cm)) = (run_hooks, self.context_manager.as_mut()) {
             let mut null_writer = SharedWriter::null();
             let updates = if quiet {
@@ -513,7 +785,7 @@ impl ConversationState {
             } else {
                 Some(self.updates.as_mut().unwrap_or(&mut null_writer))
             };
-            let hook_results = cm.run_hooks(updates).await;
+            hook_results = cm.run_hooks(updates).await;
             conversation_start_context = Some(format_hook_context(hook_results.iter(), HookTrigger::ConversationStart));
 
             // add per prompt content to next_user_message if available
@@ -522,7 +794,8 @@ impl ConversationState {
             }
         }
 
-        let (context_messages, dropped_context_files) = self.context_messages(conversation_start_context).await;
+        let (context_messages, context_files, dropped_context_files) =
+            self.context_messages(conversation_start_context).await;
 
         BackendConversationState {
             conversation_id: self.conversation_id.as_str(),
@@ -531,7 +804,12 @@ impl ConversationState {
                 .history
                 .range(self.valid_history_range.0..self.valid_history_range.1),
             context_messages,
+            context_files,
             dropped_context_files,
+            hook_outputs: hook_results
+                .into_iter()
+                .map(|(hook, output)| (hook.name, output))
+                .collect(),
             tools: &self.tools,
             model_id: self.model.as_deref(),
         }
@@ -546,8 +824,15 @@ impl ConversationState {
 
     /// Returns a [FigConversationState] capable of replacing the history of the current
     /// conversation with a summary generated by the model.
-    pub async fn create_summary_request(&mut self, custom_prompt: Option<impl AsRef<str>>) -> FigConversationState {
-        let summary_content = match custom_prompt {
+    ///
+    /// Unless `keep_recent_turns` is set, everything but the last message in the history is
+    /// summarized. Otherwise the last `keep_recent_turns` turns are left out of the summary.
+    pub async fn create_summary_request(
+        &mut self,
+        custom_prompt: Option<impl AsRef<str>>,
+        keep_recent_turns: Option<usize>,
+    ) -> FigConversationState {
+        let mut summary_content = match custom_prompt {
             Some(custom_prompt) => {
                 // Make the custom instructions much more prominent and directive
                 format!(
@@ -590,14 +875,24 @@ impl ConversationState {
             },
         };
 
+        // Fold the previous summary into the new one, since it is replaced.
+        if let Some(summary) = &self.latest_summary {
+            summary_content.push_str(
+                "\n\nThe earlier part of the conversation was already summarized as follows. \
+                Include all of its details in the new summary:\n\n",
+            );
+            summary_content.push_str(summary);
+        }
+
+        let retained_len = self.retained_history_len(keep_recent_turns);
         let conv_state = self.backend_conversation_state(false, true).await;
 
-        // Include everything but the last message in the history.
+        // Include everything but the retained messages in the history.
         let history_len = conv_state.history.len();
         let history = if history_len < 2 {
             vec![]
         } else {
-            flatten_history(conv_state.history.take(history_len.saturating_sub(1)))
+            flatten_history(conv_state.history.take(history_len.saturating_sub(retained_len)))
         };
 
         let user_input_message_context = UserInputMessageContext {
@@ -636,14 +931,24 @@ impl ConversationState {
         }
     }
 
-    pub fn replace_history_with_summary(&mut self, summary: String) {
-        self.history.drain(..(self.history.len().saturating_sub(1)));
+    /// Replaces the history summarized by [Self::create_summary_request], called with the same
+    /// `keep_recent_turns`, with `summary`.
+    pub fn replace_history_with_summary(&mut self, summary: String, keep_recent_turns: Option<usize>) {
+        let retained_len = self.retained_history_len(keep_recent_turns);
+        let summarized_turns = self
+            .history
+            .drain(..(self.history.len().saturating_sub(retained_len)))
+            .filter(|(user, _)| user.prompt().is_some())
+            .count();
+        if keep_recent_turns.is_some() {
+            self.auto_compact_stats.summarized_turns += summarized_turns;
+        }
         self.latest_summary = Some(summary);
-        // If the last message contains tool results, then we add the results to the content field
-        // instead. This is required to avoid validation errors.
+        // If the first retained message contains tool results, then we add the results to the
+        // content field instead. This is required to avoid validation errors.
         // TODO: this can break since the max user content size is less than the max tool response
         // size! Alternative could be to set the last tool use as part of the context messages.
-        if let Some((user, _)) = self.history.back_mut() {
+        if let Some((user, _)) = self.history.front_mut() {
             if let Some(tool_results) = user.tool_use_results() {
                 let tool_content: Vec<String> = tool_results
                     .iter()
@@ -687,9 +992,27 @@ impl ConversationState {
     async fn context_messages(
         &mut self,
         conversation_start_context: Option<String>,
-    ) -> (Option<Vec<(UserMessage, AssistantMessage)>>, Vec<(String, String)>) {
+    ) -> (
+        Option<Vec<(UserMessage, AssistantMessage)>>,
+        Vec<(String, String)>,
+        Vec<(String, String)>,
+    ) {
         let mut context_content = String::new();
+        let mut context_files = Vec::new();
         let mut dropped_context_files = Vec::new();
+        if let Some(instructions) = self.context_manager.as_ref().and_then(|cm| cm.agent_instructions()) {
+            context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+            context_content.push_str("The user selected an agent with the following instructions. Follow them throughout this conversation.\n\n");
+            context_content.push_str(instructions);
+            context_content.push('\n');
+            context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+        }
+        if self.mode == ChatMode::Plan {
+            context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+            context_content.push_str(PLAN_MODE_INSTRUCTIONS);
+            context_content.push('\n');
+            context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+        }
         if let Some(summary) = &self.latest_summary {
             context_content.push_str(CONTEXT_ENTRY_START_HEADER);
             context_content.push_str("This summary contains ALL relevant information from our previous conversation including tool uses, results, code analysis, and file operations. YOU MUST reference this information when answering questions and explicitly acknowledge specific details from the summary when they're relevant to the current question.\n\n");
@@ -709,10 +1032,11 @@ impl ConversationState {
 
                     if !files_to_use.is_empty() {
                         context_content.push_str(CONTEXT_ENTRY_START_HEADER);
-                        for (filename, content) in files_to_use {
+                        for (filename, content) in &files_to_use {
                             context_content.push_str(&format!("[{}]\n{}\n", filename, content));
                         }
                         context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+                        context_files = files_to_use;
                     }
                 },
                 Err(e) => {
@@ -721,6 +1045,10 @@ impl ConversationState {
             }
         }
 
+        if let Some(shell_history) = &self.shell_history {
+            context_content.push_str(shell_history);
+        }
+
         if let Some(context) = conversation_start_context {
             context_content.push_str(&context);
         }
@@ -729,9 +1057,13 @@ impl ConversationState {
             self.context_message_length = Some(context_content.len());
             let user_msg = UserMessage::new_prompt(context_content);
             let assistant_msg = AssistantMessage::new_response(None, "I will fully incorporate this information when generating my responses, and explicitly acknowledge relevant parts of the summary when answering questions.".into());
-            (Some(vec![(user_msg, assistant_msg)]), dropped_context_files)
+            (
+                Some(vec![(user_msg, assistant_msg)]),
+                context_files,
+                dropped_context_files,
+            )
         } else {
-            (None, dropped_context_files)
+            (None, context_files, dropped_context_files)
         }
     }
 
@@ -740,16 +1072,23 @@ impl ConversationState {
         self.context_message_length
     }
 
-    /// Calculate the total character count in the conversation
-    pub async fn calculate_char_count(&mut self) -> CharCount {
-        self.backend_conversation_state(false, true).await.char_count()
+    /// Calculate the total token count in the conversation
+    pub async fn calculate_token_count(&mut self) -> TokenCount {
+        self.backend_conversation_state(false, true).await.token_count()
+    }
+
+    /// Returns the number of tokens used by the conversation, along with the context window size
+    /// of the model it is sent to.
+    pub async fn context_window_usage(&mut self) -> (TokenCount, usize) {
+        let state = self.backend_conversation_state(false, true).await;
+        (state.token_count(), state.context_window_size())
     }
 
     /// Get the current token warning level
     pub async fn get_token_warning_level(&mut self) -> TokenWarningLevel {
-        let total_chars = self.calculate_char_count().await;
+        let (tokens, context_window_size) = self.context_window_usage().await;
 
-        if *total_chars >= MAX_CHARS {
+        if *tokens >= context_window_size {
             TokenWarningLevel::Critical
         } else {
             TokenWarningLevel::None
@@ -757,7 +1096,7 @@ impl ConversationState {
     }
 
     pub fn append_user_transcript(&mut self, message: &str) {
-        self.append_transcript(format!("> {}", message.replace("\n", "> \n")));
+        self.append_transcript(user_transcript(message));
     }
 
     pub fn append_assistant_transcript(&mut self, message: &AssistantMessage) {
@@ -770,6 +1109,9 @@ impl ConversationState {
     pub fn append_transcript(&mut self, message: String) {
         if self.transcript.len() >= MAX_CONVERSATION_STATE_HISTORY_LEN {
             self.transcript.pop_front();
+            for len in &mut self.turn_transcript_lens {
+                *len = len.saturating_sub(1);
+            }
         }
         self.transcript.push_back(message);
     }
@@ -846,7 +1188,11 @@ pub struct BackendConversationStateImpl<'a, T, U> {
     pub next_user_message: Option<&'a UserMessage>,
     pub history: T,
     pub context_messages: U,
+    /// The context files included in [Self::context_messages], as `(filename, content)`.
+    pub context_files: Vec<(String, String)>,
     pub dropped_context_files: Vec<(String, String)>,
+    /// The output of each context hook that was run, as `(hook name, output)`.
+    pub hook_outputs: Vec<(String, String)>,
     pub tools: &'a HashMap<ToolOrigin, Vec<Tool>>,
     pub model_id: Option<&'a str>,
 }
@@ -877,44 +1223,113 @@ impl
         })
     }
 
+    /// The context window size in tokens of the model this state is sent to.
+    pub fn context_window_size(&self) -> usize {
+        super::context_window_size(self.model_id)
+    }
+
     pub fn calculate_conversation_size(&self) -> ConversationSize {
-        let mut user_chars = 0;
-        let mut assistant_chars = 0;
-        let mut context_chars = 0;
+        let mut user_tokens = TokenCount::default();
+        let mut assistant_tokens = TokenCount::default();
 
-        // Count the chars used by the messages in the history.
+        // Count the tokens used by the messages in the history.
         // this clone is cheap
         let history = self.history.clone();
         for (user, assistant) in history {
-            user_chars += *user.char_count();
-            assistant_chars += *assistant.char_count();
+            user_tokens += user.token_count();
+            assistant_tokens += assistant.token_count();
         }
 
-        // Add any chars from context messages, if available.
-        context_chars += self
+        // Add any tokens from context messages, if available.
+        let context_tokens = self
             .context_messages
-            .as_ref()
-            .map(|v| {
-                v.iter().fold(0, |acc, (user, assistant)| {
-                    acc + *user.char_count() + *assistant.char_count()
-                })
-            })
-            .unwrap_or_default();
+            .iter()
+            .flatten()
+            .map(|(user, assistant)| user.token_count() + assistant.token_count())
+            .sum();
+
+        let tools_tokens = self.tool_spec_token_counts().into_iter().map(|(_, count)| count).sum();
 
         ConversationSize {
-            context_messages: context_chars.into(),
-            user_messages: user_chars.into(),
-            assistant_messages: assistant_chars.into(),
+            context_messages: context_tokens,
+            user_messages: user_tokens,
+            assistant_messages: assistant_tokens,
+            tools: tools_tokens,
+        }
+    }
+
+    /// Breaks down the context window utilization per context file, hook output, tool spec and
+    /// history turn. Each list is sorted from the largest to the smallest item, except for the
+    /// history turns which are kept in conversation order.
+    pub fn calculate_token_breakdown(&self) -> TokenBreakdown {
+        let count_sorted = |items: &[(String, String)]| {
+            let mut counts = items
+                .iter()
+                .map(|(name, content)| (name.clone(), TokenCount::from(TokenCounter::count_tokens(content))))
+                .collect::<Vec<_>>();
+            counts.sort_by(|a, b| b.1.cmp(&a.1));
+            counts
+        };
+
+        let mut tools = self.tool_spec_token_counts();
+        tools.sort_by(|a, b| b.1.cmp(&a.1));
+
+        // A turn starts with a prompt from the user and includes every tool use that follows it.
+        let mut history_turns: Vec<(String, TokenCount)> = Vec::new();
+        for (user, assistant) in self.history.clone() {
+            let tokens = user.token_count() + assistant.token_count();
+            if let (None, Some((_, turn_tokens))) = (user.prompt(), history_turns.last_mut()) {
+                *turn_tokens += tokens;
+                continue;
+            }
+            let label = user
+                .prompt()
+                .and_then(|prompt| prompt.lines().find(|line| !line.trim().is_empty()))
+                .map_or("(tool results)".to_string(), |line| {
+                    truncate_safe(line.trim(), 60).to_string()
+                });
+            history_turns.push((label, tokens));
+        }
+
+        TokenBreakdown {
+            context_files: count_sorted(&self.context_files),
+            hook_outputs: count_sorted(&self.hook_outputs),
+            tools,
+            history_turns,
         }
     }
+
+    /// Returns the number of tokens used by the spec of each tool, as `(tool name, tokens)`.
+    fn tool_spec_token_counts(&self) -> Vec<(String, TokenCount)> {
+        self.tools
+            .values()
+            .flatten()
+            .map(|tool| {
+                let Tool::ToolSpecification(spec) = tool;
+                let tokens = serde_json::to_string(tool).map_or(0, |json| TokenCounter::count_tokens(&json));
+                (spec.name.clone(), tokens.into())
+            })
+            .collect()
+    }
 }
 
 /// Reflects a detailed accounting of the context window utilization for a given conversation.
 #[derive(Debug, Clone, Copy)]
 pub struct ConversationSize {
-    pub context_messages: CharCount,
-    pub user_messages: CharCount,
-    pub assistant_messages: CharCount,
+    pub context_messages: TokenCount,
+    pub user_messages: TokenCount,
+    pub assistant_messages: TokenCount,
+    pub tools: TokenCount,
+}
+
+/// Token counts of the individual items sent to the model, see
+/// [BackendConversationState::calculate_token_breakdown].
+#[derive(Debug, Clone, Default)]
+pub struct TokenBreakdown {
+    pub context_files: Vec<(String, TokenCount)>,
+    pub hook_outputs: Vec<(String, TokenCount)>,
+    pub tools: Vec<(String, TokenCount)>,
+    pub history_turns: Vec<(String, TokenCount)>,
 }
 
 /// Converts a list of user/assistant message pairs into a flattened list of ChatMessage.
@@ -929,12 +1344,12 @@ where
     })
 }
 
-/// Character count warning levels for conversation size
+/// Token count warning levels for conversation size
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TokenWarningLevel {
     /// No warning, conversation is within normal limits
     None,
-    /// Critical level - at single warning threshold (600K characters)
+    /// Critical level - the conversation fills the context window of the model
     Critical,
 }
 
@@ -963,8 +1378,71 @@ fn format_hook_context<'a>(hook_results: impl IntoIterator<Item = &'a (Hook, Str
     context_content
 }
 
+/// Formats the commands recently run in the terminal as context, or [None] if there are none.
+fn format_shell_history(commands: &[ShellCommand]) -> Option<String> {
+    if commands.is_empty() {
+        return None;
+    }
+
+    let mut context_content = String::new();
+    context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+    context_content.push_str("These are the last commands I ran in the terminal before starting this conversation, oldest first, along with the end of what they printed. Refer to them when I ask about a command I ran, such as why it failed.\n\n");
+    for command in commands {
+        context_content.push_str(&format!("$ {}\n", command.command));
+        let mut details = Vec::new();
+        if let Some(cwd) = &command.cwd {
+            details.push(format!("directory: {cwd}"));
+        }
+        if let Some(exit_code) = command.exit_code {
+            details.push(format!("exit code: {exit_code}"));
+        }
+        if !details.is_empty() {
+            context_content.push_str(&format!("({})\n", details.join(", ")));
+        }
+        if let Some(output) = &command.output {
+            context_content.push_str(&format!("```\n{output}\n```\n"));
+        }
+        context_content.push('\n');
+    }
+    context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+    Some(context_content)
+}
+
+/// Formats the resources attached with `/resources attach` so that they can be sent as context
+/// alongside the next prompt.
+pub fn format_resource_context<'a>(resources: impl IntoIterator<Item = &'a ResourceBundle>) -> String {
+    let mut context_content = String::new();
+
+    context_content.push_str(CONTEXT_ENTRY_START_HEADER);
+    context_content.push_str("This section (like others) contains important information that I want you to use in your responses. I have attached the following resources from mcp servers as context for my next message\n\n");
+    for resource in resources {
+        for contents in &resource.contents {
+            match &contents.text {
+                Some(text) => {
+                    context_content.push_str(&format!(
+                        "[{}] (from {}):\n{text}\n\n",
+                        contents.uri, resource.server_name
+                    ));
+                },
+                None => {
+                    context_content.push_str(&format!(
+                        "[{}] (from {}): binary content ({}) omitted\n\n",
+                        contents.uri,
+                        resource.server_name,
+                        contents.mime_type.as_deref().unwrap_or("unknown type")
+                    ));
+                },
+            }
+        }
+    }
+    context_content.push_str(CONTEXT_ENTRY_END_HEADER);
+    context_content
+}
+
 #[cfg(test)]
 mod tests {
+    use super::super::checkpoint::FileSnapshot;
+    use super::super::consts::CONTEXT_WINDOW_SIZE;
     use super::super::context::{
         AMAZONQ_FILENAME,
         profile_context_path,
@@ -1221,6 +1699,49 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_conversation_state_shell_history() {
+        let database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            ctx,
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+            None,
+        )
+        .await;
+        conversation_state.set_shell_history(&[ShellCommand {
+            command: "cargo test".into(),
+            cwd: Some("/repo".into()),
+            exit_code: Some(101),
+            output: Some("test result: FAILED".into()),
+        }]);
+
+        conversation_state
+            .set_next_user_message("why did my last command fail?".to_string())
+            .await;
+        let s = conversation_state.as_sendable_conversation_state(true).await;
+        match &s.history.as_ref().unwrap()[0] {
+            ChatMessage::UserInputMessage(user) => {
+                assert!(
+                    user.content
+                        .contains("$ cargo test\n(directory: /repo, exit code: 101)\n")
+                );
+                assert!(user.content.contains("```\ntest result: FAILED\n```"));
+            },
+            ChatMessage::AssistantResponseMessage(_) => panic!("Expected the first message to be the context message"),
+        }
+
+        conversation_state.set_shell_history(&[]);
+        assert!(conversation_state.shell_history.is_none());
+    }
+
     #[tokio::test]
     async fn test_conversation_state_additional_context() {
         // tracing_subscriber::fmt::try_init().ok();
@@ -1290,4 +1811,232 @@ mod tests {
             conversation_state.set_next_user_message(i.to_string()).await;
         }
     }
+
+    #[tokio::test]
+    async fn test_conversation_state_branches() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+        let cwd = std::env::current_dir().unwrap();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+            None,
+        )
+        .await;
+        for i in 0..3 {
+            conversation_state.append_user_transcript(&i.to_string());
+            conversation_state.start_turn(i.to_string());
+            conversation_state.set_next_user_message(i.to_string()).await;
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, i.to_string()), &mut database);
+        }
+        conversation_state.checkpoints.record(FileSnapshot {
+            path: "/new.txt".into(),
+            contents_hash: None,
+        });
+        assert_eq!(conversation_state.branch(), DEFAULT_BRANCH_NAME);
+        // Only the branches that are left are kept under their name.
+        assert!(database.get_conversation_branches(&cwd).unwrap().is_empty());
+
+        database.set_conversation_branch(&cwd, &conversation_state).unwrap();
+        conversation_state.fork("experiment");
+        assert_eq!(conversation_state.branch(), "experiment");
+        assert_ne!(conversation_state.conversation_id(), "fake_conv_id");
+        // The files edited before the fork are only restored on the branch they were edited on.
+        assert_eq!(conversation_state.checkpoints.checkpoints().count(), 0);
+        assert_eq!(conversation_state.rewind(2), 2);
+        assert_eq!(conversation_state.turn_count(), 1);
+        assert_eq!(conversation_state.transcript.len(), 2);
+        assert_eq!(conversation_state.transcript[0], "> 0");
+        assert_eq!(conversation_state.rewind(5), 1);
+        assert!(conversation_state.history().is_empty());
+        assert!(conversation_state.transcript.is_empty());
+        database.set_conversation_by_path(&cwd, &conversation_state).unwrap();
+
+        let branches = database.get_conversation_branches(&cwd).unwrap();
+        let names = branches.iter().map(|b| b.branch()).collect::<Vec<_>>();
+        assert_eq!(names, vec![DEFAULT_BRANCH_NAME]);
+        let active = database.get_conversation_by_path(&cwd).unwrap().unwrap();
+        assert_eq!(active.branch(), "experiment");
+        let main = database
+            .get_conversation_branch(&cwd, DEFAULT_BRANCH_NAME)
+            .unwrap()
+            .unwrap();
+        assert_eq!(main.turn_count(), 3);
+        assert_eq!(main.transcript.len(), 6);
+        assert_eq!(main.checkpoints.checkpoints().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_sessions() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "a1b2c3d4-session",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+            None,
+        )
+        .await;
+        conversation_state
+            .set_next_user_message("How is the user authenticated?\nSome details".to_string())
+            .await;
+        conversation_state.append_user_transcript("How is the user authenticated?");
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "With a bearer token".to_string()),
+            &mut database,
+        );
+
+        let sessions = database.list_sessions(Some("AUTHENTICATED user"), Some(10)).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].id, "a1b2c3d4-session");
+        assert_eq!(sessions[0].title, "How is the user authenticated?");
+        assert!(database.list_sessions(Some("unrelated"), Some(10)).unwrap().is_empty());
+        assert_eq!(database.list_sessions(None, None).unwrap().len(), 1);
+
+        let session = database.get_session("a1b2").unwrap().unwrap();
+        assert_eq!(session.conversation_id(), "a1b2c3d4-session");
+
+        // Saving the conversation again updates its session instead of adding one
+        conversation_state
+            .set_next_user_message("And how is it refreshed?".to_string())
+            .await;
+        conversation_state.push_assistant_message(
+            AssistantMessage::new_response(None, "With a refresh token".to_string()),
+            &mut database,
+        );
+        let sessions = database.list_sessions(None, None).unwrap();
+        assert_eq!(sessions.len(), 1);
+        let session = database.get_session("a1b2").unwrap().unwrap();
+        assert_eq!(session.history().len(), conversation_state.history().len());
+        assert!(database.get_session("ffff").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_token_breakdown() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+            None,
+        )
+        .await;
+        for prompt in ["first prompt", "second prompt\nwith details"] {
+            conversation_state.set_next_user_message(prompt.to_string()).await;
+            conversation_state.push_assistant_message(
+                AssistantMessage::new_response(None, "a response".to_string()),
+                &mut database,
+            );
+        }
+
+        let state = conversation_state.backend_conversation_state(false, true).await;
+        let size = state.calculate_conversation_size();
+        let breakdown = state.calculate_token_breakdown();
+        assert_eq!(state.context_window_size(), CONTEXT_WINDOW_SIZE);
+
+        let turns = breakdown
+            .history_turns
+            .iter()
+            .map(|(label, _)| label.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(turns, vec!["first prompt", "second prompt"]);
+        assert_eq!(
+            breakdown
+                .history_turns
+                .iter()
+                .map(|(_, count)| *count)
+                .sum::<TokenCount>(),
+            size.user_messages + size.assistant_messages
+        );
+
+        assert!(!breakdown.tools.is_empty());
+        assert!(breakdown.tools.windows(2).all(|w| w[0].1 >= w[1].1));
+        assert_eq!(
+            breakdown.tools.iter().map(|(_, count)| *count).sum::<TokenCount>(),
+            size.tools
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conversation_state_auto_compact() {
+        let mut database = Database::new().await.unwrap();
+        let mut output = SharedWriter::null();
+
+        let mut tool_manager = ToolManager::default();
+        let mut conversation_state = ConversationState::new(
+            Context::new(),
+            "fake_conv_id",
+            tool_manager.load_tools(&database, &mut output).await.unwrap(),
+            None,
+            None,
+            tool_manager,
+            None,
+        )
+        .await;
+        let large_output = (0..5_000).map(|i| format!("line {i}\n")).collect::<Vec<_>>().concat();
+        for (prompt, use_tool) in [("first", true), ("second", false), ("third", true)] {
+            conversation_state.set_next_user_message(prompt.to_string()).await;
+            if use_tool {
+                conversation_state.push_assistant_message(
+                    AssistantMessage::new_tool_use(None, "reading".to_string(), vec![AssistantToolUse {
+                        id: "tool_id".to_string(),
+                        name: "fs_read".to_string(),
+                        args: serde_json::Value::Null,
+                        ..Default::default()
+                    }]),
+                    &mut database,
+                );
+                conversation_state.add_tool_results(vec![ToolUseResult {
+                    tool_use_id: "tool_id".to_string(),
+                    content: vec![ToolUseResultBlock::Text(large_output.clone())],
+                    status: ToolResultStatus::Success,
+                }]);
+            }
+            conversation_state
+                .push_assistant_message(AssistantMessage::new_response(None, "done".to_string()), &mut database);
+        }
+        assert_eq!(conversation_state.history().len(), 5);
+
+        // Only tool results outside of the last turn are elided, and only once.
+        assert_eq!(conversation_state.elide_old_tool_results(1, 500), 1);
+        assert_eq!(conversation_state.elide_old_tool_results(1, 500), 0);
+        let result_len =
+            |entry: usize| match &conversation_state.history()[entry].0.tool_use_results().unwrap()[0].content[0] {
+                ToolUseResultBlock::Text(text) => text.len(),
+                ToolUseResultBlock::Json(_) => panic!("expected a text result"),
+            };
+        assert!(result_len(1) < large_output.len() / 10);
+        assert_eq!(result_len(4), large_output.len());
+
+        // The oldest turns are summarized while the last turn is kept.
+        assert!(conversation_state.can_summarize_oldest_turns(1));
+        let summary_request = conversation_state.create_summary_request(None::<&str>, Some(1)).await;
+        assert_eq!(summary_request.history.unwrap().len(), 6);
+        conversation_state.replace_history_with_summary("summary".to_string(), Some(1));
+        assert_eq!(conversation_state.history().len(), 2);
+        assert_eq!(conversation_state.history()[0].0.prompt(), Some("third"));
+        assert!(!conversation_state.can_summarize_oldest_turns(1));
+
+        let stats = conversation_state.auto_compact_stats();
+        assert_eq!(stats.elided_tool_results, 1);
+        assert_eq!(stats.summarized_turns, 2);
+    }
 }
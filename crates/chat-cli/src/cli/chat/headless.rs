@@ -0,0 +1,325 @@
+use std::io::Write;
+
+use clap::ValueEnum;
+use eyre::{
+    Result,
+    eyre,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::message::{
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use super::parser::ResponseEvent;
+use super::token_counter::TokenCounter;
+use crate::api_client::model::ToolResultStatus;
+
+/// Format of the output written to stdout when chatting without interactive mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChatOutputFormat {
+    /// The rendered response, as shown in interactive mode
+    #[default]
+    Text,
+    /// A single JSON object describing the result once the chat ends
+    Json,
+    /// Newline delimited JSON events as they happen, followed by the result
+    StreamJson,
+}
+
+/// Format of the input read from stdin when chatting without interactive mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ChatInputFormat {
+    /// The whole of stdin is appended to the first question
+    #[default]
+    Text,
+    /// Each line is a user turn, e.g. `{"type": "user", "content": "..."}`
+    StreamJson,
+}
+
+/// A user turn read from stdin with [ChatInputFormat::StreamJson].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputEvent {
+    User { content: String },
+}
+
+impl InputEvent {
+    /// Parses a line of input into the text of a user turn.
+    pub fn parse_line(line: &str) -> Result<String> {
+        match serde_json::from_str::<InputEvent>(line) {
+            Ok(InputEvent::User { content }) => Ok(content),
+            Err(err) => Err(eyre!("Invalid input event: {err}")),
+        }
+    }
+}
+
+/// Token counts are estimated from the size of the conversation.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Usage {
+    pub input_tokens: usize,
+    pub output_tokens: usize,
+}
+
+/// An event written to stdout with [ChatOutputFormat::StreamJson].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputEvent {
+    /// Text returned by the assistant, as it is received.
+    AssistantText {
+        text: String,
+    },
+    /// A tool use requested by the assistant.
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    /// The result of a tool use, as sent back to the assistant.
+    ToolResult {
+        tool_use_id: String,
+        is_error: bool,
+        content: Vec<serde_json::Value>,
+    },
+    /// Token usage of a single response.
+    Usage(Usage),
+    Error {
+        message: String,
+    },
+    /// The final event of the chat.
+    Result {
+        conversation_id: String,
+        is_error: bool,
+        /// Text of the last response from the assistant.
+        result: String,
+        num_turns: usize,
+        usage: Usage,
+    },
+}
+
+impl OutputEvent {
+    /// The event for a [ResponseEvent], if it is one that is reported.
+    pub fn from_response_event(event: &ResponseEvent) -> Option<Self> {
+        match event {
+            ResponseEvent::AssistantText(text) => Some(Self::AssistantText { text: text.clone() }),
+            ResponseEvent::ToolUse(tool_use) => Some(Self::ToolUse {
+                id: tool_use.id.clone(),
+                name: tool_use.name.clone(),
+                input: tool_use.args.clone(),
+            }),
+            ResponseEvent::EndStream { message } => {
+                let mut output = message.content().to_string();
+                for tool_use in message.tool_uses().unwrap_or_default() {
+                    output.push_str(&tool_use.args.to_string());
+                }
+                // The input tokens depend on the conversation, which is not known here.
+                Some(Self::Usage(Usage {
+                    input_tokens: 0,
+                    output_tokens: TokenCounter::count_tokens(&output),
+                }))
+            },
+            ResponseEvent::ToolUseStart { .. } => None,
+        }
+    }
+}
+
+impl From<&ToolUseResult> for OutputEvent {
+    fn from(value: &ToolUseResult) -> Self {
+        Self::ToolResult {
+            tool_use_id: value.tool_use_id.clone(),
+            is_error: matches!(value.status, ToolResultStatus::Error),
+            content: value
+                .content
+                .iter()
+                .map(|block| match block {
+                    ToolUseResultBlock::Text(text) => serde_json::Value::String(text.clone()),
+                    ToolUseResultBlock::Json(json) => json.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes [OutputEvent]s in a machine readable [ChatOutputFormat], keeping track of what is needed
+/// for the final [OutputEvent::Result].
+pub struct EventWriter {
+    format: ChatOutputFormat,
+    writer: Box<dyn Write + Send>,
+    last_response: String,
+    response_ended: bool,
+    num_turns: usize,
+    usage: Usage,
+    is_error: bool,
+}
+
+impl std::fmt::Debug for EventWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventWriter").field("format", &self.format).finish()
+    }
+}
+
+impl EventWriter {
+    /// Returns [None] for [ChatOutputFormat::Text], which has no events.
+    pub fn new(format: ChatOutputFormat, writer: impl Write + Send + 'static) -> Option<Self> {
+        (format != ChatOutputFormat::Text).then(|| Self {
+            format,
+            writer: Box::new(writer),
+            last_response: String::new(),
+            response_ended: false,
+            num_turns: 0,
+            usage: Usage::default(),
+            is_error: false,
+        })
+    }
+
+    pub fn emit(&mut self, event: OutputEvent) -> Result<()> {
+        match &event {
+            OutputEvent::AssistantText { text } => {
+                if self.response_ended {
+                    self.last_response.clear();
+                    self.response_ended = false;
+                }
+                self.last_response.push_str(text);
+            },
+            OutputEvent::Usage(usage) => {
+                self.response_ended = true;
+                self.num_turns += 1;
+                self.usage.input_tokens += usage.input_tokens;
+                self.usage.output_tokens += usage.output_tokens;
+            },
+            OutputEvent::Error { .. } => self.is_error = true,
+            _ => (),
+        }
+
+        if self.format == ChatOutputFormat::StreamJson {
+            self.write(&event)?;
+        }
+        Ok(())
+    }
+
+    /// Whether an [OutputEvent::Error] was reported.
+    pub fn is_error(&self) -> bool {
+        self.is_error
+    }
+
+    /// Writes the final [OutputEvent::Result].
+    pub fn finish(&mut self, conversation_id: impl Into<String>) -> Result<()> {
+        let result = OutputEvent::Result {
+            conversation_id: conversation_id.into(),
+            is_error: self.is_error,
+            result: self.last_response.trim().to_string(),
+            num_turns: self.num_turns,
+            usage: self.usage,
+        };
+        self.write(&result)
+    }
+
+    fn write(&mut self, event: &OutputEvent) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        Mutex,
+    };
+
+    use super::*;
+    use crate::cli::chat::util::shared_writer::TestWriterWithSink;
+
+    fn sink() -> TestWriterWithSink {
+        TestWriterWithSink {
+            sink: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    fn lines(sink: &TestWriterWithSink) -> Vec<serde_json::Value> {
+        String::from_utf8(sink.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn emit_turns(writer: &mut EventWriter) {
+        for turn in ["first response", "second response"] {
+            writer
+                .emit(OutputEvent::AssistantText { text: turn.to_string() })
+                .unwrap();
+            writer
+                .emit(OutputEvent::Usage(Usage {
+                    input_tokens: 10,
+                    output_tokens: 5,
+                }))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_stream_json_output() {
+        let buffer = sink();
+        let mut writer = EventWriter::new(ChatOutputFormat::StreamJson, buffer.clone()).unwrap();
+        emit_turns(&mut writer);
+        writer.finish("conversation").unwrap();
+
+        let lines = lines(&buffer);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(
+            lines[0],
+            serde_json::json!({ "type": "assistant_text", "text": "first response" })
+        );
+        assert_eq!(
+            lines[1],
+            serde_json::json!({ "type": "usage", "input_tokens": 10, "output_tokens": 5 })
+        );
+        assert_eq!(
+            lines[4],
+            serde_json::json!({
+                "type": "result",
+                "conversation_id": "conversation",
+                "is_error": false,
+                "result": "second response",
+                "num_turns": 2,
+                "usage": { "input_tokens": 20, "output_tokens": 10 },
+            })
+        );
+    }
+
+    #[test]
+    fn test_json_output() {
+        assert!(EventWriter::new(ChatOutputFormat::Text, sink()).is_none());
+
+        let buffer = sink();
+        let mut writer = EventWriter::new(ChatOutputFormat::Json, buffer.clone()).unwrap();
+        emit_turns(&mut writer);
+        writer
+            .emit(OutputEvent::Error {
+                message: "failed".to_string(),
+            })
+            .unwrap();
+        writer.finish("conversation").unwrap();
+
+        let lines = lines(&buffer);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["type"], "result");
+        assert_eq!(lines[0]["is_error"], true);
+        assert_eq!(lines[0]["result"], "second response");
+    }
+
+    #[test]
+    fn test_input_event() {
+        assert_eq!(
+            InputEvent::parse_line(r#"{"type": "user", "content": "hello"}"#).unwrap(),
+            "hello"
+        );
+        assert!(InputEvent::parse_line("hello").is_err());
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::io::Write;
 use std::process::Stdio;
+use std::str::FromStr;
 use std::time::{
     Duration,
     Instant,
@@ -34,13 +35,19 @@ use spinners::{
     Spinner,
     Spinners,
 };
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
 
 use super::util::truncate_safe;
+use crate::platform::Context;
 
 const DEFAULT_TIMEOUT_MS: u64 = 30_000;
 const DEFAULT_MAX_OUTPUT_SIZE: usize = 1024 * 10;
 const DEFAULT_CACHE_TTL_SECONDS: u64 = 0;
 
+/// Exit code with which a hook blocks a tool use, or gives feedback on it.
+const BLOCKING_EXIT_CODE: i32 = 2;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hook {
     pub trigger: HookTrigger,
@@ -62,6 +69,11 @@ pub struct Hook {
     #[serde(default = "Hook::default_cache_ttl_seconds")]
     pub cache_ttl_seconds: u64,
 
+    /// Names of the tools that tool use hooks run for, separated by `|`. Tool use hooks without a
+    /// matcher, or with the matcher `*`, run for every tool.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+
     // Type-specific fields
     /// The bash command to execute
     pub command: Option<String>, // For inline hooks
@@ -82,12 +94,21 @@ impl Hook {
             timeout_ms: Self::default_timeout_ms(),
             max_output_size: Self::default_max_output_size(),
             cache_ttl_seconds: Self::default_cache_ttl_seconds(),
+            matcher: None,
             command: Some(command),
             is_global: false,
             name: "new hook".to_string(),
         }
     }
 
+    /// Whether the hook should run for the tool named `tool_name`.
+    pub fn matches_tool(&self, tool_name: &str) -> bool {
+        match self.matcher.as_deref().map(str::trim) {
+            None | Some("" | "*") => true,
+            Some(matcher) => matcher.split('|').any(|name| name.trim() == tool_name),
+        }
+    }
+
     fn default_disabled() -> bool {
         false
     }
@@ -112,11 +133,115 @@ pub enum HookType {
     Inline,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum HookTrigger {
     ConversationStart,
     PerPrompt,
+    /// Before a tool is used. The hook can block the tool use or rewrite its input.
+    PreToolUse,
+    /// After a tool is used. Output from the hook is added to the tool result.
+    PostToolUse,
+    /// After the assistant finishes a response.
+    AssistantResponse,
+    /// When the chat session ends.
+    SessionEnd,
+}
+
+impl HookTrigger {
+    pub const ALL: [HookTrigger; 6] = [
+        HookTrigger::ConversationStart,
+        HookTrigger::PerPrompt,
+        HookTrigger::PreToolUse,
+        HookTrigger::PostToolUse,
+        HookTrigger::AssistantResponse,
+        HookTrigger::SessionEnd,
+    ];
+
+    /// Whether the output of hooks with this trigger is added to the context sent to Amazon Q.
+    pub fn is_context(&self) -> bool {
+        matches!(self, HookTrigger::ConversationStart | HookTrigger::PerPrompt)
+    }
+}
+
+impl FromStr for HookTrigger {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|_err| eyre!("Invalid trigger: {s}"))
+    }
+}
+
+/// Written as JSON to the stdin of a hook when it runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct HookInput {
+    pub hook_event_name: HookTrigger,
+    pub cwd: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_input: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_response: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assistant_response: Option<String>,
+}
+
+impl HookInput {
+    pub fn new(ctx: &Context, trigger: HookTrigger) -> Self {
+        Self {
+            hook_event_name: trigger,
+            cwd: ctx
+                .env()
+                .current_dir()
+                .map(|cwd| cwd.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            tool_name: None,
+            tool_input: None,
+            tool_response: None,
+            assistant_response: None,
+        }
+    }
+
+    pub fn tool_use(
+        ctx: &Context,
+        trigger: HookTrigger,
+        tool_name: impl Into<String>,
+        tool_input: serde_json::Value,
+    ) -> Self {
+        Self {
+            tool_name: Some(tool_name.into()),
+            tool_input: Some(tool_input),
+            ..Self::new(ctx, trigger)
+        }
+    }
+}
+
+/// What [`HookTrigger::PreToolUse`] hooks decided to do with a tool use.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreToolUseDecision {
+    Allow,
+    /// The tool must not be used. The reason is returned to the model in place of the tool's
+    /// output.
+    Block(String),
+    /// The tool should be used with the given input instead.
+    Rewrite(serde_json::Value),
+}
+
+/// JSON a [`HookTrigger::PreToolUse`] hook may print to stdout to block or rewrite a tool use.
+#[derive(Debug, Default, Deserialize)]
+struct PreToolUseOutput {
+    decision: Option<String>,
+    reason: Option<String>,
+    tool_input: Option<serde_json::Value>,
+}
+
+/// The result of running a hook command to completion.
+#[derive(Debug)]
+struct HookOutput {
+    exit_code: Option<i32>,
+    stdout: String,
+    stderr: String,
 }
 
 #[derive(Debug, Clone)]
@@ -147,7 +272,12 @@ impl HookExecutor {
     /// Errors encountered with write operations to `updates` are ignored.
     ///
     /// Note: [`HookTrigger::ConversationStart`] hooks never leave the cache.
-    pub async fn run_hooks(&mut self, hooks: Vec<&Hook>, mut updates: Option<&mut impl Write>) -> Vec<(Hook, String)> {
+    pub async fn run_hooks(
+        &mut self,
+        ctx: &Context,
+        hooks: Vec<&Hook>,
+        mut updates: Option<&mut impl Write>,
+    ) -> Vec<(Hook, String)> {
         let mut results = Vec::with_capacity(hooks.len());
         let mut futures = FuturesUnordered::new();
 
@@ -164,7 +294,7 @@ impl HookExecutor {
                 results.push((index, (hook.clone(), cached.clone())));
                 continue;
             }
-            let future = self.execute_hook(hook);
+            let future = self.execute_hook(ctx, hook);
             futures.push(async move { (index, future.await) });
         }
 
@@ -270,7 +400,7 @@ impl HookExecutor {
         results.iter().skip(start_cache_index).for_each(|(_, (hook, output))| {
             let expiry = match hook.trigger {
                 HookTrigger::ConversationStart => None,
-                HookTrigger::PerPrompt => Some(Instant::now() + Duration::from_secs(hook.cache_ttl_seconds)),
+                _ => Some(Instant::now() + Duration::from_secs(hook.cache_ttl_seconds)),
             };
             self.insert_cache(hook, CachedHook {
                 output: output.clone(),
@@ -283,35 +413,168 @@ impl HookExecutor {
         results.into_iter().map(|(_, r)| r).collect()
     }
 
-    async fn execute_hook<'a>(&self, hook: &'a Hook) -> (&'a Hook, Result<String>, Duration) {
+    /// Runs [`HookTrigger::PreToolUse`] hooks for a tool use, in order. A hook blocks the tool use
+    /// by exiting with code 2, in which case stderr is the reason, or by printing
+    /// `{"decision": "block", "reason": "..."}`. A hook rewrites the input by printing
+    /// `{"tool_input": {...}}`, which is passed on to the hooks after it.
+    ///
+    /// Hooks that fail in any other way are logged and ignored.
+    pub async fn run_pre_tool_use_hooks(
+        &self,
+        ctx: &Context,
+        hooks: Vec<&Hook>,
+        tool_name: &str,
+        tool_input: &serde_json::Value,
+    ) -> PreToolUseDecision {
+        let mut rewritten: Option<serde_json::Value> = None;
+        for hook in hooks.into_iter().filter(|h| !h.disabled && h.matches_tool(tool_name)) {
+            let input = HookInput::tool_use(
+                ctx,
+                HookTrigger::PreToolUse,
+                tool_name,
+                rewritten.clone().unwrap_or_else(|| tool_input.clone()),
+            );
+            let output = match self.run_command(hook, &input).await {
+                Ok(output) => output,
+                Err(err) => {
+                    warn!(?err, hook = hook.name, "Failed to run the pre tool use hook");
+                    continue;
+                },
+            };
+
+            match output.exit_code {
+                Some(0) => {
+                    let output = serde_json::from_str::<PreToolUseOutput>(output.stdout.trim()).unwrap_or_default();
+                    if output.decision.as_deref() == Some("block") {
+                        let reason = output.reason.unwrap_or_else(|| "no reason given".to_string());
+                        return PreToolUseDecision::Block(format!("Blocked by hook '{}': {reason}", hook.name));
+                    }
+                    if let Some(tool_input) = output.tool_input {
+                        rewritten = Some(tool_input);
+                    }
+                },
+                Some(BLOCKING_EXIT_CODE) => {
+                    return PreToolUseDecision::Block(format!(
+                        "Blocked by hook '{}': {}",
+                        hook.name,
+                        output.stderr.trim()
+                    ));
+                },
+                code => warn!(?code, hook = hook.name, "The pre tool use hook failed"),
+            }
+        }
+
+        match rewritten {
+            Some(tool_input) => PreToolUseDecision::Rewrite(tool_input),
+            None => PreToolUseDecision::Allow,
+        }
+    }
+
+    /// Runs [`HookTrigger::PostToolUse`] hooks for a tool use, in order, returning the output of
+    /// each hook that printed something: stdout on success, or stderr if it exited with code 2.
+    ///
+    /// Hooks that fail in any other way are logged and ignored.
+    pub async fn run_post_tool_use_hooks(
+        &self,
+        ctx: &Context,
+        hooks: Vec<&Hook>,
+        tool_name: &str,
+        tool_input: &serde_json::Value,
+        tool_response: serde_json::Value,
+    ) -> Vec<(Hook, String)> {
+        let mut input = HookInput::tool_use(ctx, HookTrigger::PostToolUse, tool_name, tool_input.clone());
+        input.tool_response = Some(tool_response);
+
+        let mut results = Vec::new();
+        for hook in hooks.into_iter().filter(|h| !h.disabled && h.matches_tool(tool_name)) {
+            let output = match self.run_command(hook, &input).await {
+                Ok(output) => output,
+                Err(err) => {
+                    warn!(?err, hook = hook.name, "Failed to run the post tool use hook");
+                    continue;
+                },
+            };
+            let text = match output.exit_code {
+                Some(0) => output.stdout,
+                Some(BLOCKING_EXIT_CODE) => output.stderr,
+                code => {
+                    warn!(?code, hook = hook.name, "The post tool use hook failed");
+                    continue;
+                },
+            };
+            if !text.trim().is_empty() {
+                results.push((hook.clone(), text));
+            }
+        }
+        results
+    }
+
+    /// Runs hooks for an event that nothing waits on the result of, such as
+    /// [`HookTrigger::SessionEnd`]. Hooks are executed in parallel and failures are logged.
+    pub async fn run_event_hooks(&self, hooks: Vec<&Hook>, input: &HookInput) {
+        let futures = hooks
+            .into_iter()
+            .filter(|h| !h.disabled)
+            .map(|hook| async move { (hook, self.run_command(hook, input).await) });
+        for (hook, result) in futures::future::join_all(futures).await {
+            match result {
+                Ok(output) if output.exit_code == Some(0) => (),
+                Ok(output) => warn!(code = ?output.exit_code, hook = hook.name, "The hook failed"),
+                Err(err) => warn!(?err, hook = hook.name, "Failed to run the hook"),
+            }
+        }
+    }
+
+    async fn execute_hook<'a>(&self, ctx: &Context, hook: &'a Hook) -> (&'a Hook, Result<String>, Duration) {
         let start_time = Instant::now();
         let result = match hook.r#type {
-            HookType::Inline => self.execute_inline_hook(hook).await,
+            HookType::Inline => self.execute_inline_hook(ctx, hook).await,
         };
 
         (hook, result, start_time.elapsed())
     }
 
-    async fn execute_inline_hook(&self, hook: &Hook) -> Result<String> {
+    async fn execute_inline_hook(&self, ctx: &Context, hook: &Hook) -> Result<String> {
+        let output = self.run_command(hook, &HookInput::new(ctx, hook.trigger)).await?;
+        match output.exit_code {
+            Some(0) => Ok(output.stdout),
+            Some(code) => Err(eyre!("command returned non-zero exit code: {code}")),
+            None => Err(eyre!("command was terminated by a signal")),
+        }
+    }
+
+    /// Runs the hook's command with `input` written to stdin as JSON.
+    async fn run_command(&self, hook: &Hook, input: &HookInput) -> Result<HookOutput> {
         let command = hook.command.as_ref().ok_or_else(|| eyre!("no command specified"))?;
 
         #[cfg(unix)]
-        let command_future = tokio::process::Command::new("bash")
-            .arg("-c")
-            .arg(command)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .output();
+        let mut cmd = tokio::process::Command::new("bash");
+        #[cfg(unix)]
+        cmd.arg("-c");
 
         #[cfg(windows)]
-        let command_future = tokio::process::Command::new("cmd")
-            .arg("/C")
+        let mut cmd = tokio::process::Command::new("cmd");
+        #[cfg(windows)]
+        cmd.arg("/C");
+
+        let mut child = cmd
             .arg(command)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .output();
+            .kill_on_drop(true)
+            .spawn()?;
+
+        // The input is written while the output is read, since the hook may fill its output pipes
+        // before reading all of its input.
+        let input = serde_json::to_vec(input)?;
+        if let Some(mut stdin) = child.stdin.take() {
+            tokio::spawn(async move {
+                // The hook may exit without reading its input, so a broken pipe is not an error.
+                let _ = stdin.write_all(&input).await;
+            });
+        }
+        let command_future = child.wait_with_output();
 
         let timeout = Duration::from_millis(hook.timeout_ms);
 
@@ -319,21 +582,23 @@ impl HookExecutor {
         match tokio::time::timeout(timeout, command_future).await {
             Ok(result) => {
                 let result = result?;
-                if result.status.success() {
-                    let stdout = result.stdout.to_str_lossy();
-                    let stdout = format!(
+                let truncate = |output: &[u8]| {
+                    let output = output.to_str_lossy();
+                    format!(
                         "{}{}",
-                        truncate_safe(&stdout, hook.max_output_size),
-                        if stdout.len() > hook.max_output_size {
+                        truncate_safe(&output, hook.max_output_size),
+                        if output.len() > hook.max_output_size {
                             " ... truncated"
                         } else {
                             ""
                         }
-                    );
-                    Ok(stdout)
-                } else {
-                    Err(eyre!("command returned non-zero exit code: {}", result.status))
-                }
+                    )
+                };
+                Ok(HookOutput {
+                    exit_code: result.status.code(),
+                    stdout: truncate(&result.stdout),
+                    stderr: truncate(&result.stderr),
+                })
             },
             Err(_) => Err(eyre!("command timed out after {} ms", timeout.as_millis())),
         }
@@ -379,6 +644,7 @@ mod tests {
     use tokio::time::sleep;
 
     use super::*;
+    use crate::platform::Env;
 
     #[test]
     fn test_hook_creation() {
@@ -398,6 +664,7 @@ mod tests {
     #[tokio::test]
     async fn test_hook_executor_cached_conversation_start() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
         let mut hook1 = Hook::new_inline_hook(HookTrigger::ConversationStart, "echo 'test1'".to_string());
         hook1.is_global = true;
 
@@ -406,7 +673,7 @@ mod tests {
 
         // First execution should run the command
         let mut output = Vec::new();
-        let results = executor.run_hooks(vec![&hook1, &hook2], Some(&mut output)).await;
+        let results = executor.run_hooks(&ctx, vec![&hook1, &hook2], Some(&mut output)).await;
 
         assert_eq!(results.len(), 2);
         assert!(results[0].1.contains("test1"));
@@ -415,7 +682,7 @@ mod tests {
 
         // Second execution should use cache
         let mut output = Vec::new();
-        let results = executor.run_hooks(vec![&hook1, &hook2], Some(&mut output)).await;
+        let results = executor.run_hooks(&ctx, vec![&hook1, &hook2], Some(&mut output)).await;
 
         assert_eq!(results.len(), 2);
         assert!(results[0].1.contains("test1"));
@@ -426,6 +693,7 @@ mod tests {
     #[tokio::test]
     async fn test_hook_executor_cached_per_prompt() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
         let mut hook1 = Hook::new_inline_hook(HookTrigger::PerPrompt, "echo 'test1'".to_string());
         hook1.is_global = true;
         hook1.cache_ttl_seconds = 60;
@@ -436,7 +704,7 @@ mod tests {
 
         // First execution should run the command
         let mut output = Vec::new();
-        let results = executor.run_hooks(vec![&hook1, &hook2], Some(&mut output)).await;
+        let results = executor.run_hooks(&ctx, vec![&hook1, &hook2], Some(&mut output)).await;
 
         assert_eq!(results.len(), 2);
         assert!(results[0].1.contains("test1"));
@@ -445,7 +713,7 @@ mod tests {
 
         // Second execution should use cache
         let mut output = Vec::new();
-        let results = executor.run_hooks(vec![&hook1, &hook2], Some(&mut output)).await;
+        let results = executor.run_hooks(&ctx, vec![&hook1, &hook2], Some(&mut output)).await;
 
         assert_eq!(results.len(), 2);
         assert!(results[0].1.contains("test1"));
@@ -456,6 +724,7 @@ mod tests {
     #[tokio::test]
     async fn test_hook_executor_not_cached_per_prompt() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
         let mut hook1 = Hook::new_inline_hook(HookTrigger::PerPrompt, "echo 'test1'".to_string());
         hook1.is_global = true;
 
@@ -464,7 +733,7 @@ mod tests {
 
         // First execution should run the command
         let mut output = Vec::new();
-        let results = executor.run_hooks(vec![&hook1, &hook2], Some(&mut output)).await;
+        let results = executor.run_hooks(&ctx, vec![&hook1, &hook2], Some(&mut output)).await;
 
         assert_eq!(results.len(), 2);
         assert!(results[0].1.contains("test1"));
@@ -473,7 +742,7 @@ mod tests {
 
         // Second execution should use cache
         let mut output = Vec::new();
-        let results = executor.run_hooks(vec![&hook1, &hook2], Some(&mut output)).await;
+        let results = executor.run_hooks(&ctx, vec![&hook1, &hook2], Some(&mut output)).await;
 
         assert_eq!(results.len(), 2);
         assert!(results[0].1.contains("test1"));
@@ -484,21 +753,40 @@ mod tests {
     #[tokio::test]
     async fn test_hook_timeout() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
         let mut hook = Hook::new_inline_hook(HookTrigger::PerPrompt, "sleep 2".to_string());
         hook.timeout_ms = 100; // Set very short timeout
 
-        let results = executor.run_hooks(vec![&hook], None::<&mut Stdout>).await;
+        let results = executor.run_hooks(&ctx, vec![&hook], None::<&mut Stdout>).await;
 
         assert_eq!(results.len(), 0); // Should fail due to timeout
     }
 
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hook_large_input_and_output() {
+        let executor = HookExecutor::new();
+        let ctx = Context::new();
+        // Writes more than a pipe buffer before reading more than a pipe buffer of input.
+        let hook = Hook::new_inline_hook(
+            HookTrigger::AssistantResponse,
+            "head -c 1000000 /dev/zero; wc -c > /dev/null; echo done".to_string(),
+        );
+        let mut input = HookInput::new(&ctx, HookTrigger::AssistantResponse);
+        input.assistant_response = Some("a".repeat(1_000_000));
+
+        let output = executor.run_command(&hook, &input).await.unwrap();
+        assert_eq!(output.exit_code, Some(0));
+    }
+
     #[tokio::test]
     async fn test_disabled_hook() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
         let mut hook = Hook::new_inline_hook(HookTrigger::PerPrompt, "echo 'test'".to_string());
         hook.disabled = true;
 
-        let results = executor.run_hooks(vec![&hook], None::<&mut Stdout>).await;
+        let results = executor.run_hooks(&ctx, vec![&hook], None::<&mut Stdout>).await;
 
         assert_eq!(results.len(), 0); // Disabled hook should not run
     }
@@ -506,18 +794,19 @@ mod tests {
     #[tokio::test]
     async fn test_cache_expiration() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
         let mut hook = Hook::new_inline_hook(HookTrigger::PerPrompt, "echo 'test'".to_string());
         hook.cache_ttl_seconds = 1;
 
         // First execution
-        let results1 = executor.run_hooks(vec![&hook], None::<&mut Stdout>).await;
+        let results1 = executor.run_hooks(&ctx, vec![&hook], None::<&mut Stdout>).await;
         assert_eq!(results1.len(), 1);
 
         // Wait for cache to expire
         sleep(Duration::from_millis(1001)).await;
 
         // Second execution should run command again
-        let results2 = executor.run_hooks(vec![&hook], None::<&mut Stdout>).await;
+        let results2 = executor.run_hooks(&ctx, vec![&hook], None::<&mut Stdout>).await;
         assert_eq!(results2.len(), 1);
     }
 
@@ -555,6 +844,7 @@ mod tests {
     #[tokio::test]
     async fn test_max_output_size() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
 
         // Use different commands based on OS
         #[cfg(unix)]
@@ -566,7 +856,7 @@ mod tests {
         let mut hook = Hook::new_inline_hook(HookTrigger::PerPrompt, command.to_string());
         hook.max_output_size = 100;
 
-        let results = executor.run_hooks(vec![&hook], None::<&mut Stdout>).await;
+        let results = executor.run_hooks(&ctx, vec![&hook], None::<&mut Stdout>).await;
 
         assert!(results[0].1.len() <= hook.max_output_size + " ... truncated".len());
     }
@@ -574,6 +864,7 @@ mod tests {
     #[tokio::test]
     async fn test_os_specific_command_execution() {
         let mut executor = HookExecutor::new();
+        let ctx = Context::new();
 
         // Create a simple command that outputs the shell name
         #[cfg(unix)]
@@ -584,7 +875,7 @@ mod tests {
 
         let hook = Hook::new_inline_hook(HookTrigger::PerPrompt, command.to_string());
 
-        let results = executor.run_hooks(vec![&hook], None::<&mut Stdout>).await;
+        let results = executor.run_hooks(&ctx, vec![&hook], None::<&mut Stdout>).await;
 
         assert_eq!(results.len(), 1, "Command execution should succeed");
 
@@ -598,4 +889,93 @@ mod tests {
             "Windows shell path should contain cmd.exe or command.com"
         );
     }
+
+    #[test]
+    fn test_hook_matches_tool() {
+        let mut hook = Hook::new_inline_hook(HookTrigger::PreToolUse, "true".to_string());
+        assert!(hook.matches_tool("fs_write"));
+        hook.matcher = Some("*".to_string());
+        assert!(hook.matches_tool("fs_write"));
+        hook.matcher = Some("fs_write|execute_bash".to_string());
+        assert!(hook.matches_tool("execute_bash"));
+        assert!(!hook.matches_tool("fs_read"));
+    }
+
+    #[test]
+    fn test_hook_trigger_from_str() {
+        assert_eq!("pre_tool_use".parse::<HookTrigger>().unwrap(), HookTrigger::PreToolUse);
+        assert_eq!("session_end".parse::<HookTrigger>().unwrap(), HookTrigger::SessionEnd);
+        assert!("on_tool".parse::<HookTrigger>().is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_pre_tool_use_hooks() {
+        let executor = HookExecutor::new();
+        let ctx = Context::new();
+        let tool_input = serde_json::json!({ "command": "rm -rf /" });
+
+        let mut hook = Hook::new_inline_hook(
+            HookTrigger::PreToolUse,
+            "grep -q 'rm -rf' && echo 'destructive command' >&2 && exit 2 || exit 0".to_string(),
+        );
+        hook.name = "guard".to_string();
+        hook.matcher = Some("execute_bash".to_string());
+        assert_eq!(
+            executor
+                .run_pre_tool_use_hooks(&ctx, vec![&hook], "execute_bash", &tool_input)
+                .await,
+            PreToolUseDecision::Block("Blocked by hook 'guard': destructive command".to_string())
+        );
+        assert_eq!(
+            executor
+                .run_pre_tool_use_hooks(&ctx, vec![&hook], "fs_read", &tool_input)
+                .await,
+            PreToolUseDecision::Allow
+        );
+
+        let hook = Hook::new_inline_hook(
+            HookTrigger::PreToolUse,
+            r#"echo '{"tool_input": {"command": "ls"}}'"#.to_string(),
+        );
+        assert_eq!(
+            executor
+                .run_pre_tool_use_hooks(&ctx, vec![&hook], "execute_bash", &tool_input)
+                .await,
+            PreToolUseDecision::Rewrite(serde_json::json!({ "command": "ls" }))
+        );
+
+        let hook = Hook::new_inline_hook(HookTrigger::PreToolUse, "exit 1".to_string());
+        assert_eq!(
+            executor
+                .run_pre_tool_use_hooks(&ctx, vec![&hook], "execute_bash", &tool_input)
+                .await,
+            PreToolUseDecision::Allow
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_post_tool_use_hooks() {
+        let executor = HookExecutor::new();
+        let ctx = Context::builder().with_env(Env::from_slice(&[])).build_fake();
+        let hook = Hook::new_inline_hook(HookTrigger::PostToolUse, "cat".to_string());
+
+        let results = executor
+            .run_post_tool_use_hooks(
+                &ctx,
+                vec![&hook],
+                "fs_write",
+                &serde_json::json!({ "path": "src/main.rs" }),
+                serde_json::json!({ "status": "success" }),
+            )
+            .await;
+        assert_eq!(results.len(), 1);
+        let input: serde_json::Value = serde_json::from_str(&results[0].1).unwrap();
+        assert_eq!(input["hook_event_name"], "post_tool_use");
+        assert_eq!(input["cwd"], "/");
+        assert_eq!(input["tool_name"], "fs_write");
+        assert_eq!(input["tool_input"]["path"], "src/main.rs");
+        assert_eq!(input["tool_response"]["status"], "success");
+    }
 }
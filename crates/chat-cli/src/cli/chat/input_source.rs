@@ -1,6 +1,7 @@
 use eyre::Result;
 use rustyline::error::ReadlineError;
 
+use super::headless::InputEvent;
 use super::prompt::rl;
 #[cfg(unix)]
 use super::skim_integration::SkimCommandSelector;
@@ -18,12 +19,22 @@ mod inner {
     #[derive(Debug)]
     pub enum Inner {
         Readline(Editor<ChatHelper, FileHistory>),
+        /// User turns given as JSON, one per line.
+        JsonLines(JsonLines),
         #[allow(dead_code)]
         Mock {
             index: usize,
             lines: Vec<String>,
         },
     }
+
+    pub struct JsonLines(pub Box<dyn std::io::BufRead + Send>);
+
+    impl std::fmt::Debug for JsonLines {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_struct("JsonLines").finish_non_exhaustive()
+        }
+    }
 }
 
 impl InputSource {
@@ -35,6 +46,16 @@ impl InputSource {
         Ok(Self(inner::Inner::Readline(rl(database, sender, receiver)?)))
     }
 
+    /// Reads user turns from `reader`, one JSON [InputEvent] per line.
+    pub fn new_json_lines(reader: impl std::io::BufRead + Send + 'static) -> Self {
+        Self(inner::Inner::JsonLines(inner::JsonLines(Box::new(reader))))
+    }
+
+    /// Whether user turns are read as JSON rather than from a terminal.
+    pub fn is_json_lines(&self) -> bool {
+        matches!(self.0, inner::Inner::JsonLines(_))
+    }
+
     #[cfg(unix)]
     pub fn put_skim_command_selector(
         &mut self,
@@ -80,6 +101,17 @@ impl InputSource {
                     Err(err) => Err(err),
                 }
             },
+            inner::Inner::JsonLines(reader) => loop {
+                let mut line = String::new();
+                if reader.0.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    return InputEvent::parse_line(&line).map(Some).map_err(|err| {
+                        ReadlineError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))
+                    });
+                }
+            },
             inner::Inner::Mock { index, lines } => {
                 *index += 1;
                 Ok(lines.get(*index - 1).cloned())
@@ -113,4 +145,17 @@ mod tests {
         assert_eq!(input.read_line(None).unwrap().unwrap(), l3);
         assert!(input.read_line(None).unwrap().is_none());
     }
+
+    #[test]
+    fn test_json_lines_input_source() {
+        let lines =
+            "{\"type\": \"user\", \"content\": \"Hello\"}\n\n{\"type\": \"user\", \"content\": \"World\"}\nnot json\n";
+        let mut input = InputSource::new_json_lines(std::io::Cursor::new(lines));
+
+        assert!(input.is_json_lines());
+        assert_eq!(input.read_line(None).unwrap().unwrap(), "Hello");
+        assert_eq!(input.read_line(None).unwrap().unwrap(), "World");
+        assert!(input.read_line(None).is_err());
+        assert!(input.read_line(None).unwrap().is_none());
+    }
 }
@@ -0,0 +1,288 @@
+use std::path::Path;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use semantic_search_client::embedding::EmbeddingType;
+use semantic_search_client::{
+    MemoryContext,
+    ProgressStatus,
+    SemanticSearchClient,
+};
+use serde::Serialize;
+use tokio::sync::OnceCell;
+use tracing::warn;
+
+use crate::cli::chat::tools::sanitize_path_tool_arg;
+use crate::platform::Context;
+use crate::util::directories;
+
+/// Maximum number of results returned by [KnowledgeStore::search].
+pub const DEFAULT_SEARCH_LIMIT: usize = 5;
+
+static STORE: OnceCell<KnowledgeStore> = OnceCell::const_new();
+
+/// A single match returned by [KnowledgeStore::search].
+#[derive(Debug, Clone, Serialize)]
+pub struct KnowledgeSearchResult {
+    /// Name of the knowledge base the match was found in.
+    pub knowledge_base: String,
+    /// The file the text was taken from, if any.
+    pub path: Option<String>,
+    pub text: String,
+    /// Distance between the query and the text - lower is more relevant.
+    pub distance: f32,
+}
+
+/// Persistent semantic indexes of directories and files, shared by the `knowledge` tool and the
+/// `/knowledge` command.
+///
+/// Knowledge bases are stored under `~/.aws/amazonq/knowledge_bases` so that they are available
+/// across sessions. Indexing and embedding are CPU bound, so every operation runs on a blocking
+/// thread.
+#[derive(Clone)]
+pub struct KnowledgeStore {
+    client: Arc<Mutex<SemanticSearchClient>>,
+}
+
+impl std::fmt::Debug for KnowledgeStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KnowledgeStore").finish_non_exhaustive()
+    }
+}
+
+impl KnowledgeStore {
+    /// Returns the shared store, loading it on first use.
+    pub async fn get(ctx: &Context) -> Result<Self> {
+        let base_dir = directories::chat_knowledge_dir(ctx)?;
+        STORE
+            .get_or_try_init(|| async move {
+                tokio::task::spawn_blocking(move || {
+                    // The default embedder downloads a model on first use, so fall back to BM25 when
+                    // it is unavailable (e.g. offline).
+                    let client = SemanticSearchClient::new(&base_dir).or_else(|err| {
+                        warn!(?err, "Failed to load the embedding model, falling back to BM25");
+                        SemanticSearchClient::with_embedding_type(&base_dir, EmbeddingType::BM25)
+                    })?;
+                    Ok(Self::from_client(client))
+                })
+                .await?
+            })
+            .await
+            .cloned()
+    }
+
+    fn from_client(client: SemanticSearchClient) -> Self {
+        Self {
+            client: Arc::new(Mutex::new(client)),
+        }
+    }
+
+    async fn with_client<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut SemanticSearchClient) -> Result<T> + Send + 'static,
+    {
+        let client = Arc::clone(&self.client);
+        tokio::task::spawn_blocking(move || {
+            let mut client = client
+                .lock()
+                .map_err(|err| eyre!("The knowledge store lock was poisoned: {err}"))?;
+            f(&mut client)
+        })
+        .await?
+    }
+
+    /// All knowledge bases, sorted by name.
+    pub async fn knowledge_bases(&self) -> Result<Vec<MemoryContext>> {
+        self.with_client(|client| {
+            let mut contexts = client.get_contexts();
+            contexts.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok(contexts)
+        })
+        .await
+    }
+
+    /// Indexes the file or directory at `path` as a knowledge base called `name`. A knowledge base
+    /// already created from `path` is replaced, which is how an index is brought up to date.
+    pub async fn add(
+        &self,
+        name: impl Into<String>,
+        path: impl AsRef<Path>,
+        progress: Option<impl Fn(ProgressStatus) + Send + 'static>,
+    ) -> Result<MemoryContext> {
+        let name = name.into();
+        let path = path.as_ref().canonicalize()?;
+        self.with_client(move |client| {
+            let source_path = path.to_string_lossy().to_string();
+            let existing = client
+                .get_contexts()
+                .into_iter()
+                .find(|c| c.source_path.as_deref() == Some(source_path.as_str()));
+            if let Some(existing) = existing {
+                client.remove_context_by_id(&existing.id, true)?;
+            } else if client.get_contexts().iter().any(|c| c.name == name) {
+                bail!("A knowledge base named '{name}' already exists");
+            }
+
+            let id = client.add_context_from_path(&path, &name, &source_path, true, progress)?;
+            client
+                .get_contexts()
+                .into_iter()
+                .find(|c| c.id == id)
+                .ok_or_else(|| eyre!("Failed to create the knowledge base"))
+        })
+        .await
+    }
+
+    /// Removes the knowledge base with the given name, id, or source path, relative to the
+    /// current directory of `ctx`.
+    pub async fn remove(&self, ctx: &Context, name: impl Into<String>) -> Result<MemoryContext> {
+        let name = name.into();
+        let source_path = source_path(ctx, &name);
+        self.with_client(move |client| {
+            let context = find_context(client, &name, source_path.as_deref())?;
+            client.remove_context_by_id(&context.id, true)?;
+            Ok(context)
+        })
+        .await
+    }
+
+    /// Searches for text semantically similar to `query`, either within the knowledge base given
+    /// by `knowledge_base` (a name, id, or source path relative to the current directory of `ctx`)
+    /// or across all of them. Results are sorted by relevance.
+    pub async fn search(
+        &self,
+        ctx: &Context,
+        query: impl Into<String>,
+        knowledge_base: Option<String>,
+        limit: usize,
+    ) -> Result<Vec<KnowledgeSearchResult>> {
+        let query = query.into();
+        let knowledge_base = knowledge_base.map(|name| {
+            let source_path = source_path(ctx, &name);
+            (name, source_path)
+        });
+        self.with_client(move |client| {
+            let contexts = client.get_contexts();
+            let results = match knowledge_base {
+                Some((name, source_path)) => {
+                    let context = find_context(client, &name, source_path.as_deref())?;
+                    vec![(
+                        context.id.clone(),
+                        client.search_context(&context.id, &query, Some(limit))?,
+                    )]
+                },
+                None => client.search_all(&query, Some(limit))?,
+            };
+
+            let mut results = results
+                .into_iter()
+                .flat_map(|(id, results)| {
+                    let knowledge_base = contexts.iter().find(|c| c.id == id).map_or(id, |c| c.name.clone());
+                    results.into_iter().map(move |result| KnowledgeSearchResult {
+                        knowledge_base: knowledge_base.clone(),
+                        path: result
+                            .point
+                            .payload
+                            .get("path")
+                            .and_then(|p| p.as_str())
+                            .map(String::from),
+                        text: result.text().unwrap_or_default().to_string(),
+                        distance: result.distance,
+                    })
+                })
+                .collect::<Vec<_>>();
+            results.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+            results.truncate(limit);
+            Ok(results)
+        })
+        .await
+    }
+}
+
+/// Finds a knowledge base by id, name, or source path.
+fn find_context(client: &SemanticSearchClient, name: &str, source_path: Option<&str>) -> Result<MemoryContext> {
+    client
+        .get_contexts()
+        .into_iter()
+        .find(|c| c.id == name || c.name == name || source_path.is_some() && c.source_path.as_deref() == source_path)
+        .ok_or_else(|| eyre!("No knowledge base named '{name}' was found"))
+}
+
+/// The source path a knowledge base created from `name` would have, if `name` is an existing path.
+fn source_path(ctx: &Context, name: &str) -> Option<String> {
+    ctx.env()
+        .current_dir()
+        .ok()?
+        .join(sanitize_path_tool_arg(ctx, name))
+        .canonicalize()
+        .map(|p| p.to_string_lossy().to_string())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::Env;
+
+    #[tokio::test]
+    async fn test_knowledge_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(docs.join("auth.md"), "Users log in with a username and password.").unwrap();
+        std::fs::write(
+            docs.join("deploy.md"),
+            "Releases are deployed to production on Fridays.",
+        )
+        .unwrap();
+
+        let client =
+            SemanticSearchClient::with_embedding_type(dir.path().join("knowledge_bases"), EmbeddingType::BM25).unwrap();
+        let store = KnowledgeStore::from_client(client);
+        // The current directory of the fake environment is the root.
+        let ctx = Context::builder().with_env(Env::from_slice(&[])).build_fake();
+        let relative_docs = docs.strip_prefix("/").unwrap().to_string_lossy().to_string();
+
+        let context = store.add("docs", &docs, None::<fn(ProgressStatus)>).await.unwrap();
+        assert_eq!(context.name, "docs");
+        assert!(store.add("docs", dir.path(), None::<fn(ProgressStatus)>).await.is_err());
+        // Adding the same path again re-indexes it.
+        store.add("docs", &docs, None::<fn(ProgressStatus)>).await.unwrap();
+        assert_eq!(store.knowledge_bases().await.unwrap().len(), 1);
+
+        let results = store
+            .search(&ctx, "password", None, DEFAULT_SEARCH_LIMIT)
+            .await
+            .unwrap();
+        assert_eq!(results[0].knowledge_base, "docs");
+        assert!(results[0].text.contains("password"));
+        assert!(results[0].path.as_deref().unwrap().ends_with("auth.md"));
+        assert!(
+            store
+                .search(&ctx, "password", Some("other".to_string()), 1)
+                .await
+                .is_err()
+        );
+        // Source paths are relative to the current directory of the context.
+        assert_eq!(
+            store
+                .search(&ctx, "password", Some(relative_docs.clone()), 1)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        store.remove(&ctx, relative_docs).await.unwrap();
+        assert!(store.knowledge_bases().await.unwrap().is_empty());
+        assert!(store.remove(&ctx, "docs").await.is_err());
+    }
+}
@@ -1,15 +1,23 @@
+mod agent;
+mod auto_compact;
+mod checkpoint;
 mod command;
 mod consts;
 mod context;
 mod conversation_state;
+mod headless;
 mod hooks;
 mod input_source;
+mod knowledge_store;
 mod message;
+mod mode;
 mod parse;
 mod parser;
 mod prompt;
 mod prompt_parser;
+mod sampling;
 mod server_messenger;
+mod sessions;
 #[cfg(unix)]
 mod skim_integration;
 mod token_counter;
@@ -40,21 +48,37 @@ use std::{
     io,
 };
 
+use agent::{
+    AGENT_SCHEMA,
+    Agent,
+    list_agents,
+};
 use amzn_codewhisperer_client::types::SubscriptionStatus;
+use auto_compact::AutoCompactConfig;
 use clap::Args;
 use command::{
+    AgentSubcommand,
+    CheckpointSubcommand,
     Command,
+    KnowledgeSubcommand,
+    ModeSubcommand,
     PromptsSubcommand,
+    ResourcesSubcommand,
     ToolsSubcommand,
 };
 use consts::{
     CONTEXT_FILES_MAX_SIZE,
     CONTEXT_WINDOW_SIZE,
+    DEFAULT_TOOL_CONCURRENCY,
     DUMMY_TOOL_NAME,
+    SHELL_HISTORY_CONTEXT_LEN,
 };
 use context::ContextManager;
 pub use conversation_state::ConversationState;
-use conversation_state::TokenWarningLevel;
+use conversation_state::{
+    TokenWarningLevel,
+    format_resource_context,
+};
 use crossterm::style::{
     Attribute,
     Color,
@@ -76,17 +100,36 @@ use eyre::{
     Result,
     bail,
 };
+use futures::{
+    StreamExt,
+    stream,
+};
+pub use headless::{
+    ChatInputFormat,
+    ChatOutputFormat,
+};
+use headless::{
+    EventWriter,
+    OutputEvent,
+};
 use hooks::{
     Hook,
+    HookInput,
     HookTrigger,
+    PreToolUseDecision,
 };
 use input_source::InputSource;
+use knowledge_store::KnowledgeStore;
 use message::{
     AssistantMessage,
     AssistantToolUse,
     ToolUseResult,
     ToolUseResultBlock,
 };
+use mode::{
+    APPROVE_PLAN_PROMPT,
+    ChatMode,
+};
 use parse::{
     ParseState,
     interpret_markdown,
@@ -96,6 +139,7 @@ use parser::{
     ResponseParser,
 };
 use regex::Regex;
+use sampling::SamplingHandler;
 use serde_json::Map;
 use spinners::{
     Spinner,
@@ -105,25 +149,38 @@ use thiserror::Error;
 use time::OffsetDateTime;
 use token_counter::{
     TokenCount,
+    TokenCounted,
     TokenCounter,
 };
 use tokio::signal::ctrl_c;
 use tool_manager::{
     GetPromptError,
+    GetResourceError,
     LoadingRecord,
     McpServerConfig,
     PromptBundle,
+    ResourceBundle,
     ToolManager,
     ToolManagerBuilder,
 };
+use tools::delegate::DelegateContext;
+use tools::execute::{
+    CommandPolicy,
+    ExecuteCommand,
+    PolicyAction,
+    PolicyDecision,
+    Sandbox,
+};
 use tools::gh_issue::GhIssueContext;
 use tools::{
+    InvokeOutput,
     OutputKind,
     QueuedTool,
     Tool,
     ToolOrigin,
     ToolPermissions,
     ToolSpec,
+    sanitize_path_tool_arg,
 };
 use tracing::{
     debug,
@@ -143,6 +200,7 @@ use util::{
     animate_output,
     drop_matched_context_files,
     play_notification_bell,
+    truncate_safe,
 };
 use uuid::Uuid;
 use winnow::Partial;
@@ -161,7 +219,6 @@ use crate::api_client::{
 };
 use crate::auth::AuthError;
 use crate::auth::builder_id::is_idc_user;
-use crate::cli::chat::token_counter::CharCount;
 use crate::database::Database;
 use crate::database::settings::Setting;
 use crate::mcp_client::{
@@ -176,6 +233,7 @@ use crate::telemetry::{
     TelemetryThread,
     get_error_reason,
 };
+use crate::util::env_var::QTERM_SESSION_ID;
 use crate::util::system_info::is_remote;
 
 #[derive(Debug, Clone, PartialEq, Eq, Default, Args)]
@@ -188,14 +246,29 @@ pub struct ChatArgs {
     /// prompt requests permissions to use a tool, unless --trust-all-tools is also used.
     #[arg(long)]
     pub no_interactive: bool,
-    /// Resumes the previous conversation from this directory.
-    #[arg(short, long)]
-    pub resume: bool,
+    /// Resumes the previous conversation from this directory, or the session with the given id
+    /// with --resume=<SESSION_ID> (see --list-sessions).
+    #[arg(short, long, num_args = 0..=1, require_equals = true, value_name = "SESSION_ID")]
+    #[allow(clippy::option_option)]
+    pub resume: Option<Option<String>>,
+    /// List the saved chat sessions, most recent first, and exit.
+    #[arg(long)]
+    pub list_sessions: bool,
+    /// Only list the sessions whose title or transcript contains every word of the query.
+    #[arg(long, value_name = "QUERY", requires = "list_sessions")]
+    pub search: Option<String>,
     /// The first question to ask
     pub input: Option<String>,
     /// Context profile to use
     #[arg(long = "profile")]
     pub profile: Option<String>,
+    /// Agent to use, defined in .amazonq/agents/<name>.json or ~/.aws/amazonq/agents/<name>.json
+    #[arg(long, value_name = "NAME")]
+    pub agent: Option<String>,
+    /// Mode to start in. In plan mode, the model can only use read-only tools and replies with a
+    /// plan to approve with /mode approve.
+    #[arg(long, value_enum)]
+    pub mode: Option<ChatMode>,
     /// Current model to use
     #[arg(long = "model")]
     pub model: Option<String>,
@@ -206,27 +279,79 @@ pub struct ChatArgs {
     /// '--trust-tools=fs_read,fs_write', trust no tools: '--trust-tools='
     #[arg(long, value_delimiter = ',', value_name = "TOOL_NAMES")]
     pub trust_tools: Option<Vec<String>>,
+    /// Run shell commands in a sandbox where only the current directory, the temporary directory
+    /// and the profile's sandbox writablePaths are writable, and network access is blocked unless
+    /// the profile's sandbox sets allowNetwork. Only supported on Linux.
+    #[arg(long)]
+    pub sandbox: bool,
+    /// Format of the output. The JSON formats imply --no-interactive and report the response,
+    /// tool uses, tool results, and errors in a machine readable form.
+    #[arg(long, value_enum, default_value_t)]
+    pub output_format: ChatOutputFormat,
+    /// Format of the input read from stdin. With stream-json, each line is a user turn of the
+    /// form {"type": "user", "content": "..."}. Implies --no-interactive.
+    #[arg(long, value_enum, default_value_t)]
+    pub input_format: ChatInputFormat,
 }
 
 impl ChatArgs {
     pub async fn execute(self, database: &mut Database, telemetry: &TelemetryThread) -> Result<ExitCode> {
         let ctx = Context::new();
 
+        if let Err(err) = database.cleanup_sessions() {
+            warn!(?err, "Failed to clean up old chat sessions");
+        }
+        // The checkpoints of the deleted sessions no longer keep their file contents around.
+        match database.get_all_conversation_values() {
+            Ok(saved) => {
+                if let Err(err) = checkpoint::prune_contents(&ctx, saved, checkpoint::PRUNE_MIN_AGE).await {
+                    warn!(?err, "Failed to prune the contents of old checkpoints");
+                }
+            },
+            Err(err) => warn!(?err, "Failed to read the saved chat conversations"),
+        }
+        if self.list_sessions {
+            let sessions = database.list_sessions(self.search.as_deref(), None)?;
+            sessions::print_sessions(&mut std::io::stdout(), &sessions)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        // The id must be given as `--resume=<id>`, so `q chat --resume "question"` resumes the
+        // latest conversation from this directory with the question.
+        let input = self.input;
+        let resume_conversation = match self.resume {
+            Some(Some(id)) => match database.get_session(&id)? {
+                Some(session) => Some(session),
+                None => bail!("No session found with the id '{id}'. Use --list-sessions to see them."),
+            },
+            Some(None) => std::env::current_dir()
+                .ok()
+                .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
+                .flatten(),
+            None => None,
+        };
+
         let stdin = std::io::stdin();
-        // no_interactive flag or part of a pipe
-        let interactive = !self.no_interactive && stdin.is_terminal();
-        let input = if !interactive && !stdin.is_terminal() {
+        let headless = self.output_format != ChatOutputFormat::Text || self.input_format == ChatInputFormat::StreamJson;
+        // no_interactive flag, machine readable input or output, or part of a pipe
+        let interactive = !self.no_interactive && !headless && stdin.is_terminal();
+        let input = if self.input_format == ChatInputFormat::StreamJson {
+            // User turns are read from stdin as the chat goes on
+            input
+        } else if !interactive && !stdin.is_terminal() {
             // append to input string any extra info that was provided, e.g. via pipe
-            let mut input = self.input.unwrap_or_default();
+            let mut input = input.unwrap_or_default();
             stdin.lock().read_to_string(&mut input)?;
             Some(input)
         } else {
-            self.input
+            input
         };
 
-        let mut output = match interactive {
-            true => SharedWriter::stderr(),
-            false => SharedWriter::stdout(),
+        let mut output = match (interactive, self.output_format) {
+            (true, _) => SharedWriter::stderr(),
+            (false, ChatOutputFormat::Text) => SharedWriter::stdout(),
+            // Stdout is reserved for events
+            (false, _) => SharedWriter::null(),
         };
 
         let client = match ctx.env().get("Q_MOCK_CHAT_RESPONSE") {
@@ -234,7 +359,20 @@ impl ChatArgs {
             _ => StreamingClient::new(database).await?,
         };
 
-        let mcp_server_configs = match McpServerConfig::load_config(&mut output).await {
+        let agent = match &self.agent {
+            Some(name) => Some(Agent::load(&ctx, name).await?),
+            None => None,
+        };
+        if let Some(warning) = agent.as_ref().and_then(Agent::ignored_fields_warning) {
+            execute!(
+                output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!("{warning}\n\n")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
+
+        let mut mcp_server_configs = match McpServerConfig::load_config(&mut output).await {
             Ok(config) => {
                 if interactive && !database.settings.get_bool(Setting::McpLoadedBefore).unwrap_or(false) {
                     execute!(
@@ -253,6 +391,10 @@ impl ChatArgs {
             },
         };
 
+        if let Some(mcp_servers) = agent.as_ref().and_then(|agent| agent.config.mcp_servers.clone()) {
+            mcp_server_configs.mcp_servers = mcp_servers;
+        }
+
         // If profile is specified, verify it exists before starting the chat
         if let Some(ref profile_name) = self.profile {
             // Create a temporary context manager to check if the profile exists
@@ -274,22 +416,14 @@ impl ChatArgs {
             }
         }
 
-        // If modelId is specified, verify it exists before starting the chat
-        let model_id: Option<String> = if let Some(model_name) = self.model {
-            let model_name_lower = model_name.to_lowercase();
-            match MODEL_OPTIONS.iter().find(|opt| opt.name == model_name_lower) {
-                Some(opt) => Some((opt.model_id).to_string()),
-                None => {
-                    let available_names: Vec<&str> = MODEL_OPTIONS.iter().map(|opt| opt.name).collect();
-                    bail!(
-                        "Model '{}' does not exist. Available models: {}",
-                        model_name,
-                        available_names.join(", ")
-                    );
-                },
-            }
-        } else {
-            None
+        // If modelId is specified, verify it exists before starting the chat. The agent's model is
+        // used unless another one is given.
+        let model_id = match self
+            .model
+            .or_else(|| agent.as_ref().and_then(|agent| agent.config.model.clone()))
+        {
+            Some(model_name) => Some(model_id_from_name(&model_name)?),
+            None => None,
         };
 
         // if let Some(ref id) = model_id {
@@ -305,10 +439,13 @@ impl ChatArgs {
         } else {
             Box::new(NullWriter {})
         };
+        let (sampling_request_sender, sampling_handler) =
+            SamplingHandler::new(client.clone(), output.clone(), interactive, model_id.clone());
         let mut tool_manager = ToolManagerBuilder::default()
             .mcp_server_config(mcp_server_configs)
             .prompt_list_sender(prompt_response_sender)
             .prompt_list_receiver(prompt_request_receiver)
+            .sampling_request_sender(sampling_request_sender)
             .conversation_id(&conversation_id)
             .interactive(interactive)
             .build(telemetry, tool_manager_output)
@@ -349,15 +486,20 @@ impl ChatArgs {
             }
         }
 
+        let input_source = match self.input_format {
+            ChatInputFormat::Text => InputSource::new(database, prompt_request_sender, prompt_response_receiver)?,
+            ChatInputFormat::StreamJson => InputSource::new_json_lines(std::io::BufReader::new(stdin)),
+        };
+
         let mut chat = ChatContext::new(
             ctx,
             database,
             &conversation_id,
             output,
             input,
-            InputSource::new(database, prompt_request_sender, prompt_response_receiver)?,
+            input_source,
             interactive,
-            self.resume,
+            resume_conversation,
             client,
             || terminal::window_size().map(|s| s.columns.into()).ok(),
             tool_manager,
@@ -365,18 +507,31 @@ impl ChatArgs {
             model_id,
             tool_config,
             tool_permissions,
+            self.sandbox,
+            EventWriter::new(self.output_format, std::io::stdout()),
         )
         .await?;
+        chat.sampling_handler = Some(sampling_handler);
+        if let Some(agent) = agent {
+            chat.conversation_state.set_agent(Some(agent)).await?;
+        }
+        if let Some(mode) = self.mode {
+            chat.conversation_state.set_mode(mode).await;
+        }
 
-        let result = chat.try_chat(database, telemetry).await.map(|_| ExitCode::SUCCESS);
+        let result = chat.try_chat(database, telemetry).await;
+        chat.run_session_end_hooks().await;
+        let failed = chat.finish_events(result.as_ref().err())?;
+        let result = result.map(|_| if failed { ExitCode::FAILURE } else { ExitCode::SUCCESS });
         drop(chat); // Explicit drop for clarity
 
         result
     }
 }
 
-/// Help text for the compact command
-fn compact_help_text() -> String {
+/// Help text for the compact command, describing the automatic compaction of the current profile
+fn compact_help_text(auto_compact: &AutoCompactConfig) -> String {
+    let status = if auto_compact.enabled { "enabled" } else { "disabled" };
     color_print::cformat!(
         r#"
 <magenta,em>Conversation Compaction</magenta,em>
@@ -400,7 +555,16 @@ that may eventually reach memory constraints.
 • Retains key information, code, and tool executions in the summary
 • Clears the conversation history to free up space
 • The assistant will reference the summary context in future responses
-"#
+
+<cyan!>Automatic compaction</cyan!> ({status} for this profile)
+Once the conversation uses over {threshold}% of the context window, large tool results from older
+turns are elided and then the older turns are summarized, keeping the last {turns} turns as they
+are. Configure or disable this with the <em>autoCompact</em> section of the profile's context.json, e.g.
+  <black!>{{"autoCompact": {{"enabled": false}}}}</black!>
+  <black!>{{"autoCompact": {{"thresholdPercent": 80, "keepRecentTurns": 4, "maxToolResultTokens": 2000}}}}</black!>
+"#,
+        threshold = auto_compact.threshold_percent,
+        turns = auto_compact.keep_recent_turns,
     )
 }
 
@@ -417,9 +581,10 @@ const SMALL_SCREEN_WELCOME_TEXT: &str = color_print::cstr! {"<em>Welcome to <cya
 const RESUME_TEXT: &str = color_print::cstr! {"<em>Picking up where we left off...</em>"};
 
 // Only show the model-related tip for now to make users aware of this feature.
-const ROTATING_TIPS: [&str; 16] = [
+const ROTATING_TIPS: [&str; 17] = [
     color_print::cstr! {"You can resume the last conversation from your current directory by launching with
     <green!>q chat --resume</green!>"},
+    color_print::cstr! {"Use <green!>/sessions</green!> or <green!>q chat --list-sessions</green!> to find and resume older conversations"},
     color_print::cstr! {"Get notified whenever Q CLI finishes responding.
     Just run <green!>q settings chat.enableNotifications true</green!>"},
     color_print::cstr! {"You can use
@@ -454,23 +619,52 @@ const ROTATING_TIPS: [&str; 16] = [
 pub struct ModelOption {
     pub name: &'static str,
     pub model_id: &'static str,
+    /// In tokens
+    pub context_window_size: usize,
 }
 
 pub const MODEL_OPTIONS: [ModelOption; 3] = [
     ModelOption {
         name: "claude-4-sonnet",
         model_id: "CLAUDE_SONNET_4_20250514_V1_0",
+        context_window_size: 200_000,
     },
     ModelOption {
         name: "claude-3.7-sonnet",
         model_id: "CLAUDE_3_7_SONNET_20250219_V1_0",
+        context_window_size: 200_000,
     },
     ModelOption {
         name: "claude-3.5-sonnet",
         model_id: "CLAUDE_3_5_SONNET_20241022_V2_0",
+        context_window_size: 200_000,
     },
 ];
 
+/// Returns the id of the model with the given name, as accepted by `--model`.
+fn model_id_from_name(model_name: &str) -> Result<String> {
+    let model_name_lower = model_name.to_lowercase();
+    match MODEL_OPTIONS.iter().find(|opt| opt.name == model_name_lower) {
+        Some(opt) => Ok(opt.model_id.to_string()),
+        None => {
+            let available_names: Vec<&str> = MODEL_OPTIONS.iter().map(|opt| opt.name).collect();
+            bail!(
+                "Model '{}' does not exist. Available models: {}",
+                model_name,
+                available_names.join(", ")
+            );
+        },
+    }
+}
+
+/// Returns the context window size in tokens of the model with the given id, falling back to
+/// [CONTEXT_WINDOW_SIZE] for the default or an unknown model.
+pub fn context_window_size(model_id: Option<&str>) -> usize {
+    model_id
+        .and_then(|id| MODEL_OPTIONS.iter().find(|opt| opt.model_id == id))
+        .map_or(CONTEXT_WINDOW_SIZE, |opt| opt.context_window_size)
+}
+
 const GREETING_BREAK_POINT: usize = 80;
 
 const POPULAR_SHORTCUTS: &str = color_print::cstr! {"<black!><green!>/help</green!> all commands  <em>•</em>  <green!>ctrl + j</green!> new lines  <em>•</em>  <green!>ctrl + s</green!> fuzzy search</black!>"};
@@ -511,6 +705,11 @@ const HELP_TEXT: &str = color_print::cstr! {"
   <em>help</em>        <black!>Show prompts help</black!>
   <em>list</em>        <black!>List or search available prompts</black!>
   <em>get</em>         <black!>Retrieve and send a prompt</black!>
+<em>/resources</em>    <black!>View, read and attach resources from mcp servers</black!>
+  <em>help</em>        <black!>Show resources help</black!>
+  <em>list</em>        <black!>List or search available resources</black!>
+  <em>read</em>        <black!>Print the content of a resource</black!>
+  <em>attach</em>      <black!>Include a resource as context for the next prompt</black!>
 <em>/context</em>      <black!>Manage context files and hooks for the chat session</black!>
   <em>help</em>        <black!>Show context help</black!>
   <em>show</em>        <black!>Display current context rules configuration [--expand]</black!>
@@ -518,6 +717,31 @@ const HELP_TEXT: &str = color_print::cstr! {"
   <em>rm</em>          <black!>Remove file(s) from context [--global]</black!>
   <em>clear</em>       <black!>Clear all files from current context [--global]</black!>
   <em>hooks</em>       <black!>View and manage context hooks</black!>
+<em>/undo</em>         <black!>Revert the file edits made in response to the last prompt</black!>
+<em>/checkpoint</em>   <black!>View and restore file edit checkpoints</black!>
+  <em>help</em>        <black!>Show checkpoint help</black!>
+  <em>list</em>        <black!>List the checkpoints of this conversation</black!>
+  <em>restore</em>     <black!>Revert all file edits made since a checkpoint</black!>
+<em>/knowledge</em>    <black!>Manage knowledge bases for semantic search</black!>
+  <em>help</em>        <black!>Show knowledge help</black!>
+  <em>show</em>        <black!>List all knowledge bases</black!>
+  <em>add</em>         <black!>Index a file or directory [--name <<name>>]</black!>
+  <em>remove</em>      <black!>Remove a knowledge base</black!>
+  <em>search</em>      <black!>Search all knowledge bases</black!>
+<em>/fork</em>         <black!>Branch the conversation into a new branch [name]</black!>
+<em>/branches</em>     <black!>List the branches of this conversation</black!>
+<em>/switch</em>       <black!>Switch to another branch of this conversation</black!>
+<em>/rewind</em>       <black!>Drop the last prompts, their responses and file edits [turns]</black!>
+<em>/sessions</em>     <black!>Search and resume previous sessions [query]</black!>
+<em>/subagents</em>    <black!>List the sub-agents run by the delegate tool, or show one [id]</black!>
+<em>/agent</em>        <black!>Manage agents</black!>
+  <em>help</em>        <black!>Show agent help</black!>
+  <em>list</em>        <black!>List the available agents</black!>
+  <em>swap</em>        <black!>Switch to another agent</black!>
+  <em>clear</em>       <black!>Stop using the current agent</black!>
+  <em>show</em>        <black!>Show the definition of the current agent</black!>
+  <em>schema</em>      <black!>Show the JSON schema of agent files</black!>
+<em>/mode</em>         <black!>Toggle plan mode, where the model can only explore and plan [normal|plan|approve]</black!>
 <em>/usage</em>        <black!>Show current session's context window usage</black!>
 <em>/load</em>         <black!>Load conversation state from a JSON file</black!>
 <em>/save</em>         <black!>Save conversation state to a JSON file</black!>
@@ -566,6 +790,21 @@ enum ToolUseStatus {
     RetryInProgress(String),
 }
 
+/// The outcome of a tool invoked ahead of its turn by [ChatContext::invoke_concurrently].
+struct ToolInvocation {
+    result: Result<InvokeOutput>,
+    /// What the tool wrote to its updates while it was invoked.
+    output: Vec<u8>,
+    start: std::time::Instant,
+    end: std::time::Instant,
+}
+
+impl ToolInvocation {
+    fn duration(&self) -> Duration {
+        self.end.duration_since(self.start)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ChatError {
     #[error("{0}")]
@@ -648,6 +887,14 @@ pub struct ChatContext {
     failed_request_ids: Vec<String>,
     /// Pending prompts to be sent
     pending_prompts: VecDeque<Prompt>,
+    /// Resources attached via /resources attach, to be sent as context with the next prompt
+    pending_resources: Vec<ResourceBundle>,
+    /// Whether shell commands are sandboxed regardless of the profile's sandbox settings.
+    sandbox: bool,
+    /// Where events are reported when the output format is machine readable.
+    events: Option<EventWriter>,
+    /// Services the sampling requests of mcp servers, see [SamplingHandler].
+    sampling_handler: Option<SamplingHandler>,
 }
 
 impl ChatContext {
@@ -660,7 +907,7 @@ impl ChatContext {
         mut input: Option<String>,
         input_source: InputSource,
         interactive: bool,
-        resume_conversation: bool,
+        resume_conversation: Option<ConversationState>,
         client: StreamingClient,
         terminal_width_provider: fn() -> Option<usize>,
         tool_manager: ToolManager,
@@ -668,6 +915,8 @@ impl ChatContext {
         model_id: Option<String>,
         tool_config: HashMap<String, ToolSpec>,
         tool_permissions: ToolPermissions,
+        sandbox: bool,
+        events: Option<EventWriter>,
     ) -> Result<Self> {
         let ctx_clone = Arc::clone(&ctx);
         let output_clone = output.clone();
@@ -687,17 +936,10 @@ impl ChatContext {
             })
             .unwrap_or_else(|| default_model_id(database).to_owned());
 
-        let conversation_state = if resume_conversation {
-            let prior = std::env::current_dir()
-                .ok()
-                .and_then(|cwd| database.get_conversation_by_path(cwd).ok())
-                .flatten();
-
-            // Only restore conversations where there were actual messages.
-            // Prevents edge case where user clears conversation with --new, then exits without chatting.
-            if prior.as_ref().is_some_and(|cs| !cs.history().is_empty()) {
-                let mut cs = prior.unwrap();
-
+        // Only restore conversations where there were actual messages.
+        // Prevents edge case where user clears conversation with --new, then exits without chatting.
+        let mut conversation_state = match resume_conversation.filter(|cs| !cs.history().is_empty()) {
+            Some(mut cs) => {
                 existing_conversation = true;
                 cs.reload_serialized_state(Arc::clone(&ctx), Some(output.clone())).await;
                 input = Some(input.unwrap_or("In a few words, summarize our conversation so far.".to_owned()));
@@ -705,7 +947,8 @@ impl ChatContext {
                 cs.update_state(true).await;
                 cs.enforce_tool_use_history_invariants();
                 cs
-            } else {
+            },
+            None => {
                 ConversationState::new(
                     ctx_clone,
                     conversation_id,
@@ -716,20 +959,28 @@ impl ChatContext {
                     Some(valid_model_id),
                 )
                 .await
-            }
-        } else {
-            ConversationState::new(
-                ctx_clone,
-                conversation_id,
-                tool_config,
-                profile,
-                Some(output_clone),
-                tool_manager,
-                Some(valid_model_id),
-            )
-            .await
+            },
         };
 
+        // Commands the user just ran in this terminal, e.g. to ask why the last one failed
+        let shell_history = match ctx.env().get(QTERM_SESSION_ID) {
+            Ok(session_id)
+                if database
+                    .settings
+                    .get_bool(Setting::HistoryCaptureOutput)
+                    .unwrap_or(false) =>
+            {
+                database
+                    .get_shell_history(&session_id, SHELL_HISTORY_CONTEXT_LEN)
+                    .unwrap_or_else(|err| {
+                        warn!(?err, "Failed to get the shell history");
+                        Vec::new()
+                    })
+            },
+            _ => Vec::new(),
+        };
+        conversation_state.set_shell_history(&shell_history);
+
         Ok(Self {
             ctx,
             output,
@@ -746,6 +997,10 @@ impl ChatContext {
             tool_use_status: ToolUseStatus::Idle,
             failed_request_ids: Vec::new(),
             pending_prompts: VecDeque::new(),
+            pending_resources: Vec::new(),
+            sandbox,
+            events,
+            sampling_handler: None,
         })
     }
 }
@@ -809,6 +1064,9 @@ enum ChatState {
         show_summary: bool,
         /// Whether or not to show the /compact help text.
         help: bool,
+        /// Number of most recent turns to keep verbatim. If not set, only the last message is
+        /// kept.
+        keep_recent_turns: Option<usize>,
     },
     /// Exit the chat.
     Exit,
@@ -877,6 +1135,7 @@ impl ChatContext {
     }
 
     async fn try_chat(&mut self, database: &mut Database, telemetry: &TelemetryThread) -> Result<()> {
+        self.update_mcp_roots().await;
         let is_small_screen = self.terminal_width() < GREETING_BREAK_POINT;
         if self.interactive && database.settings.get_bool(Setting::ChatGreetingEnabled).unwrap_or(true) {
             let welcome_text = match self.existing_conversation {
@@ -980,10 +1239,15 @@ impl ChatContext {
                     pending_tool_index,
                     skip_printing_tools,
                 } => {
-                    // Cannot prompt in non-interactive mode no matter what.
-                    if !self.interactive {
+                    // Cannot prompt in non-interactive mode, unless user turns are read as JSON.
+                    if !self.interactive && !self.input_source.is_json_lines() {
                         return Ok(());
                     }
+                    // Sampling requests that arrived since the last prompt are approved before
+                    // the input prompt takes over the terminal.
+                    if let Some(sampling_handler) = self.sampling_handler.as_mut() {
+                        sampling_handler.handle_queued().await;
+                    }
                     self.prompt_user(database, tool_uses, pending_tool_index, skip_printing_tools)
                         .await
                 },
@@ -1004,19 +1268,31 @@ impl ChatContext {
                     prompt,
                     show_summary,
                     help,
+                    keep_recent_turns,
                 } => {
                     let tool_uses_clone = tool_uses.clone();
                     tokio::select! {
-                        res = self.compact_history(telemetry, database, tool_uses, pending_tool_index, prompt, show_summary, help) => res,
+                        res = self.compact_history(telemetry, database, tool_uses, pending_tool_index, prompt, show_summary, help, keep_recent_turns) => res,
                         Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: tool_uses_clone })
                     }
                 },
                 ChatState::ExecuteTools(tool_uses) => {
                     let tool_uses_clone = tool_uses.clone();
-                    tokio::select! {
+                    // Tools may wait on completions requested by their servers, so sampling
+                    // requests are serviced while they run.
+                    let mut sampling_handler = self.sampling_handler.take();
+                    let result = tokio::select! {
                         res = self.tool_use_execute(database, telemetry, tool_uses) => res,
-                        Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: Some(tool_uses_clone) })
-                    }
+                        Ok(_) = ctrl_c_stream => Err(ChatError::Interrupted { tool_uses: Some(tool_uses_clone) }),
+                        () = async {
+                            match sampling_handler.as_mut() {
+                                Some(sampling_handler) => sampling_handler.handle_incoming().await,
+                                None => std::future::pending().await,
+                            }
+                        } => unreachable!("sampling requests are serviced until the tools complete"),
+                    };
+                    self.sampling_handler = sampling_handler;
+                    result
                 },
                 ChatState::ValidateTools(tool_uses) => {
                     tokio::select! {
@@ -1039,6 +1315,14 @@ impl ChatContext {
         }
     }
 
+    /// Runs the [HookTrigger::SessionEnd] hooks, whichever way [Self::try_chat] returned.
+    async fn run_session_end_hooks(&mut self) {
+        if let Some(cm) = self.conversation_state.context_manager.as_mut() {
+            cm.run_event_hooks(HookInput::new(&self.ctx, HookTrigger::SessionEnd))
+                .await;
+        }
+    }
+
     /// Handles the result of processing a [ChatState], returning the next [ChatState] to change
     /// to.
     async fn handle_state_execution_result(
@@ -1052,6 +1336,7 @@ impl ChatContext {
         match result {
             Ok(state) => Ok(state),
             Err(e) => {
+                self.emit_event(OutputEvent::Error { message: e.to_string() });
                 let (reason, reason_desc) = get_error_reason(&e);
                 self.send_error_telemetry(database, telemetry, reason, Some(reason_desc), e.status_code())
                     .await;
@@ -1159,6 +1444,7 @@ impl ChatContext {
                                 prompt: None,
                                 show_summary: false,
                                 help: false,
+                                keep_recent_turns: None,
                             });
                         },
                         crate::api_client::ApiClientError::QuotaBreach { message, .. } => {
@@ -1259,7 +1545,8 @@ impl ChatContext {
     /// Compacts the conversation history, replacing the history with a summary generated by the
     /// model.
     ///
-    /// The last two user messages in the history are not included in the compaction process.
+    /// The last two user messages in the history are not included in the compaction process, or
+    /// the last `keep_recent_turns` turns if set.
     #[allow(clippy::too_many_arguments)]
     async fn compact_history(
         &mut self,
@@ -1270,6 +1557,7 @@ impl ChatContext {
         custom_prompt: Option<String>,
         show_summary: bool,
         help: bool,
+        keep_recent_turns: Option<usize>,
     ) -> Result<ChatState, ChatError> {
         let hist = self.conversation_state.history();
         debug!(?hist, "compacting history");
@@ -1279,7 +1567,7 @@ impl ChatContext {
             execute!(
                 self.output,
                 style::Print("\n"),
-                style::Print(compact_help_text()),
+                style::Print(compact_help_text(&self.conversation_state.auto_compact_config())),
                 style::Print("\n")
             )?;
 
@@ -1308,7 +1596,7 @@ impl ChatContext {
         // Send a request for summarizing the history.
         let summary_state = self
             .conversation_state
-            .create_summary_request(custom_prompt.as_ref())
+            .create_summary_request(custom_prompt.as_ref(), keep_recent_turns)
             .await;
         if self.interactive {
             execute!(self.output, cursor::Hide, style::Print("\n"))?;
@@ -1410,14 +1698,19 @@ impl ChatContext {
         )
         .await;
 
-        self.conversation_state.replace_history_with_summary(summary.clone());
+        self.conversation_state
+            .replace_history_with_summary(summary.clone(), keep_recent_turns);
 
         // Print output to the user.
         {
             execute!(
                 self.output,
                 style::SetForegroundColor(Color::Green),
-                style::Print("✔ Conversation history has been compacted successfully!\n\n"),
+                style::Print(match keep_recent_turns {
+                    Some(turns) =>
+                        format!("✔ Older turns have been summarized, keeping the last {turns} turns as they are.\n\n"),
+                    None => "✔ Conversation history has been compacted successfully!\n\n".to_string(),
+                }),
                 style::SetForegroundColor(Color::DarkGrey)
             )?;
 
@@ -1485,6 +1778,17 @@ impl ChatContext {
         }
     }
 
+    /// Exposes the current working directory and context paths to mcp servers as roots.
+    async fn update_mcp_roots(&self) {
+        let Some(context_manager) = &self.conversation_state.context_manager else {
+            return;
+        };
+        match context_manager.roots() {
+            Ok(roots) => self.conversation_state.tool_manager.update_roots(roots).await,
+            Err(e) => warn!("Failed to determine roots for mcp servers: {:?}", e),
+        }
+    }
+
     /// Read input from the user.
     async fn prompt_user(
         &mut self,
@@ -1496,12 +1800,23 @@ impl ChatContext {
         execute!(self.output, cursor::Show)?;
         let tool_uses = tool_uses.take().unwrap_or_default();
 
-        // Check token usage and display warnings if needed
+        // Check token usage and compact the conversation or display warnings if needed
         if pending_tool_index.is_none() {
-            // Only display warnings when not waiting for tool approval
+            // Only manage the context window when not waiting for tool approval
             if self.conversation_state.can_create_summary_request().await {
-                if let Err(e) = self.display_char_warnings().await {
-                    warn!("Failed to display character limit warnings: {}", e);
+                match self.manage_context_window().await {
+                    Ok(Some(keep_recent_turns)) => {
+                        return Ok(ChatState::CompactHistory {
+                            tool_uses: Some(tool_uses),
+                            pending_tool_index,
+                            prompt: None,
+                            show_summary: false,
+                            help: false,
+                            keep_recent_turns: Some(keep_recent_turns),
+                        });
+                    },
+                    Ok(None) => (),
+                    Err(e) => warn!("Failed to manage the context window: {}", e),
                 }
             }
         }
@@ -1548,6 +1863,9 @@ impl ChatContext {
             self.input_source
                 .put_skim_command_selector(database, Arc::new(context_manager.clone()), tool_names);
         }
+        // Likewise, context paths added or removed during the session are reflected in the roots
+        // exposed to mcp servers.
+        self.update_mcp_roots().await;
         execute!(
             self.output,
             style::SetForegroundColor(Color::Reset),
@@ -1634,6 +1952,11 @@ impl ChatContext {
                         .append_prompts(prompts)
                         .ok_or(ChatError::Custom("Prompt append failed".into()))?;
                 }
+                self.conversation_state.start_turn(user_input.clone());
+                if !self.pending_resources.is_empty() {
+                    let resources = std::mem::take(&mut self.pending_resources);
+                    user_input = format!("{}{}", format_resource_context(resources.iter()), user_input);
+                }
 
                 // Otherwise continue with normal chat on 'n' or other responses
                 self.tool_use_status = ToolUseStatus::Idle;
@@ -1753,6 +2076,7 @@ impl ChatContext {
                     prompt,
                     show_summary,
                     help,
+                    None,
                 )
                 .await?
             },
@@ -2008,19 +2332,8 @@ impl ChatContext {
                                     style::SetForegroundColor(Color::DarkYellow),
                                     style::Print("\n    🔧 Hooks:\n")
                                 )?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.global_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.global_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                print_hook_sections(&mut self.output, &context_manager.global_config.hooks)
+                                    .map_err(map_chat_error)?;
                             }
 
                             // Display profile context
@@ -2067,18 +2380,8 @@ impl ChatContext {
                                     style::SetForegroundColor(Color::DarkYellow),
                                     style::Print("    🔧 Hooks:\n")
                                 )?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                print_hook_sections(&mut self.output, &context_manager.profile_config.hooks)
+                                    .map_err(map_chat_error)?;
                                 execute!(self.output, style::Print("\n"))?;
                             }
 
@@ -2321,17 +2624,14 @@ impl ChatContext {
                                         name,
                                         trigger,
                                         command,
+                                        matcher,
                                         global,
                                     } => {
-                                        let trigger = if trigger == "conversation_start" {
-                                            HookTrigger::ConversationStart
-                                        } else {
-                                            HookTrigger::PerPrompt
-                                        };
+                                        let trigger = trigger.parse::<HookTrigger>().map_err(map_chat_error)?;
+                                        let mut hook = Hook::new_inline_hook(trigger, command);
+                                        hook.matcher = matcher;
 
-                                        let result = context_manager
-                                            .add_hook(name.clone(), Hook::new_inline_hook(trigger, command), global)
-                                            .await;
+                                        let result = context_manager.add_hook(name.clone(), hook, global).await;
                                         match result {
                                             Ok(_) => {
                                                 execute!(
@@ -2484,18 +2784,8 @@ impl ChatContext {
                                     style::SetAttribute(Attribute::Reset),
                                 )?;
 
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.global_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.global_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                print_hook_sections(&mut self.output, &context_manager.global_config.hooks)
+                                    .map_err(map_chat_error)?;
 
                                 queue!(
                                     self.output,
@@ -2505,18 +2795,8 @@ impl ChatContext {
                                     style::SetAttribute(Attribute::Reset),
                                 )?;
 
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::ConversationStart,
-                                )
-                                .map_err(map_chat_error)?;
-                                print_hook_section(
-                                    &mut self.output,
-                                    &context_manager.profile_config.hooks,
-                                    HookTrigger::PerPrompt,
-                                )
-                                .map_err(map_chat_error)?;
+                                print_hook_sections(&mut self.output, &context_manager.profile_config.hooks)
+                                    .map_err(map_chat_error)?;
 
                                 execute!(
                                     self.output,
@@ -2740,7 +3020,7 @@ impl ChatContext {
                                                 "- {}{:>width$}{}\n",
                                                 spec.name,
                                                 "",
-                                                self.tool_permissions.display_label(&spec.name),
+                                                self.tool_permission_label(&spec.name),
                                                 width = width
                                             )
                                             .as_str(),
@@ -2773,6 +3053,48 @@ impl ChatContext {
                             }
                         }
 
+                        let profile = self
+                            .conversation_state
+                            .context_manager
+                            .as_ref()
+                            .map(|cm| cm.current_profile.clone());
+                        match CommandPolicy::load(&self.ctx, profile.as_deref()).await {
+                            Ok(policy) if !policy.rules.is_empty() => {
+                                queue!(
+                                    self.output,
+                                    style::SetAttribute(Attribute::Bold),
+                                    style::Print("Command policy (execute_bash)"),
+                                    style::SetAttribute(Attribute::Reset),
+                                    style::Print("\n"),
+                                    style::Print("▔".repeat(terminal_width)),
+                                )?;
+                                for rule in &policy.rules {
+                                    let action = match rule.action {
+                                        PolicyAction::Allow => rule.action.to_string().green(),
+                                        PolicyAction::Ask => rule.action.to_string().yellow(),
+                                        PolicyAction::Deny => rule.action.to_string().red(),
+                                    };
+                                    queue!(
+                                        self.output,
+                                        style::Print(format!("- {:<6}{} ", action, rule.matcher_str())),
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print(format!("({})\n", rule.source)),
+                                        style::SetForegroundColor(Color::Reset),
+                                    )?;
+                                }
+                                queue!(self.output, style::Print("\n"))?;
+                            },
+                            Ok(_) => {},
+                            Err(err) => {
+                                queue!(
+                                    self.output,
+                                    style::SetForegroundColor(Color::Red),
+                                    style::Print(format!("Failed to load the command policy: {err}\n")),
+                                    style::SetForegroundColor(Color::Reset),
+                                )?;
+                            },
+                        }
+
                         queue!(
                             self.output,
                             style::Print("\nTrusted tools will run without confirmation."),
@@ -3022,107 +3344,530 @@ impl ChatContext {
                     skip_printing_tools: true,
                 }
             },
-            Command::Usage => {
-                let state = self.conversation_state.backend_conversation_state(true, true).await;
-
-                if !state.dropped_context_files.is_empty() {
-                    execute!(
-                        self.output,
-                        style::SetForegroundColor(Color::DarkYellow),
-                        style::Print("\nSome context files are dropped due to size limit, please run "),
-                        style::SetForegroundColor(Color::DarkGreen),
-                        style::Print("/context show "),
-                        style::SetForegroundColor(Color::DarkYellow),
-                        style::Print("to learn more.\n"),
-                        style::SetForegroundColor(style::Color::Reset)
-                    )?;
-                }
-
-                let data = state.calculate_conversation_size();
-                let tool_specs_json: String = state
-                    .tools
-                    .values()
-                    .filter_map(|s| serde_json::to_string(s).ok())
-                    .collect();
-
-                let context_token_count: TokenCount = data.context_messages.into();
-                let assistant_token_count: TokenCount = data.assistant_messages.into();
-                let user_token_count: TokenCount = data.user_messages.into();
-                let tools_char_count: CharCount = tool_specs_json.len().into(); // usize → CharCount
-                let tools_token_count: TokenCount = tools_char_count.into(); // CharCount → TokenCount
-                let total_token_used: TokenCount =
-                    (data.context_messages + data.user_messages + data.assistant_messages + tools_char_count).into();
-                let window_width = self.terminal_width();
-                // set a max width for the progress bar for better aesthetic
-                let progress_bar_width = std::cmp::min(window_width, 80);
-
-                let context_width = ((context_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-                let assistant_width = ((assistant_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-                let tools_width = ((tools_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-                let user_width = ((user_token_count.value() as f64 / CONTEXT_WINDOW_SIZE as f64)
-                    * progress_bar_width as f64) as usize;
-
-                let left_over_width = progress_bar_width
-                    - std::cmp::min(
-                        context_width + assistant_width + user_width + tools_width,
-                        progress_bar_width,
-                    );
-
-                let is_overflow = (context_width + assistant_width + user_width + tools_width) > progress_bar_width;
-
-                if is_overflow {
-                    queue!(
-                        self.output,
-                        style::Print(format!(
-                            "\nCurrent context window ({} of {}k tokens used)\n",
-                            total_token_used,
-                            CONTEXT_WINDOW_SIZE / 1000
-                        )),
-                        style::SetForegroundColor(Color::DarkRed),
-                        style::Print("█".repeat(progress_bar_width)),
-                        style::SetForegroundColor(Color::Reset),
-                        style::Print(" "),
-                        style::Print(format!(
-                            "{:.2}%",
-                            (total_token_used.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
-                        )),
-                    )?;
-                } else {
-                    queue!(
-                        self.output,
-                        style::Print(format!(
-                            "\nCurrent context window ({} of {}k tokens used)\n",
-                            total_token_used,
-                            CONTEXT_WINDOW_SIZE / 1000
-                        )),
-                        // Context files
-                        style::SetForegroundColor(Color::DarkCyan),
-                        // add a nice visual to mimic "tiny" progress, so the overral progress bar doesn't look too
-                        // empty
-                        style::Print("|".repeat(if context_width == 0 && *context_token_count > 0 {
-                            1
-                        } else {
-                            0
-                        })),
-                        style::Print("█".repeat(context_width)),
-                        // Tools
-                        style::SetForegroundColor(Color::DarkRed),
-                        style::Print("|".repeat(if tools_width == 0 && *tools_token_count > 0 {
-                            1
-                        } else {
-                            0
-                        })),
-                        style::Print("█".repeat(tools_width)),
-                        // Assistant responses
-                        style::SetForegroundColor(Color::Blue),
-                        style::Print("|".repeat(if assistant_width == 0 && *assistant_token_count > 0 {
-                            1
+            Command::Resources { subcommand } => {
+                let is_attach = matches!(subcommand, Some(ResourcesSubcommand::Attach { .. }));
+                match subcommand {
+                    Some(ResourcesSubcommand::Help) => {
+                        queue!(self.output, style::Print(command::ResourcesSubcommand::help_text()))?;
+                    },
+                    Some(ResourcesSubcommand::Read { get_command } | ResourcesSubcommand::Attach { get_command }) => {
+                        let resource = match self.conversation_state.tool_manager.read_resource(get_command).await {
+                            Ok(resource) => resource,
+                            Err(e) => {
+                                match e {
+                                    GetResourceError::AmbiguousResource(name, alt_msg) => {
+                                        queue!(
+                                            self.output,
+                                            style::Print("\n"),
+                                            style::SetForegroundColor(Color::Yellow),
+                                            style::Print("Resource "),
+                                            style::SetForegroundColor(Color::Cyan),
+                                            style::Print(name),
+                                            style::SetForegroundColor(Color::Yellow),
+                                            style::Print(" is ambiguous. Use one of the following "),
+                                            style::SetForegroundColor(Color::Cyan),
+                                            style::Print(alt_msg),
+                                            style::SetForegroundColor(Color::Reset),
+                                        )?;
+                                    },
+                                    GetResourceError::ResourceNotFound(name) => {
+                                        queue!(
+                                            self.output,
+                                            style::Print("\n"),
+                                            style::SetForegroundColor(Color::Yellow),
+                                            style::Print("Resource "),
+                                            style::SetForegroundColor(Color::Cyan),
+                                            style::Print(name),
+                                            style::SetForegroundColor(Color::Yellow),
+                                            style::Print(" not found. Use "),
+                                            style::SetForegroundColor(Color::Cyan),
+                                            style::Print("/resources list"),
+                                            style::SetForegroundColor(Color::Yellow),
+                                            style::Print(" to see available resources.\n"),
+                                            style::SetForegroundColor(Color::Reset),
+                                        )?;
+                                    },
+                                    e => {
+                                        queue!(
+                                            self.output,
+                                            style::Print("\n"),
+                                            style::SetForegroundColor(Color::Red),
+                                            style::Print(format!("{e}\n")),
+                                            style::SetForegroundColor(Color::Reset),
+                                        )?;
+                                    },
+                                }
+                                execute!(self.output, style::Print("\n"))?;
+                                return Ok(ChatState::PromptUser {
+                                    tool_uses: Some(tool_uses),
+                                    pending_tool_index,
+                                    skip_printing_tools: true,
+                                });
+                            },
+                        };
+                        if is_attach {
+                            queue!(
+                                self.output,
+                                style::Print("\nAttached "),
+                                style::SetForegroundColor(Color::Green),
+                                style::Print(&resource.uri),
+                                style::SetForegroundColor(Color::Reset),
+                                style::Print(" from "),
+                                style::SetForegroundColor(Color::Blue),
+                                style::Print(&resource.server_name),
+                                style::SetForegroundColor(Color::Reset),
+                                style::Print(". It will be included as context with your next prompt.\n"),
+                            )?;
+                            self.pending_resources.push(resource);
                         } else {
-                            0
-                        })),
+                            let terminal_width = self.terminal_width();
+                            for contents in &resource.contents {
+                                queue!(
+                                    self.output,
+                                    style::Print("\n"),
+                                    style::SetAttribute(Attribute::Bold),
+                                    style::Print(&contents.uri),
+                                    style::SetAttribute(Attribute::Reset),
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(format!(
+                                        " ({})\n",
+                                        contents.mime_type.as_deref().unwrap_or("unknown type")
+                                    )),
+                                    style::SetForegroundColor(Color::Reset),
+                                    style::Print(format!("{}\n", "▔".repeat(terminal_width))),
+                                )?;
+                                match &contents.text {
+                                    Some(text) => queue!(self.output, style::Print(text), style::Print("\n"))?,
+                                    None => queue!(
+                                        self.output,
+                                        style::SetForegroundColor(Color::DarkGrey),
+                                        style::Print("Binary content not displayed\n"),
+                                        style::SetForegroundColor(Color::Reset),
+                                    )?,
+                                }
+                            }
+                        }
+                    },
+                    subcommand => {
+                        let search_word = match subcommand {
+                            Some(ResourcesSubcommand::List { search_word }) => search_word,
+                            _ => None,
+                        };
+                        let search_word = search_word.as_deref().unwrap_or("");
+                        let terminal_width = self.terminal_width();
+                        let resources = self.conversation_state.tool_manager.resources.read().await;
+                        queue!(
+                            self.output,
+                            style::Print("\n"),
+                            style::SetAttribute(Attribute::Bold),
+                            style::Print("Usage: "),
+                            style::SetAttribute(Attribute::Reset),
+                            style::Print("You can attach a resource as context for your next prompt by typing "),
+                            style::SetAttribute(Attribute::Bold),
+                            style::SetForegroundColor(Color::Green),
+                            style::Print("'/resources attach <resource> [...args]'"),
+                            style::SetForegroundColor(Color::Reset),
+                            style::SetAttribute(Attribute::Reset),
+                            style::Print("\n\n"),
+                        )?;
+                        let mut servers = resources
+                            .iter()
+                            .filter(|(_, r)| !r.resources.is_empty() || !r.resource_templates.is_empty())
+                            .collect::<Vec<_>>();
+                        servers.sort_by_key(|(server_name, _)| server_name.as_str());
+                        if servers.is_empty() {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print("No mcp server has offered any resources.\n"),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        }
+                        for (i, (server_name, server_resources)) in servers.into_iter().enumerate() {
+                            if i > 0 {
+                                queue!(self.output, style::Print("\n"))?;
+                            }
+                            queue!(
+                                self.output,
+                                style::SetAttribute(Attribute::Bold),
+                                style::Print(server_name),
+                                style::Print(" (MCP):"),
+                                style::SetAttribute(Attribute::Reset),
+                                style::Print("\n"),
+                                style::Print(format!("{}\n", "▔".repeat(terminal_width))),
+                            )?;
+                            let matched = server_resources
+                                .resources
+                                .iter()
+                                .map(|r| (&r.name, &r.uri))
+                                .chain(
+                                    server_resources
+                                        .resource_templates
+                                        .iter()
+                                        .map(|t| (&t.name, &t.uri_template)),
+                                )
+                                .filter(|(name, uri)| name.contains(search_word) || uri.contains(search_word));
+                            for (name, uri) in matched {
+                                queue!(
+                                    self.output,
+                                    style::Print("- "),
+                                    style::Print(name),
+                                    style::Print(" "),
+                                    style::SetForegroundColor(Color::DarkGrey),
+                                    style::Print(uri),
+                                    style::SetForegroundColor(Color::Reset),
+                                    style::Print("\n"),
+                                )?;
+                            }
+                        }
+                    },
+                }
+                execute!(self.output, style::Print("\n"))?;
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Undo
+            | Command::Checkpoint {
+                subcommand: CheckpointSubcommand::Restore { .. },
+            } => {
+                let result = match command {
+                    Command::Checkpoint {
+                        subcommand: CheckpointSubcommand::Restore { id },
+                    } => self.conversation_state.checkpoints.restore(&self.ctx, id).await,
+                    _ => self.conversation_state.checkpoints.undo(&self.ctx).await,
+                };
+                match result {
+                    Ok(paths) => {
+                        if let Ok(cwd) = self.ctx.env().current_dir() {
+                            database.set_conversation_by_path(cwd, &self.conversation_state).ok();
+                        }
+                        let cwd = self.ctx.env().current_dir().unwrap_or_default();
+                        queue!(
+                            self.output,
+                            style::SetForegroundColor(Color::Green),
+                            style::Print(format!("\n✔ Restored {} file(s):\n", paths.len())),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                        for path in paths {
+                            queue!(
+                                self.output,
+                                style::Print(format!("- {}\n", tools::format_path(&cwd, &path)))
+                            )?;
+                        }
+                        execute!(self.output, style::Print("\n"))?;
+                    },
+                    Err(err) => {
+                        execute!(
+                            self.output,
+                            style::SetForegroundColor(Color::Red),
+                            style::Print(format!("\n{err}\n\n")),
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    },
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Checkpoint { subcommand } => {
+                match subcommand {
+                    CheckpointSubcommand::Help => {
+                        execute!(
+                            self.output,
+                            style::Print("\n"),
+                            style::Print(CheckpointSubcommand::help_text()),
+                            style::Print("\n")
+                        )?;
+                    },
+                    _ => {
+                        let cwd = self.ctx.env().current_dir().unwrap_or_default();
+                        let mut checkpoints = self.conversation_state.checkpoints.checkpoints().rev().peekable();
+                        if checkpoints.peek().is_none() {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print("\nNo files have been edited in this conversation.\n"),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        }
+                        for checkpoint in checkpoints {
+                            let prompt = checkpoint.prompt.lines().next().unwrap_or_default();
+                            queue!(
+                                self.output,
+                                style::Print("\n"),
+                                style::SetAttribute(Attribute::Bold),
+                                style::Print(format!("[{}] ", checkpoint.id)),
+                                style::SetAttribute(Attribute::Reset),
+                                style::Print(truncate_safe(prompt, 60)),
+                                style::SetForegroundColor(Color::DarkGrey),
+                                style::Print(format!(
+                                    " ({})\n",
+                                    checkpoint
+                                        .created_at
+                                        .to_offset(crate::util::local_offset())
+                                        .format(time::macros::format_description!("[hour]:[minute]:[second]"))
+                                        .unwrap_or_default()
+                                )),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                            for file in &checkpoint.files {
+                                queue!(
+                                    self.output,
+                                    style::Print(format!(
+                                        "  {} {}\n",
+                                        if file.contents_hash.is_some() { "M" } else { "A" },
+                                        tools::format_path(&cwd, &file.path)
+                                    ))
+                                )?;
+                            }
+                        }
+                        execute!(self.output, style::Print("\n"))?;
+                    },
+                }
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Knowledge { subcommand } => {
+                execute!(self.output, style::Print("\n"))?;
+                let result = self.handle_knowledge_command(subcommand).await;
+                if self.interactive {
+                    execute!(self.output, cursor::Show)?;
+                }
+                if let Err(err) = result {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("{err}\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+
+                ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Sessions { search } => {
+                execute!(self.output, style::Print("\n"))?;
+                if let Err(err) = self.handle_sessions_command(search, database).await {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("{err}\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Mode { subcommand } => {
+                let mode = match &subcommand {
+                    ModeSubcommand::Help => {
+                        execute!(
+                            self.output,
+                            style::Print("\n"),
+                            style::Print(ModeSubcommand::help_text()),
+                            style::Print("\n\n")
+                        )?;
+                        return Ok(ChatState::PromptUser {
+                            tool_uses: None,
+                            pending_tool_index: None,
+                            skip_printing_tools: true,
+                        });
+                    },
+                    ModeSubcommand::Toggle => self.conversation_state.mode().toggled(),
+                    ModeSubcommand::Normal | ModeSubcommand::Approve => ChatMode::Normal,
+                    ModeSubcommand::Plan => ChatMode::Plan,
+                };
+                self.conversation_state.set_mode(mode).await;
+                execute!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("\n✔ Switched to {mode} mode\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+
+                if subcommand == ModeSubcommand::Approve {
+                    ChatState::HandleInput {
+                        input: APPROVE_PLAN_PROMPT.to_string(),
+                        tool_uses: None,
+                        pending_tool_index: None,
+                    }
+                } else {
+                    ChatState::PromptUser {
+                        tool_uses: None,
+                        pending_tool_index: None,
+                        skip_printing_tools: true,
+                    }
+                }
+            },
+            Command::Agent { subcommand } => {
+                execute!(self.output, style::Print("\n"))?;
+                if let Err(err) = self.handle_agent_command(subcommand).await {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("{err}\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::SubAgents { id } => {
+                execute!(self.output, style::Print("\n"))?;
+                if let Err(err) = self.handle_sub_agents_command(id) {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("{err}\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Fork { .. } | Command::Branches | Command::Switch { .. } | Command::Rewind { .. } => {
+                execute!(self.output, style::Print("\n"))?;
+                if let Err(err) = self.handle_branch_command(command, database).await {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Red),
+                        style::Print(format!("{err}\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                execute!(self.output, style::Print("\n"))?;
+
+                ChatState::PromptUser {
+                    tool_uses: None,
+                    pending_tool_index: None,
+                    skip_printing_tools: true,
+                }
+            },
+            Command::Usage => {
+                let state = self.conversation_state.backend_conversation_state(true, true).await;
+
+                if !state.dropped_context_files.is_empty() {
+                    execute!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkYellow),
+                        style::Print("\nSome context files are dropped due to size limit, please run "),
+                        style::SetForegroundColor(Color::DarkGreen),
+                        style::Print("/context show "),
+                        style::SetForegroundColor(Color::DarkYellow),
+                        style::Print("to learn more.\n"),
+                        style::SetForegroundColor(style::Color::Reset)
+                    )?;
+                }
+
+                let data = state.calculate_conversation_size();
+                let breakdown = state.calculate_token_breakdown();
+                let context_window = state.context_window_size();
+
+                let context_token_count = data.context_messages;
+                let assistant_token_count = data.assistant_messages;
+                let user_token_count = data.user_messages;
+                let tools_token_count = data.tools;
+                let total_token_used = data.token_count();
+                let window_width = self.terminal_width();
+                // set a max width for the progress bar for better aesthetic
+                let progress_bar_width = std::cmp::min(window_width, 80);
+
+                let context_width =
+                    ((context_token_count.value() as f64 / context_window as f64) * progress_bar_width as f64) as usize;
+                let assistant_width = ((assistant_token_count.value() as f64 / context_window as f64)
+                    * progress_bar_width as f64) as usize;
+                let tools_width =
+                    ((tools_token_count.value() as f64 / context_window as f64) * progress_bar_width as f64) as usize;
+                let user_width =
+                    ((user_token_count.value() as f64 / context_window as f64) * progress_bar_width as f64) as usize;
+
+                let left_over_width = progress_bar_width
+                    - std::cmp::min(
+                        context_width + assistant_width + user_width + tools_width,
+                        progress_bar_width,
+                    );
+
+                let is_overflow = (context_width + assistant_width + user_width + tools_width) > progress_bar_width;
+
+                if is_overflow {
+                    queue!(
+                        self.output,
+                        style::Print(format!(
+                            "\nCurrent context window ({} of {}k tokens used)\n",
+                            total_token_used,
+                            context_window / 1000
+                        )),
+                        style::SetForegroundColor(Color::DarkRed),
+                        style::Print("█".repeat(progress_bar_width)),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(" "),
+                        style::Print(format!(
+                            "{:.2}%",
+                            (total_token_used.value() as f32 / context_window as f32) * 100.0
+                        )),
+                    )?;
+                } else {
+                    queue!(
+                        self.output,
+                        style::Print(format!(
+                            "\nCurrent context window ({} of {}k tokens used)\n",
+                            total_token_used,
+                            context_window / 1000
+                        )),
+                        // Context files
+                        style::SetForegroundColor(Color::DarkCyan),
+                        // add a nice visual to mimic "tiny" progress, so the overral progress bar doesn't look too
+                        // empty
+                        style::Print("|".repeat(if context_width == 0 && *context_token_count > 0 {
+                            1
+                        } else {
+                            0
+                        })),
+                        style::Print("█".repeat(context_width)),
+                        // Tools
+                        style::SetForegroundColor(Color::DarkRed),
+                        style::Print("|".repeat(if tools_width == 0 && *tools_token_count > 0 {
+                            1
+                        } else {
+                            0
+                        })),
+                        style::Print("█".repeat(tools_width)),
+                        // Assistant responses
+                        style::SetForegroundColor(Color::Blue),
+                        style::Print("|".repeat(if assistant_width == 0 && *assistant_token_count > 0 {
+                            1
+                        } else {
+                            0
+                        })),
                         style::Print("█".repeat(assistant_width)),
                         // User prompts
                         style::SetForegroundColor(Color::Magenta),
@@ -3134,7 +3879,7 @@ impl ChatContext {
                         style::SetForegroundColor(Color::Reset),
                         style::Print(format!(
                             "{:.2}%",
-                            (total_token_used.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                            (total_token_used.value() as f32 / context_window as f32) * 100.0
                         )),
                     )?;
                 }
@@ -3148,36 +3893,83 @@ impl ChatContext {
                     style::Print("█ Context files: "),
                     style::SetForegroundColor(Color::Reset),
                     style::Print(format!(
-                        "~{} tokens ({:.2}%)\n",
+                        "{} tokens ({:.2}%)\n",
                         context_token_count,
-                        (context_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                        (context_token_count.value() as f32 / context_window as f32) * 100.0
                     )),
                     style::SetForegroundColor(Color::DarkRed),
                     style::Print("█ Tools:    "),
                     style::SetForegroundColor(Color::Reset),
                     style::Print(format!(
-                        " ~{} tokens ({:.2}%)\n",
+                        " {} tokens ({:.2}%)\n",
                         tools_token_count,
-                        (tools_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                        (tools_token_count.value() as f32 / context_window as f32) * 100.0
                     )),
                     style::SetForegroundColor(Color::Blue),
                     style::Print("█ Q responses: "),
                     style::SetForegroundColor(Color::Reset),
                     style::Print(format!(
-                        "  ~{} tokens ({:.2}%)\n",
+                        "  {} tokens ({:.2}%)\n",
                         assistant_token_count,
-                        (assistant_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                        (assistant_token_count.value() as f32 / context_window as f32) * 100.0
                     )),
                     style::SetForegroundColor(Color::Magenta),
                     style::Print("█ Your prompts: "),
                     style::SetForegroundColor(Color::Reset),
                     style::Print(format!(
-                        " ~{} tokens ({:.2}%)\n\n",
+                        " {} tokens ({:.2}%)\n\n",
                         user_token_count,
-                        (user_token_count.value() as f32 / CONTEXT_WINDOW_SIZE as f32) * 100.0
+                        (user_token_count.value() as f32 / context_window as f32) * 100.0
                     )),
                 )?;
 
+                let recent_turns: Vec<_> = breakdown.history_turns.iter().rev().cloned().collect();
+                for (title, color, items) in [
+                    ("Context files", Color::DarkCyan, &breakdown.context_files),
+                    ("Hook outputs", Color::DarkCyan, &breakdown.hook_outputs),
+                    ("Tools", Color::DarkRed, &breakdown.tools),
+                    ("Conversation turns (most recent first)", Color::Magenta, &recent_turns),
+                ] {
+                    print_token_breakdown(&mut self.output, title, color, items, context_window)?;
+                }
+
+                let auto_compact = self.conversation_state.auto_compact_config();
+                let auto_compact_stats = self.conversation_state.auto_compact_stats();
+                queue!(
+                    self.output,
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print("Auto compaction: "),
+                    style::SetAttribute(Attribute::Reset),
+                )?;
+                if auto_compact.enabled {
+                    queue!(
+                        self.output,
+                        style::Print(format!(
+                            "above {}% ({}k tokens), keeping the last {} turns verbatim\n",
+                            auto_compact.threshold_percent,
+                            auto_compact.threshold_tokens(context_window) / 1000,
+                            auto_compact.keep_recent_turns
+                        )),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(
+                            "{} tool results over {} tokens elided, {} turns summarized so far\n",
+                            auto_compact_stats.elided_tool_results,
+                            auto_compact.max_tool_result_tokens,
+                            auto_compact_stats.summarized_turns
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                } else {
+                    queue!(
+                        self.output,
+                        style::Print("disabled for this profile, run "),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print("/compact help"),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(" to learn how to enable it\n"),
+                    )?;
+                }
+
                 queue!(
                     self.output,
                     style::SetAttribute(Attribute::Bold),
@@ -3440,43 +4232,527 @@ impl ChatContext {
                                 )?;
                             }
                         },
-                        Err(err) => {
+                        Err(err) => {
+                            queue!(
+                                self.output,
+                                style::SetForegroundColor(Color::Red),
+                                style::Print(format!("Failed to get subscription status: {}\n\n", err)),
+                                style::SetForegroundColor(Color::Reset),
+                            )?;
+                        },
+                    }
+
+                    let url = format!(
+                        "https://{}.console.aws.amazon.com/amazonq/developer/home#/subscriptions",
+                        database
+                            .get_idc_region()
+                            .ok()
+                            .flatten()
+                            .unwrap_or("us-east-1".to_string())
+                    );
+                    if is_remote() || crate::util::open::open_url_async(&url).await.is_err() {
+                        execute!(
+                            self.output,
+                            style::Print(format!("Open this URL to manage your subscription: {}\n\n", url.blue())),
+                            style::ResetColor,
+                            style::SetForegroundColor(Color::Reset),
+                        )?;
+                    }
+                } else {
+                    self.upgrade_to_pro(database).await?;
+                }
+
+                return Ok(ChatState::PromptUser {
+                    tool_uses: Some(tool_uses),
+                    pending_tool_index,
+                    skip_printing_tools: true,
+                });
+            },
+        })
+    }
+
+    async fn handle_knowledge_command(&mut self, subcommand: KnowledgeSubcommand) -> Result<()> {
+        match subcommand {
+            KnowledgeSubcommand::Help => {
+                queue!(self.output, style::Print(KnowledgeSubcommand::help_text()))?;
+            },
+            KnowledgeSubcommand::Show => {
+                let store = self.knowledge_store().await?;
+                let knowledge_bases = store.knowledge_bases().await?;
+                if knowledge_bases.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("No knowledge bases. Use /knowledge add <path> to create one.\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                for kb in knowledge_bases {
+                    queue!(
+                        self.output,
+                        style::SetAttribute(Attribute::Bold),
+                        style::Print(&kb.name),
+                        style::SetAttribute(Attribute::Reset),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(
+                            " ({} items, updated {})\n",
+                            kb.item_count,
+                            kb.updated_at.format("%Y-%m-%d %H:%M")
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                        style::Print(format!("  {}\n", kb.source_path.unwrap_or_default())),
+                    )?;
+                }
+            },
+            KnowledgeSubcommand::Add { path, name } => {
+                let store = self.knowledge_store().await?;
+                let path = tools::sanitize_path_tool_arg(&self.ctx, &path);
+                if !path.exists() {
+                    bail!("{} does not exist", path.display());
+                }
+                let name = name.unwrap_or_else(|| tools::knowledge::default_name(&path));
+                let spinner_text = format!("Indexing {}...", path.display());
+                let kb = with_spinner(self.interactive, &mut self.output, &spinner_text, || {
+                    store.add(name, &path, None::<fn(semantic_search_client::ProgressStatus)>)
+                })
+                .await?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("✔ Indexed {} items into '{}'\n", kb.item_count, kb.name)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            KnowledgeSubcommand::Remove { name } => {
+                let store = self.knowledge_store().await?;
+                let kb = store.remove(&self.ctx, name).await?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("✔ Removed the knowledge base '{}'\n", kb.name)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            KnowledgeSubcommand::Search { query } => {
+                let store = self.knowledge_store().await?;
+                let results = with_spinner(self.interactive, &mut self.output, "Searching...", || {
+                    store.search(&self.ctx, query, None, knowledge_store::DEFAULT_SEARCH_LIMIT)
+                })
+                .await?;
+                if results.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print("No results found.\n"),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                let cwd = self.ctx.env().current_dir().unwrap_or_default();
+                for result in results {
+                    let source = match &result.path {
+                        Some(path) => tools::format_path(&cwd, path),
+                        None => result.knowledge_base.clone(),
+                    };
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(source),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(" [{}]\n", result.knowledge_base)),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    for line in result.text.lines().filter(|l| !l.trim().is_empty()).take(5) {
+                        queue!(self.output, style::Print(format!("  {}\n", truncate_safe(line, 100))))?;
+                    }
+                    queue!(self.output, style::Print("\n"))?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Loads the knowledge bases, which takes a while the first time as the embedding model is
+    /// loaded.
+    async fn knowledge_store(&mut self) -> Result<KnowledgeStore> {
+        with_spinner(self.interactive, &mut self.output, "Loading knowledge bases...", || {
+            KnowledgeStore::get(&self.ctx)
+        })
+        .await
+    }
+
+    /// Handles `/fork`, `/branches`, `/switch` and `/rewind`. The active branch of the conversation
+    /// in the current directory is persisted with [Database::set_conversation_by_path], and the
+    /// branches it leaves with [Database::set_conversation_branch].
+    async fn handle_branch_command(&mut self, command: Command, database: &mut Database) -> Result<()> {
+        let cwd = self.ctx.env().current_dir()?;
+        match command {
+            Command::Fork { name } => {
+                let branches = database.get_conversation_branches(&cwd)?;
+                let name = match name {
+                    Some(name) => name,
+                    None => (branches.len() + 1..)
+                        .map(|i| format!("branch-{i}"))
+                        .find(|name| !branches.iter().any(|b| b.branch() == name))
+                        .expect("an unused branch name exists"),
+                };
+                if name == self.conversation_state.branch() || branches.iter().any(|b| b.branch() == name) {
+                    bail!("A branch named '{}' already exists", name);
+                }
+
+                // Keep the branch we're leaving before moving onto the new one.
+                database.set_conversation_branch(&cwd, &self.conversation_state)?;
+                let parent = self.conversation_state.branch().to_string();
+                self.conversation_state.fork(&name);
+                database.set_conversation_by_path(&cwd, &self.conversation_state)?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("✔ Forked '{}' into the new branch '{}'\n", parent, name)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            Command::Branches => {
+                let mut branches = database.get_conversation_branches(&cwd)?;
+                if !branches.iter().any(|b| b.branch() == self.conversation_state.branch()) {
+                    branches.push(self.conversation_state.clone());
+                }
+                for branch in branches {
+                    let current = branch.branch() == self.conversation_state.branch();
+                    let turns = if current {
+                        self.conversation_state.turn_count()
+                    } else {
+                        branch.turn_count()
+                    };
+                    queue!(
+                        self.output,
+                        style::Print(if current { "* " } else { "  " }),
+                        style::SetAttribute(Attribute::Bold),
+                        style::Print(branch.branch()),
+                        style::SetAttribute(Attribute::Reset),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(" ({} prompts)\n", turns)),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+            },
+            Command::Switch { name } => {
+                if name == self.conversation_state.branch() {
+                    bail!("Already on the branch '{}'", name);
+                }
+                let Some(new_state) = database.get_conversation_branch(&cwd, &name)? else {
+                    bail!("No branch named '{}'. Use /branches to list them.", name);
+                };
+
+                database.set_conversation_branch(&cwd, &self.conversation_state)?;
+                self.replace_conversation_state(new_state).await;
+                database.set_conversation_by_path(&cwd, &self.conversation_state)?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("✔ Switched to the branch '{}'\n", name)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            Command::Rewind { turns } => {
+                let dropped = self.conversation_state.rewind(turns);
+                // The files edited during the dropped turns are restored along with them.
+                let restored = self.conversation_state.checkpoints.rewind(&self.ctx, dropped).await?;
+                database.set_conversation_by_path(&cwd, &self.conversation_state)?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!(
+                        "✔ Dropped the last {} prompt(s) and their responses\n",
+                        dropped
+                    )),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                if !restored.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format!("✔ Restored {} file(s):\n", restored.len())),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    for path in restored {
+                        queue!(
+                            self.output,
+                            style::Print(format!("- {}\n", tools::format_path(&cwd, &path)))
+                        )?;
+                    }
+                }
+            },
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Handles `/sessions`, letting the user pick a previous session to resume in place of the
+    /// current conversation.
+    async fn handle_sessions_command(&mut self, search: Option<String>, database: &mut Database) -> Result<()> {
+        let sessions = database.list_sessions(search.as_deref(), Some(sessions::SESSION_LIST_LIMIT))?;
+        if sessions.is_empty() || !self.interactive {
+            return sessions::print_sessions(&mut self.output, &sessions);
+        }
+
+        #[cfg(unix)]
+        let selected = skim_integration::select_session_with_skim(&sessions)?;
+        #[cfg(not(unix))]
+        let selected: Option<String> = {
+            sessions::print_sessions(&mut self.output, &sessions)?;
+            queue!(self.output, style::Print("\nResume one with q chat --resume=<id>\n"))?;
+            None
+        };
+
+        let Some(id) = selected else {
+            return Ok(());
+        };
+        if id == self.conversation_state.conversation_id() {
+            bail!("Already in this session");
+        }
+        let Some(new_state) = database.get_session(&id)? else {
+            bail!("No session found with the id '{}'", id);
+        };
+
+        let cwd = self.ctx.env().current_dir()?;
+        database.set_conversation_by_path(&cwd, &self.conversation_state)?;
+        let title = new_state.title().unwrap_or_default();
+        self.replace_conversation_state(new_state).await;
+        database.set_conversation_by_path(&cwd, &self.conversation_state)?;
+        queue!(
+            self.output,
+            style::SetForegroundColor(Color::Green),
+            style::Print(format!("✔ Resumed the session '{}'\n", title)),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        Ok(())
+    }
+
+    /// Handles `/agent`, listing the available agents, switching to another one, or showing the
+    /// definition of the current one.
+    async fn handle_agent_command(&mut self, subcommand: AgentSubcommand) -> Result<()> {
+        match subcommand {
+            AgentSubcommand::Help => {
+                queue!(
+                    self.output,
+                    style::Print(AgentSubcommand::help_text()),
+                    style::Print("\n")
+                )?;
+            },
+            AgentSubcommand::Schema => {
+                queue!(self.output, style::Print(AGENT_SCHEMA))?;
+            },
+            AgentSubcommand::Show => {
+                let Some(agent) = self.conversation_state.agent() else {
+                    bail!("No agent is selected. Use /agent swap <name> to select one.");
+                };
+                queue!(
+                    self.output,
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print(&agent.name),
+                    style::SetAttribute(Attribute::Reset),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(" ({})\n\n", agent.path.display())),
+                    style::SetForegroundColor(Color::Reset),
+                    style::Print(serde_json::to_string_pretty(&agent.config)?),
+                    style::Print("\n"),
+                )?;
+            },
+            AgentSubcommand::List => {
+                let agents = list_agents(&self.ctx).await?;
+                if agents.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(
+                            "No agents found. Agents are defined in .amazonq/agents/<name>.json or ~/.aws/amazonq/agents/<name>.json.\n"
+                        ),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                let current = self.conversation_state.agent().map(|agent| agent.name.clone());
+                for (name, source, agent) in agents {
+                    let is_current = current.as_deref() == Some(name.as_str());
+                    queue!(
+                        self.output,
+                        style::Print(if is_current { "* " } else { "  " }),
+                        style::SetAttribute(Attribute::Bold),
+                        style::Print(&name),
+                        style::SetAttribute(Attribute::Reset),
+                        style::SetForegroundColor(Color::DarkGrey),
+                        style::Print(format!(" ({source})")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                    match agent {
+                        Ok(agent) => {
+                            if let Some(description) = agent.config.description {
+                                queue!(self.output, style::Print(format!("  {description}")))?;
+                            }
+                        },
+                        Err(_) => {
                             queue!(
                                 self.output,
                                 style::SetForegroundColor(Color::Red),
-                                style::Print(format!("Failed to get subscription status: {}\n\n", err)),
+                                style::Print(format!("  invalid, see /agent swap {name}")),
                                 style::SetForegroundColor(Color::Reset),
                             )?;
                         },
                     }
-
-                    let url = format!(
-                        "https://{}.console.aws.amazon.com/amazonq/developer/home#/subscriptions",
-                        database
-                            .get_idc_region()
-                            .ok()
-                            .flatten()
-                            .unwrap_or("us-east-1".to_string())
-                    );
-                    if is_remote() || crate::util::open::open_url_async(&url).await.is_err() {
-                        execute!(
-                            self.output,
-                            style::Print(format!("Open this URL to manage your subscription: {}\n\n", url.blue())),
-                            style::ResetColor,
-                            style::SetForegroundColor(Color::Reset),
-                        )?;
-                    }
-                } else {
-                    self.upgrade_to_pro(database).await?;
+                    queue!(self.output, style::Print("\n"))?;
                 }
+            },
+            AgentSubcommand::Clear => {
+                let Some(name) = self.conversation_state.agent().map(|agent| agent.name.clone()) else {
+                    bail!("No agent is selected.");
+                };
+                self.conversation_state.set_agent(None).await?;
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("✔ Stopped using the agent '{name}'\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            },
+            AgentSubcommand::Swap { name } => {
+                let agent = Agent::load(&self.ctx, &name).await?;
+                let model_id = agent.config.model.as_deref().map(model_id_from_name).transpose()?;
+                // MCP servers are only launched at startup, so servers of the agent that are not
+                // running cannot be used until the chat is restarted.
+                let schema = &self.conversation_state.tool_manager.schema;
+                let missing_servers = agent
+                    .config
+                    .mcp_servers
+                    .iter()
+                    .flat_map(|servers| servers.keys())
+                    .filter(|server| {
+                        !schema
+                            .values()
+                            .any(|spec| spec.tool_origin == ToolOrigin::McpServer((*server).clone()))
+                    })
+                    .cloned()
+                    .collect::<Vec<_>>();
 
-                return Ok(ChatState::PromptUser {
-                    tool_uses: Some(tool_uses),
-                    pending_tool_index,
-                    skip_printing_tools: true,
-                });
+                let warning = agent.ignored_fields_warning();
+                self.conversation_state.set_agent(Some(agent)).await?;
+                if let Some(model_id) = model_id {
+                    self.conversation_state.model = Some(model_id);
+                }
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format!("✔ Switched to the agent '{}'\n", name)),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+                if let Some(warning) = warning {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print(format!("{warning}\n")),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
+                if !missing_servers.is_empty() {
+                    queue!(
+                        self.output,
+                        style::SetForegroundColor(Color::Yellow),
+                        style::Print(format!(
+                            "The MCP servers {} of this agent are not running. Restart with q chat --agent {} to launch them.\n",
+                            missing_servers.join(", "),
+                            name
+                        )),
+                        style::SetForegroundColor(Color::Reset),
+                    )?;
+                }
             },
-        })
+        }
+        Ok(())
+    }
+
+    /// Handles `/subagents`, listing the sub-agents run in this conversation or showing the
+    /// transcript of one of them.
+    fn handle_sub_agents_command(&mut self, id: Option<usize>) -> Result<()> {
+        let sub_agents = self.conversation_state.sub_agents();
+        let Some(id) = id else {
+            if sub_agents.is_empty() {
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print("No sub-agents have been run in this conversation.\n"),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            }
+            for sub_agent in sub_agents {
+                queue!(
+                    self.output,
+                    style::Print(if sub_agent.succeeded() {
+                        "✓ ".green()
+                    } else {
+                        "✗ ".red()
+                    }),
+                    style::SetAttribute(Attribute::Bold),
+                    style::Print(sub_agent.id),
+                    style::SetAttribute(Attribute::Reset),
+                    style::Print(format!(
+                        "  {}\n",
+                        truncate_safe(sub_agent.task.lines().next().unwrap_or_default(), 80)
+                    )),
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!(
+                        "   {:.2} s • {} tool uses • {}\n",
+                        sub_agent.duration_secs,
+                        sub_agent.tool_uses,
+                        sub_agent.tools.join(", ")
+                    )),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            }
+            if !sub_agents.is_empty() {
+                queue!(self.output, style::Print("\nShow a transcript with /subagents <id>\n"))?;
+            }
+            return Ok(());
+        };
+
+        let Some(sub_agent) = sub_agents.iter().find(|sub_agent| sub_agent.id == id) else {
+            bail!("No sub-agent with the id {}. Use /subagents to list them.", id);
+        };
+        queue!(
+            self.output,
+            style::SetAttribute(Attribute::Bold),
+            style::Print(format!("Sub-agent {}", sub_agent.id)),
+            style::SetAttribute(Attribute::Reset),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!(
+                " ({:.2} s, {} tool uses, tools: {})\n\n",
+                sub_agent.duration_secs,
+                sub_agent.tool_uses,
+                sub_agent.tools.join(", ")
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+        for entry in &sub_agent.entries {
+            queue!(self.output, style::Print(format!("{entry}\n\n")))?;
+        }
+        if let Some(error) = &sub_agent.error {
+            queue!(
+                self.output,
+                style::SetForegroundColor(Color::Red),
+                style::Print(format!("{error}\n")),
+                style::SetForegroundColor(Color::Reset),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Replaces the current conversation with one loaded from the database, keeping the tools
+    /// that are already loaded.
+    async fn replace_conversation_state(&mut self, mut new_state: ConversationState) {
+        new_state
+            .reload_serialized_state(Arc::clone(&self.ctx), Some(self.output.clone()))
+            .await;
+        new_state.tool_manager = std::mem::take(&mut self.conversation_state.tool_manager);
+        new_state.update_state(true).await;
+        new_state.enforce_tool_use_history_invariants();
+        self.conversation_state = new_state;
     }
 
     async fn tool_use_execute(
@@ -3492,10 +4768,56 @@ impl ChatContext {
                 continue;
             }
 
+            // Tools blocked by a pre tool use hook are never invoked.
+            if tool.denied_reason.is_some() {
+                self.print_tool_descriptions(tool, false).await?;
+                tool.accepted = true;
+                continue;
+            }
+
+            // Only read-only tools are invoked in plan mode.
+            if self.conversation_state.mode() == ChatMode::Plan && !tool.tool.is_read_only() {
+                self.print_tool_descriptions(tool, false).await?;
+                tool.denied_reason = Some(
+                    "Plan mode is enabled, so only read-only tools can be used. Include this step in the plan instead"
+                        .to_string(),
+                );
+                tool.accepted = true;
+                continue;
+            }
+
+            // Commands are checked against the command policy, which may refuse them outright.
+            let policy_decision = match &tool.tool {
+                Tool::ExecuteCommand(execute_command) => Some(self.command_policy_decision(execute_command).await),
+                _ => None,
+            };
+            if let Some(PolicyDecision {
+                action: PolicyAction::Deny,
+                rule,
+            }) = &policy_decision
+            {
+                self.print_tool_descriptions(tool, false).await?;
+                tool.denied_reason = Some(format!(
+                    "The command was denied by the command policy rule {}",
+                    rule.as_deref().unwrap_or_default()
+                ));
+                tool.accepted = true;
+                continue;
+            }
+
             // If there is an override, we will use it. Otherwise fall back to Tool's default.
-            let allowed = self.tool_permissions.trust_all
+            let trusted = self.tool_permissions.trust_all
                 || (self.tool_permissions.has(&tool.name) && self.tool_permissions.is_trusted(&tool.name))
-                || !tool.tool.requires_acceptance(&self.ctx);
+                || self.agent_trusts_tool(&tool.name);
+            let allowed = match &policy_decision {
+                // Explicit ask rules take precedence over trust.
+                Some(PolicyDecision {
+                    action: PolicyAction::Ask,
+                    rule: Some(_),
+                }) => false,
+                Some(decision) => trusted || decision.action == PolicyAction::Allow,
+                None => trusted || !tool.tool.requires_acceptance(&self.ctx),
+            };
 
             if database
                 .settings
@@ -3506,6 +4828,18 @@ impl ChatContext {
             }
 
             self.print_tool_descriptions(tool, allowed).await?;
+            if let Some(PolicyDecision {
+                action: PolicyAction::Ask,
+                rule: Some(rule),
+            }) = &policy_decision
+            {
+                queue!(
+                    self.output,
+                    style::SetForegroundColor(Color::DarkGrey),
+                    style::Print(format!("Confirmation required by command policy rule {rule}\n\n")),
+                    style::SetForegroundColor(Color::Reset),
+                )?;
+            }
 
             if allowed {
                 tool.accepted = true;
@@ -3525,16 +4859,59 @@ impl ChatContext {
             });
         }
 
-        // Execute the requested tools.
+        // Execute the requested tools. Consecutive read-only tools are invoked concurrently when
+        // the first of them is reached, and are then rendered in the order they were requested.
+        let concurrency = database
+            .settings
+            .get_int(Setting::ChatToolConcurrency)
+            .map_or(DEFAULT_TOOL_CONCURRENCY, |n| n.max(1) as usize);
+        let mut invocations = HashMap::<String, ToolInvocation>::new();
         let mut tool_results = vec![];
         let mut image_blocks: Vec<RichImageBlock> = Vec::new();
 
-        for tool in tool_uses {
+        for (index, tool) in tool_uses.iter().enumerate() {
+            if concurrency > 1 && !invocations.contains_key(&tool.id) {
+                let batch = tool_uses[index..]
+                    .iter()
+                    .take_while(|tool| tool.denied_reason.is_none() && tool.tool.is_read_only())
+                    .collect::<Vec<_>>();
+                if batch.len() > 1 {
+                    invocations.extend(self.invoke_concurrently(batch, concurrency).await?);
+                }
+            }
+
             let mut tool_telemetry = self.tool_use_telemetry_events.entry(tool.id.clone());
-            tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = true);
+            tool_telemetry = tool_telemetry.and_modify(|ev| ev.is_accepted = tool.denied_reason.is_none());
 
+            if let (Tool::FsWrite(fs_write), None) = (&tool.tool, &tool.denied_reason) {
+                for path in fs_write.paths(&self.ctx) {
+                    if let Err(err) = self.conversation_state.checkpoints.snapshot(&self.ctx, &path).await {
+                        warn!(?err, ?path, "Failed to save the file to a checkpoint");
+                    }
+                }
+            }
             let tool_start = std::time::Instant::now();
-            let invoke_result = tool.tool.invoke(&self.ctx, &mut self.output).await;
+            let (invoke_result, tool_time) = match invocations.remove(&tool.id) {
+                Some(invocation) => {
+                    self.output.write_all(&invocation.output)?;
+                    let duration = invocation.duration();
+                    (invocation.result, duration)
+                },
+                None => {
+                    let invoke_result = match &tool.denied_reason {
+                        Some(reason) => Err(eyre::eyre!("{reason}")),
+                        None => tool.tool.invoke(&self.ctx, &mut self.output).await,
+                    };
+                    (invoke_result, std::time::Instant::now().duration_since(tool_start))
+                },
+            };
+            if let Tool::Delegate(delegate) = &tool.tool {
+                self.conversation_state
+                    .add_sub_agent_transcripts(delegate.take_transcripts());
+                for snapshot in delegate.take_snapshots() {
+                    self.conversation_state.checkpoints.record(snapshot);
+                }
+            }
 
             if self.interactive && self.spinner.is_some() {
                 queue!(
@@ -3546,7 +4923,19 @@ impl ChatContext {
             }
             execute!(self.output, style::Print("\n"))?;
 
-            let tool_time = std::time::Instant::now().duration_since(tool_start);
+            // Post tool use hooks only run for tools that were invoked, and their output is added
+            // to the tool result.
+            let post_hook_results = match (&tool.denied_reason, self.conversation_state.context_manager.as_mut()) {
+                (None, Some(cm)) => {
+                    let tool_response = match &invoke_result {
+                        Ok(result) => serde_json::json!({ "status": "success", "output": result.as_str() }),
+                        Err(err) => serde_json::json!({ "status": "error", "error": err.to_string() }),
+                    };
+                    cm.run_post_tool_use_hooks(&tool.name, &tool.args, tool_response).await
+                },
+                _ => Vec::new(),
+            };
+
             if let Tool::Custom(ct) = &tool.tool {
                 tool_telemetry = tool_telemetry.and_modify(|ev| {
                     ev.custom_tool_call_latency = Some(tool_time.as_secs() as usize);
@@ -3587,7 +4976,7 @@ impl ChatContext {
                             .and_modify(|ev| ev.output_token_size = Some(TokenCounter::count_tokens(result.as_str())));
                     }
                     tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
+                        tool_use_id: tool.id.clone(),
                         content: vec![result.into()],
                         status: ToolResultStatus::Success,
                     });
@@ -3610,7 +4999,7 @@ impl ChatContext {
 
                     tool_telemetry.and_modify(|ev| ev.is_success = Some(false));
                     tool_results.push(ToolUseResult {
-                        tool_use_id: tool.id,
+                        tool_use_id: tool.id.clone(),
                         content: vec![ToolUseResultBlock::Text(format!(
                             "An error occurred processing the tool: \n{}",
                             &err
@@ -3626,8 +5015,19 @@ impl ChatContext {
                     }
                 },
             }
+
+            if let Some(tool_result) = tool_results.last_mut() {
+                tool_result
+                    .content
+                    .extend(post_hook_results.into_iter().map(|(hook, output)| {
+                        ToolUseResultBlock::Text(format!("Output from the hook '{}':\n{output}", hook.name))
+                    }));
+            }
         }
 
+        for tool_result in &tool_results {
+            self.emit_event(tool_result.into());
+        }
         if !image_blocks.is_empty() {
             let images = image_blocks.into_iter().map(|(block, _)| block).collect();
             self.conversation_state
@@ -3655,6 +5055,71 @@ impl ChatContext {
         ));
     }
 
+    /// Invokes `tools` concurrently, at most `limit` at a time, reporting each tool as it finishes.
+    /// What the tools write to their updates is buffered so that it can be rendered in order.
+    async fn invoke_concurrently(
+        &mut self,
+        tools: Vec<&QueuedTool>,
+        limit: usize,
+    ) -> Result<HashMap<String, ToolInvocation>, ChatError> {
+        let ctx = &self.ctx;
+        let total = tools.len();
+        let mut pending = stream::iter(tools.into_iter().map(|tool| async move {
+            let mut output = Vec::new();
+            let start = std::time::Instant::now();
+            let result = tool.tool.invoke(ctx, &mut output).await;
+            (tool, ToolInvocation {
+                result,
+                output,
+                start,
+                end: std::time::Instant::now(),
+            })
+        }))
+        .buffer_unordered(limit);
+
+        let spinner_text = |finished: usize| {
+            format!(
+                "{} of {} tools finished",
+                finished.to_string().blue(),
+                total.to_string().blue()
+            )
+        };
+        let mut spinner = self.interactive.then(|| Spinner::new(Spinners::Dots, spinner_text(0)));
+        let mut invocations = HashMap::with_capacity(total);
+        while let Some((tool, invocation)) = pending.next().await {
+            if let Some(mut spinner) = spinner.take() {
+                spinner.stop();
+                queue!(
+                    self.output,
+                    cursor::MoveToColumn(0),
+                    terminal::Clear(terminal::ClearType::CurrentLine),
+                )?;
+            }
+            let (symbol, status) = match &invocation.result {
+                Ok(_) => ("✓ ".green(), " finished in "),
+                Err(_) => ("✗ ".red(), " failed after "),
+            };
+            execute!(
+                self.output,
+                style::Print(symbol),
+                style::SetForegroundColor(Color::Blue),
+                style::Print(&tool.name),
+                style::ResetColor,
+                style::Print(status),
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!("{:.2} s\n", invocation.duration().as_secs_f32())),
+                style::ResetColor,
+            )?;
+            invocations.insert(tool.id.clone(), invocation);
+            if self.interactive && invocations.len() < total {
+                spinner = Some(Spinner::new(Spinners::Dots, spinner_text(invocations.len())));
+            }
+        }
+        execute!(self.output, style::Print("\n"))?;
+
+        Ok(invocations)
+    }
+
     async fn handle_response(
         &mut self,
         database: &mut Database,
@@ -3670,6 +5135,10 @@ impl ChatContext {
 
         let mut tool_uses = Vec::new();
         let mut tool_name_being_recvd: Option<String> = None;
+        let input_tokens = match self.events {
+            Some(_) => self.conversation_state.calculate_token_count().await.value(),
+            None => 0,
+        };
 
         if self.interactive && self.spinner.is_some() {
             drop(self.spinner.take());
@@ -3688,6 +5157,12 @@ impl ChatContext {
             match parser.recv().await {
                 Ok(msg_event) => {
                     trace!("Consumed: {:?}", msg_event);
+                    if let Some(mut event) = OutputEvent::from_response_event(&msg_event) {
+                        if let OutputEvent::Usage(usage) = &mut event {
+                            usage.input_tokens = input_tokens;
+                        }
+                        self.emit_event(event);
+                    }
                     match msg_event {
                         parser::ResponseEvent::ToolUseStart { name } => {
                             // We need to flush the buffer here, otherwise text will not be
@@ -3858,6 +5333,12 @@ impl ChatContext {
                 )
                 .await;
 
+                if let Some(cm) = self.conversation_state.context_manager.as_mut() {
+                    let mut input = HookInput::new(&self.ctx, HookTrigger::AssistantResponse);
+                    input.assistant_response = Some(buf.trim().to_string());
+                    cm.run_event_hooks(input).await;
+                }
+
                 if self.interactive
                     && database
                         .settings
@@ -3910,9 +5391,29 @@ impl ChatContext {
         let mut queued_tools: Vec<QueuedTool> = Vec::new();
         let mut tool_results: Vec<ToolUseResult> = Vec::new();
 
-        for tool_use in tool_uses {
+        for mut tool_use in tool_uses {
             let tool_use_id = tool_use.id.clone();
             let tool_use_name = tool_use.name.clone();
+
+            // Tools the agent cannot use are never invoked, even though the model was not offered them.
+            let mut denied_reason = match self.conversation_state.agent() {
+                Some(agent) if !self.agent_allows_tool(&tool_use.name) => Some(format!(
+                    "The agent {} cannot use the tool {}. Only use the tools that are available",
+                    agent.name, tool_use.name
+                )),
+                _ => None,
+            };
+
+            // Pre tool use hooks may block the tool use or rewrite its input.
+            if let (None, Some(cm)) = (&denied_reason, self.conversation_state.context_manager.as_mut()) {
+                match cm.run_pre_tool_use_hooks(&tool_use.name, &tool_use.args).await {
+                    PreToolUseDecision::Allow => (),
+                    PreToolUseDecision::Block(reason) => denied_reason = Some(reason),
+                    PreToolUseDecision::Rewrite(args) => tool_use.args = args,
+                }
+            }
+            let args = tool_use.args.clone();
+
             let mut tool_telemetry = ToolUseEventBuilder::new(
                 conv_id.clone(),
                 tool_use.id.clone(),
@@ -3934,6 +5435,8 @@ impl ChatContext {
                                 name: tool_use_name,
                                 tool,
                                 accepted: false,
+                                denied_reason,
+                                args,
                             });
                         },
                         Err(err) => {
@@ -3984,6 +5487,7 @@ impl ChatContext {
                         )?;
                     }
                 }
+                self.emit_event(tool_result.into());
             }
             self.conversation_state.add_tool_results(tool_results);
             self.send_tool_use_telemetry(telemetry).await;
@@ -4102,7 +5606,6 @@ impl ChatContext {
     // output from Amazon Q.
     // TODO: Is there a better way?
     fn contextualize_tool(&self, tool: &mut Tool) {
-        #[allow(clippy::single_match)]
         match tool {
             Tool::GhIssue(gh_issue) => {
                 gh_issue.set_context(GhIssueContext {
@@ -4116,10 +5619,88 @@ impl ChatContext {
                     interactive: self.interactive,
                 });
             },
+            Tool::ExecuteCommand(execute_command) => {
+                execute_command.sandbox = self.sandbox();
+            },
+            Tool::Delegate(delegate) => {
+                // Sub-agents are restricted to the tools of the current agent, and trust the same.
+                let mut tool_manager = self.conversation_state.tool_manager.clone();
+                let mut tool_permissions = self.tool_permissions.clone();
+                if let Some(agent) = self.conversation_state.agent() {
+                    tool_manager.schema.retain(|_, spec| agent.config.allows_tool(spec));
+                    for name in tool_manager.schema.keys() {
+                        if self.agent_trusts_tool(name) {
+                            tool_permissions.trust_tool(name);
+                        }
+                    }
+                }
+                delegate.set_context(DelegateContext {
+                    client: self.client.clone(),
+                    ctx: Arc::clone(&self.ctx),
+                    tool_manager,
+                    tool_permissions,
+                    profile: self.conversation_state.current_profile().map(str::to_string),
+                    model: self.conversation_state.model.clone(),
+                    sandbox: self.sandbox(),
+                    context_manager: self
+                        .conversation_state
+                        .context_manager
+                        .clone()
+                        .map(|cm| Arc::new(tokio::sync::Mutex::new(cm))),
+                    first_transcript_id: self.conversation_state.next_sub_agent_id(),
+                });
+            },
             _ => (),
         };
     }
 
+    /// The sandbox shell commands run in, if enabled either by `--sandbox` or by the current
+    /// profile.
+    fn sandbox(&self) -> Option<Sandbox> {
+        let config = self
+            .conversation_state
+            .context_manager
+            .as_ref()
+            .and_then(|cm| cm.sandbox_config())
+            .cloned()
+            .unwrap_or_default();
+        if !self.sandbox && !config.enabled {
+            return None;
+        }
+
+        let mut writable_paths = vec![self.ctx.env().current_dir().unwrap_or_default(), std::env::temp_dir()];
+        writable_paths.extend(
+            config
+                .writable_paths
+                .iter()
+                .map(|path| sanitize_path_tool_arg(&self.ctx, path)),
+        );
+        Some(Sandbox {
+            writable_paths,
+            allow_network: config.allow_network,
+        })
+    }
+
+    /// Evaluates `command` against the global command policy and the policy of the current profile.
+    /// A policy that cannot be loaded results in every command requiring confirmation.
+    async fn command_policy_decision(&self, command: &ExecuteCommand) -> PolicyDecision {
+        let profile = self
+            .conversation_state
+            .context_manager
+            .as_ref()
+            .map(|cm| cm.current_profile.as_str());
+        match CommandPolicy::load(&self.ctx, profile).await {
+            Ok(policy) => command.evaluate_policy(&policy),
+            Err(err) => {
+                warn!(?err, "Failed to load the command policy");
+                PolicyDecision {
+                    action: PolicyAction::Ask,
+                    rule: Some(format!("(invalid command policy: {err})")),
+                }
+            },
+        }
+    }
+
     async fn print_tool_descriptions(&mut self, tool_use: &QueuedTool, trusted: bool) -> Result<(), ChatError> {
         queue!(
             self.output,
@@ -4182,14 +5763,50 @@ impl ChatContext {
                     ctrl_c = true;
                 },
                 (Ok(None), true) => return None, // Exit if Ctrl+C was pressed twice
-                (Err(_), _) => return None,
+                (Err(err), _) => {
+                    self.emit_event(OutputEvent::Error {
+                        message: err.to_string(),
+                    });
+                    return None;
+                },
             }
         }
     }
 
+    /// Reports an event when the output format is machine readable. Failing to write an event is
+    /// logged rather than interrupting the chat.
+    fn emit_event(&mut self, event: OutputEvent) {
+        if let Some(events) = self.events.as_mut() {
+            if let Err(err) = events.emit(event) {
+                error!(?err, "Failed to write the output event");
+            }
+        }
+    }
+
+    /// Reports the end of the chat when the output format is machine readable, returning whether
+    /// any errors were reported.
+    fn finish_events(&mut self, error: Option<&eyre::Report>) -> Result<bool> {
+        if let Some(err) = error {
+            self.emit_event(OutputEvent::Error {
+                message: err.to_string(),
+            });
+        }
+        match self.events.as_mut() {
+            Some(events) => {
+                events.finish(self.conversation_state.conversation_id())?;
+                Ok(events.is_error())
+            },
+            None => Ok(false),
+        }
+    }
+
     /// Helper function to generate a prompt based on the current context
     fn generate_tool_trust_prompt(&self) -> String {
-        prompt::generate_prompt(self.conversation_state.current_profile(), self.all_tools_trusted())
+        prompt::generate_prompt(
+            self.conversation_state.current_profile(),
+            self.conversation_state.mode() == ChatMode::Plan,
+            self.all_tools_trusted(),
+        )
     }
 
     async fn send_tool_use_telemetry(&mut self, telemetry: &TelemetryThread) {
@@ -4202,16 +5819,109 @@ impl ChatContext {
 
             telemetry.send_tool_use_suggested(event).ok();
         }
-    }
+    }
+
+    fn terminal_width(&self) -> usize {
+        (self.terminal_width_provider)().unwrap_or(80)
+    }
+
+    fn all_tools_trusted(&self) -> bool {
+        self.conversation_state.tools.values().flatten().all(|t| match t {
+            FigTool::ToolSpecification(t) => {
+                self.tool_permissions.is_trusted(&t.name) || self.agent_trusts_tool(&t.name)
+            },
+        })
+    }
+
+    /// Whether the current agent, if any, can use `tool_name`.
+    fn agent_allows_tool(&self, tool_name: &str) -> bool {
+        self.conversation_state.agent().is_none_or(|agent| {
+            self.conversation_state
+                .tool_manager
+                .schema
+                .get(tool_name)
+                .is_some_and(|spec| agent.config.allows_tool(spec))
+        })
+    }
+
+    /// Whether the current agent trusts `tool_name`, and the user has not changed its permission.
+    fn agent_trusts_tool(&self, tool_name: &str) -> bool {
+        !self.tool_permissions.trust_all
+            && !self.tool_permissions.has(tool_name)
+            && self.conversation_state.agent().is_some_and(|agent| {
+                self.conversation_state
+                    .tool_manager
+                    .schema
+                    .get(tool_name)
+                    .is_some_and(|spec| agent.config.trusts_tool(spec))
+            })
+    }
+
+    /// The permission label of a tool shown by `/tools`.
+    fn tool_permission_label(&self, tool_name: &str) -> String {
+        if self.agent_trusts_tool(tool_name) {
+            format!("  {}", "trusted by agent".dark_green().bold())
+        } else {
+            self.tool_permissions.display_label(tool_name)
+        }
+    }
+
+    /// Applies the auto compaction policy of the current profile once the conversation uses more
+    /// than its share of the context window: large tool results outside of the recent turns are
+    /// elided first, and if that is not enough the number of recent turns to keep verbatim while
+    /// summarizing the older ones is returned. Falls back to displaying a warning.
+    async fn manage_context_window(&mut self) -> Result<Option<usize>, std::io::Error> {
+        let config = self.conversation_state.auto_compact_config();
+        if !config.enabled {
+            self.display_char_warnings().await?;
+            return Ok(None);
+        }
+
+        let (tokens, context_window_size) = self.conversation_state.context_window_usage().await;
+        let threshold = config.threshold_tokens(context_window_size);
+        if *tokens < threshold {
+            return Ok(None);
+        }
+
+        let elided = self
+            .conversation_state
+            .elide_old_tool_results(config.keep_recent_turns, config.max_tool_result_tokens);
+        if elided > 0 {
+            let (remaining_tokens, _) = self.conversation_state.context_window_usage().await;
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!(
+                    "\nElided {} large tool {} from older turns, freeing {} tokens.\n",
+                    elided,
+                    if elided == 1 { "result" } else { "results" },
+                    tokens.value().saturating_sub(remaining_tokens.value())
+                )),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+            if *remaining_tokens < threshold {
+                return Ok(None);
+            }
+        }
 
-    fn terminal_width(&self) -> usize {
-        (self.terminal_width_provider)().unwrap_or(80)
-    }
+        if self
+            .conversation_state
+            .can_summarize_oldest_turns(config.keep_recent_turns)
+        {
+            execute!(
+                self.output,
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!(
+                    "\nThe conversation is using over {}% of the context window, summarizing older turns...\n\n",
+                    config.threshold_percent
+                )),
+                style::SetForegroundColor(Color::Reset)
+            )?;
+            return Ok(Some(config.keep_recent_turns));
+        }
 
-    fn all_tools_trusted(&self) -> bool {
-        self.conversation_state.tools.values().flatten().all(|t| match t {
-            FigTool::ToolSpecification(t) => self.tool_permissions.is_trusted(&t.name),
-        })
+        self.display_char_warnings().await?;
+        Ok(None)
     }
 
     /// Display character limit warnings based on current conversation size
@@ -4332,11 +6042,26 @@ where
     result
 }
 
-/// Prints hook configuration grouped by trigger: conversation session start or per user message
+/// Prints hook configuration grouped by trigger. Context triggers are always listed, while
+/// sections for the other triggers are only printed when they have hooks.
+fn print_hook_sections(output: &mut impl Write, hooks: &HashMap<String, Hook>) -> Result<()> {
+    for trigger in HookTrigger::ALL {
+        if trigger.is_context() || hooks.values().any(|h| h.trigger == trigger) {
+            print_hook_section(output, hooks, trigger)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the hooks configured for a single trigger
 fn print_hook_section(output: &mut impl Write, hooks: &HashMap<String, Hook>, trigger: HookTrigger) -> Result<()> {
     let section = match trigger {
         HookTrigger::ConversationStart => "On Session Start",
         HookTrigger::PerPrompt => "Per User Message",
+        HookTrigger::PreToolUse => "Before Tool Use",
+        HookTrigger::PostToolUse => "After Tool Use",
+        HookTrigger::AssistantResponse => "After Assistant Response",
+        HookTrigger::SessionEnd => "On Session End",
     };
     let hooks: Vec<(&String, &Hook)> = hooks.iter().filter(|(_, h)| h.trigger == trigger).collect();
 
@@ -4371,6 +6096,52 @@ fn print_hook_section(output: &mut impl Write, hooks: &HashMap<String, Hook>, tr
     Ok(())
 }
 
+/// Prints the token count of each item in a category of the `/usage` breakdown, skipping empty
+/// categories.
+fn print_token_breakdown(
+    output: &mut impl Write,
+    title: &str,
+    color: Color,
+    items: &[(String, TokenCount)],
+    context_window: usize,
+) -> io::Result<()> {
+    const MAX_ITEMS: usize = 10;
+
+    if items.is_empty() {
+        return Ok(());
+    }
+
+    queue!(
+        output,
+        style::SetForegroundColor(color),
+        style::Print(format!("{title}:\n")),
+        style::SetForegroundColor(Color::Reset),
+    )?;
+    for (name, tokens) in items.iter().take(MAX_ITEMS) {
+        queue!(
+            output,
+            style::Print(format!(
+                "  {:>7} tokens ({:>5.2}%)  ",
+                tokens,
+                (tokens.value() as f32 / context_window as f32) * 100.0
+            )),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("{name}\n")),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+    if items.len() > MAX_ITEMS {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("  ... and {} more\n", items.len() - MAX_ITEMS)),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+    queue!(output, style::Print("\n"))?;
+    Ok(())
+}
+
 /// Testing helper
 fn split_tool_use_event(value: &Map<String, serde_json::Value>) -> Vec<ChatResponseStream> {
     let tool_use_id = value.get("tool_use_id").unwrap().as_str().unwrap().to_string();
@@ -4535,7 +6306,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -4543,6 +6314,8 @@ mod tests {
             None,
             tool_config,
             ToolPermissions::new(0),
+            false,
+            None,
         )
         .await
         .unwrap()
@@ -4684,7 +6457,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -4692,6 +6465,8 @@ mod tests {
             None,
             tool_config,
             ToolPermissions::new(0),
+            false,
+            None,
         )
         .await
         .unwrap()
@@ -4784,7 +6559,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -4792,6 +6567,8 @@ mod tests {
             None,
             tool_config,
             ToolPermissions::new(0),
+            false,
+            None,
         )
         .await
         .unwrap()
@@ -4863,7 +6640,7 @@ mod tests {
                 "exit".to_string(),
             ]),
             true,
-            false,
+            None,
             test_client,
             || Some(80),
             tool_manager,
@@ -4871,6 +6648,8 @@ mod tests {
             None,
             tool_config,
             ToolPermissions::new(0),
+            false,
+            None,
         )
         .await
         .unwrap()
@@ -4882,6 +6661,341 @@ mod tests {
         assert!(!ctx.fs().exists("/file2.txt"));
     }
 
+    #[tokio::test]
+    async fn test_flow_stream_json() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll create a file for you",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": {
+                        "command": "create",
+                        "file_text": "Hello, world!",
+                        "path": "/file.txt",
+                    }
+                }
+            ],
+            [
+                "Hope that looks good to you!",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let events = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            None,
+            InputSource::new_json_lines(std::io::Cursor::new(
+                r#"{"type": "user", "content": "create a new file"}"#,
+            )),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            None,
+            tool_config,
+            tool_permissions,
+            false,
+            EventWriter::new(ChatOutputFormat::StreamJson, events.clone()),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+        assert!(!chat.finish_events(None).unwrap());
+
+        let events = String::from_utf8(events.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        let tool_use = events.iter().find(|e| e["type"] == "tool_use").unwrap();
+        assert_eq!(tool_use["name"], "fs_write");
+        assert_eq!(tool_use["input"]["path"], "/file.txt");
+        let tool_result = events.iter().find(|e| e["type"] == "tool_result").unwrap();
+        assert_eq!(tool_result["tool_use_id"], "1");
+        assert_eq!(tool_result["is_error"], false);
+        let result = events.last().unwrap();
+        assert_eq!(result["type"], "result");
+        assert_eq!(result["result"], "Hope that looks good to you!");
+        assert_eq!(result["num_turns"], 2);
+        assert_eq!(ctx.fs().read_to_string("/file.txt").await.unwrap(), "Hello, world!\n");
+    }
+
+    #[tokio::test]
+    async fn test_flow_concurrent_tools() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().write("/file.txt", "Hello").await.unwrap();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Sure, I'll read and update the file",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_read",
+                    "args": { "mode": "Line", "path": "/file.txt" }
+                },
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_read",
+                    "args": { "mode": "Directory", "path": "/" }
+                },
+                {
+                    "tool_use_id": "3",
+                    "name": "fs_write",
+                    "args": { "command": "append", "path": "/file.txt", "new_str": "world" }
+                },
+                {
+                    "tool_use_id": "4",
+                    "name": "fs_read",
+                    "args": { "mode": "Line", "path": "/file.txt" }
+                }
+            ],
+            [
+                "Done",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let events = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            None,
+            InputSource::new_json_lines(std::io::Cursor::new(
+                r#"{"type": "user", "content": "read and update the file"}"#,
+            )),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            None,
+            tool_config,
+            tool_permissions,
+            false,
+            EventWriter::new(ChatOutputFormat::StreamJson, events.clone()),
+        )
+        .await
+        .unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // The first two reads run concurrently, while the last one waits for the write before it.
+        let tool_results = String::from_utf8(events.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["type"] == "tool_result")
+            .collect::<Vec<_>>();
+        let ids = tool_results
+            .iter()
+            .map(|r| r["tool_use_id"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "2", "3", "4"]);
+        assert!(tool_results.iter().all(|r| r["is_error"] == false));
+        assert_eq!(tool_results[0]["content"][0], "Hello");
+        assert!(tool_results[1]["content"][0].as_str().unwrap().contains("file.txt"));
+        assert_eq!(tool_results[3]["content"][0], "Hello\nworld");
+
+        // Read-only tools overlap: the second one starts before the first one ends.
+        let queued = |id: &str, args: serde_json::Value| QueuedTool {
+            id: id.to_owned(),
+            name: "fs_read".to_owned(),
+            accepted: true,
+            tool: Tool::FsRead(serde_json::from_value(args.clone()).unwrap()),
+            denied_reason: None,
+            args,
+        };
+        let tools = [
+            queued("5", serde_json::json!({ "mode": "Line", "path": "/file.txt" })),
+            queued("6", serde_json::json!({ "mode": "Line", "path": "/file.txt" })),
+        ];
+        let invocations = chat.invoke_concurrently(tools.iter().collect(), 2).await.unwrap();
+        let (first, second) = (&invocations["5"], &invocations["6"]);
+        assert!(first.result.is_ok() && second.result.is_ok());
+        assert!(first.start < second.end && second.start < first.end);
+    }
+
+    #[tokio::test]
+    async fn test_flow_plan_mode() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Let me look around first",
+                {
+                    "tool_use_id": "1",
+                    "name": "execute_bash",
+                    "args": { "command": "echo hello" }
+                },
+                {
+                    "tool_use_id": "2",
+                    "name": "fs_write",
+                    "args": { "command": "create", "path": "/file.txt", "file_text": "Hello" }
+                }
+            ],
+            [
+                "Here is the plan",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let events = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let tool_manager = ToolManager::default();
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            None,
+            InputSource::new_json_lines(std::io::Cursor::new(r#"{"type": "user", "content": "make a plan"}"#)),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            None,
+            tool_config,
+            ToolPermissions::new(0),
+            false,
+            EventWriter::new(ChatOutputFormat::StreamJson, events.clone()),
+        )
+        .await
+        .unwrap();
+        chat.conversation_state.set_mode(ChatMode::Plan).await;
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // Read-only commands still run in plan mode, while edits are refused.
+        let tool_results = String::from_utf8(events.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["type"] == "tool_result")
+            .collect::<Vec<_>>();
+        assert_eq!(tool_results.len(), 2);
+        assert_eq!(tool_results[0]["is_error"], false);
+        assert!(tool_results[0]["content"].to_string().contains("hello"));
+        assert_eq!(tool_results[1]["is_error"], true);
+        assert!(tool_results[1]["content"].to_string().contains("Plan mode is enabled"));
+        assert!(!ctx.fs().exists("/file.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_flow_agent_tools() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        let test_client = create_stream(serde_json::json!([
+            [
+                "Let me write that down",
+                {
+                    "tool_use_id": "1",
+                    "name": "fs_write",
+                    "args": { "command": "create", "path": "/file.txt", "file_text": "Hello" }
+                }
+            ],
+            [
+                "I cannot write files",
+            ],
+        ]));
+
+        let env = Env::new();
+        let mut database = Database::new().await.unwrap();
+        let telemetry = TelemetryThread::new(&env, &mut database).await.unwrap();
+
+        let events = TestWriterWithSink {
+            sink: Arc::new(std::sync::Mutex::new(Vec::new())),
+        };
+        let tool_config = serde_json::from_str::<HashMap<String, ToolSpec>>(include_str!("tools/tool_index.json"))
+            .expect("Tools failed to load");
+        let mut tool_manager = ToolManager::default();
+        tool_manager.schema = tool_config.clone();
+        let mut tool_permissions = ToolPermissions::new(0);
+        tool_permissions.trust_all = true;
+        let mut chat = ChatContext::new(
+            Arc::clone(&ctx),
+            &mut database,
+            "fake_conv_id",
+            SharedWriter::null(),
+            None,
+            InputSource::new_json_lines(std::io::Cursor::new(r#"{"type": "user", "content": "write a file"}"#)),
+            false,
+            None,
+            test_client,
+            || Some(80),
+            tool_manager,
+            None,
+            None,
+            tool_config,
+            tool_permissions,
+            false,
+            EventWriter::new(ChatOutputFormat::StreamJson, events.clone()),
+        )
+        .await
+        .unwrap();
+        let agent = Agent {
+            name: "reader".to_string(),
+            source: agent::AgentSource::Global,
+            path: std::path::PathBuf::from("reader.json"),
+            config: agent::AgentConfig {
+                tools: Some(vec!["fs_read".to_string()]),
+                ..Default::default()
+            },
+            ignored_fields: vec![],
+        };
+        chat.conversation_state.set_agent(Some(agent)).await.unwrap();
+        chat.try_chat(&mut database, &telemetry).await.unwrap();
+
+        // Tools outside of the agent's tools are refused even when all tools are trusted.
+        let tool_results = String::from_utf8(events.get_content())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .filter(|event| event["type"] == "tool_result")
+            .collect::<Vec<_>>();
+        assert_eq!(tool_results.len(), 1);
+        assert_eq!(tool_results[0]["is_error"], true);
+        assert!(
+            tool_results[0]["content"]
+                .to_string()
+                .contains("cannot use the tool fs_write")
+        );
+        assert!(!ctx.fs().exists("/file.txt"));
+    }
+
     #[test]
     fn test_editor_content_processing() {
         // Since we no longer have template replacement, this test is simplified
@@ -4920,7 +7034,7 @@ mod tests {
             None,
             InputSource::new_mock(vec!["/subscribe".to_string(), "y".to_string(), "/quit".to_string()]),
             true,
-            false,
+            None,
             create_stream(serde_json::json!([])),
             || Some(80),
             tool_manager,
@@ -4928,6 +7042,8 @@ mod tests {
             None,
             tool_config,
             ToolPermissions::new(0),
+            false,
+            None,
         )
         .await
         .unwrap()
@@ -0,0 +1,123 @@
+//! Chat modes restrict what the model can do. In plan mode the model can explore the workspace
+//! but not change anything, and instead produces a plan that the user approves with
+//! `/mode approve`, which executes it in normal mode.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::tools::{
+    ToolOrigin,
+    ToolSpec,
+};
+
+/// Native tools that always modify their environment, and are hidden in plan mode.
+const MUTATING_TOOLS: &[&str] = &["fs_write", "delegate"];
+
+/// Instructions given to the model in plan mode.
+pub const PLAN_MODE_INSTRUCTIONS: &str = "The user enabled plan mode. You can only use tools that do not modify anything: files cannot be written, and commands or AWS calls that change something are rejected. Explore as needed, then reply with a concise, numbered plan of the changes you would make, including the files and commands involved. Do not claim to have made any change. The user will review the plan and approve it before it is executed.";
+
+/// The prompt sent on behalf of the user by `/mode approve`.
+pub const APPROVE_PLAN_PROMPT: &str = "I approve the plan. Plan mode is now disabled, go ahead and execute it.";
+
+/// Whether the model can change anything while chatting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatMode {
+    /// Every tool can be used, subject to the tool permissions
+    #[default]
+    Normal,
+    /// Only read-only tools can be used, and the model is asked for a plan
+    Plan,
+}
+
+impl ChatMode {
+    /// The other mode, used when `/mode` is given no argument.
+    pub fn toggled(self) -> Self {
+        match self {
+            ChatMode::Normal => ChatMode::Plan,
+            ChatMode::Plan => ChatMode::Normal,
+        }
+    }
+
+    /// Whether the tool is sent to the model in this mode.
+    ///
+    /// Tools such as `execute_bash` and `use_aws` can be used read-only, so they are kept in plan
+    /// mode and their mutating uses are rejected when invoked instead. MCP tools are only kept
+    /// when annotated as read-only by their server.
+    pub fn allows_tool(&self, spec: &ToolSpec) -> bool {
+        match self {
+            ChatMode::Normal => true,
+            ChatMode::Plan => match &spec.tool_origin {
+                ToolOrigin::Native => !MUTATING_TOOLS.contains(&spec.name.as_str()),
+                ToolOrigin::McpServer(_) => spec
+                    .annotations
+                    .as_ref()
+                    .and_then(|annotations| annotations.read_only_hint)
+                    .unwrap_or(false),
+            },
+        }
+    }
+}
+
+impl fmt::Display for ChatMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatMode::Normal => write!(f, "normal"),
+            ChatMode::Plan => write!(f, "plan"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::chat::tools::{
+        InputSchema,
+        ToolAnnotations,
+    };
+
+    fn tool_spec(name: &str, origin: ToolOrigin, read_only_hint: Option<bool>) -> ToolSpec {
+        ToolSpec {
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: InputSchema(serde_json::json!({})),
+            tool_origin: origin,
+            annotations: Some(ToolAnnotations {
+                read_only_hint,
+                ..Default::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_chat_mode_allows_tool() {
+        let mcp = || ToolOrigin::McpServer("git".to_string());
+        let fs_read = tool_spec("fs_read", ToolOrigin::Native, None);
+        let fs_write = tool_spec("fs_write", ToolOrigin::Native, None);
+        let execute_bash = tool_spec("execute_bash", ToolOrigin::Native, None);
+        let git_log = tool_spec("git_log", mcp(), Some(true));
+        let git_commit = tool_spec("git_commit", mcp(), Some(false));
+        let git_push = tool_spec("git_push", mcp(), None);
+
+        for spec in [&fs_read, &fs_write, &execute_bash, &git_log, &git_commit, &git_push] {
+            assert!(ChatMode::Normal.allows_tool(spec));
+        }
+        assert!(ChatMode::Plan.allows_tool(&fs_read));
+        assert!(ChatMode::Plan.allows_tool(&execute_bash));
+        assert!(ChatMode::Plan.allows_tool(&git_log));
+        assert!(!ChatMode::Plan.allows_tool(&fs_write));
+        assert!(!ChatMode::Plan.allows_tool(&git_commit));
+        assert!(!ChatMode::Plan.allows_tool(&git_push));
+    }
+
+    #[test]
+    fn test_chat_mode_toggled() {
+        assert_eq!(ChatMode::Normal.toggled(), ChatMode::Plan);
+        assert_eq!(ChatMode::Plan.toggled(), ChatMode::Normal);
+    }
+}
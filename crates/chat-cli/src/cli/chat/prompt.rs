@@ -59,6 +59,11 @@ pub const COMMANDS: &[&str] = &[
     "/profile delete",
     "/profile rename",
     "/profile set",
+    "/resources",
+    "/resources help",
+    "/resources list",
+    "/resources read",
+    "/resources attach",
     "/context help",
     "/context show",
     "/context show --expand",
@@ -77,6 +82,35 @@ pub const COMMANDS: &[&str] = &[
     "/context hooks disable-all",
     "/compact",
     "/compact help",
+    "/undo",
+    "/checkpoint",
+    "/checkpoint help",
+    "/checkpoint list",
+    "/checkpoint restore",
+    "/knowledge",
+    "/knowledge help",
+    "/knowledge show",
+    "/knowledge add",
+    "/knowledge remove",
+    "/knowledge search",
+    "/fork",
+    "/branches",
+    "/switch",
+    "/rewind",
+    "/sessions",
+    "/subagents",
+    "/agent",
+    "/agent help",
+    "/agent list",
+    "/agent swap",
+    "/agent clear",
+    "/agent show",
+    "/agent schema",
+    "/mode",
+    "/mode help",
+    "/mode normal",
+    "/mode plan",
+    "/mode approve",
     "/usage",
     "/save",
     "/load",
@@ -271,6 +305,11 @@ impl Highlighter for ChatHelper {
                 result.push_str(&format!("[{}] ", profile).cyan().to_string());
             }
 
+            // Add plan mode marker if present
+            if components.plan {
+                result.push_str(&"plan ".yellow().to_string());
+            }
+
             // Add warning symbol if present
             if components.warning {
                 result.push_str(&"!".red().to_string());
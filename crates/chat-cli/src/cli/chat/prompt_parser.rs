@@ -2,13 +2,15 @@
 #[derive(Debug, PartialEq)]
 pub struct PromptComponents {
     pub profile: Option<String>,
+    pub plan: bool,
     pub warning: bool,
 }
 
 /// Parse prompt components from a plain text prompt
 pub fn parse_prompt_components(prompt: &str) -> Option<PromptComponents> {
-    // Expected format: "[profile] !> " or "> " or "!> " etc.
+    // Expected format: "[profile] plan !> " or "> " or "!> " etc.
     let mut profile = None;
+    let mut plan = false;
     let mut warning = false;
     let mut remaining = prompt.trim();
 
@@ -22,6 +24,12 @@ pub fn parse_prompt_components(prompt: &str) -> Option<PromptComponents> {
         }
     }
 
+    // Check for plan mode
+    if let Some(rest) = remaining.strip_prefix("plan") {
+        plan = true;
+        remaining = rest.trim_start();
+    }
+
     // Check for warning symbol !
     if remaining.starts_with('!') {
         warning = true;
@@ -30,21 +38,22 @@ pub fn parse_prompt_components(prompt: &str) -> Option<PromptComponents> {
 
     // Should end with "> "
     if remaining.trim_end() == ">" {
-        Some(PromptComponents { profile, warning })
+        Some(PromptComponents { profile, plan, warning })
     } else {
         None
     }
 }
 
-pub fn generate_prompt(current_profile: Option<&str>, warning: bool) -> String {
+pub fn generate_prompt(current_profile: Option<&str>, plan: bool, warning: bool) -> String {
     // Generate plain text prompt that will be colored by highlight_prompt
     let warning_symbol = if warning { "!" } else { "" };
     let profile_part = current_profile
         .filter(|&p| p != "default")
         .map(|p| format!("[{p}] "))
         .unwrap_or_default();
+    let plan_part = if plan { "plan " } else { "" };
 
-    format!("{profile_part}{warning_symbol}> ")
+    format!("{profile_part}{plan_part}{warning_symbol}> ")
 }
 
 #[cfg(test)]
@@ -54,15 +63,18 @@ mod tests {
     #[test]
     fn test_generate_prompt() {
         // Test default prompt (no profile)
-        assert_eq!(generate_prompt(None, false), "> ");
+        assert_eq!(generate_prompt(None, false, false), "> ");
         // Test default prompt with warning
-        assert_eq!(generate_prompt(None, true), "!> ");
+        assert_eq!(generate_prompt(None, false, true), "!> ");
         // Test default profile (should be same as no profile)
-        assert_eq!(generate_prompt(Some("default"), false), "> ");
+        assert_eq!(generate_prompt(Some("default"), false, false), "> ");
         // Test custom profile
-        assert_eq!(generate_prompt(Some("test-profile"), false), "[test-profile] > ");
+        assert_eq!(generate_prompt(Some("test-profile"), false, false), "[test-profile] > ");
         // Test another custom profile with warning
-        assert_eq!(generate_prompt(Some("dev"), true), "[dev] !> ");
+        assert_eq!(generate_prompt(Some("dev"), false, true), "[dev] !> ");
+        // Test plan mode
+        assert_eq!(generate_prompt(None, true, false), "plan > ");
+        assert_eq!(generate_prompt(Some("dev"), true, true), "[dev] plan !> ");
     }
 
     #[test]
@@ -87,6 +99,12 @@ mod tests {
         assert_eq!(components.profile.as_deref(), Some("dev"));
         assert!(components.warning);
 
+        // Test plan mode with profile and warning
+        let components = parse_prompt_components("[dev] plan !> ").unwrap();
+        assert_eq!(components.profile.as_deref(), Some("dev"));
+        assert!(components.plan);
+        assert!(components.warning);
+
         // Test invalid prompt
         assert!(parse_prompt_components("invalid").is_none());
     }
@@ -0,0 +1,385 @@
+use std::io::Write;
+
+use crossterm::event::{
+    Event,
+    EventStream,
+    KeyCode,
+    KeyEvent,
+    KeyEventKind,
+    KeyModifiers,
+};
+use crossterm::style::Color;
+use crossterm::{
+    execute,
+    style,
+    terminal,
+};
+use futures::StreamExt;
+use tokio::sync::mpsc::{
+    Receiver,
+    Sender,
+    channel,
+};
+use tracing::{
+    error,
+    warn,
+};
+
+use super::server_messenger::SamplingRequest;
+use super::util::shared_writer::SharedWriter;
+use crate::api_client::StreamingClient;
+use crate::api_client::model::{
+    AssistantResponseMessage,
+    ChatMessage,
+    ChatResponseStream,
+    ConversationState as FigConversationState,
+    UserInputMessage,
+};
+use crate::mcp_client::{
+    CreateMessageParams,
+    CreateMessageResult,
+    MessageContent,
+    Role,
+};
+
+/// Maximum number of characters of each message shown to the user when asking for approval
+const PREVIEW_MAX_LEN: usize = 500;
+
+/// Services the `sampling/createMessage` requests of mcp servers, one at a time.
+///
+/// Requests are queued until the chat loop services them, so that asking for approval never
+/// competes with the input prompt for the terminal. Every request has to be approved by the user
+/// before anything is sent to the model, and requests are declined outright when running
+/// non-interactively since there is nobody to ask.
+pub struct SamplingHandler {
+    requests: Receiver<SamplingRequest>,
+    client: StreamingClient,
+    output: SharedWriter,
+    interactive: bool,
+    model_id: Option<String>,
+}
+
+impl SamplingHandler {
+    /// Returns the handler along with the sender mcp servers queue their requests with.
+    pub fn new(
+        client: StreamingClient,
+        output: SharedWriter,
+        interactive: bool,
+        model_id: Option<String>,
+    ) -> (Sender<SamplingRequest>, Self) {
+        let (tx, rx) = channel::<SamplingRequest>(5);
+        (tx, Self {
+            requests: rx,
+            client,
+            output,
+            interactive,
+            model_id,
+        })
+    }
+
+    /// Services the requests that are already queued, without waiting for more.
+    pub async fn handle_queued(&mut self) {
+        while let Ok(request) = self.requests.try_recv() {
+            self.handle(request).await;
+        }
+    }
+
+    /// Services requests as they arrive, and never completes. Only to be raced against work that
+    /// does not read from the terminal, e.g. tools waiting on a completion requested by their
+    /// server.
+    pub async fn handle_incoming(&mut self) {
+        while let Some(request) = self.requests.recv().await {
+            self.handle(request).await;
+        }
+        std::future::pending::<()>().await;
+    }
+
+    async fn handle(&mut self, request: SamplingRequest) {
+        let SamplingRequest {
+            server_name,
+            params,
+            responder,
+        } = request;
+        let result = if !self.interactive {
+            Err("Sampling requests cannot be approved in non-interactive mode".to_string())
+        } else {
+            match ask_for_approval(&server_name, &params, &mut self.output).await {
+                Ok(true) => create_message(&self.client, params, self.model_id.clone()).await,
+                Ok(false) => Err("User rejected the sampling request".to_string()),
+                Err(e) => {
+                    error!("Failed to ask for sampling approval: {:?}", e);
+                    Err(format!("Failed to obtain approval: {e}"))
+                },
+            }
+        };
+        if let Err(e) = &result {
+            warn!("Sampling request from {server_name} failed: {e}");
+        }
+        let _ = responder.send(result);
+    }
+}
+
+async fn ask_for_approval(
+    server_name: &str,
+    params: &CreateMessageParams,
+    output: &mut SharedWriter,
+) -> eyre::Result<bool> {
+    execute!(
+        output,
+        style::Print("\n"),
+        style::SetForegroundColor(Color::Green),
+        style::Print(server_name),
+        style::ResetColor,
+        style::Print(" is requesting a completion from the model:\n"),
+    )?;
+    if let Some(system_prompt) = &params.system_prompt {
+        execute!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("system: "),
+            style::ResetColor,
+            style::Print(preview(system_prompt)),
+            style::Print("\n"),
+        )?;
+    }
+    for message in &params.messages {
+        execute!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!("{}: ", message.role)),
+            style::ResetColor,
+            style::Print(preview(&message.content.to_string())),
+            style::Print("\n"),
+        )?;
+    }
+
+    execute!(
+        output,
+        style::SetForegroundColor(Color::Magenta),
+        style::Print("Allow this request? "),
+        style::ResetColor,
+        style::Print("[y/N] "),
+    )?;
+    let approved = read_confirmation().await?;
+    execute!(output, style::Print(if approved { "yes\n" } else { "no\n" }))?;
+    Ok(approved)
+}
+
+/// Reads key presses until the request is approved with y, or declined with n, enter, escape or
+/// ctrl+c.
+///
+/// The keys are read without blocking a thread, so that the prompt goes away once the future is
+/// dropped, e.g. when the tool that requested the completion completes or is interrupted, instead
+/// of reading the next line typed at the chat prompt.
+async fn read_confirmation() -> eyre::Result<bool> {
+    let _raw_mode = RawModeGuard::enable()?;
+    let mut events = EventStream::new();
+    while let Some(event) = events.next().await.transpose()? {
+        let Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind: KeyEventKind::Press,
+            ..
+        }) = event
+        else {
+            continue;
+        };
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(false),
+            KeyCode::Char('y' | 'Y') => return Ok(true),
+            KeyCode::Char('n' | 'N') | KeyCode::Enter | KeyCode::Esc => return Ok(false),
+            _ => {},
+        }
+    }
+    Ok(false)
+}
+
+/// Keeps the terminal in raw mode until dropped, including when the prompt is cancelled.
+struct RawModeGuard;
+
+impl RawModeGuard {
+    fn enable() -> eyre::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(Self)
+    }
+}
+
+impl Drop for RawModeGuard {
+    fn drop(&mut self) {
+        if let Err(err) = terminal::disable_raw_mode() {
+            error!(?err, "Failed to disable raw mode");
+        }
+    }
+}
+
+fn preview(text: &str) -> String {
+    match text.char_indices().nth(PREVIEW_MAX_LEN) {
+        Some((idx, _)) => format!("{}...", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+async fn create_message(
+    client: &StreamingClient,
+    params: CreateMessageParams,
+    model_id: Option<String>,
+) -> Result<CreateMessageResult, String> {
+    let conversation_state = sampling_conversation_state(params, model_id.clone())?;
+    let mut response = client
+        .send_message(conversation_state)
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut text = String::new();
+    while let Some(event) = response.recv().await.map_err(|e| e.to_string())? {
+        if let ChatResponseStream::AssistantResponseEvent { content } = event {
+            text.push_str(&content);
+        }
+    }
+    Ok(CreateMessageResult {
+        role: Role::Assistant,
+        content: MessageContent::Text { text },
+        model: model_id.unwrap_or_else(|| "default".to_string()),
+        stop_reason: Some("endTurn".to_string()),
+    })
+}
+
+/// Converts the messages of a sampling request into a standalone conversation (i.e. one that does
+/// not share any history with the chat session).
+///
+/// The backend expects alternating user and assistant messages, so consecutive messages of the
+/// same role are merged. The system prompt, if any, is prepended to the first user message.
+fn sampling_conversation_state(
+    params: CreateMessageParams,
+    model_id: Option<String>,
+) -> Result<FigConversationState, String> {
+    let mut merged = Vec::<(Role, String)>::new();
+    for message in params.messages {
+        let MessageContent::Text { text } = message.content else {
+            return Err("Only text content is supported for sampling".to_string());
+        };
+        match merged.last_mut() {
+            Some((role, content)) if *role == message.role => {
+                content.push_str("\n\n");
+                content.push_str(&text);
+            },
+            _ => merged.push((message.role, text)),
+        }
+    }
+    if merged.first().is_none_or(|(role, _)| *role != Role::User)
+        || merged.last().is_none_or(|(role, _)| *role != Role::User)
+    {
+        return Err("Sampling messages must start and end with a user message".to_string());
+    }
+    if let (Some(system_prompt), Some((_, content))) = (params.system_prompt, merged.first_mut()) {
+        *content = format!("{system_prompt}\n\n{content}");
+    }
+
+    let user_message = |content: String| UserInputMessage {
+        content,
+        user_input_message_context: None,
+        user_intent: None,
+        images: None,
+        model_id: model_id.clone(),
+    };
+    let (_, last) = merged.pop().ok_or("Sampling request contained no messages")?;
+    let history = merged
+        .into_iter()
+        .map(|(role, content)| match role {
+            Role::User => ChatMessage::UserInputMessage(user_message(content)),
+            Role::Assistant => ChatMessage::AssistantResponseMessage(AssistantResponseMessage {
+                message_id: None,
+                content,
+                tool_uses: None,
+            }),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(FigConversationState {
+        conversation_id: None,
+        user_input_message: user_message(last),
+        history: Some(history),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp_client::SamplingMessage;
+
+    fn params(messages: &[(Role, &str)], system_prompt: Option<&str>) -> CreateMessageParams {
+        serde_json::from_value(serde_json::json!({
+            "messages": messages
+                .iter()
+                .map(|(role, text)| SamplingMessage {
+                    role: role.clone(),
+                    content: MessageContent::Text { text: (*text).to_string() },
+                })
+                .collect::<Vec<_>>(),
+            "systemPrompt": system_prompt,
+            "maxTokens": 100,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sampling_conversation_state() {
+        let state = sampling_conversation_state(
+            params(
+                &[
+                    (Role::User, "one"),
+                    (Role::User, "two"),
+                    (Role::Assistant, "three"),
+                    (Role::User, "four"),
+                ],
+                Some("be brief"),
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(state.user_input_message.content, "four");
+        let history = state.history.unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(matches!(&history[0], ChatMessage::UserInputMessage(m) if m.content == "be brief\n\none\n\ntwo"));
+        assert!(matches!(&history[1], ChatMessage::AssistantResponseMessage(m) if m.content == "three"));
+
+        assert!(sampling_conversation_state(params(&[(Role::User, "a"), (Role::Assistant, "b")], None), None).is_err());
+        assert!(sampling_conversation_state(params(&[], None), None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_message() {
+        let client = StreamingClient::mock(vec![vec![
+            ChatResponseStream::AssistantResponseEvent {
+                content: "Hello".to_string(),
+            },
+            ChatResponseStream::AssistantResponseEvent {
+                content: " world".to_string(),
+            },
+        ]]);
+        let result = create_message(&client, params(&[(Role::User, "hi")], None), None)
+            .await
+            .unwrap();
+        assert_eq!(result.role, Role::Assistant);
+        assert!(matches!(result.content, MessageContent::Text { text } if text == "Hello world"));
+    }
+
+    #[tokio::test]
+    async fn test_sampling_handler_queues_requests() {
+        let (sender, mut handler) =
+            SamplingHandler::new(StreamingClient::mock(vec![]), SharedWriter::null(), false, None);
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        sender
+            .send(SamplingRequest {
+                server_name: "server".to_string(),
+                params: params(&[(Role::User, "hi")], None),
+                responder: tx,
+            })
+            .await
+            .unwrap();
+
+        // Nothing is answered until the chat loop services the queue.
+        assert!(rx.try_recv().is_err());
+        handler.handle_queued().await;
+        assert!(rx.await.unwrap().unwrap_err().contains("non-interactive"));
+    }
+}
@@ -1,15 +1,24 @@
+use std::sync::{
+    Arc,
+    RwLock as SyncRwLock,
+};
+
 use tokio::sync::mpsc::{
     Receiver,
     Sender,
     channel,
 };
+use tokio::sync::oneshot;
 
 use crate::mcp_client::{
+    CreateMessageParams,
+    CreateMessageResult,
     Messenger,
     MessengerError,
     PromptsListResult,
     ResourceTemplatesListResult,
     ResourcesListResult,
+    Root,
     ToolsListResult,
 };
 
@@ -37,9 +46,20 @@ pub enum UpdateEventMessage {
     },
 }
 
+/// A `sampling/createMessage` request from a server that is waiting on the chat session to
+/// obtain approval from the user and a completion from the model.
+#[derive(Debug)]
+pub struct SamplingRequest {
+    pub server_name: String,
+    pub params: CreateMessageParams,
+    pub responder: oneshot::Sender<Result<CreateMessageResult, String>>,
+}
+
 #[derive(Clone, Debug)]
 pub struct ServerMessengerBuilder {
     pub update_event_sender: Sender<UpdateEventMessage>,
+    pub roots: Arc<SyncRwLock<Vec<Root>>>,
+    pub sampling_request_sender: Option<Sender<SamplingRequest>>,
 }
 
 impl ServerMessengerBuilder {
@@ -47,6 +67,8 @@ impl ServerMessengerBuilder {
         let (tx, rx) = channel::<UpdateEventMessage>(capacity);
         let this = Self {
             update_event_sender: tx,
+            roots: Arc::new(SyncRwLock::new(Vec::new())),
+            sampling_request_sender: None,
         };
         (rx, this)
     }
@@ -55,6 +77,8 @@ impl ServerMessengerBuilder {
         ServerMessenger {
             server_name,
             update_event_sender: self.update_event_sender.clone(),
+            roots: self.roots.clone(),
+            sampling_request_sender: self.sampling_request_sender.clone(),
         }
     }
 }
@@ -63,6 +87,10 @@ impl ServerMessengerBuilder {
 pub struct ServerMessenger {
     pub server_name: String,
     pub update_event_sender: Sender<UpdateEventMessage>,
+    /// Roots shared by all servers, kept up to date by the tool manager
+    pub roots: Arc<SyncRwLock<Vec<Root>>>,
+    /// Absent when nothing is set up to service sampling, in which case requests are declined
+    pub sampling_request_sender: Option<Sender<SamplingRequest>>,
 }
 
 #[async_trait::async_trait]
@@ -127,6 +155,31 @@ impl Messenger for ServerMessenger {
             .map_err(|e| MessengerError::Custom(e.to_string()))?)
     }
 
+    async fn list_roots(&self) -> Result<Vec<Root>, MessengerError> {
+        self.roots
+            .read()
+            .map(|roots| roots.clone())
+            .map_err(|e| MessengerError::Custom(e.to_string()))
+    }
+
+    async fn create_message(&self, params: CreateMessageParams) -> Result<CreateMessageResult, MessengerError> {
+        let Some(sender) = &self.sampling_request_sender else {
+            return Err(MessengerError::Custom("Sampling is not supported".to_string()));
+        };
+        let (tx, rx) = oneshot::channel();
+        sender
+            .send(SamplingRequest {
+                server_name: self.server_name.clone(),
+                params,
+                responder: tx,
+            })
+            .await
+            .map_err(|e| MessengerError::Custom(e.to_string()))?;
+        rx.await
+            .map_err(|e| MessengerError::Custom(e.to_string()))?
+            .map_err(MessengerError::Custom)
+    }
+
     fn duplicate(&self) -> Box<dyn Messenger> {
         Box::new(self.clone())
     }
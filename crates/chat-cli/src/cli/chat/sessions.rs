@@ -0,0 +1,87 @@
+use std::io::Write;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Attribute,
+    Color,
+};
+use eyre::Result;
+
+use super::util::truncate_safe;
+use crate::database::SessionSummary;
+use crate::util::local_offset;
+
+/// Maximum number of sessions listed by `/sessions`.
+pub const SESSION_LIST_LIMIT: usize = 100;
+
+/// Length of the id prefix shown for sessions. Any unique prefix can be given to `--resume=<id>`.
+const SHORT_ID_LEN: usize = 8;
+
+/// Returns the prefix of the session id that is shown to the user.
+pub fn short_id(session: &SessionSummary) -> &str {
+    truncate_safe(&session.id, SHORT_ID_LEN)
+}
+
+/// Formats a unix timestamp in the local timezone.
+pub fn format_timestamp(timestamp: i64) -> String {
+    time::OffsetDateTime::from_unix_timestamp(timestamp)
+        .map(|date| date.to_offset(local_offset()))
+        .ok()
+        .and_then(|date| {
+            date.format(time::macros::format_description!(
+                "[year]-[month]-[day] [hour]:[minute]"
+            ))
+            .ok()
+        })
+        .unwrap_or_default()
+}
+
+/// Formats a session as a single line, starting with its short id.
+pub fn format_session(session: &SessionSummary) -> String {
+    format!(
+        "{}  {}  {}  ({})",
+        short_id(session),
+        format_timestamp(session.updated_at),
+        truncate_safe(&session.title, 60),
+        session.cwd
+    )
+}
+
+pub fn print_sessions(output: &mut impl Write, sessions: &[SessionSummary]) -> Result<()> {
+    if sessions.is_empty() {
+        queue!(
+            output,
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print("No sessions found.\n"),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+
+    for session in sessions {
+        queue!(
+            output,
+            style::SetAttribute(Attribute::Bold),
+            style::Print(short_id(session)),
+            style::SetAttribute(Attribute::Reset),
+            style::Print(format!("  {}\n", truncate_safe(&session.title, 80))),
+            style::SetForegroundColor(Color::DarkGrey),
+            style::Print(format!(
+                "          {} • {} • {} tokens in, {} tokens out{}\n",
+                format_timestamp(session.updated_at),
+                session.cwd,
+                session.input_tokens,
+                session.output_tokens,
+                session
+                    .model
+                    .as_deref()
+                    .map(|model| format!(" • {model}"))
+                    .unwrap_or_default()
+            )),
+            style::SetForegroundColor(Color::Reset),
+        )?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
@@ -24,6 +24,8 @@ use skim::prelude::*;
 use tempfile::NamedTempFile;
 
 use super::context::ContextManager;
+use super::sessions::format_session;
+use crate::database::SessionSummary;
 
 pub fn select_profile_with_skim(context_manager: &ContextManager) -> Result<Option<String>> {
     let profiles = context_manager.list_profiles_blocking()?;
@@ -32,6 +34,17 @@ pub fn select_profile_with_skim(context_manager: &ContextManager) -> Result<Opti
         .map(|selected| selected.and_then(|s| s.into_iter().next()))
 }
 
+/// Select a session using skim, returning the id of the selected session
+pub fn select_session_with_skim(sessions: &[SessionSummary]) -> Result<Option<String>> {
+    let lines = sessions.iter().map(format_session).collect::<Vec<_>>();
+
+    let selected = launch_skim_selector(&lines, "Select session: ", false)?;
+    Ok(selected
+        .and_then(|s| s.into_iter().next())
+        .and_then(|line| lines.iter().position(|l| *l == line))
+        .map(|i| sessions[i].id.clone()))
+}
+
 pub struct SkimCommandSelector {
     context_manager: Arc<ContextManager>,
     tool_names: Vec<String>,
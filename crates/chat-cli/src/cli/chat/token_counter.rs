@@ -1,4 +1,16 @@
+use std::collections::HashMap;
+use std::hash::{
+    DefaultHasher,
+    Hash,
+    Hasher,
+};
 use std::ops::Deref;
+use std::sync::{
+    LazyLock,
+    Mutex,
+};
+
+use tiktoken_rs::CoreBPE;
 
 use super::conversation_state::{
     BackendConversationState,
@@ -12,16 +24,16 @@ use super::message::{
     UserMessageContent,
 };
 
-#[derive(Debug, Clone, Copy)]
-pub struct CharCount(usize);
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TokenCount(usize);
 
-impl CharCount {
+impl TokenCount {
     pub fn value(&self) -> usize {
         self.0
     }
 }
 
-impl Deref for CharCount {
+impl Deref for TokenCount {
     type Target = usize;
 
     fn deref(&self) -> &Self::Target {
@@ -29,40 +41,29 @@ impl Deref for CharCount {
     }
 }
 
-impl From<usize> for CharCount {
+impl From<usize> for TokenCount {
     fn from(value: usize) -> Self {
         Self(value)
     }
 }
 
-impl std::ops::Add for CharCount {
-    type Output = CharCount;
+impl std::ops::Add for TokenCount {
+    type Output = TokenCount;
 
     fn add(self, rhs: Self) -> Self::Output {
         Self(self.value() + rhs.value())
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct TokenCount(usize);
-
-impl TokenCount {
-    pub fn value(&self) -> usize {
-        self.0
-    }
-}
-
-impl Deref for TokenCount {
-    type Target = usize;
-
-    fn deref(&self) -> &Self::Target {
-        &self.0
+impl std::ops::AddAssign for TokenCount {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
     }
 }
 
-impl From<CharCount> for TokenCount {
-    fn from(value: CharCount) -> Self {
-        Self(TokenCounter::count_tokens_char_count(value.value()))
+impl std::iter::Sum for TokenCount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |acc, v| acc + v)
     }
 }
 
@@ -72,180 +73,187 @@ impl std::fmt::Display for TokenCount {
     }
 }
 
+/// Contents shorter than this are counted again rather than cached, see [COUNT_CACHE].
+const COUNT_CACHE_MIN_LEN: usize = 256;
+/// Maximum number of cached counts, the cache is cleared once it is full.
+const COUNT_CACHE_MAX_ENTRIES: usize = 16_384;
+
+/// Token counts by the hash of the content that was counted.
+///
+/// The whole history is counted on every turn, so counting each history entry only once keeps
+/// the cost of a turn from growing with the length of the conversation. Entries are keyed by
+/// their content rather than their position, since they are edited in place when tool results
+/// are elided or the history is compacted.
+static COUNT_CACHE: LazyLock<Mutex<HashMap<u64, usize>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
 pub struct TokenCounter;
 
 impl TokenCounter {
-    pub const TOKEN_TO_CHAR_RATIO: usize = 4;
-
-    /// Estimates the number of tokens in the input content.
-    /// Currently uses a simple heuristic: content length / TOKEN_TO_CHAR_RATIO
+    /// Counts the number of tokens in the input content.
     ///
-    /// Rounds up to the nearest multiple of 10 to avoid giving users a false sense of precision.
+    /// The tokenizer used by the Claude models is not published, so this uses the bundled
+    /// `cl100k_base` BPE encoding. The count is an estimate, which can differ from the number of
+    /// tokens the model sees.
     pub fn count_tokens(content: &str) -> usize {
-        Self::count_tokens_char_count(content.len())
+        if content.is_empty() {
+            return 0;
+        }
+        if content.len() < COUNT_CACHE_MIN_LEN {
+            return Self::bpe().encode_ordinary(content).len();
+        }
+
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let key = hasher.finish();
+        if let Some(count) = COUNT_CACHE.lock().ok().and_then(|cache| cache.get(&key).copied()) {
+            return count;
+        }
+
+        let count = Self::bpe().encode_ordinary(content).len();
+        if let Ok(mut cache) = COUNT_CACHE.lock() {
+            if cache.len() >= COUNT_CACHE_MAX_ENTRIES {
+                cache.clear();
+            }
+            cache.insert(key, count);
+        }
+        count
     }
 
-    fn count_tokens_char_count(count: usize) -> usize {
-        (count / Self::TOKEN_TO_CHAR_RATIO + 5) / 10 * 10
+    /// Counts the number of tokens in a JSON document, as it is serialized when sent to the model.
+    pub fn count_value_tokens(document: &serde_json::Value) -> usize {
+        match document {
+            serde_json::Value::String(s) => Self::count_tokens(s),
+            other => Self::count_tokens(&other.to_string()),
+        }
     }
 
-    pub const fn token_to_chars(token: usize) -> usize {
-        token * Self::TOKEN_TO_CHAR_RATIO
+    fn bpe() -> &'static CoreBPE {
+        tiktoken_rs::cl100k_base_singleton()
     }
 }
 
-/// A trait for types that represent some number of characters (aka bytes). For use in calculating
-/// context window size utilization.
-pub trait CharCounter {
-    /// Returns the number of characters contained within this type.
-    ///
-    /// One "character" is essentially the same as one "byte"
-    fn char_count(&self) -> CharCount;
+/// A trait for types that represent some number of tokens. For use in calculating context window
+/// size utilization.
+pub trait TokenCounted {
+    /// Returns the number of tokens contained within this type.
+    fn token_count(&self) -> TokenCount;
 }
 
-impl CharCounter for BackendConversationState<'_> {
-    fn char_count(&self) -> CharCount {
-        self.calculate_conversation_size().char_count()
+impl TokenCounted for BackendConversationState<'_> {
+    fn token_count(&self) -> TokenCount {
+        self.calculate_conversation_size().token_count()
     }
 }
 
-impl CharCounter for ConversationSize {
-    fn char_count(&self) -> CharCount {
-        self.user_messages + self.assistant_messages + self.context_messages
+impl TokenCounted for ConversationSize {
+    fn token_count(&self) -> TokenCount {
+        self.user_messages + self.assistant_messages + self.context_messages + self.tools
     }
 }
 
-impl CharCounter for UserMessage {
-    fn char_count(&self) -> CharCount {
-        let mut total_chars = 0;
-        total_chars += self.additional_context().len();
+impl TokenCounted for UserMessage {
+    fn token_count(&self) -> TokenCount {
+        let mut total_tokens = 0;
+        total_tokens += TokenCounter::count_tokens(self.additional_context());
         match self.content() {
             UserMessageContent::Prompt { prompt } => {
-                total_chars += prompt.len();
+                total_tokens += TokenCounter::count_tokens(prompt);
             },
             UserMessageContent::CancelledToolUses {
                 prompt,
                 tool_use_results,
             } => {
-                total_chars += prompt.as_ref().map_or(0, String::len);
-                total_chars += tool_use_results.as_slice().char_count().0;
+                total_tokens += prompt.as_deref().map_or(0, TokenCounter::count_tokens);
+                total_tokens += *tool_use_results.as_slice().token_count();
             },
             UserMessageContent::ToolUseResults { tool_use_results } => {
-                total_chars += tool_use_results.as_slice().char_count().0;
+                total_tokens += *tool_use_results.as_slice().token_count();
             },
         }
-        total_chars.into()
+        total_tokens.into()
     }
 }
 
-impl CharCounter for AssistantMessage {
-    fn char_count(&self) -> CharCount {
-        let mut total_chars = 0;
-        total_chars += self.content().len();
+impl TokenCounted for AssistantMessage {
+    fn token_count(&self) -> TokenCount {
+        let mut total_tokens = 0;
+        total_tokens += TokenCounter::count_tokens(self.content());
         if let Some(tool_uses) = self.tool_uses() {
-            total_chars += tool_uses
+            total_tokens += tool_uses
                 .iter()
-                .map(|v| calculate_value_char_count(&v.args))
-                .reduce(|acc, e| acc + e)
-                .unwrap_or_default();
+                .map(|v| TokenCounter::count_value_tokens(&v.args))
+                .sum::<usize>();
         }
-        total_chars.into()
+        total_tokens.into()
     }
 }
 
-impl CharCounter for &[ToolUseResult] {
-    fn char_count(&self) -> CharCount {
+impl TokenCounted for &[ToolUseResult] {
+    fn token_count(&self) -> TokenCount {
         self.iter()
             .flat_map(|v| &v.content)
-            .fold(0, |acc, v| {
-                acc + match v {
-                    ToolUseResultBlock::Json(v) => calculate_value_char_count(v),
-                    ToolUseResultBlock::Text(s) => s.len(),
-                }
+            .map(|v| match v {
+                ToolUseResultBlock::Json(v) => TokenCounter::count_value_tokens(v),
+                ToolUseResultBlock::Text(s) => TokenCounter::count_tokens(s),
             })
+            .sum::<usize>()
             .into()
     }
 }
 
-fn calculate_value_char_count(document: &serde_json::Value) -> usize {
-    match document {
-        serde_json::Value::Null => 1,
-        serde_json::Value::Bool(_) => 1,
-        serde_json::Value::Number(_) => 1,
-        serde_json::Value::String(s) => s.len(),
-        serde_json::Value::Array(vec) => vec.iter().fold(0, |acc, v| acc + calculate_value_char_count(v)),
-        serde_json::Value::Object(map) => map.values().fold(0, |acc, v| acc + calculate_value_char_count(v)),
-    }
-}
-
 #[cfg(test)]
 mod tests {
-
     use super::*;
 
     #[test]
     fn test_token_count() {
-        let text = "This is a test sentence.";
-        let count = TokenCounter::count_tokens(text);
-        assert_eq!(count, (text.len() / 3 + 5) / 10 * 10);
+        assert_eq!(TokenCounter::count_tokens(""), 0);
+        assert_eq!(TokenCounter::count_tokens("This is a test sentence."), 6);
+        assert!(TokenCounter::count_tokens("fn main() {\n    println!(\"hello\");\n}\n") > 5);
     }
 
     #[test]
-    fn test_calculate_value_char_count() {
-        // Test simple types
+    fn test_token_count_cached() {
+        let content = "This is a test sentence. ".repeat(20);
+        let count = TokenCounter::bpe().encode_ordinary(&content).len();
+        assert_eq!(TokenCounter::count_tokens(&content), count);
+        assert_eq!(TokenCounter::count_tokens(&content), count);
+
+        // Edited content is counted again.
+        let edited = format!("{content}And another one.");
         assert_eq!(
-            calculate_value_char_count(&serde_json::Value::String("hello".to_string())),
-            5
+            TokenCounter::count_tokens(&edited),
+            TokenCounter::bpe().encode_ordinary(&edited).len()
         );
+    }
+
+    #[test]
+    fn test_count_value_tokens() {
+        // Strings are counted without surrounding quotes.
         assert_eq!(
-            calculate_value_char_count(&serde_json::Value::Number(serde_json::Number::from(123))),
-            1
-        );
-        assert_eq!(calculate_value_char_count(&serde_json::Value::Bool(true)), 1);
-        assert_eq!(calculate_value_char_count(&serde_json::Value::Null), 1);
-
-        // Test array
-        let array = serde_json::Value::Array(vec![
-            serde_json::Value::String("test".to_string()),
-            serde_json::Value::Number(serde_json::Number::from(42)),
-            serde_json::Value::Bool(false),
-        ]);
-        assert_eq!(calculate_value_char_count(&array), 6); // "test" (4) + Number (1) + Bool (1)
-
-        // Test object
-        let mut obj = serde_json::Map::new();
-        obj.insert("key1".to_string(), serde_json::Value::String("value1".to_string()));
-        obj.insert(
-            "key2".to_string(),
-            serde_json::Value::Number(serde_json::Number::from(99)),
-        );
-        let object = serde_json::Value::Object(obj);
-        assert_eq!(calculate_value_char_count(&object), 7); // "value1" (6) + Number (1)
-
-        // Test nested structure
-        let mut nested_obj = serde_json::Map::new();
-        let mut inner_obj = serde_json::Map::new();
-        inner_obj.insert(
-            "inner_key".to_string(),
-            serde_json::Value::String("inner_value".to_string()),
+            TokenCounter::count_value_tokens(&serde_json::Value::String("hello".to_string())),
+            TokenCounter::count_tokens("hello")
         );
-        nested_obj.insert("outer_key".to_string(), serde_json::Value::Object(inner_obj));
-        nested_obj.insert(
-            "array_key".to_string(),
-            serde_json::Value::Array(vec![
-                serde_json::Value::String("item1".to_string()),
-                serde_json::Value::String("item2".to_string()),
-            ]),
+        assert_eq!(
+            TokenCounter::count_value_tokens(&serde_json::Value::Array(vec![])),
+            TokenCounter::count_tokens("[]")
         );
 
-        let complex = serde_json::Value::Object(nested_obj);
-        assert_eq!(calculate_value_char_count(&complex), 21); // "inner_value" (11) + "item1" (5) + "item2" (5)
-
-        // Test empty structures
-        assert_eq!(calculate_value_char_count(&serde_json::Value::Array(vec![])), 0);
+        let object = serde_json::json!({
+            "path": "/tmp/file.txt",
+            "mode": "Line",
+            "start_line": 10,
+        });
         assert_eq!(
-            calculate_value_char_count(&serde_json::Value::Object(serde_json::Map::new())),
-            0
+            TokenCounter::count_value_tokens(&object),
+            TokenCounter::count_tokens(&object.to_string())
         );
     }
+
+    #[test]
+    fn test_token_count_sum() {
+        let total: TokenCount = [1, 2, 3].into_iter().map(TokenCount::from).sum();
+        assert_eq!(total, TokenCount::from(6));
+        assert_eq!(TokenCount::from(4) + TokenCount::from(5), TokenCount::from(9));
+    }
 }
@@ -65,9 +65,13 @@ use crate::api_client::model::{
     ToolResultContentBlock,
     ToolResultStatus,
 };
-use crate::cli::chat::command::PromptsGetCommand;
+use crate::cli::chat::command::{
+    PromptsGetCommand,
+    ResourcesGetCommand,
+};
 use crate::cli::chat::message::AssistantToolUse;
 use crate::cli::chat::server_messenger::{
+    SamplingRequest,
     ServerMessengerBuilder,
     UpdateEventMessage,
 };
@@ -76,10 +80,12 @@ use crate::cli::chat::tools::custom_tool::{
     CustomToolClient,
     CustomToolConfig,
 };
+use crate::cli::chat::tools::delegate::Delegate;
 use crate::cli::chat::tools::execute::ExecuteCommand;
 use crate::cli::chat::tools::fs_read::FsRead;
 use crate::cli::chat::tools::fs_write::FsWrite;
 use crate::cli::chat::tools::gh_issue::GhIssue;
+use crate::cli::chat::tools::knowledge::Knowledge;
 use crate::cli::chat::tools::thinking::Thinking;
 use crate::cli::chat::tools::use_aws::UseAws;
 use crate::cli::chat::tools::{
@@ -93,6 +99,11 @@ use crate::mcp_client::{
     JsonRpcResponse,
     Messenger,
     PromptGet,
+    ResourceGet,
+    ResourceReadContents,
+    ResourceReadResult,
+    ResourceTemplateGet,
+    Root,
 };
 use crate::platform::Context;
 use crate::telemetry::TelemetryThread;
@@ -130,6 +141,26 @@ pub enum GetPromptError {
     General(#[from] eyre::Report),
 }
 
+#[derive(Debug, Error)]
+pub enum GetResourceError {
+    #[error("Resource {0} does not exist")]
+    ResourceNotFound(String),
+    #[error("Resource {0} is offered by more than one server. Use one of the following {1}")]
+    AmbiguousResource(String, String),
+    #[error("Missing client")]
+    MissingClient,
+    #[error("Missing argument for {0}")]
+    MissingArgument(String),
+    #[error("Unexpected argument {0}")]
+    UnexpectedArgument(String),
+    #[error("Malformed resource template {0}")]
+    MalformedTemplate(String),
+    #[error("Error encountered while reading resource: {0}")]
+    Server(String),
+    #[error(transparent)]
+    General(#[from] eyre::Report),
+}
+
 /// Messages used for communication between the tool initialization thread and the loading
 /// display thread. These messages control the visual loading indicators shown to
 /// the user during tool initialization.
@@ -246,6 +277,7 @@ pub struct ToolManagerBuilder {
     mcp_server_config: Option<McpServerConfig>,
     prompt_list_sender: Option<std::sync::mpsc::Sender<Vec<String>>>,
     prompt_list_receiver: Option<std::sync::mpsc::Receiver<Option<String>>>,
+    sampling_request_sender: Option<tokio::sync::mpsc::Sender<SamplingRequest>>,
     conversation_id: Option<String>,
     is_interactive: bool,
 }
@@ -266,6 +298,11 @@ impl ToolManagerBuilder {
         self
     }
 
+    pub fn sampling_request_sender(mut self, sender: tokio::sync::mpsc::Sender<SamplingRequest>) -> Self {
+        self.sampling_request_sender.replace(sender);
+        self
+    }
+
     pub fn conversation_id(mut self, conversation_id: &str) -> Self {
         self.conversation_id.replace(conversation_id.to_string());
         self
@@ -438,12 +475,16 @@ impl ToolManagerBuilder {
         let has_new_stuff_clone = has_new_stuff.clone();
         let pending = Arc::new(RwLock::new(HashSet::<String>::new()));
         let pending_clone = pending.clone();
-        let (mut msg_rx, messenger_builder) = ServerMessengerBuilder::new(20);
+        let (mut msg_rx, mut messenger_builder) = ServerMessengerBuilder::new(20);
+        messenger_builder.sampling_request_sender = self.sampling_request_sender.take();
+        let roots = messenger_builder.roots.clone();
         let telemetry_clone = telemetry.clone();
         let notify = Arc::new(Notify::new());
         let notify_weak = Arc::downgrade(&notify);
         let load_record = Arc::new(Mutex::new(HashMap::<String, Vec<LoadingRecord>>::new()));
         let load_record_clone = load_record.clone();
+        let resources = Arc::new(RwLock::new(HashMap::<String, ServerResources>::new()));
+        let resources_clone = resources.clone();
         tokio::spawn(async move {
             let mut record_temp_buf = Vec::<u8>::new();
             let mut initialized = HashSet::<String>::new();
@@ -585,14 +626,33 @@ impl ToolManagerBuilder {
                         server_name: _,
                         result: _,
                     } => {},
-                    UpdateEventMessage::ResourcesListResult {
-                        server_name: _,
-                        result: _,
-                    } => {},
-                    UpdateEventMessage::ResourceTemplatesListResult {
-                        server_name: _,
-                        result: _,
-                    } => {},
+                    UpdateEventMessage::ResourcesListResult { server_name, result } => match result {
+                        Ok(result) => {
+                            let list = result
+                                .resources
+                                .into_iter()
+                                .filter_map(|v| serde_json::from_value::<ResourceGet>(v).ok())
+                                .collect::<Vec<_>>();
+                            resources_clone.write().await.entry(server_name).or_default().resources = list;
+                        },
+                        Err(e) => warn!("Error retrieving resources for server {server_name}: {:?}", e),
+                    },
+                    UpdateEventMessage::ResourceTemplatesListResult { server_name, result } => match result {
+                        Ok(result) => {
+                            let list = result
+                                .resource_templates
+                                .into_iter()
+                                .filter_map(|v| serde_json::from_value::<ResourceTemplateGet>(v).ok())
+                                .collect::<Vec<_>>();
+                            resources_clone
+                                .write()
+                                .await
+                                .entry(server_name)
+                                .or_default()
+                                .resource_templates = list;
+                        },
+                        Err(e) => warn!("Error retrieving resource templates for server {server_name}: {:?}", e),
+                    },
                     UpdateEventMessage::InitStart { server_name } => {
                         pending_clone.write().await.insert(server_name.clone());
                         loading_servers.insert(server_name, std::time::Instant::now());
@@ -716,6 +776,8 @@ impl ToolManagerBuilder {
             conversation_id,
             clients,
             prompts,
+            resources,
+            roots,
             pending_clients: pending,
             notify: Some(notify),
             loading_status_sender,
@@ -740,6 +802,45 @@ pub struct PromptBundle {
     pub prompt_get: PromptGet,
 }
 
+#[derive(Clone, Debug, Default)]
+/// Resources and resource templates offered by a single server
+pub struct ServerResources {
+    pub resources: Vec<ResourceGet>,
+    pub resource_templates: Vec<ResourceTemplateGet>,
+}
+
+impl ServerResources {
+    /// Looks for a resource or resource template that goes by the identifier given (either by its
+    /// uri or its name) and returns the uri to be read.
+    /// Returns [None] if nothing offered by this server matches.
+    fn resolve(&self, identifier: &str, arguments: &[String]) -> Option<Result<String, GetResourceError>> {
+        if let Some(resource) = self
+            .resources
+            .iter()
+            .find(|r| r.uri == identifier || r.name == identifier)
+        {
+            return Some(match arguments.first() {
+                Some(arg) => Err(GetResourceError::UnexpectedArgument(arg.clone())),
+                None => Ok(resource.uri.clone()),
+            });
+        }
+        self.resource_templates
+            .iter()
+            .find(|t| t.uri_template == identifier || t.name == identifier)
+            .map(|template| expand_uri_template(&template.uri_template, arguments))
+    }
+}
+
+#[derive(Clone, Debug)]
+/// The outcome of a `resources/read`, along with where it came from
+pub struct ResourceBundle {
+    /// The server name from which the resource is offered
+    pub server_name: String,
+    /// The uri that was read. For templates this is the uri after expansion.
+    pub uri: String,
+    pub contents: Vec<ResourceReadContents>,
+}
+
 /// Categorizes different types of tool name validation failures:
 /// - `TooLong`: The tool name exceeds the maximum allowed length
 /// - `IllegalChar`: The tool name contains characters that are not allowed
@@ -787,6 +888,17 @@ pub struct ToolManager {
     /// cases where multiple servers offer prompts with the same name.
     pub prompts: Arc<SyncRwLock<HashMap<String, Vec<PromptBundle>>>>,
 
+    /// Resources and resource templates collected from different servers.
+    /// Key: server name
+    /// Value: what the server offers as of the last `resources/list` and
+    /// `resources/templates/list` (which are re-queried upon
+    /// `notifications/resources/list_changed`)
+    pub resources: Arc<RwLock<HashMap<String, ServerResources>>>,
+
+    /// Roots exposed to servers via `roots/list`. This is shared with the messengers assigned to
+    /// each client.
+    roots: Arc<SyncRwLock<Vec<Root>>>,
+
     /// A notifier to understand if the initial loading has completed.
     /// This is only used for initial loading and is discarded after.
     notify: Option<Arc<Notify>>,
@@ -826,6 +938,8 @@ impl Clone for ToolManager {
             has_new_stuff: self.has_new_stuff.clone(),
             new_tool_specs: self.new_tool_specs.clone(),
             prompts: self.prompts.clone(),
+            resources: self.resources.clone(),
+            roots: self.roots.clone(),
             tn_map: self.tn_map.clone(),
             schema: self.schema.clone(),
             is_interactive: self.is_interactive,
@@ -876,6 +990,7 @@ impl ToolManager {
                     },
                         "required": ["command"]})),
                     tool_origin: ToolOrigin::Native,
+                    annotations: None,
                 });
             }
 
@@ -995,6 +1110,8 @@ impl ToolManager {
             "use_aws" => Tool::UseAws(serde_json::from_value::<UseAws>(value.args).map_err(map_err)?),
             "report_issue" => Tool::GhIssue(serde_json::from_value::<GhIssue>(value.args).map_err(map_err)?),
             "thinking" => Tool::Thinking(serde_json::from_value::<Thinking>(value.args).map_err(map_err)?),
+            "knowledge" => Tool::Knowledge(serde_json::from_value::<Knowledge>(value.args).map_err(map_err)?),
+            "delegate" => Tool::Delegate(serde_json::from_value::<Delegate>(value.args).map_err(map_err)?),
             // Note that this name is namespaced with server_name{DELIMITER}tool_name
             name => {
                 // Note: tn_map also has tools that underwent no transformation. In otherwords, if
@@ -1038,6 +1155,12 @@ impl ToolManager {
                         }
                     },
                 }?;
+                let read_only = self
+                    .schema
+                    .get(name)
+                    .and_then(|spec| spec.annotations.as_ref())
+                    .and_then(|annotations| annotations.read_only_hint)
+                    .unwrap_or(false);
                 let name = self.tn_map.get(name).map_or(name, String::as_str);
                 let (server_name, tool_name) = name.split_once(NAMESPACE_DELIMITER).ok_or(ToolResult {
                     tool_use_id: value.id.clone(),
@@ -1069,6 +1192,7 @@ impl ToolManager {
                     client: client.clone(),
                     method: "tools/call".to_owned(),
                     params: Some(params),
+                    read_only,
                 };
                 Tool::Custom(custom_tool)
             },
@@ -1275,6 +1399,91 @@ impl ToolManager {
         Ok(())
     }
 
+    /// Replaces the roots exposed to servers. Servers are only notified when the roots have
+    /// actually changed.
+    pub async fn update_roots(&self, roots: Vec<Root>) {
+        {
+            let Ok(mut current) = self.roots.write() else {
+                error!("Failed to obtain write lock for roots");
+                return;
+            };
+            if *current == roots {
+                return;
+            }
+            *current = roots;
+        }
+        for (server_name, client) in &self.clients {
+            if let Err(e) = client.notify("roots/list_changed", None).await {
+                warn!("Failed to notify {server_name} of roots change: {:?}", e);
+            }
+        }
+    }
+
+    /// Reads the resource specified by its uri or name, expanding resource templates with the
+    /// arguments supplied.
+    /// The name can optionally be prefixed by the server name (i.e. {server_name}/{name}) to
+    /// disambiguate resources offered by more than one server.
+    pub async fn read_resource(&self, get_command: ResourcesGetCommand) -> Result<ResourceBundle, GetResourceError> {
+        let ResourcesGetCommand { name, arguments } = get_command;
+        let (server_name, uri) = {
+            let resources = self.resources.read().await;
+            let (server_name, identifier) = match name.split_once('/') {
+                Some((server_name, identifier)) if resources.contains_key(server_name) => {
+                    (Some(server_name), identifier)
+                },
+                _ => (None, name.as_str()),
+            };
+            let candidates = resources
+                .iter()
+                .filter(|(sn, _)| server_name.is_none_or(|server_name| server_name == sn.as_str()))
+                .collect::<Vec<_>>();
+            let mut matches = candidates
+                .iter()
+                .filter_map(|(sn, server_resources)| {
+                    server_resources
+                        .resolve(identifier, &arguments)
+                        .map(|uri| ((*sn).clone(), uri))
+                })
+                .collect::<Vec<_>>();
+            if matches.len() > 1 {
+                return Err(GetResourceError::AmbiguousResource(
+                    name.clone(),
+                    matches.iter().fold("\n".to_string(), |mut acc, (sn, _)| {
+                        acc.push_str(&format!("- {sn}/{identifier}\n"));
+                        acc
+                    }),
+                ));
+            } else if let Some((server_name, uri)) = matches.pop() {
+                (server_name, uri?)
+            } else if identifier.contains("://") && candidates.len() == 1 {
+                // Servers are not obligated to list everything they are able to serve. If the user
+                // has typed out a uri and there is only one place to look, we shall just ask.
+                (candidates[0].0.clone(), identifier.to_string())
+            } else {
+                return Err(GetResourceError::ResourceNotFound(name));
+            }
+        };
+        let client = self.clients.get(&server_name).ok_or(GetResourceError::MissingClient)?;
+        let resp = client
+            .request("resources/read", Some(serde_json::json!({ "uri": uri })))
+            .await?;
+        if let Some(error) = resp.error {
+            return Err(GetResourceError::Server(
+                serde_json::to_string(&error).unwrap_or_else(|_| format!("{:?}", error)),
+            ));
+        }
+        let result = resp.result.ok_or(GetResourceError::Server(
+            "result field missing from response".to_string(),
+        ))?;
+        let ResourceReadResult { contents } =
+            serde_json::from_value::<ResourceReadResult>(result).map_err(|e| eyre::eyre!(e))?;
+        Ok(ResourceBundle {
+            server_name,
+            uri,
+            contents,
+        })
+    }
+
     pub async fn pending_clients(&self) -> Vec<String> {
         self.pending_clients.read().await.iter().cloned().collect::<Vec<_>>()
     }
@@ -1406,6 +1615,144 @@ fn sanitize_name(orig: String, regex: &regex::Regex, hasher: &mut impl Hasher) -
     }
 }
 
+/// Expands a uri template as per RFC 6570 (without support for prefix and explode modifiers,
+/// which are accepted but ignored).
+///
+/// Arguments are either positional, in which case they are assigned in the order the variables
+/// appear in the template, or in the form of {name}={value}.
+/// Variables of query-like expressions (i.e. ?, &, ;, #) are optional. The rest are required.
+fn expand_uri_template(template: &str, arguments: &[String]) -> Result<String, GetResourceError> {
+    use percent_encoding::{
+        AsciiSet,
+        NON_ALPHANUMERIC,
+        utf8_percent_encode,
+    };
+
+    const UNRESERVED: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+    const RESERVED: &AsciiSet = &UNRESERVED
+        .remove(b':')
+        .remove(b'/')
+        .remove(b'?')
+        .remove(b'#')
+        .remove(b'[')
+        .remove(b']')
+        .remove(b'@')
+        .remove(b'!')
+        .remove(b'$')
+        .remove(b'&')
+        .remove(b'\'')
+        .remove(b'(')
+        .remove(b')')
+        .remove(b'*')
+        .remove(b'+')
+        .remove(b',')
+        .remove(b';')
+        .remove(b'=');
+
+    let malformed = || GetResourceError::MalformedTemplate(template.to_string());
+
+    // Split the template into literals and expressions: (operator, variable names)
+    let mut parts = Vec::<Result<&str, (Option<char>, Vec<&str>)>>::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').ok_or_else(malformed)? + start;
+        parts.push(Ok(&rest[..start]));
+        let expression = &rest[start + 1..end];
+        let (operator, vars) = match expression.chars().next() {
+            Some(c @ ('+' | '#' | '.' | '/' | ';' | '?' | '&')) => (Some(c), &expression[1..]),
+            Some(_) => (None, expression),
+            None => return Err(malformed()),
+        };
+        let vars = vars
+            .split(',')
+            .map(|v| v.split(':').next().unwrap_or(v).trim_end_matches('*'))
+            .collect::<Vec<_>>();
+        if vars.iter().any(|v| v.is_empty()) {
+            return Err(malformed());
+        }
+        parts.push(Err((operator, vars)));
+        rest = &rest[end + 1..];
+    }
+    if rest.contains('}') {
+        return Err(malformed());
+    }
+    parts.push(Ok(rest));
+
+    // Assign the arguments to variables
+    let var_names = parts
+        .iter()
+        .filter_map(|p| p.as_ref().err())
+        .flat_map(|(_, vars)| vars.iter().copied())
+        .collect::<Vec<_>>();
+    let mut values = HashMap::<&str, &str>::new();
+    let mut positional = Vec::<&str>::new();
+    for arg in arguments {
+        match arg.split_once('=') {
+            Some((name, value)) if var_names.contains(&name) => {
+                values.insert(name, value);
+            },
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let mut positional = positional.into_iter();
+    for name in &var_names {
+        if values.contains_key(name) {
+            continue;
+        }
+        match positional.next() {
+            Some(value) => {
+                values.insert(name, value);
+            },
+            None => break,
+        }
+    }
+    if let Some(extra) = positional.next() {
+        return Err(GetResourceError::UnexpectedArgument(extra.to_string()));
+    }
+
+    let mut uri = String::new();
+    for part in parts {
+        let (operator, vars) = match part {
+            Ok(literal) => {
+                uri.push_str(literal);
+                continue;
+            },
+            Err(expression) => expression,
+        };
+        let (first, separator, named, allowed) = match operator {
+            None => ("", ",", false, UNRESERVED),
+            Some('+') => ("", ",", false, RESERVED),
+            Some('#') => ("#", ",", false, RESERVED),
+            Some('.') => (".", ".", false, UNRESERVED),
+            Some('/') => ("/", "/", false, UNRESERVED),
+            Some(';') => (";", ";", true, UNRESERVED),
+            Some('?') => ("?", "&", true, UNRESERVED),
+            Some(_) => ("&", "&", true, UNRESERVED),
+        };
+        let is_optional = matches!(operator, Some('#' | ';' | '?' | '&'));
+        let mut expanded = Vec::<String>::new();
+        for var in vars {
+            let Some(value) = values.get(var) else {
+                if is_optional {
+                    continue;
+                }
+                return Err(GetResourceError::MissingArgument(var.to_string()));
+            };
+            let value = utf8_percent_encode(value, allowed).to_string();
+            expanded.push(match (named, operator) {
+                (true, Some(';')) if value.is_empty() => var.to_string(),
+                (true, _) => format!("{var}={value}"),
+                (false, _) => value,
+            });
+        }
+        if !expanded.is_empty() {
+            uri.push_str(first);
+            uri.push_str(&expanded.join(separator));
+        }
+    }
+    Ok(uri)
+}
+
 fn queue_success_message(name: &str, time_taken: &str, output: &mut impl Write) -> eyre::Result<()> {
     Ok(queue!(
         output,
@@ -1577,4 +1924,46 @@ mod tests {
         let sanitized = sanitize_name(with_delim, &regex, &mut hasher);
         assert_eq!(sanitized, "abc");
     }
+
+    #[test]
+    fn test_expand_uri_template() {
+        let args = |args: &[&str]| args.iter().map(|a| (*a).to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            expand_uri_template("db://{schema}/{table}", &args(&["public", "users"])).unwrap(),
+            "db://public/users"
+        );
+        assert_eq!(
+            expand_uri_template("db://{schema}/{table}", &args(&["table=users", "public"])).unwrap(),
+            "db://public/users"
+        );
+        assert_eq!(
+            expand_uri_template("search://{term}", &args(&["hello world/x"])).unwrap(),
+            "search://hello%20world%2Fx"
+        );
+        assert_eq!(
+            expand_uri_template("file://{+path}", &args(&["/var/log/app.log"])).unwrap(),
+            "file:///var/log/app.log"
+        );
+        assert_eq!(
+            expand_uri_template("api://items{?page,limit}", &args(&["limit=10"])).unwrap(),
+            "api://items?limit=10"
+        );
+        assert_eq!(
+            expand_uri_template("repo://{owner}{/path*}", &args(&["aws", "src"])).unwrap(),
+            "repo://aws/src"
+        );
+        assert!(matches!(
+            expand_uri_template("db://{schema}/{table}", &args(&["public"])),
+            Err(GetResourceError::MissingArgument(name)) if name == "table"
+        ));
+        assert!(matches!(
+            expand_uri_template("db://{schema}", &args(&["public", "extra"])),
+            Err(GetResourceError::UnexpectedArgument(arg)) if arg == "extra"
+        ));
+        assert!(matches!(
+            expand_uri_template("db://{schema", &[]),
+            Err(GetResourceError::MalformedTemplate(_))
+        ));
+    }
 }
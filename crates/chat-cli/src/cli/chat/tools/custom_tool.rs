@@ -7,7 +7,10 @@ use crossterm::{
     queue,
     style,
 };
-use eyre::Result;
+use eyre::{
+    Result,
+    bail,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -21,31 +24,61 @@ use crate::cli::chat::token_counter::TokenCounter;
 use crate::mcp_client::{
     Client as McpClient,
     ClientConfig as McpClientConfig,
+    HttpTransport,
     JsonRpcResponse,
     JsonRpcStdioTransport,
     MessageContent,
     Messenger,
     PromptGet,
+    RemoteClientConfig,
     ServerCapabilities,
     StdioTransport,
     ToolCallResult,
+    TransportType,
+    WebSocketTransport,
 };
 use crate::platform::Context;
 
-// TODO: support http transport type
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CustomToolConfig {
+    /// The command used to launch a local server. Left empty for remote servers.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub command: String,
     #[serde(default)]
     pub args: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub env: Option<HashMap<String, String>>,
+    /// Url of an already running server. `http(s)://` urls are spoken to over streamable http and
+    /// `ws(s)://` urls over websocket.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Headers sent along with every request to a remote server. Values may reference
+    /// environment variables, e.g. `"Authorization": "Bearer ${MY_TOKEN}"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub headers: Option<HashMap<String, String>>,
     #[serde(default = "default_timeout")]
     pub timeout: u64,
     #[serde(default)]
     pub disabled: bool,
 }
 
+impl CustomToolConfig {
+    /// Determines how the server is to be reached based on which of `command` and `url` is
+    /// supplied.
+    pub fn transport_type(&self) -> Result<TransportType> {
+        match (self.command.is_empty(), &self.url) {
+            (false, None) => Ok(TransportType::Stdio),
+            (true, Some(url)) => match url.split_once("://").map(|(scheme, _)| scheme.to_lowercase()) {
+                Some(scheme) if scheme == "http" || scheme == "https" => Ok(TransportType::Http),
+                Some(scheme) if scheme == "ws" || scheme == "wss" => Ok(TransportType::Websocket),
+                _ => bail!("Unsupported url {url}. Expected a url with one of http, https, ws, or wss as its scheme"),
+            },
+            (false, Some(_)) => bail!("Only one of command and url can be specified"),
+            (true, None) => bail!("One of command or url must be specified"),
+        }
+    }
+}
+
 pub fn default_timeout() -> u64 {
     120 * 1000
 }
@@ -57,101 +90,137 @@ pub enum CustomToolClient {
         client: McpClient<StdioTransport>,
         server_capabilities: RwLock<Option<ServerCapabilities>>,
     },
+    Http {
+        server_name: String,
+        client: McpClient<HttpTransport>,
+        server_capabilities: RwLock<Option<ServerCapabilities>>,
+    },
+    WebSocket {
+        server_name: String,
+        client: McpClient<WebSocketTransport>,
+        server_capabilities: RwLock<Option<ServerCapabilities>>,
+    },
+}
+
+/// Evaluates `$body` against the fields named of whichever variant `$client` is. The variants
+/// only differ in the transport type of the underlying client, which cannot be erased since
+/// [crate::mcp_client::Transport] is not object safe.
+macro_rules! with_client {
+    ($client:expr, |$($field:ident),+| $body:expr) => {
+        match $client {
+            CustomToolClient::Stdio { $($field,)+ .. } => $body,
+            CustomToolClient::Http { $($field,)+ .. } => $body,
+            CustomToolClient::WebSocket { $($field,)+ .. } => $body,
+        }
+    };
 }
 
 impl CustomToolClient {
-    // TODO: add support for http transport
     pub fn from_config(server_name: String, config: CustomToolConfig) -> Result<Self> {
+        let transport_type = config.transport_type()?;
         let CustomToolConfig {
             command,
             args,
             env,
+            url,
+            headers,
             timeout,
             disabled: _,
         } = config;
-        let mcp_client_config = McpClientConfig {
+        let client_info = serde_json::json!({
+           "name": "Q CLI Chat",
+           "version": "1.0.0"
+        });
+        let server_capabilities = RwLock::new(None);
+        let remote_config = || RemoteClientConfig {
             server_name: server_name.clone(),
-            bin_path: command.clone(),
-            args,
+            url: url.clone().unwrap_or_default(),
+            headers: headers
+                .clone()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(name, value)| {
+                    let value = shellexpand::env(&value).map_or(value.clone(), |v| v.into_owned());
+                    (name, value)
+                })
+                .collect(),
             timeout,
-            client_info: serde_json::json!({
-               "name": "Q CLI Chat",
-               "version": "1.0.0"
-            }),
-            env,
+            client_info: client_info.clone(),
         };
-        let client = McpClient::<JsonRpcStdioTransport>::from_config(mcp_client_config)?;
-        Ok(CustomToolClient::Stdio {
-            server_name,
-            client,
-            server_capabilities: RwLock::new(None),
+        Ok(match transport_type {
+            TransportType::Stdio => {
+                let mcp_client_config = McpClientConfig {
+                    server_name: server_name.clone(),
+                    bin_path: command,
+                    args,
+                    timeout,
+                    client_info: client_info.clone(),
+                    env,
+                };
+                CustomToolClient::Stdio {
+                    client: McpClient::<JsonRpcStdioTransport>::from_config(mcp_client_config)?,
+                    server_name,
+                    server_capabilities,
+                }
+            },
+            TransportType::Http => CustomToolClient::Http {
+                client: McpClient::<HttpTransport>::from_config(remote_config())?,
+                server_name,
+                server_capabilities,
+            },
+            TransportType::Websocket => CustomToolClient::WebSocket {
+                client: McpClient::<WebSocketTransport>::from_config(remote_config())?,
+                server_name,
+                server_capabilities,
+            },
         })
     }
 
     pub async fn init(&self) -> Result<()> {
-        match self {
-            CustomToolClient::Stdio {
-                client,
-                server_capabilities,
-                ..
-            } => {
-                if let Some(messenger) = &client.messenger {
-                    let _ = messenger.send_init_msg().await;
-                }
-                // We'll need to first initialize. This is the handshake every client and server
-                // needs to do before proceeding to anything else
-                let cap = client.init().await?;
-                // We'll be scrapping this for background server load: https://github.com/aws/amazon-q-developer-cli/issues/1466
-                // So don't worry about the tidiness for now
-                server_capabilities.write().await.replace(cap);
-                Ok(())
-            },
-        }
+        with_client!(self, |client, server_capabilities| {
+            if let Some(messenger) = &client.messenger {
+                let _ = messenger.send_init_msg().await;
+            }
+            // We'll need to first initialize. This is the handshake every client and server
+            // needs to do before proceeding to anything else
+            let cap = client.init().await?;
+            // We'll be scrapping this for background server load: https://github.com/aws/amazon-q-developer-cli/issues/1466
+            // So don't worry about the tidiness for now
+            server_capabilities.write().await.replace(cap);
+            Ok(())
+        })
     }
 
     pub fn assign_messenger(&mut self, messenger: Box<dyn Messenger>) {
-        match self {
-            CustomToolClient::Stdio { client, .. } => {
-                client.messenger = Some(messenger);
-            },
-        }
+        with_client!(self, |client| {
+            client.messenger = Some(messenger);
+        });
     }
 
     pub fn get_server_name(&self) -> &str {
-        match self {
-            CustomToolClient::Stdio { server_name, .. } => server_name.as_str(),
-        }
+        with_client!(self, |server_name| server_name.as_str())
     }
 
     pub async fn request(&self, method: &str, params: Option<serde_json::Value>) -> Result<JsonRpcResponse> {
-        match self {
-            CustomToolClient::Stdio { client, .. } => Ok(client.request(method, params).await?),
-        }
+        with_client!(self, |client| Ok(client.request(method, params).await?))
     }
 
     pub fn list_prompt_gets(&self) -> Arc<std::sync::RwLock<HashMap<String, PromptGet>>> {
-        match self {
-            CustomToolClient::Stdio { client, .. } => client.prompt_gets.clone(),
-        }
+        with_client!(self, |client| client.prompt_gets.clone())
     }
 
-    #[allow(dead_code)]
     pub async fn notify(&self, method: &str, params: Option<serde_json::Value>) -> Result<()> {
-        match self {
-            CustomToolClient::Stdio { client, .. } => Ok(client.notify(method, params).await?),
-        }
+        with_client!(self, |client| Ok(client.notify(method, params).await?))
     }
 
     pub fn is_prompts_out_of_date(&self) -> bool {
-        match self {
-            CustomToolClient::Stdio { client, .. } => client.is_prompts_out_of_date.load(Ordering::Relaxed),
-        }
+        with_client!(self, |client| client.is_prompts_out_of_date.load(Ordering::Relaxed))
     }
 
     pub fn prompts_updated(&self) {
-        match self {
-            CustomToolClient::Stdio { client, .. } => client.is_prompts_out_of_date.store(false, Ordering::Relaxed),
-        }
+        with_client!(self, |client| client
+            .is_prompts_out_of_date
+            .store(false, Ordering::Relaxed));
     }
 }
 
@@ -169,6 +238,8 @@ pub struct CustomTool {
     /// Optional parameters to pass to the tool when invoking the method.
     /// Structured as a JSON value to accommodate various parameter types and structures.
     pub params: Option<serde_json::Value>,
+    /// Whether the server annotated the tool with `readOnlyHint`.
+    pub read_only: bool,
 }
 
 impl CustomTool {
@@ -0,0 +1,632 @@
+//! Delegation of self-contained tasks to sub-agents.
+//!
+//! Each sub-agent runs its own conversation, starting from nothing but the task description, with
+//! a restricted set of tools. Sub-agents cannot ask the user for anything, so tools requiring
+//! acceptance can only be used if the user trusts them in the delegating conversation. Their tool
+//! uses go through the hooks and checkpoints of the delegating conversation as well. Only the
+//! final report of each sub-agent is returned to the model, while the full transcripts are kept
+//! in the delegating conversation and can be inspected with `/subagents`.
+
+use std::io::Write;
+use std::sync::{
+    Arc,
+    Mutex,
+};
+use std::time::Instant;
+
+use crossterm::style::{
+    Color,
+    Stylize,
+};
+use crossterm::{
+    execute,
+    queue,
+    style,
+};
+use eyre::{
+    Result,
+    bail,
+    eyre,
+};
+use futures::{
+    StreamExt,
+    stream,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tracing::warn;
+
+use super::execute::{
+    CommandPolicy,
+    PolicyAction,
+    PolicyDecision,
+    Sandbox,
+};
+use super::{
+    InvokeOutput,
+    OutputKind,
+    Tool,
+    ToolPermissions,
+};
+use crate::api_client::StreamingClient;
+use crate::api_client::model::ToolResultStatus;
+use crate::cli::chat::checkpoint::FileSnapshot;
+use crate::cli::chat::context::ContextManager;
+use crate::cli::chat::conversation_state::ConversationState;
+use crate::cli::chat::hooks::PreToolUseDecision;
+use crate::cli::chat::message::{
+    AssistantToolUse,
+    ToolUseResult,
+    ToolUseResultBlock,
+};
+use crate::cli::chat::parser::{
+    ResponseEvent,
+    ResponseParser,
+};
+use crate::cli::chat::tool_manager::ToolManager;
+use crate::cli::chat::util::truncate_safe;
+use crate::platform::Context;
+
+/// Maximum number of tasks that can be delegated in a single tool use.
+const MAX_TASKS: usize = 10;
+
+/// Maximum number of sub-agents running at the same time.
+const MAX_PARALLEL_SUB_AGENTS: usize = 4;
+
+/// Maximum number of requests a sub-agent can send before it is stopped.
+const MAX_SUB_AGENT_REQUESTS: usize = 25;
+
+/// Tools available to sub-agents unless the task lists its own.
+#[cfg(not(windows))]
+const DEFAULT_SUB_AGENT_TOOLS: &[&str] = &["fs_read", "execute_bash", "use_aws", "thinking"];
+#[cfg(windows)]
+const DEFAULT_SUB_AGENT_TOOLS: &[&str] = &["fs_read", "execute_cmd", "use_aws", "thinking"];
+
+/// Tools that are never available to sub-agents. Nested delegation is not supported, and issues
+/// are reported from the delegating conversation.
+const EXCLUDED_SUB_AGENT_TOOLS: &[&str] = &["delegate", "report_issue", "dummy"];
+
+const SUB_AGENT_PROMPT: &str = "You are a sub-agent working on a task delegated to you by another agent. \
+You cannot see its conversation, so the task below is all you know about it. Complete the task with the \
+tools available to you without asking questions, since nobody can answer them. Tools that modify the \
+environment may be refused unless the user has trusted them. Once you are done, reply with a concise \
+final report of what you found and of any changes you made. Only this final reply is returned to the \
+delegating agent.\n\nTask:\n";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Delegate {
+    pub tasks: Vec<DelegateTask>,
+
+    #[serde(skip_deserializing)]
+    pub context: Option<DelegateContext>,
+
+    /// Transcripts of the sub-agents that have finished, taken by the delegating conversation
+    /// after the tool is invoked.
+    #[serde(skip)]
+    transcripts: Arc<Mutex<Vec<SubAgentTranscript>>>,
+
+    /// Files modified by the sub-agents, snapshotted before their first modification and taken
+    /// by the delegating conversation to add them to its checkpoint.
+    #[serde(skip)]
+    snapshots: Arc<Mutex<Vec<FileSnapshot>>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DelegateTask {
+    pub task: String,
+    pub tools: Option<Vec<String>>,
+}
+
+impl DelegateTask {
+    /// The names of the tools available to the sub-agent working on this task.
+    pub fn tool_names(&self) -> Vec<String> {
+        match &self.tools {
+            Some(tools) => tools
+                .iter()
+                .filter(|name| !EXCLUDED_SUB_AGENT_TOOLS.contains(&name.as_str()))
+                .cloned()
+                .collect(),
+            None => DEFAULT_SUB_AGENT_TOOLS.iter().map(|&name| name.to_owned()).collect(),
+        }
+    }
+}
+
+/// Runtime context of the delegating conversation, set by the program before the tool is
+/// validated.
+#[derive(Debug, Clone)]
+pub struct DelegateContext {
+    pub client: StreamingClient,
+    pub ctx: Arc<Context>,
+    pub tool_manager: ToolManager,
+    pub tool_permissions: ToolPermissions,
+    pub profile: Option<String>,
+    pub model: Option<String>,
+    pub sandbox: Option<Sandbox>,
+    /// Context of the delegating conversation, whose pre and post tool use hooks run for the tool
+    /// uses of the sub-agents.
+    pub context_manager: Option<Arc<tokio::sync::Mutex<ContextManager>>>,
+    /// Id given to the first sub-agent of this tool use.
+    pub first_transcript_id: usize,
+}
+
+/// Everything a sub-agent did, kept in the delegating conversation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubAgentTranscript {
+    pub id: usize,
+    pub task: String,
+    pub tools: Vec<String>,
+    /// Human-readable messages and tool uses, in the same format as the conversation transcript.
+    pub entries: Vec<String>,
+    pub report: Option<String>,
+    pub error: Option<String>,
+    pub tool_uses: usize,
+    pub duration_secs: f32,
+}
+
+impl SubAgentTranscript {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+impl Delegate {
+    pub fn set_context(&mut self, context: DelegateContext) {
+        self.context = Some(context);
+    }
+
+    /// Removes and returns the transcripts of the sub-agents run by [Self::invoke].
+    pub fn take_transcripts(&self) -> Vec<SubAgentTranscript> {
+        self.transcripts
+            .lock()
+            .map(|mut t| std::mem::take(&mut *t))
+            .unwrap_or_default()
+    }
+
+    /// Removes and returns the snapshots of the files modified by the sub-agents run by
+    /// [Self::invoke].
+    pub fn take_snapshots(&self) -> Vec<FileSnapshot> {
+        self.snapshots
+            .lock()
+            .map(|mut s| std::mem::take(&mut *s))
+            .unwrap_or_default()
+    }
+
+    pub async fn invoke(&self, mut updates: impl Write) -> Result<InvokeOutput> {
+        let Some(context) = self.context.as_ref() else {
+            return Err(eyre!(
+                "delegate: Required tool context (DelegateContext) not set by the program."
+            ));
+        };
+
+        let total = self.tasks.len();
+        let mut pending = stream::iter(self.tasks.iter().enumerate().map(|(index, task)| {
+            SubAgent {
+                context,
+                id: context.first_transcript_id + index,
+                task,
+                snapshots: &self.snapshots,
+            }
+            .run()
+        }))
+        .buffer_unordered(MAX_PARALLEL_SUB_AGENTS);
+
+        let mut transcripts = Vec::with_capacity(total);
+        while let Some(transcript) = pending.next().await {
+            let (symbol, status) = if transcript.succeeded() {
+                ("✓ ".green(), " finished in ")
+            } else {
+                ("✗ ".red(), " failed after ")
+            };
+            execute!(
+                updates,
+                style::Print(symbol),
+                style::SetForegroundColor(Color::Blue),
+                style::Print(format!("sub-agent {}", transcript.id)),
+                style::ResetColor,
+                style::Print(status),
+                style::SetForegroundColor(Color::Yellow),
+                style::Print(format!("{:.2} s", transcript.duration_secs)),
+                style::ResetColor,
+                style::Print(format!(" ({} tool uses)\n", transcript.tool_uses)),
+            )?;
+            transcripts.push(transcript);
+        }
+        transcripts.sort_by_key(|transcript| transcript.id);
+
+        let results = transcripts
+            .iter()
+            .map(|transcript| match &transcript.error {
+                None => serde_json::json!({
+                    "task": transcript.task,
+                    "status": "success",
+                    "report": transcript.report,
+                }),
+                Some(error) => serde_json::json!({
+                    "task": transcript.task,
+                    "status": "error",
+                    "error": error,
+                    "partial_report": transcript.report,
+                }),
+            })
+            .collect::<Vec<_>>();
+
+        if let Ok(mut stored) = self.transcripts.lock() {
+            stored.extend(transcripts);
+        }
+
+        Ok(InvokeOutput {
+            output: OutputKind::Json(serde_json::Value::Array(results)),
+        })
+    }
+
+    pub fn queue_description(&self, updates: &mut impl Write) -> Result<()> {
+        queue!(
+            updates,
+            style::Print(format!("Delegating {} to sub-agents:\n", match self.tasks.len() {
+                1 => "1 task".to_string(),
+                n => format!("{n} tasks"),
+            })),
+        )?;
+        for task in &self.tasks {
+            queue!(
+                updates,
+                style::Print("\n- "),
+                style::Print(truncate_safe(task.task.trim(), 200)),
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!("\n  tools: {}", task.tool_names().join(", "))),
+                style::ResetColor,
+            )?;
+        }
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, _ctx: &Context) -> Result<()> {
+        if self.tasks.is_empty() {
+            bail!("At least one task must be provided");
+        }
+        if self.tasks.len() > MAX_TASKS {
+            bail!("At most {MAX_TASKS} tasks can be delegated at once");
+        }
+        for task in &self.tasks {
+            if task.task.trim().is_empty() {
+                bail!("Task descriptions must not be empty");
+            }
+            if let Some(tools) = &task.tools {
+                if let Some(name) = tools
+                    .iter()
+                    .find(|name| EXCLUDED_SUB_AGENT_TOOLS.contains(&name.as_str()))
+                {
+                    bail!("The tool {name} is not available to sub-agents");
+                }
+                if let Some(context) = &self.context {
+                    if let Some(name) = tools
+                        .iter()
+                        .find(|name| !context.tool_manager.schema.contains_key(*name))
+                    {
+                        bail!("Unknown tool {name}");
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single sub-agent working on one of the delegated tasks.
+struct SubAgent<'a> {
+    context: &'a DelegateContext,
+    id: usize,
+    task: &'a DelegateTask,
+    snapshots: &'a Mutex<Vec<FileSnapshot>>,
+}
+
+impl SubAgent<'_> {
+    async fn run(self) -> SubAgentTranscript {
+        let start = Instant::now();
+        let mut transcript = SubAgentTranscript {
+            id: self.id,
+            task: self.task.task.clone(),
+            tools: self.task.tool_names(),
+            entries: Vec::new(),
+            report: None,
+            error: None,
+            tool_uses: 0,
+            duration_secs: 0.0,
+        };
+        if let Err(err) = self.converse(&mut transcript).await {
+            transcript.error = Some(err.to_string());
+        }
+        transcript.duration_secs = start.elapsed().as_secs_f32();
+        transcript
+    }
+
+    /// Sends requests until the model replies without using any tools, storing the last reply
+    /// as the report.
+    async fn converse(&self, transcript: &mut SubAgentTranscript) -> Result<()> {
+        let context = self.context;
+        let tool_config = context
+            .tool_manager
+            .schema
+            .iter()
+            .filter(|(name, _)| transcript.tools.contains(name))
+            .map(|(name, spec)| (name.clone(), spec.clone()))
+            .collect();
+        let mut conversation = ConversationState::new(
+            Arc::clone(&context.ctx),
+            &uuid::Uuid::new_v4().to_string(),
+            tool_config,
+            context.profile.clone(),
+            None,
+            context.tool_manager.clone(),
+            context.model.clone(),
+        )
+        .await;
+
+        transcript.entries.push(format!("> {}", self.task.task));
+        conversation
+            .set_next_user_message(format!("{SUB_AGENT_PROMPT}{}", self.task.task))
+            .await;
+
+        for _ in 0..MAX_SUB_AGENT_REQUESTS {
+            let response = context
+                .client
+                .send_message(conversation.as_sendable_conversation_state(false).await)
+                .await?;
+            let mut parser = ResponseParser::new(response);
+            let message = loop {
+                if let ResponseEvent::EndStream { message } = parser.recv().await? {
+                    break message;
+                }
+            };
+
+            let content = message.content().trim().to_string();
+            let tool_uses = message.tool_uses().map(<[_]>::to_vec).unwrap_or_default();
+            conversation.append_assistant_message(message);
+            if !content.is_empty() {
+                transcript.entries.push(content.clone());
+                transcript.report = Some(content);
+            }
+            if tool_uses.is_empty() {
+                return Ok(());
+            }
+
+            let mut results = Vec::with_capacity(tool_uses.len());
+            for tool_use in tool_uses {
+                transcript.tool_uses += 1;
+                transcript
+                    .entries
+                    .push(format!("[Tool use: {}] {}", tool_use.name, tool_use.args));
+                let result = self.run_tool(tool_use, &transcript.tools).await;
+                if matches!(result.status, ToolResultStatus::Error) {
+                    let error = result
+                        .content
+                        .iter()
+                        .filter_map(|block| match block {
+                            ToolUseResultBlock::Text(text) => Some(text.as_str()),
+                            ToolUseResultBlock::Json(_) => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    transcript.entries.push(format!("[Tool error] {error}"));
+                }
+                results.push(result);
+            }
+            conversation.add_tool_results(results);
+        }
+
+        bail!("The sub-agent did not finish within {MAX_SUB_AGENT_REQUESTS} requests")
+    }
+
+    async fn run_tool(&self, mut tool_use: AssistantToolUse, tools: &[String]) -> ToolUseResult {
+        let tool_use_id = tool_use.id.clone();
+        if !tools.contains(&tool_use.name) {
+            return ToolUseResult {
+                tool_use_id,
+                content: vec![ToolUseResultBlock::Text(format!(
+                    "The tool {} is not available to this sub-agent",
+                    tool_use.name
+                ))],
+                status: ToolResultStatus::Error,
+            };
+        }
+
+        // The same pre tool use hooks as for the delegating conversation, which may block the
+        // tool use or rewrite its input.
+        if let Some(context_manager) = &self.context.context_manager {
+            let decision = context_manager
+                .lock()
+                .await
+                .run_pre_tool_use_hooks(&tool_use.name, &tool_use.args)
+                .await;
+            match decision {
+                PreToolUseDecision::Allow => (),
+                PreToolUseDecision::Block(reason) => {
+                    return ToolUseResult {
+                        tool_use_id,
+                        content: vec![ToolUseResultBlock::Text(reason)],
+                        status: ToolResultStatus::Error,
+                    };
+                },
+                PreToolUseDecision::Rewrite(args) => tool_use.args = args,
+            }
+        }
+
+        let name = tool_use.name.clone();
+        let args = tool_use.args.clone();
+        let mut tool = match self.context.tool_manager.get_tool_from_tool_use(tool_use) {
+            Ok(tool) => tool,
+            Err(result) => return result.into(),
+        };
+        let (invoke_result, invoked) = match self.check_tool(&name, &mut tool).await {
+            Ok(()) => {
+                self.snapshot(&tool).await;
+                // The output is not shown, only the result is kept. Boxed since this recurses
+                // into [Tool::invoke].
+                let mut output = Vec::new();
+                (Box::pin(tool.invoke(&self.context.ctx, &mut output)).await, true)
+            },
+            Err(err) => (Err(err), false),
+        };
+
+        // Post tool use hooks only run for tools that were invoked, and their output is added to
+        // the tool result.
+        let post_hook_results = match (invoked, &self.context.context_manager) {
+            (true, Some(context_manager)) => {
+                let tool_response = match &invoke_result {
+                    Ok(result) => serde_json::json!({ "status": "success", "output": result.as_str() }),
+                    Err(err) => serde_json::json!({ "status": "error", "error": err.to_string() }),
+                };
+                context_manager
+                    .lock()
+                    .await
+                    .run_post_tool_use_hooks(&name, &args, tool_response)
+                    .await
+            },
+            _ => Vec::new(),
+        };
+
+        let mut result = match invoke_result {
+            Ok(output) => ToolUseResult {
+                tool_use_id,
+                content: vec![output.into()],
+                status: ToolResultStatus::Success,
+            },
+            Err(err) => ToolUseResult {
+                tool_use_id,
+                content: vec![ToolUseResultBlock::Text(format!(
+                    "An error occurred processing the tool: \n{err}"
+                ))],
+                status: ToolResultStatus::Error,
+            },
+        };
+        result
+            .content
+            .extend(post_hook_results.into_iter().map(|(hook, output)| {
+                ToolUseResultBlock::Text(format!("Output from the hook '{}':\n{output}", hook.name))
+            }));
+        result
+    }
+
+    /// Snapshots the files the tool is about to modify, so that `/undo` reverts the edits of the
+    /// sub-agent as well.
+    async fn snapshot(&self, tool: &Tool) {
+        let Tool::FsWrite(fs_write) = tool else {
+            return;
+        };
+        for path in fs_write.paths(&self.context.ctx) {
+            match FileSnapshot::read(&self.context.ctx, &path).await {
+                Ok(snapshot) => {
+                    if let Ok(mut snapshots) = self.snapshots.lock() {
+                        if !snapshots.iter().any(|s| s.path == snapshot.path) {
+                            snapshots.push(snapshot);
+                        }
+                    }
+                },
+                Err(err) => warn!(?err, ?path, "Failed to save the file to a checkpoint"),
+            }
+        }
+    }
+
+    /// Applies the command policy, validates the tool and checks that it can be used without
+    /// asking the user.
+    async fn check_tool(&self, name: &str, tool: &mut Tool) -> Result<()> {
+        let context = self.context;
+        let policy_decision = match tool {
+            Tool::Delegate(_) | Tool::GhIssue(_) => bail!("The tool {name} is not available to sub-agents"),
+            Tool::ExecuteCommand(execute_command) => {
+                execute_command.sandbox = context.sandbox.clone();
+                Some(
+                    match CommandPolicy::load(&context.ctx, context.profile.as_deref()).await {
+                        Ok(policy) => execute_command.evaluate_policy(&policy),
+                        Err(err) => bail!("The command policy is invalid: {err}"),
+                    },
+                )
+            },
+            _ => None,
+        };
+        tool.validate(&context.ctx).await?;
+
+        // The same checks as for the delegating conversation, except that nobody can be asked.
+        let trusted = context.tool_permissions.trust_all
+            || (context.tool_permissions.has(name) && context.tool_permissions.is_trusted(name));
+        let allowed = match &policy_decision {
+            Some(PolicyDecision {
+                action: PolicyAction::Deny,
+                rule,
+            }) => bail!(
+                "The command was denied by the command policy rule {}",
+                rule.as_deref().unwrap_or_default()
+            ),
+            Some(PolicyDecision {
+                action: PolicyAction::Ask,
+                rule: Some(_),
+            }) => false,
+            Some(decision) => trusted || decision.action == PolicyAction::Allow,
+            None => trusted || !tool.requires_acceptance(&context.ctx),
+        };
+        if !allowed {
+            bail!(
+                "The tool {name} requires the user's approval for this use, which sub-agents cannot ask for. Only read-only operations and tools trusted by the user can be used."
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    macro_rules! delegate {
+        ($value:tt) => {
+            serde_json::from_value::<Delegate>(serde_json::json!($value)).unwrap()
+        };
+    }
+
+    #[test]
+    fn test_delegate_deser() {
+        let delegate = delegate!({
+            "tasks": [
+                { "task": "Find all uses of the foo function" },
+                { "task": "Read the README", "tools": ["fs_read", "delegate"] }
+            ]
+        });
+        assert_eq!(delegate.tasks.len(), 2);
+        assert_eq!(
+            delegate.tasks[0].tool_names(),
+            DEFAULT_SUB_AGENT_TOOLS
+                .iter()
+                .map(|&s| s.to_owned())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(delegate.tasks[1].tool_names(), vec!["fs_read".to_string()]);
+        assert!(delegate.context.is_none());
+        assert!(delegate.take_transcripts().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_delegate_validate() {
+        let ctx = Context::new();
+
+        assert!(delegate!({ "tasks": [] }).validate(&ctx).await.is_err());
+        assert!(delegate!({ "tasks": [{ "task": "  " }] }).validate(&ctx).await.is_err());
+        assert!(
+            delegate!({ "tasks": [{ "task": "Nested", "tools": ["delegate"] }] })
+                .validate(&ctx)
+                .await
+                .is_err()
+        );
+        let too_many = (0..=MAX_TASKS)
+            .map(|i| serde_json::json!({ "task": format!("task {i}") }))
+            .collect::<Vec<_>>();
+        assert!(delegate!({ "tasks": too_many }).validate(&ctx).await.is_err());
+        assert!(
+            delegate!({ "tasks": [{ "task": "Summarize the README", "tools": ["fs_read"] }] })
+                .validate(&ctx)
+                .await
+                .is_ok()
+        );
+    }
+}
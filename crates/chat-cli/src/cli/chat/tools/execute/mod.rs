@@ -20,6 +20,18 @@ use crate::cli::chat::{
 };
 use crate::platform::Context;
 
+mod policy;
+mod sandbox;
+pub use policy::{
+    CommandPolicy,
+    PolicyAction,
+    PolicyDecision,
+};
+pub use sandbox::{
+    Sandbox,
+    SandboxConfig,
+};
+
 // Platform-specific modules
 #[cfg(windows)]
 mod windows;
@@ -40,73 +52,41 @@ pub const READONLY_COMMANDS: &[&str] = &[
 pub struct ExecuteCommand {
     pub command: String,
     pub summary: Option<String>,
+    /// Set for sandboxed sessions, see [Sandbox]
+    #[serde(skip)]
+    pub sandbox: Option<Sandbox>,
 }
 
 impl ExecuteCommand {
+    /// Whether the command needs to be confirmed by the user when no command policy is configured.
     pub fn requires_acceptance(&self) -> bool {
-        let Some(args) = shlex::split(&self.command) else {
-            return true;
-        };
-
-        const DANGEROUS_PATTERNS: &[&str] = &["<(", "$(", "`", ">", "&&", "||", "&", ";"];
-        if args
-            .iter()
-            .any(|arg| DANGEROUS_PATTERNS.iter().any(|p| arg.contains(p)))
-        {
-            return true;
-        }
-
-        // Split commands by pipe and check each one
-        let mut current_cmd = Vec::new();
-        let mut all_commands = Vec::new();
-
-        for arg in args {
-            if arg == "|" {
-                if !current_cmd.is_empty() {
-                    all_commands.push(current_cmd);
-                }
-                current_cmd = Vec::new();
-            } else if arg.contains("|") {
-                // if pipe appears without spacing e.g. `echo myimportantfile|args rm` it won't get
-                // parsed out, in this case - we want to verify before running
-                return true;
-            } else {
-                current_cmd.push(arg);
-            }
-        }
-        if !current_cmd.is_empty() {
-            all_commands.push(current_cmd);
-        }
-
-        // Check if each command in the pipe chain starts with a safe command
-        for cmd_args in all_commands {
-            match cmd_args.first() {
-                // Special casing for `find` so that we support most cases while safeguarding
-                // against unwanted mutations
-                Some(cmd)
-                    if cmd == "find"
-                        && cmd_args
-                            .iter()
-                            .any(|arg| arg.contains("-exec") || arg.contains("-delete")) =>
-                {
-                    return true;
-                },
-                Some(cmd) if !READONLY_COMMANDS.contains(&cmd.as_str()) => return true,
-                None => return true,
-                _ => (),
-            }
-        }
+        self.evaluate_policy(&CommandPolicy::default()).action != PolicyAction::Allow
+    }
 
-        false
+    /// Evaluates every command within [Self::command] against `policy`.
+    pub fn evaluate_policy(&self, policy: &CommandPolicy) -> PolicyDecision {
+        policy.evaluate(&self.command)
     }
 
     pub async fn invoke(&self, updates: impl Write) -> Result<InvokeOutput> {
-        let output = run_command(&self.command, MAX_TOOL_RESPONSE_SIZE / 3, Some(updates)).await?;
-        let result = serde_json::json!({
+        let output = run_command(
+            &self.command,
+            MAX_TOOL_RESPONSE_SIZE / 3,
+            Some(updates),
+            self.sandbox.as_ref(),
+        )
+        .await?;
+        let mut result = serde_json::json!({
             "exit_status": output.exit_status.unwrap_or(0).to_string(),
             "stdout": output.stdout,
             "stderr": output.stderr,
         });
+        if let Some(sandbox) = &self.sandbox {
+            let violations = sandbox.violations(&output.stderr);
+            if output.exit_status != Some(0) && !violations.is_empty() {
+                result["sandbox_violations"] = serde_json::json!(violations);
+            }
+        }
 
         Ok(InvokeOutput {
             output: OutputKind::Json(result),
@@ -144,6 +124,24 @@ impl ExecuteCommand {
             )?;
         }
 
+        if let Some(sandbox) = &self.sandbox {
+            queue!(
+                updates,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print(format!(
+                    "Sandboxed: writable {}, network {}\n",
+                    sandbox
+                        .writable_paths
+                        .iter()
+                        .map(|p| p.to_string_lossy())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    if sandbox.allow_network { "allowed" } else { "blocked" }
+                )),
+                style::ResetColor,
+            )?;
+        }
+
         queue!(updates, style::Print("\n"))?;
 
         Ok(())
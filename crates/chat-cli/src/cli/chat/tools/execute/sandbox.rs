@@ -0,0 +1,277 @@
+//! Sandboxed execution for `execute_bash`.
+//!
+//! When enabled (`q chat --sandbox` or the `sandbox` section of a profile's context.json),
+//! commands run with the filesystem mounted read-only apart from the working directory and any
+//! additionally configured paths, and without network access unless allowed.
+//!
+//! [bubblewrap](https://github.com/containers/bubblewrap) is used when `bwrap` is on the `PATH`.
+//! Otherwise the command is confined using a new user and network namespace along with a Landlock
+//! ruleset. Commands are never run unsandboxed as a fallback - if neither is available the command
+//! fails.
+
+#[cfg(target_os = "linux")]
+use std::path::Path;
+use std::path::PathBuf;
+
+#[cfg(target_os = "linux")]
+use eyre::Context as _;
+use eyre::Result;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+/// The sandbox section of a profile's context.json, e.g.
+/// `{"sandbox": {"enabled": true, "allowNetwork": false, "writablePaths": ["~/.cargo"]}}`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SandboxConfig {
+    pub enabled: bool,
+    pub allow_network: bool,
+    /// Paths writable in addition to the working directory and the temporary directory
+    pub writable_paths: Vec<String>,
+}
+
+/// The restrictions a sandboxed command runs under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sandbox {
+    /// Paths the command may write to. Everything else is read-only.
+    pub writable_paths: Vec<PathBuf>,
+    pub allow_network: bool,
+}
+
+impl Sandbox {
+    /// Creates the `bash` process that runs `command` within the sandbox.
+    #[cfg(target_os = "linux")]
+    pub fn command(&self, command: &str) -> Result<tokio::process::Command> {
+        match find_executable("bwrap") {
+            Some(bwrap) => Ok(self.bubblewrap_command(&bwrap, command)),
+            None => self.namespace_command(command),
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn command(&self, _command: &str) -> Result<tokio::process::Command> {
+        eyre::bail!("Sandboxed execution is only supported on Linux")
+    }
+
+    #[cfg(target_os = "linux")]
+    fn bubblewrap_command(&self, bwrap: &Path, command: &str) -> tokio::process::Command {
+        let mut cmd = tokio::process::Command::new(bwrap);
+        cmd.args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"]);
+        for path in self.writable_paths.iter().filter(|p| p.exists()) {
+            cmd.arg("--bind").arg(path).arg(path);
+        }
+        if !self.allow_network {
+            cmd.arg("--unshare-net");
+        }
+        cmd.args(["--unshare-pid", "--die-with-parent", "--", "bash", "-c", command]);
+        cmd
+    }
+
+    #[cfg(target_os = "linux")]
+    fn namespace_command(&self, command: &str) -> Result<tokio::process::Command> {
+        use std::os::fd::{
+            AsRawFd,
+            OwnedFd,
+        };
+
+        use landlock::{
+            ABI,
+            Access,
+            AccessFs,
+            Ruleset,
+            RulesetAttr,
+            RulesetCreatedAttr,
+            path_beneath_rules,
+        };
+
+        let abi = ABI::V5;
+        let writable = self
+            .writable_paths
+            .iter()
+            .map(PathBuf::as_path)
+            .chain([Path::new("/dev")])
+            .filter(|p| p.exists());
+        let ruleset = Ruleset::default()
+            .handle_access(AccessFs::from_all(abi))?
+            .create()?
+            .add_rules(path_beneath_rules(["/"], AccessFs::from_read(abi)))?
+            .add_rules(path_beneath_rules(writable, AccessFs::from_all(abi)))
+            .wrap_err("Failed to create the Landlock ruleset")?;
+        // The ruleset has no file descriptor when the kernel does not support Landlock.
+        let Some(ruleset) = Option::<OwnedFd>::from(ruleset) else {
+            eyre::bail!("Landlock is not supported by this kernel");
+        };
+
+        let allow_network = self.allow_network;
+        let mut cmd = tokio::process::Command::new("bash");
+        cmd.arg("-c").arg(command);
+        // SAFETY: the ruleset is created before forking, so the closure only makes the `unshare`,
+        // `prctl` and `landlock_restrict_self` syscalls, which are async-signal-safe, and does not
+        // allocate: errors are built from the errno.
+        unsafe {
+            cmd.pre_exec(move || {
+                if !allow_network && libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNET) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if libc::syscall(libc::SYS_landlock_restrict_self, ruleset.as_raw_fd(), 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        Ok(cmd)
+    }
+
+    /// Explains the failures in `stderr` that look like they were caused by the sandbox, so that
+    /// the model does not keep retrying commands that cannot succeed.
+    ///
+    /// Writes outside of the writable paths fail with `EROFS` under bubblewrap. Landlock denies
+    /// them with `EACCES`, which cannot be told apart from an ordinary permission error, so those
+    /// are not reported.
+    pub fn violations(&self, stderr: &str) -> Vec<String> {
+        const WRITE_ERRORS: &[&str] = &["Read-only file system"];
+        const NETWORK_ERRORS: &[&str] = &[
+            "Network is unreachable",
+            "Could not resolve host",
+            "Temporary failure in name resolution",
+            "Name or service not known",
+            "getaddrinfo",
+        ];
+
+        let mut violations = Vec::new();
+        if WRITE_ERRORS.iter().any(|e| stderr.contains(e)) {
+            violations.push(format!(
+                "Writes are blocked by the sandbox outside of: {}",
+                self.writable_paths
+                    .iter()
+                    .map(|p| p.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if !self.allow_network && NETWORK_ERRORS.iter().any(|e| stderr.contains(e)) {
+            violations.push("Network access is blocked by the sandbox".to_string());
+        }
+        violations
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn find_executable(name: &str) -> Option<PathBuf> {
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_violations() {
+        let sandbox = Sandbox {
+            writable_paths: vec![PathBuf::from("/workspace")],
+            allow_network: false,
+        };
+        assert_eq!(
+            sandbox.violations("touch: cannot touch '/etc/x': Read-only file system"),
+            vec!["Writes are blocked by the sandbox outside of: /workspace".to_string()]
+        );
+        assert_eq!(
+            sandbox.violations("curl: (6) Could not resolve host: example.com"),
+            vec!["Network access is blocked by the sandbox".to_string()]
+        );
+        assert!(sandbox.violations("error: no such file").is_empty());
+        assert!(sandbox.violations("cat: /root/secret: Permission denied").is_empty());
+
+        let sandbox = Sandbox {
+            allow_network: true,
+            ..sandbox
+        };
+        assert!(
+            sandbox
+                .violations("curl: (6) Could not resolve host: example.com")
+                .is_empty()
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_bubblewrap_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let writable = dir.path().to_string_lossy().into_owned();
+        let sandbox = Sandbox {
+            writable_paths: vec![dir.path().to_path_buf(), dir.path().join("missing")],
+            allow_network: false,
+        };
+        let args = |sandbox: &Sandbox| {
+            sandbox
+                .bubblewrap_command(Path::new("bwrap"), "true")
+                .as_std()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        // Paths that do not exist are not bound, since bubblewrap would fail to start.
+        assert_eq!(args(&sandbox), vec![
+            "--ro-bind",
+            "/",
+            "/",
+            "--dev",
+            "/dev",
+            "--proc",
+            "/proc",
+            "--bind",
+            writable.as_str(),
+            writable.as_str(),
+            "--unshare-net",
+            "--unshare-pid",
+            "--die-with-parent",
+            "--",
+            "bash",
+            "-c",
+            "true",
+        ]);
+
+        let sandbox = Sandbox {
+            allow_network: true,
+            ..sandbox
+        };
+        assert!(!args(&sandbox).iter().any(|arg| arg == "--unshare-net"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[ignore = "requires Landlock or bubblewrap support from the host"]
+    #[tokio::test]
+    async fn test_sandbox_command() {
+        let dir = tempfile::tempdir().unwrap();
+        let sandbox = Sandbox {
+            writable_paths: vec![dir.path().to_path_buf()],
+            allow_network: false,
+        };
+        let inside = dir.path().join("inside");
+        let status = sandbox
+            .command(&format!("echo hi > {}", inside.display()))
+            .unwrap()
+            .status()
+            .await
+            .unwrap();
+        assert!(status.success());
+        assert!(inside.exists());
+
+        let output = sandbox
+            .command("touch /etc/q_sandbox_test")
+            .unwrap()
+            .output()
+            .await
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(!Path::new("/etc/q_sandbox_test").exists());
+    }
+}
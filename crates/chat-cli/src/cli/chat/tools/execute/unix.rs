@@ -12,6 +12,7 @@ use tracing::error;
 
 use super::{
     CommandResult,
+    Sandbox,
     format_output,
 };
 
@@ -20,17 +21,26 @@ use super::{
 /// * `command` - The command to run
 /// * `max_result_size` - max size of output streams, truncating if required
 /// * `updates` - output stream to push informational messages about the progress
+/// * `sandbox` - restrictions to run the command under, if any
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     command: &str,
     max_result_size: usize,
     mut updates: Option<W>,
+    sandbox: Option<&Sandbox>,
 ) -> Result<CommandResult> {
+    let mut cmd = match sandbox {
+        Some(sandbox) => sandbox.command(command)?,
+        None => {
+            let mut cmd = tokio::process::Command::new("bash");
+            cmd.arg("-c").arg(command);
+            cmd
+        },
+    };
+
     // We need to maintain a handle on stderr and stdout, but pipe it to the terminal as well
-    let mut child = tokio::process::Command::new("bash")
-        .arg("-c")
-        .arg(command)
+    let mut child = cmd
         .stdin(Stdio::inherit())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
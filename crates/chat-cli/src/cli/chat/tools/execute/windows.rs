@@ -5,6 +5,7 @@ use std::process::Stdio;
 use eyre::{
     Context as EyreContext,
     Result,
+    bail,
 };
 use tokio::io::AsyncBufReadExt;
 use tokio::select;
@@ -12,6 +13,7 @@ use tracing::error;
 
 use super::{
     CommandResult,
+    Sandbox,
     format_output,
 };
 
@@ -20,13 +22,19 @@ use super::{
 /// * `command` - The command to run
 /// * `max_result_size` - max size of output streams, truncating if required
 /// * `updates` - output stream to push informational messages about the progress
+/// * `sandbox` - restrictions to run the command under, which are not supported on Windows
 /// # Returns
 /// A [`CommandResult`]
 pub async fn run_command<W: Write>(
     command: &str,
     max_result_size: usize,
     mut updates: Option<W>,
+    sandbox: Option<&Sandbox>,
 ) -> Result<CommandResult> {
+    if sandbox.is_some() {
+        bail!("Sandboxed execution is only supported on Linux");
+    }
+
     // We need to maintain a handle on stderr and stdout, but pipe it to the terminal as well
     let mut child = tokio::process::Command::new("cmd")
         .arg("/C")
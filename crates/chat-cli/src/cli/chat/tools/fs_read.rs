@@ -1,6 +1,10 @@
 use std::collections::VecDeque;
 use std::fs::Metadata;
 use std::io::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
 
 use crossterm::queue;
 use crossterm::style::{
@@ -11,6 +15,12 @@ use eyre::{
     Result,
     bail,
 };
+use ignore::WalkBuilder;
+use ignore::overrides::OverrideBuilder;
+use regex::{
+    Regex,
+    RegexBuilder,
+};
 use serde::{
     Deserialize,
     Serialize,
@@ -236,18 +246,38 @@ time. You tried to read {byte_count} bytes. Try executing with fewer lines speci
     }
 }
 
-/// Search in a file.
+/// Search for a pattern in a file, or recursively in the files of a directory.
 #[derive(Debug, Clone, Deserialize)]
 pub struct FsSearch {
     pub path: String,
     pub pattern: String,
     pub context_lines: Option<usize>,
+    /// Whether `pattern` is a regular expression rather than a literal string.
+    pub regex: Option<bool>,
+    pub case_sensitive: Option<bool>,
+    /// Globs that files must match to be searched, when searching a directory.
+    pub include: Option<Vec<String>>,
+    /// Globs of files and directories to skip, when searching a directory.
+    pub exclude: Option<Vec<String>>,
+    /// Whether hidden files and directories are searched.
+    pub include_hidden: Option<bool>,
+    /// Whether files ignored by `.gitignore` (and `.ignore`) files are searched.
+    pub include_ignored: Option<bool>,
+    pub max_matches_per_file: Option<usize>,
+    /// Number of matches to skip, for fetching the next page of results.
+    pub offset: Option<usize>,
+    /// Maximum number of matches to return.
+    pub limit: Option<usize>,
 }
 
 impl FsSearch {
     const CONTEXT_LINE_PREFIX: &str = "  ";
     const DEFAULT_CONTEXT_LINES: usize = 2;
+    /// Only applies to directory searches, a single file is searched in full unless `limit` is set.
+    const DEFAULT_LIMIT: usize = 50;
     const MATCHING_LINE_PREFIX: &str = "→ ";
+    /// In bytes, larger files are skipped when searching a directory and not searched otherwise.
+    const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
 
     pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
         let path = sanitize_path_tool_arg(ctx, &self.path);
@@ -255,12 +285,24 @@ impl FsSearch {
         if !path.exists() {
             bail!("File not found: {}", relative_path);
         }
-        if !ctx.fs().symlink_metadata(path).await?.is_file() {
-            bail!("Path is not a file: {}", relative_path);
-        }
         if self.pattern.is_empty() {
             bail!("Search pattern cannot be empty");
         }
+        if let Err(err) = self.matcher() {
+            bail!("Invalid search pattern: {err}");
+        }
+        if self.limit == Some(0) {
+            bail!("Limit must be greater than 0");
+        }
+        let metadata = ctx.fs().symlink_metadata(&path).await?;
+        if metadata.is_file() && metadata.len() > Self::MAX_FILE_SIZE {
+            bail!(
+                "{} is too large to search ({} bytes, the maximum is {} bytes)",
+                relative_path,
+                metadata.len(),
+                Self::MAX_FILE_SIZE
+            );
+        }
         Ok(())
     }
 
@@ -276,26 +318,87 @@ impl FsSearch {
             style::Print(&self.pattern.to_lowercase()),
             style::ResetColor,
         )?;
+        if let Some(include) = &self.include {
+            queue!(
+                updates,
+                style::Print(" in files matching: "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(include.join(", ")),
+                style::ResetColor,
+            )?;
+        }
         Ok(())
     }
 
     pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
-        let file_path = sanitize_path_tool_arg(ctx, &self.path);
+        let path = sanitize_path_tool_arg(ctx, &self.path);
         let pattern = &self.pattern;
-        let relative_path = format_path(ctx.env().current_dir()?, &file_path);
-
-        let file_bytes = ctx.fs().read(&file_path).await?;
-        let file_content = String::from_utf8_lossy(&file_bytes);
-        let lines: Vec<&str> = LinesWithEndings::from(&file_content).collect();
-
+        let cwd = ctx.env().current_dir()?;
+        let relative_path = format_path(&cwd, &path);
+        let matcher = self.matcher()?;
+
+        let is_dir = ctx.fs().symlink_metadata(&path).await?.is_dir();
+        let files = if is_dir {
+            let search = self.clone();
+            let root = path.clone();
+            tokio::task::spawn_blocking(move || search.walk(&root)).await??
+        } else {
+            vec![path.clone()]
+        };
+
+        let offset = self.offset.unwrap_or_default();
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None if is_dir => Self::DEFAULT_LIMIT,
+            None => usize::MAX,
+        };
         let mut results = Vec::new();
         let mut total_matches = 0;
+        let mut matched_files = 0;
+        let mut large_files = 0;
+        for file in &files {
+            if is_dir {
+                match ctx.fs().symlink_metadata(file).await {
+                    Ok(metadata) if metadata.len() > Self::MAX_FILE_SIZE => {
+                        large_files += 1;
+                        continue;
+                    },
+                    Ok(_) => (),
+                    Err(err) => {
+                        warn!(?err, ?file, "Failed to read a file while searching");
+                        continue;
+                    },
+                }
+            }
+            let file_bytes = match ctx.fs().read(file).await {
+                Ok(file_bytes) => file_bytes,
+                Err(err) if is_dir => {
+                    warn!(?err, ?file, "Failed to read a file while searching");
+                    continue;
+                },
+                Err(err) => return Err(err.into()),
+            };
+            // Binary files are skipped when searching a directory.
+            if is_dir && file_bytes.contains(&0) {
+                continue;
+            }
+            let file_content = String::from_utf8_lossy(&file_bytes);
+            let lines: Vec<&str> = LinesWithEndings::from(&file_content).collect();
 
-        // Case insensitive search
-        let pattern_lower = pattern.to_lowercase();
-        for (line_num, line) in lines.iter().enumerate() {
-            if line.to_lowercase().contains(&pattern_lower) {
+            let mut file_matches = 0;
+            for (line_num, line) in lines.iter().enumerate() {
+                if !matcher.is_match(line.trim_end_matches(['\n', '\r'])) {
+                    continue;
+                }
+                if self.max_matches_per_file.is_some_and(|max| file_matches >= max) {
+                    break;
+                }
+                file_matches += 1;
                 total_matches += 1;
+                if total_matches <= offset || results.len() >= limit {
+                    continue;
+                }
+
                 let start = line_num.saturating_sub(self.context_lines());
                 let end = lines.len().min(line_num + self.context_lines() + 1);
                 let mut context_text = Vec::new();
@@ -310,10 +413,14 @@ impl FsSearch {
                 });
                 let match_text = context_text.join("");
                 results.push(SearchMatch {
+                    path: is_dir.then(|| format_path(&cwd, file)),
                     line_number: line_num + 1,
                     context: match_text,
                 });
             }
+            if file_matches > 0 {
+                matched_files += 1;
+            }
         }
 
         queue!(
@@ -321,21 +428,87 @@ impl FsSearch {
             style::SetForegroundColor(Color::Yellow),
             style::ResetColor,
             style::Print(format!(
-                "Found {} matches for pattern '{}' in {}\n",
+                "Found {} matches for pattern '{}' in {}",
                 total_matches, pattern, relative_path
             )),
+            style::Print(if is_dir {
+                format!(" ({matched_files} of {} files)\n", files.len())
+            } else {
+                "\n".to_string()
+            }),
             style::Print("\n"),
             style::ResetColor,
         )?;
 
+        let mut output = serde_json::to_string(&results)?;
+        let shown = offset + results.len();
+        if shown < total_matches {
+            output.push_str(&format!(
+                "\n\nShowing matches {} to {shown} of {total_matches}. Search again with an offset of {shown} to see more.",
+                offset + 1
+            ));
+        }
+        if large_files > 0 {
+            output.push_str(&format!(
+                "\n\nSkipped {large_files} files larger than {} bytes.",
+                Self::MAX_FILE_SIZE
+            ));
+        }
+
         Ok(InvokeOutput {
-            output: OutputKind::Text(serde_json::to_string(&results)?),
+            output: OutputKind::Text(output),
         })
     }
 
     fn context_lines(&self) -> usize {
         self.context_lines.unwrap_or(Self::DEFAULT_CONTEXT_LINES)
     }
+
+    /// Matches lines against the pattern, which is case insensitive unless `case_sensitive` is set.
+    fn matcher(&self) -> Result<Regex> {
+        let pattern = match self.regex {
+            Some(true) => self.pattern.clone(),
+            _ => regex::escape(&self.pattern),
+        };
+        Ok(RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive.unwrap_or(false))
+            .build()?)
+    }
+
+    /// The files to search under `root`, sorted by path. Hidden files and files ignored by
+    /// `.gitignore` are skipped unless requested, as are files not matching the include globs.
+    fn walk(&self, root: &Path) -> Result<Vec<PathBuf>> {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in self.include.iter().flatten() {
+            overrides.add(glob)?;
+        }
+        for glob in self.exclude.iter().flatten() {
+            overrides.add(&format!("!{glob}"))?;
+        }
+
+        let respect_ignore_files = !self.include_ignored.unwrap_or(false);
+        let walker = WalkBuilder::new(root)
+            .hidden(!self.include_hidden.unwrap_or(false))
+            .ignore(respect_ignore_files)
+            .git_ignore(respect_ignore_files)
+            .git_global(respect_ignore_files)
+            .git_exclude(respect_ignore_files)
+            .parents(respect_ignore_files)
+            .require_git(false)
+            .overrides(overrides.build()?)
+            .sort_by_file_path(|a, b| a.cmp(b))
+            .build();
+
+        let mut files = Vec::new();
+        for entry in walker {
+            match entry {
+                Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => files.push(entry.into_path()),
+                Ok(_) => (),
+                Err(err) => warn!(?err, "Failed to read a directory entry while searching"),
+            }
+        }
+        Ok(files)
+    }
 }
 
 /// List directory contents.
@@ -481,6 +654,9 @@ fn convert_negative_index(line_count: usize, i: i32) -> usize {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SearchMatch {
+    /// The file the match is in, when searching a directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
     line_number: usize,
     context: String,
 }
@@ -745,4 +921,144 @@ mod tests {
             )
         );
     }
+
+    #[tokio::test]
+    async fn test_fs_read_search_directory_invoke() {
+        let ctx = setup_test_directory().await;
+        let fs = ctx.fs();
+        fs.create_dir_all("/src").await.unwrap();
+        fs.write("/src/main.rs", "fn main() {\n    hello();\n}\n")
+            .await
+            .unwrap();
+        fs.write("/src/lib.rs", "pub fn hello() {}\n").await.unwrap();
+        fs.create_dir_all("/target").await.unwrap();
+        fs.write("/target/out.txt", "hello from the build").await.unwrap();
+        fs.write("/.gitignore", "target/\n").await.unwrap();
+        fs.write("/data.bin", b"hello\0binary").await.unwrap();
+        let mut stdout = std::io::stdout();
+
+        macro_rules! invoke_search {
+            ($value:tt) => {{
+                let mut fs_read = serde_json::from_value::<FsRead>(serde_json::json!($value)).unwrap();
+                fs_read.validate(&ctx).await.unwrap();
+                let output = fs_read.invoke(&ctx, &mut stdout).await.unwrap();
+                let OutputKind::Text(value) = output.output else {
+                    panic!("expected Text output")
+                };
+                let (matches, note) = value.split_once("\n\n").unwrap_or((&value, ""));
+                (
+                    serde_json::from_str::<Vec<SearchMatch>>(matches).unwrap(),
+                    note.to_string(),
+                )
+            }};
+        }
+        let paths = |matches: &[SearchMatch]| {
+            matches
+                .iter()
+                .map(|m| {
+                    let path = m.path.clone().unwrap();
+                    path.rsplit('/').next().unwrap().to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // Ignored and binary files are skipped.
+        let (matches, note) = invoke_search!({ "mode": "Search", "path": "/", "pattern": "HELLO" });
+        assert_eq!(paths(&matches), vec![
+            "lib.rs",
+            "main.rs",
+            "test_file.txt",
+            "test_file.txt"
+        ]);
+        assert!(note.is_empty());
+
+        let (matches, _) = invoke_search!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "hello",
+            "include": ["*.rs"],
+            "exclude": ["main.rs"],
+        });
+        assert_eq!(paths(&matches), vec!["lib.rs"]);
+
+        let (matches, _) = invoke_search!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "^fn \\w+\\(\\)",
+            "regex": true,
+        });
+        assert_eq!(paths(&matches), vec!["main.rs"]);
+
+        let (matches, _) = invoke_search!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "hello",
+            "case_sensitive": true,
+            "include_ignored": true,
+        });
+        assert_eq!(paths(&matches), vec!["lib.rs", "main.rs", "out.txt"]);
+
+        let (matches, _) = invoke_search!({ "mode": "Search", "path": "/", "pattern": "hidden file" });
+        assert!(matches.is_empty());
+        let (matches, _) = invoke_search!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "hidden file",
+            "include_hidden": true,
+        });
+        assert_eq!(paths(&matches), vec![".hidden"]);
+
+        // Per file caps and pagination.
+        let (matches, note) = invoke_search!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "hello",
+            "max_matches_per_file": 1,
+            "limit": 2,
+        });
+        assert_eq!(paths(&matches), vec!["lib.rs", "main.rs"]);
+        assert!(note.contains("offset of 2"));
+        let (matches, note) = invoke_search!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "hello",
+            "max_matches_per_file": 1,
+            "offset": 2,
+        });
+        assert_eq!(paths(&matches), vec!["test_file.txt"]);
+        assert!(note.is_empty());
+
+        let mut fs_read = serde_json::from_value::<FsRead>(serde_json::json!({
+            "mode": "Search",
+            "path": "/",
+            "pattern": "(",
+            "regex": true,
+        }))
+        .unwrap();
+        assert!(fs_read.validate(&ctx).await.is_err());
+
+        // Only directory searches are limited by default.
+        fs.write("/many.txt", "hello\n".repeat(60)).await.unwrap();
+        let (matches, note) = invoke_search!({ "mode": "Search", "path": "/many.txt", "pattern": "hello" });
+        assert_eq!(matches.len(), 60);
+        assert!(note.is_empty());
+        let (matches, note) = invoke_search!({ "mode": "Search", "path": "/", "pattern": "hello" });
+        assert_eq!(matches.len(), FsSearch::DEFAULT_LIMIT);
+        assert!(note.contains("offset of 50"));
+
+        // Large files are skipped.
+        let mut large = vec![b'a'; FsSearch::MAX_FILE_SIZE as usize];
+        large.extend_from_slice(b"\nneedle\n");
+        fs.write("/large.log", large).await.unwrap();
+        let (matches, note) = invoke_search!({ "mode": "Search", "path": "/", "pattern": "needle" });
+        assert!(matches.is_empty());
+        assert!(note.contains("Skipped 1 files"));
+        let mut fs_read = serde_json::from_value::<FsRead>(serde_json::json!({
+            "mode": "Search",
+            "path": "/large.log",
+            "pattern": "needle",
+        }))
+        .unwrap();
+        assert!(fs_read.validate(&ctx).await.is_err());
+    }
 }
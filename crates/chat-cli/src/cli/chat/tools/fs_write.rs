@@ -1,5 +1,8 @@
 use std::io::Write;
-use std::path::Path;
+use std::path::{
+    Path,
+    PathBuf,
+};
 use std::sync::LazyLock;
 
 use crossterm::queue;
@@ -33,6 +36,7 @@ use super::{
     sanitize_path_tool_arg,
     supports_truecolor,
 };
+use crate::cli::chat::util::patch::parse_patch;
 use crate::platform::Context;
 
 static SYNTAX_SET: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
@@ -63,6 +67,30 @@ pub enum FsWrite {
     },
     #[serde(rename = "append")]
     Append { path: String, new_str: String },
+    /// Replacements applied in order to a single file. Either all of them are applied, or none.
+    #[serde(rename = "multi_edit")]
+    MultiEdit { path: String, edits: Vec<Edit> },
+    /// A unified diff, possibly spanning multiple files.
+    #[serde(rename = "apply_patch")]
+    ApplyPatch { patch: String },
+}
+
+/// A single replacement within [FsWrite::MultiEdit], with the same semantics as
+/// [FsWrite::StrReplace].
+#[derive(Debug, Clone, Deserialize)]
+pub struct Edit {
+    pub old_str: String,
+    pub new_str: String,
+}
+
+/// A file changed by [FsWrite::ApplyPatch].
+#[derive(Debug, Clone)]
+struct PatchedFile {
+    path: PathBuf,
+    /// [None] if the file is created by the patch.
+    original: Option<String>,
+    /// [None] if the file is deleted by the patch.
+    patched: Option<String>,
 }
 
 impl FsWrite {
@@ -160,12 +188,59 @@ impl FsWrite {
                 write_to_file(ctx, path, file).await?;
                 Ok(Default::default())
             },
+            FsWrite::MultiEdit { path, edits } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                let file = fs.read_to_string(&path).await?;
+                queue!(
+                    updates,
+                    style::Print("Updating: "),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(format_path(&cwd, &path)),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+                let file = apply_edits(&file, edits)?;
+                fs.write(path, file).await?;
+                Ok(Default::default())
+            },
+            FsWrite::ApplyPatch { .. } => {
+                // Every file is patched in memory first so that nothing is written if any part of
+                // the patch fails to apply.
+                let files = self.patched_files(ctx)?;
+                for file in files {
+                    let invoke_description = match (&file.original, &file.patched) {
+                        (None, _) => "Creating: ",
+                        (_, None) => "Deleting: ",
+                        _ => "Updating: ",
+                    };
+                    queue!(
+                        updates,
+                        style::Print(invoke_description),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(format_path(&cwd, &file.path)),
+                        style::ResetColor,
+                        style::Print("\n"),
+                    )?;
+                    match file.patched {
+                        Some(patched) => {
+                            if let Some(parent) = file.path.parent() {
+                                fs.create_dir_all(parent).await?;
+                            }
+                            fs.write(&file.path, patched).await?;
+                        },
+                        None => fs.remove_file(&file.path).await?,
+                    }
+                }
+                Ok(Default::default())
+            },
         }
     }
 
     pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
         let cwd = ctx.env().current_dir()?;
-        self.print_relative_path(ctx, updates)?;
+        if !matches!(self, FsWrite::ApplyPatch { .. }) {
+            self.print_relative_path(ctx, updates)?;
+        }
         match self {
             FsWrite::Create { path, .. } => {
                 let file_text = self.canonical_create_command_text();
@@ -227,6 +302,40 @@ impl FsWrite {
                 print_diff(updates, &Default::default(), &file, start_line)?;
                 Ok(())
             },
+            FsWrite::MultiEdit { path, edits } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                let relative_path = format_path(cwd, &path);
+                let file = ctx.fs().read_to_string_sync(&path)?;
+                let new_file = apply_edits(&file, edits)?;
+                print_file_diff(ctx, updates, &relative_path, &file, &new_file)
+            },
+            FsWrite::ApplyPatch { .. } => {
+                for file in self.patched_files(ctx)? {
+                    let relative_path = format_path(&cwd, &file.path);
+                    let action = match (&file.original, &file.patched) {
+                        (None, _) => "Create",
+                        (_, None) => "Delete",
+                        _ => "Update",
+                    };
+                    queue!(
+                        updates,
+                        style::Print(format!("{action}: ")),
+                        style::SetForegroundColor(Color::Green),
+                        style::Print(&relative_path),
+                        style::ResetColor,
+                        style::Print("\n\n"),
+                    )?;
+                    print_file_diff(
+                        ctx,
+                        updates,
+                        &relative_path,
+                        file.original.as_deref().unwrap_or_default(),
+                        file.patched.as_deref().unwrap_or_default(),
+                    )?;
+                    queue!(updates, style::Print("\n"))?;
+                }
+                Ok(())
+            },
         }
     }
 
@@ -251,22 +360,113 @@ impl FsWrite {
                     bail!("Content to append must not be empty")
                 };
             },
+            FsWrite::MultiEdit { path, edits } => {
+                if edits.is_empty() {
+                    bail!("At least one edit must be provided")
+                }
+                let path = sanitize_path_tool_arg(ctx, path);
+                if !path.exists() {
+                    bail!("The provided path must exist in order to edit it")
+                }
+                apply_edits(&ctx.fs().read_to_string(&path).await?, edits)?;
+            },
+            FsWrite::ApplyPatch { .. } => {
+                self.patched_files(ctx)?;
+            },
         }
 
         Ok(())
     }
 
-    fn print_relative_path(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
-        let cwd = ctx.env().current_dir()?;
+    /// The paths of the files written to, with tilde expansion applied.
+    pub fn paths(&self, ctx: &Context) -> Vec<PathBuf> {
         let path = match self {
             FsWrite::Create { path, .. } => path,
             FsWrite::StrReplace { path, .. } => path,
             FsWrite::Insert { path, .. } => path,
             FsWrite::Append { path, .. } => path,
+            FsWrite::MultiEdit { path, .. } => path,
+            FsWrite::ApplyPatch { patch } => {
+                let cwd = ctx.env().current_dir().unwrap_or_default();
+                return parse_patch(patch)
+                    .unwrap_or_default()
+                    .iter()
+                    .flat_map(|file| [file.old_path.as_deref(), file.new_path.as_deref()])
+                    .flatten()
+                    .map(|path| resolve_patch_path(ctx, &cwd, path))
+                    .fold(Vec::new(), |mut paths, path| {
+                        if !paths.contains(&path) {
+                            paths.push(path);
+                        }
+                        paths
+                    });
+            },
+        };
+        vec![sanitize_path_tool_arg(ctx, path)]
+    }
+
+    /// Applies [FsWrite::ApplyPatch] in memory, returning the resulting files. Renames are
+    /// represented as the deletion of the old file along with the creation of the new one.
+    fn patched_files(&self, ctx: &Context) -> Result<Vec<PatchedFile>> {
+        let FsWrite::ApplyPatch { patch } = self else {
+            return Ok(Vec::new());
         };
-        // Sanitize the path to handle tilde expansion
-        let path = sanitize_path_tool_arg(ctx, path);
-        let relative_path = format_path(cwd, &path);
+        let cwd = ctx.env().current_dir()?;
+
+        let mut files: Vec<PatchedFile> = Vec::new();
+        for file_patch in parse_patch(patch)? {
+            let old_path = file_patch.old_path.as_deref().map(|p| resolve_patch_path(ctx, &cwd, p));
+            let new_path = file_patch.new_path.as_deref().map(|p| resolve_patch_path(ctx, &cwd, p));
+            for path in old_path.iter().chain(new_path.iter()) {
+                if files.iter().any(|f| &f.path == path) {
+                    bail!("{} is changed more than once by the patch", path.display());
+                }
+            }
+
+            let original = match &old_path {
+                Some(path) if !ctx.fs().exists(path) => bail!("{} does not exist", path.display()),
+                Some(path) => Some(ctx.fs().read_to_string_sync(path)?),
+                None => None,
+            };
+            let patched = file_patch.apply(original.as_deref().unwrap_or_default())?;
+
+            match (old_path, new_path) {
+                (Some(old_path), Some(new_path)) if old_path != new_path => {
+                    if ctx.fs().exists(&new_path) {
+                        bail!("{} already exists", new_path.display());
+                    }
+                    files.push(PatchedFile {
+                        path: old_path,
+                        original,
+                        patched: None,
+                    });
+                    files.push(PatchedFile {
+                        path: new_path,
+                        original: None,
+                        patched,
+                    });
+                },
+                (_, Some(path)) if original.is_none() && ctx.fs().exists(&path) => {
+                    bail!("{} already exists", path.display());
+                },
+                (old_path, new_path) => files.push(PatchedFile {
+                    path: new_path.or(old_path).unwrap_or_default(),
+                    original,
+                    patched,
+                }),
+            }
+        }
+        Ok(files)
+    }
+
+    fn print_relative_path(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+        let cwd = ctx.env().current_dir()?;
+        let relative_path = self
+            .paths(ctx)
+            .iter()
+            .map(|path| format_path(&cwd, path))
+            .collect::<Vec<_>>()
+            .join(", ");
         queue!(
             updates,
             style::Print("Path: "),
@@ -298,6 +498,66 @@ impl FsWrite {
     }
 }
 
+/// Applies each of `edits` in order to `file`. Fails if any `old_str` does not occur exactly once
+/// at the time it is applied.
+fn apply_edits(file: &str, edits: &[Edit]) -> Result<String> {
+    let mut file = file.to_string();
+    for (i, Edit { old_str, new_str }) in edits.iter().enumerate() {
+        match file.match_indices(old_str.as_str()).count() {
+            0 => bail!("edit {}: no occurrences of \"{old_str}\" were found", i + 1),
+            1 => file = file.replacen(old_str.as_str(), new_str, 1),
+            x => bail!(
+                "edit {}: {x} occurrences of old_str were found when only 1 is expected",
+                i + 1
+            ),
+        }
+    }
+    Ok(file)
+}
+
+/// Resolves a path from a patch header, which is usually relative to the working directory.
+fn resolve_patch_path(ctx: &Context, cwd: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() || path.starts_with("~") {
+        sanitize_path_tool_arg(ctx, path)
+    } else {
+        sanitize_path_tool_arg(ctx, cwd.join(path))
+    }
+}
+
+/// Prints the changed regions between two versions of a whole file, with a few lines of context.
+fn print_file_diff(
+    ctx: &Context,
+    updates: &mut impl Write,
+    relative_path: &str,
+    old_file: &str,
+    new_file: &str,
+) -> Result<()> {
+    let diff = similar::TextDiff::from_lines(old_file, new_file);
+    for (i, group) in diff.grouped_ops(3).iter().enumerate() {
+        let (Some(first), Some(last)) = (group.first(), group.last()) else {
+            continue;
+        };
+        let old_range = first.old_range().start..last.old_range().end;
+        let new_range = first.new_range().start..last.new_range().end;
+        let old = diff.old_slices()[old_range.clone()].concat();
+        let new = diff.new_slices()[new_range].concat();
+
+        if i > 0 {
+            queue!(
+                updates,
+                style::SetForegroundColor(Color::DarkGrey),
+                style::Print("  ...\n"),
+                style::ResetColor
+            )?;
+        }
+        let old = stylize_output_if_able(ctx, relative_path, &old);
+        let new = stylize_output_if_able(ctx, relative_path, &new);
+        print_diff(updates, &old, &new, old_range.start + 1)?;
+    }
+    Ok(())
+}
+
 /// Writes `content` to `path`, adding a newline if necessary.
 async fn write_to_file(ctx: &Context, path: impl AsRef<Path>, mut content: String) -> Result<()> {
     let path_ref = path.as_ref();
@@ -679,6 +939,23 @@ mod tests {
         });
         let fw = serde_json::from_value::<FsWrite>(v).unwrap();
         assert!(matches!(fw, FsWrite::Append { .. }));
+
+        // multi_edit
+        let v = serde_json::json!({
+            "path": path,
+            "command": "multi_edit",
+            "edits": [{ "old_str": "prev string", "new_str": "new string" }],
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(matches!(fw, FsWrite::MultiEdit { .. }));
+
+        // apply_patch
+        let v = serde_json::json!({
+            "command": "apply_patch",
+            "patch": "--- a/my-file\n+++ b/my-file\n@@ -1 +1 @@\n-a\n+b\n",
+        });
+        let fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(matches!(fw, FsWrite::ApplyPatch { .. }));
     }
 
     #[tokio::test]
@@ -947,6 +1224,107 @@ mod tests {
         assert!(result.is_err(), "Appending to non-existent file should fail");
     }
 
+    #[tokio::test]
+    async fn test_fs_write_tool_multi_edit() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+
+        // A failing edit prevents earlier edits from being applied.
+        let v = serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "multi_edit",
+            "edits": [
+                { "old_str": "2: This is line 2", "new_str": "2: Edited" },
+                { "old_str": "Hello world!", "new_str": "Goodbye world!" },
+            ],
+        });
+        let mut fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(fw.validate(&ctx).await.is_err());
+        assert!(fw.invoke(&ctx, &mut stdout).await.is_err());
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            TEST_FILE_CONTENTS
+        );
+
+        // Edits apply in order, so later edits can match the output of earlier ones.
+        let v = serde_json::json!({
+            "path": TEST_FILE_PATH,
+            "command": "multi_edit",
+            "edits": [
+                { "old_str": "1: Hello world!", "new_str": "1: Goodbye world!" },
+                { "old_str": "Goodbye", "new_str": "Farewell" },
+                { "old_str": "3: asdf", "new_str": "3: jkl" },
+            ],
+        });
+        let mut fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        fw.validate(&ctx).await.unwrap();
+        fw.invoke(&ctx, &mut stdout).await.unwrap();
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            "1: Farewell world!\n2: This is line 2\n3: jkl\n4: Hello world!\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fs_write_tool_apply_patch() {
+        let ctx = setup_test_directory().await;
+        let mut stdout = std::io::stdout();
+
+        // Nothing is written if any file fails to apply.
+        let v = serde_json::json!({
+            "command": "apply_patch",
+            "patch": "\
+--- /dev/null
++++ b/new_file.txt
+@@ -0,0 +1 @@
++new
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -1 +1 @@
+-does not exist
++3: changed
+",
+        });
+        let mut fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        assert!(fw.validate(&ctx).await.is_err());
+        assert!(fw.invoke(&ctx, &mut stdout).await.is_err());
+        assert!(!ctx.fs().exists("/new_file.txt"));
+
+        let v = serde_json::json!({
+            "command": "apply_patch",
+            "patch": "\
+--- /dev/null
++++ b/new_file.txt
+@@ -0,0 +1 @@
++new
+--- a/test_file.txt
++++ b/test_file.txt
+@@ -2,2 +2,2 @@
+ 2: This is line 2
+-3: asdf
++3: changed
+--- a/aaaa2/.hidden
++++ /dev/null
+@@ -1 +0,0 @@
+-this is a hidden file
+",
+        });
+        let mut fw = serde_json::from_value::<FsWrite>(v).unwrap();
+        fw.validate(&ctx).await.unwrap();
+        assert_eq!(fw.paths(&ctx), vec![
+            sanitize_path_tool_arg(&ctx, "/new_file.txt"),
+            sanitize_path_tool_arg(&ctx, TEST_FILE_PATH),
+            sanitize_path_tool_arg(&ctx, TEST_HIDDEN_FILE_PATH),
+        ]);
+        fw.invoke(&ctx, &mut stdout).await.unwrap();
+        assert_eq!(ctx.fs().read_to_string("/new_file.txt").await.unwrap(), "new\n");
+        assert_eq!(
+            ctx.fs().read_to_string(TEST_FILE_PATH).await.unwrap(),
+            "1: Hello world!\n2: This is line 2\n3: changed\n4: Hello world!\n"
+        );
+        assert!(!ctx.fs().exists(TEST_HIDDEN_FILE_PATH));
+    }
+
     #[test]
     fn test_lines_with_context() {
         let content = "Hello\nWorld!\nhow\nare\nyou\ntoday?";
@@ -0,0 +1,232 @@
+use std::io::Write;
+
+use crossterm::queue;
+use crossterm::style::{
+    self,
+    Color,
+};
+use eyre::{
+    Result,
+    bail,
+};
+use semantic_search_client::ProgressStatus;
+use serde::Deserialize;
+
+use super::{
+    InvokeOutput,
+    OutputKind,
+    format_path,
+    sanitize_path_tool_arg,
+};
+use crate::cli::chat::knowledge_store::{
+    DEFAULT_SEARCH_LIMIT,
+    KnowledgeStore,
+};
+use crate::platform::Context;
+
+/// Semantic search over persistent indexes of directories and files, so that a codebase or a docs
+/// folder can be indexed once and queried across sessions.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "command")]
+pub enum Knowledge {
+    #[serde(rename = "show")]
+    Show,
+    #[serde(rename = "add")]
+    Add { path: String, name: Option<String> },
+    #[serde(rename = "remove")]
+    Remove { name: String },
+    #[serde(rename = "search")]
+    Search {
+        query: String,
+        knowledge_base: Option<String>,
+        limit: Option<usize>,
+    },
+}
+
+impl Knowledge {
+    /// Only commands that modify the knowledge bases require acceptance.
+    pub fn requires_acceptance(&self) -> bool {
+        matches!(self, Knowledge::Add { .. } | Knowledge::Remove { .. })
+    }
+
+    pub async fn invoke(&self, ctx: &Context, updates: &mut impl Write) -> Result<InvokeOutput> {
+        let store = KnowledgeStore::get(ctx).await?;
+        match self {
+            Knowledge::Show => {
+                let knowledge_bases = store
+                    .knowledge_bases()
+                    .await?
+                    .into_iter()
+                    .map(|kb| {
+                        serde_json::json!({
+                            "name": kb.name,
+                            "path": kb.source_path,
+                            "items": kb.item_count,
+                            "updated_at": kb.updated_at.to_rfc3339(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(InvokeOutput {
+                    output: OutputKind::Json(serde_json::Value::Array(knowledge_bases)),
+                })
+            },
+            Knowledge::Add { path, name } => {
+                let path = sanitize_path_tool_arg(ctx, path);
+                let name = name.clone().unwrap_or_else(|| default_name(&path));
+                let kb = store.add(name, &path, None::<fn(ProgressStatus)>).await?;
+                queue!(
+                    updates,
+                    style::Print(format!("Indexed {} items into ", kb.item_count)),
+                    style::SetForegroundColor(Color::Green),
+                    style::Print(&kb.name),
+                    style::ResetColor,
+                    style::Print("\n"),
+                )?;
+                Ok(InvokeOutput {
+                    output: OutputKind::Text(format!(
+                        "Created the knowledge base '{}' with {} items",
+                        kb.name, kb.item_count
+                    )),
+                })
+            },
+            Knowledge::Remove { name } => {
+                let kb = store.remove(ctx, name.clone()).await?;
+                Ok(InvokeOutput {
+                    output: OutputKind::Text(format!("Removed the knowledge base '{}'", kb.name)),
+                })
+            },
+            Knowledge::Search {
+                query,
+                knowledge_base,
+                limit,
+            } => {
+                let results = store
+                    .search(
+                        ctx,
+                        query.clone(),
+                        knowledge_base.clone(),
+                        limit.unwrap_or(DEFAULT_SEARCH_LIMIT),
+                    )
+                    .await?;
+                Ok(InvokeOutput {
+                    output: OutputKind::Json(serde_json::to_value(results)?),
+                })
+            },
+        }
+    }
+
+    pub fn queue_description(&self, ctx: &Context, updates: &mut impl Write) -> Result<()> {
+        let (action, target) = match self {
+            Knowledge::Show => ("Listing knowledge bases", None),
+            Knowledge::Add { path, .. } => {
+                let cwd = ctx.env().current_dir()?;
+                ("Indexing", Some(format_path(cwd, sanitize_path_tool_arg(ctx, path))))
+            },
+            Knowledge::Remove { name } => ("Removing the knowledge base", Some(name.clone())),
+            Knowledge::Search {
+                query, knowledge_base, ..
+            } => (
+                "Searching knowledge bases for",
+                Some(match knowledge_base {
+                    Some(kb) => format!("\"{query}\" in {kb}"),
+                    None => format!("\"{query}\""),
+                }),
+            ),
+        };
+        queue!(updates, style::Print(action))?;
+        if let Some(target) = target {
+            queue!(
+                updates,
+                style::Print(" "),
+                style::SetForegroundColor(Color::Green),
+                style::Print(target),
+                style::ResetColor,
+            )?;
+        }
+        queue!(updates, style::Print("\n"))?;
+        Ok(())
+    }
+
+    pub async fn validate(&mut self, ctx: &Context) -> Result<()> {
+        match self {
+            Knowledge::Show => (),
+            Knowledge::Add { path, name } => {
+                if !sanitize_path_tool_arg(ctx, &path).exists() {
+                    bail!("'{path}' does not exist");
+                }
+                if name.as_ref().is_some_and(|n| n.trim().is_empty()) {
+                    bail!("The knowledge base name must not be empty");
+                }
+            },
+            Knowledge::Remove { name } => {
+                if name.trim().is_empty() {
+                    bail!("The knowledge base name must not be empty");
+                }
+            },
+            Knowledge::Search { query, limit, .. } => {
+                if query.trim().is_empty() {
+                    bail!("The search query must not be empty");
+                }
+                if *limit == Some(0) {
+                    bail!("The limit must be greater than 0");
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// The name given to a knowledge base when none is provided - the last component of its path.
+pub fn default_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_knowledge_deserialize() {
+        let knowledge = serde_json::from_value::<Knowledge>(serde_json::json!({
+            "command": "search",
+            "query": "how are users authenticated",
+        }))
+        .unwrap();
+        assert!(matches!(knowledge, Knowledge::Search { limit: None, .. }));
+        assert!(!knowledge.requires_acceptance());
+
+        let knowledge = serde_json::from_value::<Knowledge>(serde_json::json!({
+            "command": "add",
+            "path": "~/docs",
+        }))
+        .unwrap();
+        assert!(matches!(knowledge, Knowledge::Add { name: None, .. }));
+        assert!(knowledge.requires_acceptance());
+    }
+
+    #[tokio::test]
+    async fn test_knowledge_validate() {
+        let ctx = Context::builder().with_test_home().await.unwrap().build_fake();
+        ctx.fs().create_dir_all("/docs").await.unwrap();
+
+        let mut knowledge = Knowledge::Add {
+            path: "/docs".to_string(),
+            name: None,
+        };
+        knowledge.validate(&ctx).await.unwrap();
+        let mut knowledge = Knowledge::Add {
+            path: "/missing".to_string(),
+            name: None,
+        };
+        assert!(knowledge.validate(&ctx).await.is_err());
+        let mut knowledge = Knowledge::Search {
+            query: " ".to_string(),
+            knowledge_base: None,
+            limit: None,
+        };
+        assert!(knowledge.validate(&ctx).await.is_err());
+    }
+}
@@ -1,8 +1,10 @@
 pub mod custom_tool;
+pub mod delegate;
 pub mod execute;
 pub mod fs_read;
 pub mod fs_write;
 pub mod gh_issue;
+pub mod knowledge;
 pub mod thinking;
 pub mod use_aws;
 
@@ -15,11 +17,13 @@ use std::path::{
 
 use crossterm::style::Stylize;
 use custom_tool::CustomTool;
+use delegate::Delegate;
 use execute::ExecuteCommand;
 use eyre::Result;
 use fs_read::FsRead;
 use fs_write::FsWrite;
 use gh_issue::GhIssue;
+use knowledge::Knowledge;
 use serde::{
     Deserialize,
     Serialize,
@@ -42,6 +46,8 @@ pub enum Tool {
     Custom(CustomTool),
     GhIssue(GhIssue),
     Thinking(Thinking),
+    Knowledge(Knowledge),
+    Delegate(Delegate),
 }
 
 impl Tool {
@@ -58,6 +64,8 @@ impl Tool {
             Tool::Custom(custom_tool) => &custom_tool.name,
             Tool::GhIssue(_) => "gh_issue",
             Tool::Thinking(_) => "thinking (prerelease)",
+            Tool::Knowledge(_) => "knowledge",
+            Tool::Delegate(_) => "delegate",
         }
         .to_owned()
     }
@@ -72,6 +80,24 @@ impl Tool {
             Tool::Custom(_) => true,
             Tool::GhIssue(_) => false,
             Tool::Thinking(_) => false,
+            Tool::Knowledge(knowledge) => knowledge.requires_acceptance(),
+            // Sub-agents use the tools trusted by the user without asking, and run the hooks of
+            // the conversation.
+            Tool::Delegate(_) => true,
+        }
+    }
+
+    /// Whether the tool can be invoked concurrently with other tools, i.e. it does not modify its
+    /// environment. MCP tools are only considered read-only when annotated as such by their
+    /// server.
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Tool::FsRead(_) | Tool::Thinking(_) => true,
+            Tool::ExecuteCommand(execute_command) => !execute_command.requires_acceptance(),
+            Tool::UseAws(use_aws) => !use_aws.requires_acceptance(),
+            Tool::Knowledge(knowledge) => !knowledge.requires_acceptance(),
+            Tool::Custom(custom_tool) => custom_tool.read_only,
+            Tool::FsWrite(_) | Tool::GhIssue(_) | Tool::Delegate(_) => false,
         }
     }
 
@@ -85,6 +111,8 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.invoke(context, updates).await,
             Tool::GhIssue(gh_issue) => gh_issue.invoke(updates).await,
             Tool::Thinking(think) => think.invoke(updates).await,
+            Tool::Knowledge(knowledge) => knowledge.invoke(context, updates).await,
+            Tool::Delegate(delegate) => delegate.invoke(updates).await,
         }
     }
 
@@ -98,6 +126,8 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.queue_description(updates),
             Tool::GhIssue(gh_issue) => gh_issue.queue_description(updates),
             Tool::Thinking(thinking) => thinking.queue_description(updates),
+            Tool::Knowledge(knowledge) => knowledge.queue_description(ctx, updates),
+            Tool::Delegate(delegate) => delegate.queue_description(updates),
         }
     }
 
@@ -111,6 +141,8 @@ impl Tool {
             Tool::Custom(custom_tool) => custom_tool.validate(ctx).await,
             Tool::GhIssue(gh_issue) => gh_issue.validate(ctx).await,
             Tool::Thinking(think) => think.validate(ctx).await,
+            Tool::Knowledge(knowledge) => knowledge.validate(ctx).await,
+            Tool::Delegate(delegate) => delegate.validate(ctx).await,
         }
     }
 }
@@ -193,6 +225,7 @@ impl ToolPermissions {
             "use_aws" => "trust read-only commands".dark_grey(),
             "report_issue" => "trusted".dark_green().bold(),
             "thinking" => "trusted (prerelease)".dark_green().bold(),
+            "knowledge" => "trust read-only commands".dark_grey(),
             _ if self.trust_all => "trusted".dark_grey().bold(),
             _ => "not trusted".dark_grey(),
         };
@@ -211,6 +244,25 @@ pub struct ToolSpec {
     pub input_schema: InputSchema,
     #[serde(skip_serializing, default = "tool_origin")]
     pub tool_origin: ToolOrigin,
+    /// Hints about the behavior of the tool, as reported by its MCP server.
+    #[serde(skip_serializing, default)]
+    pub annotations: Option<ToolAnnotations>,
+}
+
+/// Annotations of an MCP tool, see
+/// https://modelcontextprotocol.io/specification/2025-03-26/server/tools#tool-annotations.
+///
+/// These are hints from the server and are not verified. Only `readOnlyHint` is acted upon: plan
+/// mode trusts it to keep the tool available, see [super::mode::ChatMode::allows_tool].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    pub title: Option<String>,
+    /// The tool does not modify its environment.
+    pub read_only_hint: Option<bool>,
+    pub destructive_hint: Option<bool>,
+    pub idempotent_hint: Option<bool>,
+    pub open_world_hint: Option<bool>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -264,6 +316,11 @@ pub struct QueuedTool {
     pub name: String,
     pub accepted: bool,
     pub tool: Tool,
+    /// Set when the tool must not be invoked, e.g. because a command policy rule denies it. The
+    /// reason is returned to the model in place of the tool's output.
+    pub denied_reason: Option<String>,
+    /// The tool's input, as given to tool use hooks.
+    pub args: serde_json::Value,
 }
 
 /// The schema specification describing a tool's fields.
@@ -354,7 +411,7 @@ fn absolute_to_relative(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> Result
 }
 
 /// Small helper for formatting the path as a relative path, if able.
-fn format_path(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
+pub fn format_path(cwd: impl AsRef<Path>, path: impl AsRef<Path>) -> String {
     absolute_to_relative(cwd, path.as_ref())
         .map(|p| p.to_string_lossy().to_string())
         // If we have three consecutive ".." then it should probably just stay as an absolute path.
@@ -405,6 +462,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_tool_spec_annotations() {
+        let spec = serde_json::from_value::<ToolSpec>(serde_json::json!({
+            "name": "get_weather",
+            "description": "Gets the weather",
+            "inputSchema": { "type": "object" },
+            "annotations": { "title": "Weather", "readOnlyHint": true },
+        }))
+        .unwrap();
+        let annotations = spec.annotations.unwrap();
+        assert_eq!(annotations.title.as_deref(), Some("Weather"));
+        assert_eq!(annotations.read_only_hint, Some(true));
+        assert_eq!(annotations.destructive_hint, None);
+    }
+
     #[tokio::test]
     async fn test_format_path() {
         async fn assert_paths(cwd: &str, path: &str, expected: &str) {
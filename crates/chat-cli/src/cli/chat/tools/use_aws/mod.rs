@@ -1,3 +1,6 @@
+mod native;
+mod operations;
+
 use std::collections::HashMap;
 use std::io::Write;
 use std::process::Stdio;
@@ -15,6 +18,8 @@ use eyre::{
     Result,
     WrapErr,
 };
+use native::NativeOperation;
+use operations::AccessLevel;
 use serde::Deserialize;
 
 use super::{
@@ -24,8 +29,6 @@ use super::{
 };
 use crate::platform::Context;
 
-const READONLY_OPS: [&str; 6] = ["get", "describe", "list", "ls", "search", "batch_get"];
-
 /// The environment variable name where we set additional metadata for the AWS CLI user agent.
 const USER_AGENT_ENV_VAR: &str = "AWS_EXECUTION_ENV";
 const USER_AGENT_APP_NAME: &str = "AmazonQ-For-CLI";
@@ -46,10 +49,27 @@ pub struct UseAws {
 
 impl UseAws {
     pub fn requires_acceptance(&self) -> bool {
-        !READONLY_OPS.iter().any(|op| self.operation_name.starts_with(op))
+        operations::access_level(&self.service_name, &self.operation_name) != AccessLevel::Read
     }
 
     pub async fn invoke(&self, _ctx: &Context, _updates: impl Write) -> Result<InvokeOutput> {
+        // Prefer running through the SDK, falling back to the CLI for anything not supported.
+        if let Some(operation) =
+            NativeOperation::parse(&self.service_name, &self.operation_name, self.parameters.as_ref())
+        {
+            let output = operation
+                .invoke(&self.region, self.profile_name.as_deref())
+                .await
+                .wrap_err_with(|| format!("Failed to run {} {}", self.service_name, self.operation_name))?;
+            return Ok(InvokeOutput {
+                output: OutputKind::Json(serde_json::json!({
+                    "exit_status": "0",
+                    "stdout": output,
+                    "stderr": ""
+                })),
+            });
+        }
+
         let mut command = tokio::process::Command::new("aws");
         command.envs(std::env::vars());
 
@@ -0,0 +1,835 @@
+//! In-process execution of common read-only AWS operations through the SDK, so that they work
+//! without the `aws` CLI installed and return structured JSON directly.
+
+use std::collections::HashMap;
+
+use aws_config::Region;
+use aws_smithy_async::future::pagination_stream::PaginationStream;
+use aws_smithy_runtime_api::client::result::SdkError;
+use aws_smithy_types::DateTime;
+use aws_smithy_types::date_time::Format;
+use aws_types::SdkConfig;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use convert_case::{
+    Case,
+    Casing,
+};
+use eyre::Result;
+use serde_json::{
+    Map,
+    Value,
+    json,
+};
+
+use super::MAX_TOOL_RESPONSE_SIZE;
+use crate::aws_common::{
+    SdkErrorDisplay,
+    app_name,
+    behavior_version,
+};
+
+/// An operation that can be executed natively, along with its parsed parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NativeOperation {
+    StsGetCallerIdentity,
+    S3ListBuckets,
+    S3ListObjectsV2 {
+        bucket: String,
+        prefix: Option<String>,
+        delimiter: Option<String>,
+    },
+    S3GetObject {
+        bucket: String,
+        key: String,
+    },
+    Ec2DescribeInstances {
+        instance_ids: Option<Vec<String>>,
+        filters: Option<Vec<Ec2Filter>>,
+    },
+    Ec2DescribeVpcs {
+        vpc_ids: Option<Vec<String>>,
+        filters: Option<Vec<Ec2Filter>>,
+    },
+    Ec2DescribeSubnets {
+        subnet_ids: Option<Vec<String>>,
+        filters: Option<Vec<Ec2Filter>>,
+    },
+    Ec2DescribeSecurityGroups {
+        group_ids: Option<Vec<String>>,
+        filters: Option<Vec<Ec2Filter>>,
+    },
+    LogsFilterLogEvents {
+        log_group_name: String,
+        log_stream_names: Option<Vec<String>>,
+        filter_pattern: Option<String>,
+        start_time: Option<i64>,
+        end_time: Option<i64>,
+    },
+    DynamoDbQuery {
+        table_name: String,
+        index_name: Option<String>,
+        key_condition_expression: String,
+        expressions: DynamoDbExpressions,
+    },
+    DynamoDbScan {
+        table_name: String,
+        index_name: Option<String>,
+        expressions: DynamoDbExpressions,
+    },
+    LambdaListFunctions,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ec2Filter {
+    pub name: String,
+    pub values: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamoDbExpressions {
+    pub filter_expression: Option<String>,
+    pub projection_expression: Option<String>,
+    pub expression_attribute_names: Option<HashMap<String, String>>,
+    pub expression_attribute_values: Option<HashMap<String, Value>>,
+}
+
+impl NativeOperation {
+    /// Parses a `use_aws` call into a native operation. Returns [None] if the operation is not
+    /// supported natively or uses a parameter we don't handle, in which case the caller should
+    /// fall back to the CLI.
+    pub fn parse(service: &str, operation: &str, parameters: Option<&HashMap<String, Value>>) -> Option<Self> {
+        let service = service.trim().to_lowercase().replace('_', "-");
+        let operation = operation.trim().to_lowercase().replace('_', "-");
+        let mut params = Params::new(parameters)?;
+
+        let op = match (service.as_str(), operation.as_str()) {
+            ("sts", "get-caller-identity") => Self::StsGetCallerIdentity,
+            ("s3api", "list-buckets") => Self::S3ListBuckets,
+            ("s3", "ls") if params.is_empty() => Self::S3ListBuckets,
+            ("s3api", "list-objects-v2") => Self::S3ListObjectsV2 {
+                bucket: params.string("bucket")??,
+                prefix: params.string("prefix")?,
+                delimiter: params.string("delimiter")?,
+            },
+            ("s3api", "get-object") => Self::S3GetObject {
+                bucket: params.string("bucket")??,
+                key: params.string("key")??,
+            },
+            ("ec2", "describe-instances") => Self::Ec2DescribeInstances {
+                instance_ids: params.string_list("instance-ids")?,
+                filters: params.filters()?,
+            },
+            ("ec2", "describe-vpcs") => Self::Ec2DescribeVpcs {
+                vpc_ids: params.string_list("vpc-ids")?,
+                filters: params.filters()?,
+            },
+            ("ec2", "describe-subnets") => Self::Ec2DescribeSubnets {
+                subnet_ids: params.string_list("subnet-ids")?,
+                filters: params.filters()?,
+            },
+            ("ec2", "describe-security-groups") => Self::Ec2DescribeSecurityGroups {
+                group_ids: params.string_list("group-ids")?,
+                filters: params.filters()?,
+            },
+            ("logs", "filter-log-events") => Self::LogsFilterLogEvents {
+                log_group_name: params.string("log-group-name")??,
+                log_stream_names: params.string_list("log-stream-names")?,
+                filter_pattern: params.string("filter-pattern")?,
+                start_time: params.integer("start-time")?,
+                end_time: params.integer("end-time")?,
+            },
+            ("dynamodb", "query") => Self::DynamoDbQuery {
+                table_name: params.string("table-name")??,
+                index_name: params.string("index-name")?,
+                key_condition_expression: params.string("key-condition-expression")??,
+                expressions: params.dynamodb_expressions()?,
+            },
+            ("dynamodb", "scan") => Self::DynamoDbScan {
+                table_name: params.string("table-name")??,
+                index_name: params.string("index-name")?,
+                expressions: params.dynamodb_expressions()?,
+            },
+            ("lambda", "list-functions") => Self::LambdaListFunctions,
+            _ => return None,
+        };
+
+        params.is_empty().then_some(op)
+    }
+
+    /// Executes the operation, following pagination until the response would exceed the tool
+    /// response size.
+    pub async fn invoke(self, region: &str, profile_name: Option<&str>) -> Result<Value> {
+        let config = sdk_config(region, profile_name).await;
+
+        match self {
+            Self::StsGetCallerIdentity => {
+                let output = aws_sdk_sts::Client::new(&config)
+                    .get_caller_identity()
+                    .send()
+                    .await
+                    .map_err(sdk_error)?;
+                Ok(json!({
+                    "UserId": output.user_id(),
+                    "Account": output.account(),
+                    "Arn": output.arn(),
+                }))
+            },
+            Self::S3ListBuckets => {
+                let output = aws_sdk_s3::Client::new(&config)
+                    .list_buckets()
+                    .send()
+                    .await
+                    .map_err(sdk_error)?;
+                let buckets = output
+                    .buckets()
+                    .iter()
+                    .map(|bucket| {
+                        json!({
+                            "Name": bucket.name(),
+                            "CreationDate": date_time(bucket.creation_date()),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(json!({ "Buckets": buckets }))
+            },
+            Self::S3ListObjectsV2 {
+                bucket,
+                prefix,
+                delimiter,
+            } => {
+                let pages = aws_sdk_s3::Client::new(&config)
+                    .list_objects_v2()
+                    .bucket(bucket)
+                    .set_prefix(prefix)
+                    .set_delimiter(delimiter)
+                    .into_paginator()
+                    .send();
+                collect_pages("Contents", pages, |page| {
+                    let objects = page.contents().iter().map(|object| {
+                        json!({
+                            "Key": object.key(),
+                            "Size": object.size(),
+                            "LastModified": date_time(object.last_modified()),
+                            "ETag": object.e_tag(),
+                            "StorageClass": object.storage_class().map(|c| c.as_str()),
+                        })
+                    });
+                    let prefixes = page
+                        .common_prefixes()
+                        .iter()
+                        .map(|prefix| json!({ "Prefix": prefix.prefix() }));
+                    objects.chain(prefixes).collect()
+                })
+                .await
+            },
+            Self::S3GetObject { bucket, key } => {
+                let mut output = aws_sdk_s3::Client::new(&config)
+                    .get_object()
+                    .bucket(bucket)
+                    .key(key)
+                    .send()
+                    .await
+                    .map_err(sdk_error)?;
+
+                let limit = MAX_TOOL_RESPONSE_SIZE / 3;
+                let mut body = Vec::new();
+                while let Some(chunk) = output.body.next().await {
+                    body.extend_from_slice(&chunk?);
+                    if body.len() > limit {
+                        break;
+                    }
+                }
+                let truncated = body.len() > limit;
+                body.truncate(limit);
+
+                Ok(json!({
+                    "ContentType": output.content_type(),
+                    "ContentLength": output.content_length(),
+                    "LastModified": date_time(output.last_modified()),
+                    "ETag": output.e_tag(),
+                    "Body": String::from_utf8_lossy(&body),
+                    "Truncated": truncated,
+                }))
+            },
+            Self::Ec2DescribeInstances { instance_ids, filters } => {
+                let pages = aws_sdk_ec2::Client::new(&config)
+                    .describe_instances()
+                    .set_instance_ids(instance_ids)
+                    .set_filters(ec2_filters(filters))
+                    .into_paginator()
+                    .send();
+                collect_pages("Instances", pages, |page| {
+                    page.reservations()
+                        .iter()
+                        .flat_map(|reservation| reservation.instances())
+                        .map(|instance| {
+                            json!({
+                                "InstanceId": instance.instance_id(),
+                                "InstanceType": instance.instance_type().map(|t| t.as_str()),
+                                "State": instance.state().and_then(|s| s.name()).map(|n| n.as_str()),
+                                "ImageId": instance.image_id(),
+                                "LaunchTime": date_time(instance.launch_time()),
+                                "AvailabilityZone": instance.placement().and_then(|p| p.availability_zone()),
+                                "VpcId": instance.vpc_id(),
+                                "SubnetId": instance.subnet_id(),
+                                "PrivateIpAddress": instance.private_ip_address(),
+                                "PublicIpAddress": instance.public_ip_address(),
+                                "Tags": ec2_tags(instance.tags()),
+                            })
+                        })
+                        .collect()
+                })
+                .await
+            },
+            Self::Ec2DescribeVpcs { vpc_ids, filters } => {
+                let pages = aws_sdk_ec2::Client::new(&config)
+                    .describe_vpcs()
+                    .set_vpc_ids(vpc_ids)
+                    .set_filters(ec2_filters(filters))
+                    .into_paginator()
+                    .send();
+                collect_pages("Vpcs", pages, |page| {
+                    page.vpcs()
+                        .iter()
+                        .map(|vpc| {
+                            json!({
+                                "VpcId": vpc.vpc_id(),
+                                "CidrBlock": vpc.cidr_block(),
+                                "State": vpc.state().map(|s| s.as_str()),
+                                "IsDefault": vpc.is_default(),
+                                "Tags": ec2_tags(vpc.tags()),
+                            })
+                        })
+                        .collect()
+                })
+                .await
+            },
+            Self::Ec2DescribeSubnets { subnet_ids, filters } => {
+                let pages = aws_sdk_ec2::Client::new(&config)
+                    .describe_subnets()
+                    .set_subnet_ids(subnet_ids)
+                    .set_filters(ec2_filters(filters))
+                    .into_paginator()
+                    .send();
+                collect_pages("Subnets", pages, |page| {
+                    page.subnets()
+                        .iter()
+                        .map(|subnet| {
+                            json!({
+                                "SubnetId": subnet.subnet_id(),
+                                "VpcId": subnet.vpc_id(),
+                                "CidrBlock": subnet.cidr_block(),
+                                "AvailabilityZone": subnet.availability_zone(),
+                                "AvailableIpAddressCount": subnet.available_ip_address_count(),
+                                "Tags": ec2_tags(subnet.tags()),
+                            })
+                        })
+                        .collect()
+                })
+                .await
+            },
+            Self::Ec2DescribeSecurityGroups { group_ids, filters } => {
+                let pages = aws_sdk_ec2::Client::new(&config)
+                    .describe_security_groups()
+                    .set_group_ids(group_ids)
+                    .set_filters(ec2_filters(filters))
+                    .into_paginator()
+                    .send();
+                collect_pages("SecurityGroups", pages, |page| {
+                    page.security_groups()
+                        .iter()
+                        .map(|group| {
+                            let permissions = |permissions: &[aws_sdk_ec2::types::IpPermission]| {
+                                permissions
+                                    .iter()
+                                    .map(|permission| {
+                                        json!({
+                                            "IpProtocol": permission.ip_protocol(),
+                                            "FromPort": permission.from_port(),
+                                            "ToPort": permission.to_port(),
+                                            "IpRanges": permission
+                                                .ip_ranges()
+                                                .iter()
+                                                .map(|range| range.cidr_ip())
+                                                .collect::<Vec<_>>(),
+                                            "UserIdGroupPairs": permission
+                                                .user_id_group_pairs()
+                                                .iter()
+                                                .map(|pair| pair.group_id())
+                                                .collect::<Vec<_>>(),
+                                        })
+                                    })
+                                    .collect::<Vec<_>>()
+                            };
+                            json!({
+                                "GroupId": group.group_id(),
+                                "GroupName": group.group_name(),
+                                "Description": group.description(),
+                                "VpcId": group.vpc_id(),
+                                "IpPermissions": permissions(group.ip_permissions()),
+                                "IpPermissionsEgress": permissions(group.ip_permissions_egress()),
+                                "Tags": ec2_tags(group.tags()),
+                            })
+                        })
+                        .collect()
+                })
+                .await
+            },
+            Self::LogsFilterLogEvents {
+                log_group_name,
+                log_stream_names,
+                filter_pattern,
+                start_time,
+                end_time,
+            } => {
+                let pages = aws_sdk_cloudwatchlogs::Client::new(&config)
+                    .filter_log_events()
+                    .log_group_name(log_group_name)
+                    .set_log_stream_names(log_stream_names)
+                    .set_filter_pattern(filter_pattern)
+                    .set_start_time(start_time)
+                    .set_end_time(end_time)
+                    .into_paginator()
+                    .send();
+                collect_pages("Events", pages, |page| {
+                    page.events()
+                        .iter()
+                        .map(|event| {
+                            json!({
+                                "LogStreamName": event.log_stream_name(),
+                                "Timestamp": event.timestamp(),
+                                "Message": event.message(),
+                                "EventId": event.event_id(),
+                            })
+                        })
+                        .collect()
+                })
+                .await
+            },
+            Self::DynamoDbQuery {
+                table_name,
+                index_name,
+                key_condition_expression,
+                expressions,
+            } => {
+                let pages = aws_sdk_dynamodb::Client::new(&config)
+                    .query()
+                    .table_name(table_name)
+                    .set_index_name(index_name)
+                    .key_condition_expression(key_condition_expression)
+                    .set_filter_expression(expressions.filter_expression)
+                    .set_projection_expression(expressions.projection_expression)
+                    .set_expression_attribute_names(expressions.expression_attribute_names)
+                    .set_expression_attribute_values(attribute_map(expressions.expression_attribute_values)?)
+                    .into_paginator()
+                    .send();
+                collect_pages("Items", pages, |page| page.items().iter().map(item_to_json).collect()).await
+            },
+            Self::DynamoDbScan {
+                table_name,
+                index_name,
+                expressions,
+            } => {
+                let pages = aws_sdk_dynamodb::Client::new(&config)
+                    .scan()
+                    .table_name(table_name)
+                    .set_index_name(index_name)
+                    .set_filter_expression(expressions.filter_expression)
+                    .set_projection_expression(expressions.projection_expression)
+                    .set_expression_attribute_names(expressions.expression_attribute_names)
+                    .set_expression_attribute_values(attribute_map(expressions.expression_attribute_values)?)
+                    .into_paginator()
+                    .send();
+                collect_pages("Items", pages, |page| page.items().iter().map(item_to_json).collect()).await
+            },
+            Self::LambdaListFunctions => {
+                let pages = aws_sdk_lambda::Client::new(&config)
+                    .list_functions()
+                    .into_paginator()
+                    .send();
+                collect_pages("Functions", pages, |page| {
+                    page.functions()
+                        .iter()
+                        .map(|function| {
+                            json!({
+                                "FunctionName": function.function_name(),
+                                "FunctionArn": function.function_arn(),
+                                "Runtime": function.runtime().map(|r| r.as_str()),
+                                "Handler": function.handler(),
+                                "Description": function.description(),
+                                "CodeSize": function.code_size(),
+                                "MemorySize": function.memory_size(),
+                                "Timeout": function.timeout(),
+                                "LastModified": function.last_modified(),
+                            })
+                        })
+                        .collect()
+                })
+                .await
+            },
+        }
+    }
+}
+
+async fn sdk_config(region: &str, profile_name: Option<&str>) -> SdkConfig {
+    let mut loader = aws_config::defaults(behavior_version())
+        .region(Region::new(region.to_string()))
+        .app_name(app_name());
+    if let Some(profile_name) = profile_name {
+        loader = loader.profile_name(profile_name);
+    }
+    loader.load().await
+}
+
+fn sdk_error<E, R>(err: SdkError<E, R>) -> eyre::Report
+where
+    E: std::fmt::Display,
+{
+    eyre::eyre!("{}", SdkErrorDisplay(&err))
+}
+
+/// Collects items from every page of a paginated operation under `key`, stopping early once the
+/// serialized items would no longer fit in a tool response.
+async fn collect_pages<T, E, R>(
+    key: &str,
+    mut pages: PaginationStream<Result<T, SdkError<E, R>>>,
+    items: impl Fn(&T) -> Vec<Value>,
+) -> Result<Value>
+where
+    E: std::fmt::Display,
+{
+    let limit = MAX_TOOL_RESPONSE_SIZE / 3;
+    let mut collected = Vec::new();
+    let mut size = 0;
+    let mut truncated = false;
+
+    'pages: while let Some(page) = pages.next().await {
+        for item in items(&page.map_err(sdk_error)?) {
+            size += item.to_string().len();
+            if size > limit {
+                truncated = true;
+                break 'pages;
+            }
+            collected.push(item);
+        }
+    }
+
+    let mut output = Map::new();
+    output.insert(key.to_string(), Value::Array(collected));
+    if truncated {
+        output.insert("Truncated".to_string(), Value::Bool(true));
+    }
+    Ok(Value::Object(output))
+}
+
+fn date_time(date_time: Option<&DateTime>) -> Option<String> {
+    date_time.and_then(|dt| dt.fmt(Format::DateTime).ok())
+}
+
+fn ec2_filters(filters: Option<Vec<Ec2Filter>>) -> Option<Vec<aws_sdk_ec2::types::Filter>> {
+    filters.map(|filters| {
+        filters
+            .into_iter()
+            .map(|filter| {
+                aws_sdk_ec2::types::Filter::builder()
+                    .name(filter.name)
+                    .set_values(Some(filter.values))
+                    .build()
+            })
+            .collect()
+    })
+}
+
+fn ec2_tags(tags: &[aws_sdk_ec2::types::Tag]) -> Value {
+    tags.iter()
+        .map(|tag| json!({ "Key": tag.key(), "Value": tag.value() }))
+        .collect()
+}
+
+fn attribute_map(
+    values: Option<HashMap<String, Value>>,
+) -> Result<Option<HashMap<String, aws_sdk_dynamodb::types::AttributeValue>>> {
+    values
+        .map(|values| {
+            values
+                .into_iter()
+                .map(|(name, value)| {
+                    let attribute = attribute_from_json(&value)
+                        .ok_or_else(|| eyre::eyre!("Invalid DynamoDB attribute value for {name}: {value}"))?;
+                    Ok((name, attribute))
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+/// Converts a value in the DynamoDB JSON format (e.g. `{"S": "text"}`) into an attribute value.
+fn attribute_from_json(value: &Value) -> Option<aws_sdk_dynamodb::types::AttributeValue> {
+    use aws_sdk_dynamodb::types::AttributeValue;
+
+    let (kind, value) = value.as_object().filter(|o| o.len() == 1)?.iter().next()?;
+    let strings = |value: &Value| -> Option<Vec<String>> {
+        value
+            .as_array()?
+            .iter()
+            .map(|v| v.as_str().map(str::to_string))
+            .collect()
+    };
+    let blob = |value: &Value| -> Option<aws_smithy_types::Blob> {
+        STANDARD.decode(value.as_str()?).ok().map(aws_smithy_types::Blob::new)
+    };
+
+    Some(match kind.as_str() {
+        "S" => AttributeValue::S(value.as_str()?.to_string()),
+        "N" => AttributeValue::N(value.as_str()?.to_string()),
+        "B" => AttributeValue::B(blob(value)?),
+        "BOOL" => AttributeValue::Bool(value.as_bool()?),
+        "NULL" => AttributeValue::Null(value.as_bool()?),
+        "SS" => AttributeValue::Ss(strings(value)?),
+        "NS" => AttributeValue::Ns(strings(value)?),
+        "BS" => AttributeValue::Bs(value.as_array()?.iter().map(blob).collect::<Option<_>>()?),
+        "L" => AttributeValue::L(
+            value
+                .as_array()?
+                .iter()
+                .map(attribute_from_json)
+                .collect::<Option<_>>()?,
+        ),
+        "M" => AttributeValue::M(
+            value
+                .as_object()?
+                .iter()
+                .map(|(k, v)| Some((k.clone(), attribute_from_json(v)?)))
+                .collect::<Option<_>>()?,
+        ),
+        _ => return None,
+    })
+}
+
+/// Converts an attribute value into the DynamoDB JSON format the CLI prints.
+fn attribute_to_json(value: &aws_sdk_dynamodb::types::AttributeValue) -> Value {
+    use aws_sdk_dynamodb::types::AttributeValue;
+
+    match value {
+        AttributeValue::S(s) => json!({ "S": s }),
+        AttributeValue::N(n) => json!({ "N": n }),
+        AttributeValue::B(b) => json!({ "B": STANDARD.encode(b.as_ref()) }),
+        AttributeValue::Bool(b) => json!({ "BOOL": b }),
+        AttributeValue::Null(n) => json!({ "NULL": n }),
+        AttributeValue::Ss(ss) => json!({ "SS": ss }),
+        AttributeValue::Ns(ns) => json!({ "NS": ns }),
+        AttributeValue::Bs(bs) => json!({ "BS": bs.iter().map(|b| STANDARD.encode(b.as_ref())).collect::<Vec<_>>() }),
+        AttributeValue::L(l) => json!({ "L": l.iter().map(attribute_to_json).collect::<Vec<_>>() }),
+        AttributeValue::M(m) => json!({ "M": item_to_json(m) }),
+        _ => Value::Null,
+    }
+}
+
+fn item_to_json(item: &HashMap<String, aws_sdk_dynamodb::types::AttributeValue>) -> Value {
+    Value::Object(
+        item.iter()
+            .map(|(name, value)| (name.clone(), attribute_to_json(value)))
+            .collect(),
+    )
+}
+
+/// Tool parameters keyed by their kebab case CLI name. Parameters are removed as they are read so
+/// that anything left over can be detected.
+///
+/// Accessors return `None` when a parameter is present but has a shape we can't handle natively,
+/// and `Some(None)` when it is absent.
+struct Params(HashMap<String, Value>);
+
+#[allow(clippy::option_option)]
+impl Params {
+    fn new(parameters: Option<&HashMap<String, Value>>) -> Option<Self> {
+        let mut params = HashMap::new();
+        for (name, value) in parameters.into_iter().flatten() {
+            let name = name.trim_start_matches("--").to_case(Case::Kebab);
+            // e.g. both `TableName` and `--table-name`; let the CLI decide what that means.
+            if params.insert(name, value.clone()).is_some() {
+                return None;
+            }
+        }
+        Some(Self(params))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn string(&mut self, name: &str) -> Option<Option<String>> {
+        match self.0.remove(name) {
+            None => Some(None),
+            Some(Value::String(s)) => Some(Some(s)),
+            Some(_) => None,
+        }
+    }
+
+    fn integer(&mut self, name: &str) -> Option<Option<i64>> {
+        match self.0.remove(name) {
+            None => Some(None),
+            Some(Value::Number(n)) => n.as_i64().map(Some),
+            Some(Value::String(s)) => s.parse().ok().map(Some),
+            Some(_) => None,
+        }
+    }
+
+    /// Reads a list of strings, given either as a JSON array or as the CLI's space separated form.
+    fn string_list(&mut self, name: &str) -> Option<Option<Vec<String>>> {
+        match self.0.remove(name) {
+            None => Some(None),
+            Some(Value::String(s)) => Some(Some(s.split_whitespace().map(str::to_string).collect())),
+            Some(Value::Array(values)) => values
+                .into_iter()
+                .map(|v| v.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .map(Some),
+            Some(_) => None,
+        }
+    }
+
+    /// Reads a JSON parameter, which the CLI also accepts as a JSON encoded string.
+    fn json(&mut self, name: &str) -> Option<Option<Value>> {
+        match self.0.remove(name) {
+            None => Some(None),
+            Some(Value::String(s)) => serde_json::from_str(&s).ok().map(Some),
+            Some(value) => Some(Some(value)),
+        }
+    }
+
+    /// Reads EC2 filters in their JSON form. The CLI shorthand syntax isn't supported.
+    fn filters(&mut self) -> Option<Option<Vec<Ec2Filter>>> {
+        let Some(filters) = self.json("filters")? else {
+            return Some(None);
+        };
+        filters
+            .as_array()?
+            .iter()
+            .map(|filter| {
+                Some(Ec2Filter {
+                    name: filter.get("Name")?.as_str()?.to_string(),
+                    values: filter
+                        .get("Values")?
+                        .as_array()?
+                        .iter()
+                        .map(|v| v.as_str().map(str::to_string))
+                        .collect::<Option<_>>()?,
+                })
+            })
+            .collect::<Option<_>>()
+            .map(Some)
+    }
+
+    fn dynamodb_expressions(&mut self) -> Option<DynamoDbExpressions> {
+        let expression_attribute_names = match self.json("expression-attribute-names")? {
+            Some(names) => Some(serde_json::from_value(names).ok()?),
+            None => None,
+        };
+        let expression_attribute_values = match self.json("expression-attribute-values")? {
+            Some(values) => Some(serde_json::from_value(values).ok()?),
+            None => None,
+        };
+        Some(DynamoDbExpressions {
+            filter_expression: self.string("filter-expression")?,
+            projection_expression: self.string("projection-expression")?,
+            expression_attribute_names,
+            expression_attribute_values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(service: &str, operation: &str, parameters: Value) -> Option<NativeOperation> {
+        let parameters = serde_json::from_value::<HashMap<String, Value>>(parameters).unwrap();
+        NativeOperation::parse(service, operation, Some(&parameters))
+    }
+
+    #[test]
+    fn test_parse_supported() {
+        assert_eq!(
+            parse("sts", "get-caller-identity", json!({})),
+            Some(NativeOperation::StsGetCallerIdentity)
+        );
+        assert_eq!(
+            parse(
+                "s3api",
+                "list-objects-v2",
+                json!({ "Bucket": "bucket", "--prefix": "logs/" })
+            ),
+            Some(NativeOperation::S3ListObjectsV2 {
+                bucket: "bucket".into(),
+                prefix: Some("logs/".into()),
+                delimiter: None,
+            })
+        );
+        assert_eq!(
+            parse(
+                "ec2",
+                "describe-instances",
+                json!({
+                    "instance-ids": "i-1 i-2",
+                    "filters": "[{\"Name\": \"instance-state-name\", \"Values\": [\"running\"]}]"
+                })
+            ),
+            Some(NativeOperation::Ec2DescribeInstances {
+                instance_ids: Some(vec!["i-1".into(), "i-2".into()]),
+                filters: Some(vec![Ec2Filter {
+                    name: "instance-state-name".into(),
+                    values: vec!["running".into()],
+                }]),
+            })
+        );
+        assert!(
+            parse(
+                "dynamodb",
+                "query",
+                json!({
+                    "TableName": "table",
+                    "KeyConditionExpression": "pk = :pk",
+                    "ExpressionAttributeValues": { ":pk": { "S": "value" } }
+                })
+            )
+            .is_some()
+        );
+    }
+
+    #[test]
+    fn test_parse_falls_back() {
+        // Not supported natively
+        assert!(parse("s3", "cp", json!({})).is_none());
+        // Missing a required parameter
+        assert!(parse("s3api", "get-object", json!({ "bucket": "bucket" })).is_none());
+        // Unknown parameter
+        assert!(parse("lambda", "list-functions", json!({ "max-items": 5 })).is_none());
+        // Shorthand filter syntax
+        assert!(
+            parse(
+                "ec2",
+                "describe-vpcs",
+                json!({ "filters": "Name=isDefault,Values=true" })
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_attribute_round_trip() {
+        let value = json!({
+            "M": {
+                "name": { "S": "q" },
+                "count": { "N": "3" },
+                "tags": { "SS": ["a", "b"] },
+                "data": { "B": "aGVsbG8=" },
+                "nested": { "L": [{ "BOOL": true }, { "NULL": true }] }
+            }
+        });
+        let attribute = attribute_from_json(&value).unwrap();
+        assert_eq!(attribute_to_json(&attribute), value);
+        assert!(attribute_from_json(&json!({ "X": "unknown" })).is_none());
+    }
+}
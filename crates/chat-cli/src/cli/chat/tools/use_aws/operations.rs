@@ -0,0 +1,143 @@
+//! Metadata describing AWS operations, used to decide whether a `use_aws` call only reads state
+//! and can therefore run without asking the user first. Operations are only considered read-only
+//! when they are listed, never from their name alone.
+
+/// Whether an operation only reads state or may create, modify or delete resources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLevel {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OperationMetadata {
+    pub service: &'static str,
+    pub operation: &'static str,
+    pub access: AccessLevel,
+}
+
+const fn read(service: &'static str, operation: &'static str) -> OperationMetadata {
+    OperationMetadata {
+        service,
+        operation,
+        access: AccessLevel::Read,
+    }
+}
+
+/// Operations known to only read state, which can run without asking the user. This covers
+/// everything that can run natively through the SDK, plus common read-only operations of other
+/// services. Operations that return secrets or mint credentials are deliberately left out.
+const OPERATIONS: &[OperationMetadata] = &[
+    read("sts", "get-caller-identity"),
+    read("s3", "ls"),
+    read("s3api", "list-buckets"),
+    read("s3api", "list-objects"),
+    read("s3api", "list-objects-v2"),
+    read("s3api", "get-object"),
+    read("s3api", "head-object"),
+    read("s3api", "head-bucket"),
+    read("s3api", "get-bucket-location"),
+    read("ec2", "describe-instances"),
+    read("ec2", "describe-vpcs"),
+    read("ec2", "describe-subnets"),
+    read("ec2", "describe-security-groups"),
+    read("ec2", "describe-images"),
+    read("ec2", "describe-volumes"),
+    read("ec2", "describe-regions"),
+    read("ec2", "describe-availability-zones"),
+    read("logs", "filter-log-events"),
+    read("logs", "get-log-events"),
+    read("logs", "describe-log-groups"),
+    read("logs", "describe-log-streams"),
+    read("dynamodb", "query"),
+    read("dynamodb", "scan"),
+    read("dynamodb", "get-item"),
+    read("dynamodb", "list-tables"),
+    read("dynamodb", "describe-table"),
+    read("lambda", "list-functions"),
+    read("lambda", "get-function-configuration"),
+    read("ecs", "list-clusters"),
+    read("ecs", "list-services"),
+    read("ecs", "list-tasks"),
+    read("ecs", "list-task-definitions"),
+    read("ecs", "describe-clusters"),
+    read("ecs", "describe-services"),
+    read("ecs", "describe-tasks"),
+    read("ecs", "describe-task-definition"),
+    read("eks", "list-clusters"),
+    read("eks", "describe-cluster"),
+    read("ecr", "describe-repositories"),
+    read("ecr", "describe-images"),
+    read("iam", "list-roles"),
+    read("iam", "list-users"),
+    read("iam", "list-policies"),
+    read("iam", "get-role"),
+    read("iam", "get-user"),
+    read("cloudformation", "list-stacks"),
+    read("cloudformation", "describe-stacks"),
+    read("cloudformation", "describe-stack-events"),
+    read("cloudformation", "describe-stack-resources"),
+    read("cloudwatch", "list-metrics"),
+    read("cloudwatch", "describe-alarms"),
+    read("cloudwatch", "get-metric-data"),
+    read("cloudwatch", "get-metric-statistics"),
+    read("rds", "describe-db-instances"),
+    read("rds", "describe-db-clusters"),
+    read("sqs", "list-queues"),
+    read("sqs", "get-queue-attributes"),
+    read("sns", "list-topics"),
+    read("sns", "list-subscriptions"),
+    read("route53", "list-hosted-zones"),
+];
+
+/// Looks up the metadata for `operation` of `service`, if it has an explicit entry.
+pub fn lookup(service: &str, operation: &str) -> Option<&'static OperationMetadata> {
+    let service = normalize(service);
+    let operation = normalize(operation);
+    OPERATIONS
+        .iter()
+        .find(|op| op.service == service && op.operation == operation)
+}
+
+/// Returns the access level of `operation` of `service`. Only operations listed in [OPERATIONS]
+/// are read-only, anything else is treated as a write.
+pub fn access_level(service: &str, operation: &str) -> AccessLevel {
+    lookup(service, operation).map_or(AccessLevel::Write, |metadata| metadata.access)
+}
+
+/// Service and operation names are kebab case in the CLI, but the model occasionally sends
+/// snake case or surrounding whitespace.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace('_', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access_level() {
+        assert_eq!(access_level("ecs", "list-task-definitions"), AccessLevel::Read);
+        assert_eq!(access_level("dynamodb", "query"), AccessLevel::Read);
+        assert_eq!(access_level("dynamodb", "scan"), AccessLevel::Read);
+        assert_eq!(access_level("s3", "ls"), AccessLevel::Read);
+        assert_eq!(access_level("s3api", "head_object"), AccessLevel::Read);
+        assert_eq!(access_level("s3", "cp"), AccessLevel::Write);
+        assert_eq!(access_level("dynamodb", "put-item"), AccessLevel::Write);
+        // Presigned urls grant access to whoever has them.
+        assert_eq!(access_level("s3", "presign"), AccessLevel::Write);
+        // Operations that are not listed require acceptance whatever their verb.
+        assert_eq!(access_level("cognito-identity", "get-id"), AccessLevel::Write);
+        assert_eq!(access_level("sts", "get-session-token"), AccessLevel::Write);
+        assert_eq!(access_level("secretsmanager", "get-secret-value"), AccessLevel::Write);
+        assert_eq!(access_level("example", "list-things"), AccessLevel::Write);
+        assert_eq!(access_level("example", "describe-things"), AccessLevel::Write);
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert!(lookup("ec2", "describe-instances").is_some());
+        assert!(lookup(" EC2 ", "describe_instances").is_some());
+        assert!(lookup("ec2", "run-instances").is_none());
+    }
+}
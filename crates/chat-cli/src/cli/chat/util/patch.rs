@@ -0,0 +1,340 @@
+//! Parsing and applying unified diffs, as produced by `diff -u` and `git diff`.
+//!
+//! Since patches are usually written by the model rather than by a tool, parsing is lenient:
+//! the line counts in hunk headers are ignored, and hunks are located by their content, using the
+//! line numbers only to pick between multiple matches. The line endings of the original file are
+//! kept, as is its trailing newline unless the patch changes it with `\ No newline at end of file`.
+
+use eyre::{
+    Result,
+    bail,
+};
+
+/// The changes to a single file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    /// [None] if the file is created by the patch.
+    pub old_path: Option<String>,
+    /// [None] if the file is deleted by the patch.
+    pub new_path: Option<String>,
+    pub hunks: Vec<Hunk>,
+    /// Whether the original file ends without a newline, according to the patch.
+    pub old_missing_newline: bool,
+    /// Whether the patched file ends without a newline, according to the patch.
+    pub new_missing_newline: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    /// 1-indexed line the hunk starts at in the original file, 0 if unknown.
+    pub old_start: usize,
+    pub lines: Vec<HunkLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+impl FilePatch {
+    /// The path the patch applies to.
+    pub fn path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// Applies the patch to `original`, the current contents of the file (empty if it is being
+    /// created). Returns [None] if the file is deleted by the patch, which must then remove all of
+    /// its contents.
+    pub fn apply(&self, original: &str) -> Result<Option<String>> {
+        let line_ending = if original.contains("\r\n") { "\r\n" } else { "\n" };
+        let mut lines = original.lines().map(str::to_string).collect::<Vec<_>>();
+        // Number of lines added by previous hunks, used to adjust the starting line of later ones.
+        let mut offset: isize = 0;
+        // Hunks are applied in order, so a hunk cannot match before the end of the previous one.
+        let mut min_index = 0;
+        for (i, hunk) in self.hunks.iter().enumerate() {
+            let old = hunk
+                .lines
+                .iter()
+                .filter_map(|line| match line {
+                    HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                    HunkLine::Add(_) => None,
+                })
+                .collect::<Vec<_>>();
+            let new = hunk
+                .lines
+                .iter()
+                .filter_map(|line| match line {
+                    HunkLine::Context(s) | HunkLine::Add(s) => Some(s.clone()),
+                    HunkLine::Remove(_) => None,
+                })
+                .collect::<Vec<_>>();
+
+            let hint = (hunk.old_start as isize + offset).max(0) as usize;
+            let index = if old.is_empty() {
+                // Pure insertions start after the line given in the header.
+                hint.clamp(min_index, lines.len())
+            } else {
+                match find_block(&lines, &old, min_index, hint.saturating_sub(1)) {
+                    Some(index) => index,
+                    None => bail!("hunk {} does not apply to {}", i + 1, self.path()),
+                }
+            };
+
+            offset += new.len() as isize - old.len() as isize;
+            min_index = index + new.len();
+            lines.splice(index..index + old.len(), new);
+        }
+
+        if self.new_path.is_none() {
+            if !lines.is_empty() {
+                bail!("the patch does not remove all of the contents of {}", self.path());
+            }
+            return Ok(None);
+        }
+
+        let trailing_newline = if self.new_missing_newline {
+            false
+        } else {
+            self.old_missing_newline || original.is_empty() || original.ends_with('\n')
+        };
+        let mut patched = lines.join(line_ending);
+        if !patched.is_empty() && trailing_newline {
+            patched.push_str(line_ending);
+        }
+        Ok(Some(patched))
+    }
+}
+
+/// Finds the index at which `block` occurs within `lines`, at or after `min_index`. The
+/// occurrence closest to `hint` is preferred. Falls back to ignoring trailing whitespace.
+fn find_block(lines: &[String], block: &[&str], min_index: usize, hint: usize) -> Option<usize> {
+    let find = |eq: &dyn Fn(&str, &str) -> bool| {
+        (min_index..=lines.len().saturating_sub(block.len()))
+            .filter(|&start| {
+                lines.len() >= start + block.len()
+                    && block.iter().enumerate().all(|(i, line)| eq(&lines[start + i], line))
+            })
+            .min_by_key(|&start| start.abs_diff(hint))
+    };
+    find(&|a, b| a == b).or_else(|| find(&|a, b| a.trim_end() == b.trim_end()))
+}
+
+/// Parses a unified diff containing changes to one or more files.
+pub fn parse_patch(patch: &str) -> Result<Vec<FilePatch>> {
+    let lines = patch.lines().collect::<Vec<_>>();
+    let is_file_header =
+        |i: usize| lines[i].starts_with("--- ") && lines.get(i + 1).is_some_and(|next| next.starts_with("+++ "));
+
+    let mut files = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if !is_file_header(i) {
+            // Skip anything outside of file patches, e.g. `diff --git` and `index` lines.
+            i += 1;
+            continue;
+        }
+
+        let mut file = FilePatch {
+            old_path: parse_header_path(&lines[i][4..], "a/"),
+            new_path: parse_header_path(&lines[i + 1][4..], "b/"),
+            hunks: Vec::new(),
+            old_missing_newline: false,
+            new_missing_newline: false,
+        };
+        if file.old_path.is_none() && file.new_path.is_none() {
+            bail!("invalid file header on line {}", i + 1);
+        }
+        i += 2;
+
+        while i < lines.len() && lines[i].starts_with("@@") {
+            let mut hunk = Hunk {
+                old_start: parse_hunk_start(lines[i]),
+                lines: Vec::new(),
+            };
+            i += 1;
+            // Editors commonly strip the trailing space of empty context lines, so empty lines are
+            // treated as context unless they end the hunk.
+            let mut empty_lines = 0;
+            while i < lines.len() && !lines[i].starts_with("@@") && !is_file_header(i) {
+                let line = lines[i];
+                let parsed = match line.chars().next() {
+                    Some(' ') => HunkLine::Context(line[1..].to_string()),
+                    Some('-') => HunkLine::Remove(line[1..].to_string()),
+                    Some('+') => HunkLine::Add(line[1..].to_string()),
+                    None => {
+                        empty_lines += 1;
+                        i += 1;
+                        continue;
+                    },
+                    // "\ No newline at end of file", about the line before it.
+                    Some('\\') => {
+                        match hunk.lines.last() {
+                            Some(HunkLine::Context(_)) => {
+                                file.old_missing_newline = true;
+                                file.new_missing_newline = true;
+                            },
+                            Some(HunkLine::Remove(_)) => file.old_missing_newline = true,
+                            Some(HunkLine::Add(_)) => file.new_missing_newline = true,
+                            None => (),
+                        }
+                        i += 1;
+                        continue;
+                    },
+                    _ => break,
+                };
+                hunk.lines
+                    .extend(std::iter::repeat_n(HunkLine::Context(String::new()), empty_lines));
+                empty_lines = 0;
+                hunk.lines.push(parsed);
+                i += 1;
+            }
+            file.hunks.push(hunk);
+        }
+
+        if file.hunks.is_empty() && file.new_path.is_some() {
+            bail!("no hunks found for {}", file.path());
+        }
+        files.push(file);
+    }
+
+    if files.is_empty() {
+        bail!("no file changes found, expected a unified diff with `---` and `+++` file headers");
+    }
+    Ok(files)
+}
+
+/// Parses the path from a `---` or `+++` header, returning [None] for `/dev/null`.
+fn parse_header_path(header: &str, prefix: &str) -> Option<String> {
+    // Timestamps follow the path after a tab.
+    let path = header.split('\t').next().unwrap_or_default().trim();
+    match path {
+        "/dev/null" | "" => None,
+        path => Some(path.strip_prefix(prefix).unwrap_or(path).to_string()),
+    }
+}
+
+/// Parses the original starting line from a hunk header such as `@@ -12,7 +12,8 @@`.
+fn parse_hunk_start(header: &str) -> usize {
+    header
+        .split_whitespace()
+        .find_map(|part| part.strip_prefix('-'))
+        .and_then(|range| range.split(',').next())
+        .and_then(|start| start.parse().ok())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGINAL: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+
+    #[test]
+    fn test_parse_patch() {
+        let patch = "diff --git a/src/lib.rs b/src/lib.rs
+index 123..456 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+--- /dev/null
++++ b/new.txt
+@@ -0,0 +1 @@
++hello
+--- a/old.txt\t2024-01-01 00:00:00
++++ /dev/null
+@@ -1 +0,0 @@
+-bye
+";
+        let files = parse_patch(patch).unwrap();
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].old_path.as_deref(), Some("src/lib.rs"));
+        assert_eq!(files[0].hunks[0].old_start, 1);
+        assert_eq!(files[0].hunks[0].lines, vec![
+            HunkLine::Context("one".to_string()),
+            HunkLine::Remove("two".to_string()),
+            HunkLine::Add("TWO".to_string()),
+            HunkLine::Context("three".to_string()),
+        ]);
+        assert_eq!(files[1].old_path, None);
+        assert_eq!(files[1].path(), "new.txt");
+        assert_eq!(files[2].old_path.as_deref(), Some("old.txt"));
+        assert_eq!(files[2].new_path, None);
+
+        assert!(parse_patch("just some text").is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let patch = parse_patch(
+            "--- a/f
++++ b/f
+@@ -2,2 +2,3 @@
+ two
++two and a half
+ three
+@@ -6,1 +7,1 @@
+-six
++SIX
+",
+        )
+        .unwrap();
+        assert_eq!(
+            patch[0].apply(ORIGINAL).unwrap().unwrap(),
+            "one\ntwo\ntwo and a half\nthree\nfour\nfive\nSIX\nseven\n"
+        );
+
+        // Wrong line numbers and trailing whitespace differences are tolerated.
+        let patch = parse_patch("--- a/f\n+++ b/f\n@@ -40 +40 @@\n-four  \n+4\n").unwrap();
+        assert_eq!(
+            patch[0].apply(ORIGINAL).unwrap().unwrap(),
+            "one\ntwo\nthree\n4\nfive\nsix\nseven\n"
+        );
+
+        // Content that does not exist fails.
+        let patch = parse_patch("--- a/f\n+++ b/f\n@@ -1 +1 @@\n-eight\n+8\n").unwrap();
+        assert!(patch[0].apply(ORIGINAL).is_err());
+
+        // Creating and deleting files.
+        let patch = parse_patch("--- /dev/null\n+++ b/f\n@@ -0,0 +1,2 @@\n+a\n+b\n").unwrap();
+        assert_eq!(patch[0].apply("").unwrap().unwrap(), "a\nb\n");
+        let patch = parse_patch("--- a/f\n+++ /dev/null\n@@ -1 +0,0 @@\n-a\n").unwrap();
+        assert_eq!(patch[0].apply("a\n").unwrap(), None);
+        // Deletions must match the whole file.
+        assert!(patch[0].apply("b\n").is_err());
+        assert!(patch[0].apply("a\nb\n").is_err());
+        let patch = parse_patch("--- a/f\n+++ /dev/null\n").unwrap();
+        assert_eq!(patch[0].apply("").unwrap(), None);
+        assert!(patch[0].apply("a\n").is_err());
+    }
+
+    #[test]
+    fn test_apply_line_endings() {
+        // CRLF line endings are kept.
+        let patch = parse_patch("--- a/f\n+++ b/f\n@@ -1,2 +1,3 @@\n one\n+one and a half\n two\n").unwrap();
+        assert_eq!(
+            patch[0].apply("one\r\ntwo\r\nthree\r\n").unwrap().unwrap(),
+            "one\r\none and a half\r\ntwo\r\nthree\r\n"
+        );
+
+        // A missing trailing newline is kept unless the patch changes it.
+        assert_eq!(patch[0].apply("one\ntwo").unwrap().unwrap(), "one\none and a half\ntwo");
+        let patch =
+            parse_patch("--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n one\n-two\n\\ No newline at end of file\n+TWO\n").unwrap();
+        assert!(patch[0].old_missing_newline && !patch[0].new_missing_newline);
+        assert_eq!(patch[0].apply("one\ntwo").unwrap().unwrap(), "one\nTWO\n");
+        let patch =
+            parse_patch("--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n one\n-two\n+TWO\n\\ No newline at end of file\n").unwrap();
+        assert!(!patch[0].old_missing_newline && patch[0].new_missing_newline);
+        assert_eq!(patch[0].apply("one\ntwo\n").unwrap().unwrap(), "one\nTWO");
+    }
+}
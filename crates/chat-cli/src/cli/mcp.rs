@@ -28,6 +28,7 @@ use crate::cli::chat::tools::custom_tool::{
     default_timeout,
 };
 use crate::cli::chat::util::shared_writer::SharedWriter;
+use crate::mcp_client::TransportType;
 use crate::platform::Context;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
@@ -84,16 +85,28 @@ pub struct AddArgs {
     #[arg(long)]
     pub name: String,
     /// The command used to launch the server
+    #[arg(long, required_unless_present = "url", conflicts_with = "url")]
+    pub command: Option<String>,
+    /// Url of an already running server, e.g. http://localhost:8000/mcp or ws://localhost:8000
     #[arg(long)]
-    pub command: String,
+    pub url: Option<String>,
+    /// Header to send with every request to a server specified by url, as 'name=value'
+    #[arg(long = "header", value_parser = parse_header, requires = "url")]
+    pub headers: Vec<(String, String)>,
     /// Arguments to pass to the command
-    #[arg(long, action = ArgAction::Append, allow_hyphen_values = true, value_delimiter = ',')]
+    #[arg(
+        long,
+        action = ArgAction::Append,
+        allow_hyphen_values = true,
+        value_delimiter = ',',
+        conflicts_with = "url"
+    )]
     pub args: Vec<String>,
     /// Where to add the server to.
     #[arg(long, value_enum)]
     pub scope: Option<Scope>,
     /// Environment variables to use when launching the server
-    #[arg(long, value_parser = parse_env_vars)]
+    #[arg(long, value_parser = parse_env_vars, conflicts_with = "url")]
     pub env: Vec<HashMap<String, String>>,
     /// Server launch timeout, in milliseconds
     #[arg(long)]
@@ -123,13 +136,17 @@ impl AddArgs {
         }
 
         let merged_env = self.env.into_iter().flatten().collect::<HashMap<_, _>>();
+        let merged_headers = self.headers.into_iter().collect::<HashMap<_, _>>();
         let tool: CustomToolConfig = serde_json::from_value(serde_json::json!({
-            "command": self.command,
+            "command": self.command.unwrap_or_default(),
             "args": self.args,
             "env": merged_env,
+            "url": self.url,
+            "headers": (!merged_headers.is_empty()).then_some(merged_headers),
             "timeout": self.timeout.unwrap_or(default_timeout()),
             "disabled": self.disabled,
         }))?;
+        tool.transport_type()?;
 
         writeln!(
             output,
@@ -213,7 +230,8 @@ impl ListArgs {
                 Some(cfg) if !cfg.mcp_servers.is_empty() => {
                     for (name, tool_cfg) in &cfg.mcp_servers {
                         let status = if tool_cfg.disabled { " (disabled)" } else { "" };
-                        writeln!(output, "    • {name:<12} {}{}", tool_cfg.command, status)?;
+                        let target = tool_cfg.url.as_deref().unwrap_or(&tool_cfg.command);
+                        writeln!(output, "    • {name:<12} {}{}", target, status)?;
                     }
                 },
                 _ => {
@@ -295,15 +313,38 @@ impl StatusArgs {
                     style::Print("\n─────────────\n"),
                     style::Print(format!("Scope   : {}\n", scope_display(&sc))),
                     style::Print(format!("File    : {}\n", path.display())),
-                    style::Print(format!("Command : {}\n", cfg.command)),
+                )?;
+                match &cfg.url {
+                    Some(url) => execute!(
+                        output,
+                        style::Print(format!("Url     : {}\n", url)),
+                        style::Print(format!("Type    : {}\n", match cfg.transport_type() {
+                            Ok(TransportType::Websocket) => "websocket",
+                            Ok(_) => "http",
+                            Err(_) => "(invalid)",
+                        })),
+                        style::Print(format!(
+                            "Headers : {}\n",
+                            cfg.headers
+                                .as_ref()
+                                .map_or_else(|| "(none)".into(), |h| h.keys().cloned().collect::<Vec<_>>().join(", "))
+                        )),
+                    )?,
+                    None => execute!(
+                        output,
+                        style::Print(format!("Command : {}\n", cfg.command)),
+                        style::Print(format!(
+                            "Env Vars: {}\n",
+                            cfg.env
+                                .as_ref()
+                                .map_or_else(|| "(none)".into(), |e| e.keys().cloned().collect::<Vec<_>>().join(", "))
+                        )),
+                    )?,
+                }
+                execute!(
+                    output,
                     style::Print(format!("Timeout : {} ms\n", cfg.timeout)),
                     style::Print(format!("Disabled: {}\n", cfg.disabled)),
-                    style::Print(format!(
-                        "Env Vars: {}\n",
-                        cfg.env
-                            .as_ref()
-                            .map_or_else(|| "(none)".into(), |e| e.keys().cloned().collect::<Vec<_>>().join(", "))
-                    )),
                 )?;
             }
         }
@@ -401,6 +442,13 @@ fn parse_env_vars(arg: &str) -> Result<HashMap<String, String>> {
     Ok(vars)
 }
 
+fn parse_header(arg: &str) -> Result<(String, String)> {
+    match arg.split_once('=') {
+        Some((name, value)) => Ok((name.trim().to_string(), value.trim().to_string())),
+        None => bail!("Failed to parse header '{}'. Expected 'name=value'", arg),
+    }
+}
+
 async fn load_cfg(ctx: &Context, p: &PathBuf) -> Result<McpServerConfig> {
     Ok(if ctx.fs().exists(p) {
         McpServerConfig::load_from_file(ctx, p).await?
@@ -458,7 +506,9 @@ mod tests {
         // 1. add
         AddArgs {
             name: "local".into(),
-            command: "echo hi".into(),
+            command: Some("echo hi".into()),
+            url: None,
+            headers: vec![],
             args: vec![
                 "awslabs.eks-mcp-server".to_string(),
                 "--allow-write".to_string(),
@@ -509,7 +559,9 @@ mod tests {
             ],
             RootSubcommand::Mcp(McpSubcommand::Add(AddArgs {
                 name: "test_server".to_string(),
-                command: "test_command".to_string(),
+                command: Some("test_command".to_string()),
+                url: None,
+                headers: vec![],
                 args: vec![
                     "awslabs.eks-mcp-server".to_string(),
                     "--allow-write".to_string(),
@@ -531,6 +583,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_mcp_subcommand_add_url() {
+        assert_parse!(
+            [
+                "mcp",
+                "add",
+                "--name",
+                "remote",
+                "--url",
+                "http://localhost:8000/mcp",
+                "--header",
+                "Authorization=Bearer ${TOKEN}"
+            ],
+            RootSubcommand::Mcp(McpSubcommand::Add(AddArgs {
+                name: "remote".to_string(),
+                command: None,
+                url: Some("http://localhost:8000/mcp".to_string()),
+                headers: vec![("Authorization".to_string(), "Bearer ${TOKEN}".to_string())],
+                args: vec![],
+                scope: None,
+                env: vec![],
+                timeout: None,
+                disabled: false,
+                force: false,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_mcp_subcommand_add_url_conflicts() {
+        for arg in ["--args=--verbose", "--env=KEY=value"] {
+            let result = <crate::cli::Cli as clap::Parser>::try_parse_from([
+                crate::util::CHAT_BINARY_NAME,
+                "mcp",
+                "add",
+                "--name",
+                "remote",
+                "--url",
+                "http://localhost:8000/mcp",
+                arg,
+            ]);
+            assert_eq!(result.unwrap_err().kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+
+    #[test]
+    fn test_transport_type() {
+        let cfg = |value: serde_json::Value| serde_json::from_value::<CustomToolConfig>(value).unwrap();
+        assert_eq!(
+            cfg(serde_json::json!({ "command": "uvx" })).transport_type().unwrap(),
+            TransportType::Stdio
+        );
+        assert_eq!(
+            cfg(serde_json::json!({ "url": "https://example.com/mcp" }))
+                .transport_type()
+                .unwrap(),
+            TransportType::Http
+        );
+        assert_eq!(
+            cfg(serde_json::json!({ "url": "wss://example.com" }))
+                .transport_type()
+                .unwrap(),
+            TransportType::Websocket
+        );
+        assert!(
+            cfg(serde_json::json!({ "url": "ftp://example.com" }))
+                .transport_type()
+                .is_err()
+        );
+        assert!(
+            cfg(serde_json::json!({ "command": "uvx", "url": "http://example.com" }))
+                .transport_type()
+                .is_err()
+        );
+        assert!(cfg(serde_json::json!({})).transport_type().is_err());
+    }
+
     #[test]
     fn test_mcp_subcomman_remove_workspace() {
         assert_parse!(
@@ -318,6 +318,10 @@ impl Cli {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::cli::chat::{
+        ChatInputFormat,
+        ChatOutputFormat,
+    };
     use crate::util::CHAT_BINARY_NAME;
     use crate::util::test::assert_parse;
 
@@ -351,12 +355,19 @@ mod test {
             subcommand: Some(RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: None,
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })),
             verbose: 2,
             help_all: false,
@@ -391,12 +402,19 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: Some("my-profile".to_string()),
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
     }
@@ -408,12 +426,19 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: Some("Hello".to_string()),
                 profile: Some("my-profile".to_string()),
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
     }
@@ -425,12 +450,19 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: true,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: Some("my-profile".to_string()),
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
     }
@@ -442,12 +474,19 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: true,
-                resume: true,
+                resume: Some(None),
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: None,
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
         assert_parse!(
@@ -455,12 +494,83 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: true,
-                resume: true,
+                resume: Some(None),
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: None,
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_sessions() {
+        assert_parse!(
+            ["chat", "--resume=0f3c2a"],
+            RootSubcommand::Chat(ChatArgs {
+                accept_all: false,
+                no_interactive: false,
+                resume: Some(Some("0f3c2a".to_string())),
+                list_sessions: false,
+                search: None,
+                input: None,
+                profile: None,
+                agent: None,
+                mode: None,
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+            })
+        );
+        assert_parse!(
+            ["chat", "--resume", "fix the build"],
+            RootSubcommand::Chat(ChatArgs {
+                accept_all: false,
+                no_interactive: false,
+                resume: Some(None),
+                list_sessions: false,
+                search: None,
+                input: Some("fix the build".to_string()),
+                profile: None,
+                agent: None,
+                mode: None,
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+            })
+        );
+        assert_parse!(
+            ["chat", "--list-sessions", "--search", "auth bug"],
+            RootSubcommand::Chat(ChatArgs {
+                accept_all: false,
+                no_interactive: false,
+                resume: None,
+                list_sessions: true,
+                search: Some("auth bug".to_string()),
+                input: None,
+                profile: None,
+                agent: None,
+                mode: None,
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
     }
@@ -472,12 +582,19 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: None,
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: true,
                 trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
     }
@@ -489,12 +606,19 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: None,
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["".to_string()]),
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
             })
         );
     }
@@ -506,12 +630,49 @@ mod test {
             RootSubcommand::Chat(ChatArgs {
                 accept_all: false,
                 no_interactive: false,
-                resume: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
                 input: None,
                 profile: None,
+                agent: None,
+                mode: None,
                 model: None,
                 trust_all_tools: false,
                 trust_tools: Some(vec!["fs_read".to_string(), "fs_write".to_string()]),
+                sandbox: false,
+                output_format: ChatOutputFormat::Text,
+                input_format: ChatInputFormat::Text,
+            })
+        );
+    }
+
+    #[test]
+    fn test_chat_with_stream_json() {
+        assert_parse!(
+            [
+                "chat",
+                "--output-format",
+                "stream-json",
+                "--input-format",
+                "stream-json"
+            ],
+            RootSubcommand::Chat(ChatArgs {
+                accept_all: false,
+                no_interactive: false,
+                resume: None,
+                list_sessions: false,
+                search: None,
+                input: None,
+                profile: None,
+                agent: None,
+                mode: None,
+                model: None,
+                trust_all_tools: false,
+                trust_tools: None,
+                sandbox: false,
+                output_format: ChatOutputFormat::StreamJson,
+                input_format: ChatInputFormat::StreamJson,
             })
         );
     }
@@ -25,7 +25,10 @@ use serde_json::{
     Map,
     Value,
 };
-use settings::Settings;
+use settings::{
+    Setting,
+    Settings,
+};
 use thiserror::Error;
 use tracing::{
     error,
@@ -58,6 +61,12 @@ const CLIENT_ID_KEY: &str = "telemetryClientId";
 const CODEWHISPERER_PROFILE_KEY: &str = "api.codewhisperer.profile";
 const START_URL_KEY: &str = "auth.idc.start-url";
 const IDC_REGION_KEY: &str = "auth.idc.region";
+/// Separates the directory path from the branch name in the key of a conversation branch.
+const CONVERSATION_BRANCH_SEPARATOR: &str = "#branch:";
+/// Sessions not updated in this many days are deleted, unless set with `chat.sessionRetentionDays`.
+const DEFAULT_SESSION_RETENTION_DAYS: i64 = 90;
+/// Only this many of the most recent sessions are kept, unless set with `chat.maxSessions`.
+const DEFAULT_MAX_SESSIONS: i64 = 200;
 // We include this key to remove for backwards compatibility
 const CUSTOMIZATION_STATE_KEY: &str = "api.selectedCustomization";
 
@@ -69,7 +78,9 @@ const MIGRATIONS: &[Migration] = migrations![
     "004_state_table",
     "005_auth_table",
     "006_make_state_blob",
-    "007_conversations_table"
+    "007_conversations_table",
+    "008_sessions_table",
+    "009_history_output"
 ];
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -95,6 +106,33 @@ impl From<amzn_codewhisperer_client::types::Profile> for AuthProfile {
     }
 }
 
+/// Metadata about a saved chat session, see [Database::list_sessions].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SessionSummary {
+    /// The conversation id.
+    pub id: String,
+    pub title: String,
+    /// The directory the session was last used from.
+    pub cwd: String,
+    pub model: Option<String>,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+    /// Unix timestamp in seconds.
+    pub updated_at: i64,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+}
+
+/// A command run in a terminal with the shell integrations, see [Database::get_shell_history].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShellCommand {
+    pub command: String,
+    pub cwd: Option<String>,
+    pub exit_code: Option<i32>,
+    /// The end of what the command printed, only recorded with `history.captureOutput`.
+    pub output: Option<String>,
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct Secret(pub String);
@@ -159,6 +197,10 @@ pub enum Table {
     Conversations,
     /// The auth table contains SSO and Builder ID credentials.
     Auth,
+    /// The sessions table contains every chat conversation along with its metadata.
+    Sessions,
+    /// The history table contains the commands run in terminals with the shell integrations.
+    History,
 }
 
 impl std::fmt::Display for Table {
@@ -167,6 +209,8 @@ impl std::fmt::Display for Table {
             Table::State => write!(f, "state"),
             Table::Conversations => write!(f, "conversations"),
             Table::Auth => write!(f, "auth_kv"),
+            Table::Sessions => write!(f, "sessions"),
+            Table::History => write!(f, "history"),
         }
     }
 }
@@ -341,7 +385,220 @@ impl Database {
             None => return Ok(0),
         };
 
-        self.set_json_entry(Table::Conversations, path, state)
+        // The active conversation is stored under the path itself so that `--resume` picks it up.
+        let value = serde_json::to_string(state)?;
+        self.set_session(path, state, &value)?;
+        self.set_entry(Table::Conversations, path, &value)
+    }
+
+    /// Keep a copy of a chat conversation under its branch, so that it can be switched back to
+    /// once another branch is active at the given path.
+    pub fn set_conversation_branch(
+        &mut self,
+        path: impl AsRef<Path>,
+        state: &ConversationState,
+    ) -> Result<usize, DatabaseError> {
+        let path = match path.as_ref().to_str() {
+            Some(path) => path,
+            None => return Ok(0),
+        };
+
+        self.set_entry(
+            Table::Conversations,
+            format!("{path}{CONVERSATION_BRANCH_SEPARATOR}{}", state.branch()),
+            serde_json::to_string(state)?,
+        )
+    }
+
+    /// Get the branch named `branch` of the chat conversation at the given path.
+    pub fn get_conversation_branch(
+        &mut self,
+        path: impl AsRef<Path>,
+        branch: &str,
+    ) -> Result<Option<ConversationState>, DatabaseError> {
+        let path = match path.as_ref().to_str() {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        self.get_json_entry(
+            Table::Conversations,
+            format!("{path}{CONVERSATION_BRANCH_SEPARATOR}{branch}"),
+        )
+    }
+
+    /// Get the branches kept with [Self::set_conversation_branch] for the chat conversation at the
+    /// given path, sorted by branch name.
+    pub fn get_conversation_branches(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<Vec<ConversationState>, DatabaseError> {
+        let path = match path.as_ref().to_str() {
+            Some(path) => path,
+            None => return Ok(vec![]),
+        };
+
+        let prefix = format!("{path}{CONVERSATION_BRANCH_SEPARATOR}");
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT value FROM {} WHERE substr(key, 1, length(?1)) = ?1 ORDER BY key",
+            Table::Conversations
+        ))?;
+        let rows = stmt.query_map([prefix], |row| row.get::<_, String>(0))?;
+
+        let mut branches = vec![];
+        for row in rows {
+            branches.push(serde_json::from_str(&row?)?);
+        }
+        Ok(branches)
+    }
+
+    /// Get the serialized state of every chat conversation that is kept, i.e. the active
+    /// conversations, their branches and the sessions.
+    pub fn get_all_conversation_values(&self) -> Result<Vec<String>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT value FROM {} UNION ALL SELECT value FROM {}",
+            Table::Conversations,
+            Table::Sessions
+        ))?;
+        let values = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(values)
+    }
+
+    /// Get a chat session given its id, or a prefix of its id that matches exactly one session.
+    pub fn get_session(&self, id: &str) -> Result<Option<ConversationState>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT value FROM {} WHERE substr(id, 1, length(?1)) = ?1 LIMIT 2",
+            Table::Sessions
+        ))?;
+        let values = stmt
+            .query_map([id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match values.as_slice() {
+            [value] => Some(serde_json::from_str(value)?),
+            _ => None,
+        })
+    }
+
+    /// List chat sessions, most recently updated first, up to `limit` of them if given. If `search`
+    /// is given, only sessions whose title or transcript contains every word of it are returned.
+    pub fn list_sessions(
+        &self,
+        search: Option<&str>,
+        limit: Option<usize>,
+    ) -> Result<Vec<SessionSummary>, DatabaseError> {
+        let terms = search
+            .map(|search| search.split_whitespace().map(str::to_lowercase).collect::<Vec<_>>())
+            .unwrap_or_default();
+        let filter = match terms.len() {
+            0 => String::new(),
+            n => format!(
+                "WHERE {}",
+                (1..=n)
+                    .map(|i| format!("instr(lower(title || char(10) || transcript), ?{i}) > 0"))
+                    .collect::<Vec<_>>()
+                    .join(" AND ")
+            ),
+        };
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, title, cwd, model, created_at, updated_at, input_tokens, output_tokens FROM {} {filter} \
+             ORDER BY updated_at DESC LIMIT ?{}",
+            Table::Sessions,
+            terms.len() + 1
+        ))?;
+        // A negative limit means no limit in SQLite
+        let limit = limit.map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+        let params = terms
+            .into_iter()
+            .map(rusqlite::types::Value::Text)
+            .chain([rusqlite::types::Value::Integer(limit)]);
+        let rows = stmt.query_map(rusqlite::params_from_iter(params), |row| {
+            Ok(SessionSummary {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                cwd: row.get(2)?,
+                model: row.get(3)?,
+                created_at: row.get(4)?,
+                updated_at: row.get(5)?,
+                input_tokens: row.get(6)?,
+                output_tokens: row.get(7)?,
+            })
+        })?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Delete chat sessions that are older than `chat.sessionRetentionDays`, or beyond the
+    /// `chat.maxSessions` most recent ones. A setting of 0 disables that limit. Returns the number
+    /// of sessions deleted.
+    pub fn cleanup_sessions(&self) -> Result<usize, DatabaseError> {
+        let retention_days = self
+            .settings
+            .get_int(Setting::ChatSessionRetentionDays)
+            .unwrap_or(DEFAULT_SESSION_RETENTION_DAYS);
+        let max_sessions = self
+            .settings
+            .get_int(Setting::ChatMaxSessions)
+            .unwrap_or(DEFAULT_MAX_SESSIONS);
+
+        let conn = self.pool.get()?;
+        let mut deleted = 0;
+        if retention_days > 0 {
+            deleted += conn.execute(
+                &format!(
+                    "DELETE FROM {} WHERE updated_at < strftime('%s', 'now') - ?1 * 86400",
+                    Table::Sessions
+                ),
+                [retention_days],
+            )?;
+        }
+        if max_sessions > 0 {
+            deleted += conn.execute(
+                &format!(
+                    "DELETE FROM {0} WHERE id NOT IN (SELECT id FROM {0} ORDER BY updated_at DESC LIMIT ?1)",
+                    Table::Sessions
+                ),
+                [max_sessions],
+            )?;
+        }
+
+        if deleted > 0 {
+            info!(%deleted, "Deleted old chat sessions");
+        }
+        Ok(deleted)
+    }
+
+    /// Get the last `limit` commands that finished in the terminal session with the given id,
+    /// oldest first.
+    pub fn get_shell_history(&self, session_id: &str, limit: usize) -> Result<Vec<ShellCommand>, DatabaseError> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT command, cwd, exit_code, output FROM {} \
+             WHERE session_id = ?1 AND command IS NOT NULL AND exit_code IS NOT NULL \
+             ORDER BY id DESC LIMIT ?2",
+            Table::History
+        ))?;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let mut commands = stmt
+            .query_map(params![session_id, limit], |row| {
+                Ok(ShellCommand {
+                    command: row.get(0)?,
+                    cwd: row.get(1)?,
+                    exit_code: row.get(2)?,
+                    output: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        commands.reverse();
+
+        Ok(commands)
     }
 
     pub async fn get_secret(&self, key: &str) -> Result<Option<Secret>, DatabaseError> {
@@ -391,6 +648,44 @@ impl Database {
         Ok(self)
     }
 
+    /// Upserts the session of the conversation by its id, `value` being the serialized `state`.
+    /// The row is left untouched when the conversation did not change since it was last saved.
+    fn set_session(&self, cwd: &str, state: &ConversationState, value: &str) -> Result<usize, DatabaseError> {
+        // Conversations without any messages aren't worth resuming.
+        if state.history().is_empty() {
+            return Ok(0);
+        }
+
+        let (input_tokens, output_tokens) = state.token_totals();
+        let transcript = state
+            .transcript
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(self.pool.get()?.execute(
+            &format!(
+                "INSERT INTO {} (id, title, cwd, model, created_at, updated_at, input_tokens, output_tokens, transcript, value) \
+                 VALUES (?1, ?2, ?3, ?4, strftime('%s', 'now'), strftime('%s', 'now'), ?5, ?6, ?7, ?8) \
+                 ON CONFLICT(id) DO UPDATE SET title = excluded.title, cwd = excluded.cwd, model = excluded.model, \
+                 updated_at = excluded.updated_at, input_tokens = excluded.input_tokens, \
+                 output_tokens = excluded.output_tokens, transcript = excluded.transcript, value = excluded.value \
+                 WHERE sessions.value != excluded.value OR sessions.cwd != excluded.cwd",
+                Table::Sessions
+            ),
+            params![
+                state.conversation_id(),
+                state.title().unwrap_or_default(),
+                cwd,
+                state.model,
+                input_tokens.value() as i64,
+                output_tokens.value() as i64,
+                transcript,
+                value,
+            ],
+        )?)
+    }
+
     fn get_entry<T: FromSql>(&self, table: Table, key: impl AsRef<str>) -> Result<Option<T>, DatabaseError> {
         let conn = self.pool.get()?;
         let mut stmt = conn.prepare(&format!("SELECT value FROM {table} WHERE key = ?1"))?;
@@ -576,6 +871,68 @@ mod tests {
         assert!(db.get_entry::<bool>(Table::State, "bool").unwrap().is_some());
     }
 
+    #[tokio::test]
+    async fn test_get_shell_history() {
+        let db = Database::new().await.unwrap();
+        {
+            let conn = db.pool.get().unwrap();
+            for (command, session_id, exit_code, output) in [
+                ("cargo build", "session", Some(0), None),
+                ("cargo test", "session", Some(101), Some("test result: FAILED")),
+                ("ls", "other-session", Some(0), Some("Cargo.toml")),
+                ("cargo run", "session", None, None),
+            ] {
+                conn.execute(
+                    "INSERT INTO history (command, session_id, cwd, exit_code, output) VALUES (?1, ?2, '/repo', ?3, ?4)",
+                    params![command, session_id, exit_code, output],
+                )
+                .unwrap();
+            }
+        }
+
+        assert_eq!(db.get_shell_history("session", 10).unwrap(), vec![
+            ShellCommand {
+                command: "cargo build".into(),
+                cwd: Some("/repo".into()),
+                exit_code: Some(0),
+                output: None,
+            },
+            ShellCommand {
+                command: "cargo test".into(),
+                cwd: Some("/repo".into()),
+                exit_code: Some(101),
+                output: Some("test result: FAILED".into()),
+            },
+        ]);
+        assert_eq!(db.get_shell_history("session", 1).unwrap()[0].command, "cargo test");
+        assert!(db.get_shell_history("missing", 10).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_list_sessions() {
+        let db = Database::new().await.unwrap();
+        {
+            let conn = db.pool.get().unwrap();
+            for (id, title, updated_at) in [
+                ("a", "Fix the build", 1),
+                ("b", "Add a test", 3),
+                ("c", "Fix a test", 2),
+            ] {
+                conn.execute(
+                    "INSERT INTO sessions (id, title, cwd, created_at, updated_at, value) VALUES (?1, ?2, '/repo', 0, ?3, '{}')",
+                    params![id, title, updated_at],
+                )
+                .unwrap();
+            }
+        }
+
+        let ids = |sessions: Vec<SessionSummary>| sessions.into_iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(ids(db.list_sessions(None, None).unwrap()), vec!["b", "c", "a"]);
+        assert_eq!(ids(db.list_sessions(None, Some(2)).unwrap()), vec!["b", "c"]);
+        assert_eq!(ids(db.list_sessions(Some("fix"), None).unwrap()), vec!["c", "a"]);
+        assert_eq!(ids(db.list_sessions(Some("fix test"), Some(1)).unwrap()), vec!["c"]);
+    }
+
     #[tokio::test]
     #[ignore = "not on ci"]
     async fn test_set_password() {
@@ -32,6 +32,10 @@ pub enum Setting {
     McpNoInteractiveTimeout,
     McpLoadedBefore,
     ChatDefaultModel,
+    ChatToolConcurrency,
+    ChatSessionRetentionDays,
+    ChatMaxSessions,
+    HistoryCaptureOutput,
 }
 
 impl AsRef<str> for Setting {
@@ -52,6 +56,10 @@ impl AsRef<str> for Setting {
             Self::McpNoInteractiveTimeout => "mcp.noInteractiveTimeout",
             Self::McpLoadedBefore => "mcp.loadedBefore",
             Self::ChatDefaultModel => "chat.defaultModel",
+            Self::ChatToolConcurrency => "chat.toolConcurrency",
+            Self::ChatSessionRetentionDays => "chat.sessionRetentionDays",
+            Self::ChatMaxSessions => "chat.maxSessions",
+            Self::HistoryCaptureOutput => "history.captureOutput",
         }
     }
 }
@@ -82,6 +90,10 @@ impl TryFrom<&str> for Setting {
             "mcp.noInteractiveTimeout" => Ok(Self::McpNoInteractiveTimeout),
             "mcp.loadedBefore" => Ok(Self::McpLoadedBefore),
             "chat.defaultModel" => Ok(Self::ChatDefaultModel),
+            "chat.toolConcurrency" => Ok(Self::ChatToolConcurrency),
+            "chat.sessionRetentionDays" => Ok(Self::ChatSessionRetentionDays),
+            "chat.maxSessions" => Ok(Self::ChatMaxSessions),
+            "history.captureOutput" => Ok(Self::HistoryCaptureOutput),
             _ => Err(DatabaseError::InvalidSetting(value.to_string())),
         }
     }
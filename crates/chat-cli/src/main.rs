@@ -34,6 +34,7 @@ fn main() -> Result<ExitCode> {
     };
 
     let verbose = parsed.verbose > 0;
+    util::init_local_offset();
     let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
     let result = runtime.block_on(parsed.execute());
 
@@ -19,19 +19,24 @@ use thiserror::Error;
 use tokio::time;
 use tokio::time::error::Elapsed;
 
+use super::error::ErrorCode;
 use super::transport::base_protocol::{
+    JsonRpcError,
     JsonRpcMessage,
     JsonRpcNotification,
     JsonRpcRequest,
     JsonRpcVersion,
 };
+use super::transport::http::JsonRpcHttpTransport;
 use super::transport::stdio::JsonRpcStdioTransport;
+use super::transport::websocket::JsonRpcWebSocketTransport;
 use super::transport::{
     self,
     Transport,
     TransportError,
 };
 use super::{
+    CreateMessageParams,
     JsonRpcResponse,
     Listener as _,
     LogListener,
@@ -51,6 +56,8 @@ use crate::util::process::{
 
 pub type ClientInfo = serde_json::Value;
 pub type StdioTransport = JsonRpcStdioTransport;
+pub type HttpTransport = JsonRpcHttpTransport;
+pub type WebSocketTransport = JsonRpcWebSocketTransport;
 
 /// Represents the capabilities of a client in the Model Context Protocol.
 /// This structure is sent to the server during initialization to communicate
@@ -67,8 +74,12 @@ struct ClientCapabilities {
 
 impl From<ClientInfo> for ClientCapabilities {
     fn from(client_info: ClientInfo) -> Self {
+        let mut capabilities = HashMap::new();
+        capabilities.insert("roots".to_string(), serde_json::json!({ "listChanged": true }));
+        capabilities.insert("sampling".to_string(), serde_json::json!({}));
         ClientCapabilities {
             client_info,
+            capabilities,
             ..Default::default()
         }
     }
@@ -84,6 +95,17 @@ pub struct ClientConfig {
     pub env: Option<HashMap<String, String>>,
 }
 
+/// Configuration for servers that are already running and are reached by url rather than
+/// spawned as a child process.
+#[derive(Debug, Deserialize)]
+pub struct RemoteClientConfig {
+    pub server_name: String,
+    pub url: String,
+    pub headers: HashMap<String, String>,
+    pub timeout: u64,
+    pub client_info: serde_json::Value,
+}
+
 #[allow(dead_code)]
 #[derive(Debug, Error)]
 pub enum ClientError {
@@ -265,6 +287,34 @@ impl Client<StdioTransport> {
     }
 }
 
+impl Client<HttpTransport> {
+    pub fn from_config(config: RemoteClientConfig) -> Result<Self, ClientError> {
+        let RemoteClientConfig {
+            server_name,
+            url,
+            headers,
+            timeout,
+            client_info,
+        } = config;
+        let transport = Arc::new(transport::http::JsonRpcHttpTransport::client(&url, &headers)?);
+        Ok(Self::from_transport(server_name, transport, timeout, client_info))
+    }
+}
+
+impl Client<WebSocketTransport> {
+    pub fn from_config(config: RemoteClientConfig) -> Result<Self, ClientError> {
+        let RemoteClientConfig {
+            server_name,
+            url,
+            headers,
+            timeout,
+            client_info,
+        } = config;
+        let transport = Arc::new(transport::websocket::JsonRpcWebSocketTransport::client(&url, &headers)?);
+        Ok(Self::from_transport(server_name, transport, timeout, client_info))
+    }
+}
+
 impl<T> Drop for Client<T>
 where
     T: Transport,
@@ -282,6 +332,22 @@ impl<T> Client<T>
 where
     T: Transport,
 {
+    /// Creates a client for a server that is not owned by this process (i.e. there is no server
+    /// process for us to clean up after).
+    fn from_transport(server_name: String, transport: Arc<T>, timeout: u64, client_info: serde_json::Value) -> Self {
+        Self {
+            server_name,
+            transport,
+            timeout,
+            server_process_id: None,
+            client_info,
+            current_id: Arc::new(AtomicU64::new(0)),
+            messenger: None,
+            prompt_gets: Arc::new(SyncRwLock::new(HashMap::new())),
+            is_prompts_out_of_date: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
     /// Exchange of information specified as per https://spec.modelcontextprotocol.io/specification/2024-11-05/basic/lifecycle/#initialization
     ///
     /// Also done are the following:
@@ -356,6 +422,13 @@ where
                 fetch_tools_and_notify_with_messenger(&client_ref, messenger_ref.as_ref()).await;
             });
         }
+        if cap.resources.is_some() {
+            let client_ref = (*self).clone();
+            let messenger_ref = self.messenger.as_ref().map(|m| m.duplicate());
+            tokio::spawn(async move {
+                fetch_resources_and_notify_with_messenger(&client_ref, messenger_ref.as_ref()).await;
+            });
+        }
 
         let transport_ref = self.transport.clone();
         let server_name = self.server_name.clone();
@@ -364,13 +437,22 @@ where
 
         let prompts_list_changed_supported = cap.prompts.as_ref().is_some_and(|p| p.get("listChanged").is_some());
         let tools_list_changed_supported = cap.tools.as_ref().is_some_and(|t| t.get("listChanged").is_some());
+        let resources_list_changed_supported = cap.resources.as_ref().is_some_and(|r| r.get("listChanged").is_some());
         tokio::spawn(async move {
             let mut listener = transport_ref.get_listener();
             loop {
                 match listener.recv().await {
                     Ok(msg) => {
                         match msg {
-                            JsonRpcMessage::Request(_req) => {},
+                            JsonRpcMessage::Request(req) => {
+                                // Requests such as sampling can take as long as the user needs to
+                                // approve them, so they must not hold up the rest of the loop.
+                                let client_ref = client_ref.clone();
+                                let messenger_ref = messenger_ref.as_ref().map(|m| m.duplicate());
+                                tokio::spawn(async move {
+                                    handle_server_request(&client_ref, messenger_ref.as_ref(), req).await;
+                                });
+                            },
                             JsonRpcMessage::Notification(notif) => {
                                 let JsonRpcNotification { method, params, .. } = notif;
                                 match method.as_str() {
@@ -420,6 +502,12 @@ where
                                         fetch_tools_and_notify_with_messenger(&client_ref, messenger_ref.as_ref())
                                             .await;
                                     },
+                                    "notifications/resources/list_changed" | "resources/list_changed"
+                                        if resources_list_changed_supported =>
+                                    {
+                                        fetch_resources_and_notify_with_messenger(&client_ref, messenger_ref.as_ref())
+                                            .await;
+                                    },
                                     _ => {},
                                 }
                             },
@@ -465,10 +553,12 @@ where
         };
         tracing::trace!(target: "mcp", "To {}:\n{:#?}", self.server_name, request);
         let msg = JsonRpcMessage::Request(request);
+        // The listener needs to be subscribed before the request goes out. Transports such as
+        // http can deliver the response before send even returns.
+        let mut listener = self.transport.get_listener();
         time::timeout(Duration::from_millis(self.timeout), self.transport.send(&msg))
             .await
             .map_err(send_map_err)??;
-        let mut listener = self.transport.get_listener();
         let mut resp = time::timeout(Duration::from_millis(self.timeout), async {
             // we want to ignore all other messages sent by the server at this point and let the
             // background loop handle them
@@ -681,6 +771,98 @@ where
     }
 }
 
+/// Resources and resource templates are listed separately as per the spec, and a server is free
+/// to implement only one of the two. Failure of one therefore does not affect the other.
+#[allow(clippy::borrowed_box)]
+async fn fetch_resources_and_notify_with_messenger<T>(client: &Client<T>, messenger: Option<&Box<dyn Messenger>>)
+where
+    T: Transport,
+{
+    let resources_list_result = fetch_list::<T, ResourcesListResult>(client, "resources/list").await;
+    let resource_templates_list_result =
+        fetch_list::<T, ResourceTemplatesListResult>(client, "resources/templates/list").await;
+    if let Some(messenger) = messenger {
+        let _ = messenger
+            .send_resources_list_result(resources_list_result)
+            .await
+            .map_err(|e| tracing::error!("Failed to send resource result through messenger {:?}", e));
+        let _ = messenger
+            .send_resource_templates_list_result(resource_templates_list_result)
+            .await
+            .map_err(|e| tracing::error!("Failed to send resource template result through messenger {:?}", e));
+    }
+}
+
+/// Answers requests initiated by the server, i.e. the ones enabled by the capabilities declared in
+/// [ClientCapabilities].
+#[allow(clippy::borrowed_box)]
+async fn handle_server_request<T>(client: &Client<T>, messenger: Option<&Box<dyn Messenger>>, req: JsonRpcRequest)
+where
+    T: Transport,
+{
+    let JsonRpcRequest { id, method, params, .. } = req;
+    let error = |code: ErrorCode, message: String| JsonRpcError {
+        code: code.into(),
+        message,
+        data: None,
+    };
+    let outcome = match (method.as_str(), messenger) {
+        ("ping", _) => Ok(serde_json::json!({})),
+        ("roots/list", Some(messenger)) => messenger
+            .list_roots()
+            .await
+            .map(|roots| serde_json::json!({ "roots": roots }))
+            .map_err(|e| error(ErrorCode::InternalError, e.to_string())),
+        ("sampling/createMessage", Some(messenger)) => {
+            match serde_json::from_value::<CreateMessageParams>(params.unwrap_or_default()) {
+                Ok(params) => match messenger.create_message(params).await {
+                    Ok(result) => {
+                        serde_json::to_value(result).map_err(|e| error(ErrorCode::InternalError, e.to_string()))
+                    },
+                    Err(e) => Err(error(ErrorCode::RequestFailed, e.to_string())),
+                },
+                Err(e) => Err(error(ErrorCode::InvalidParams, e.to_string())),
+            }
+        },
+        _ => Err(error(ErrorCode::MethodNotFound, format!("Method not found: {method}"))),
+    };
+    let (result, error) = match outcome {
+        Ok(result) => (Some(result), None),
+        Err(error) => (None, Some(error)),
+    };
+    let resp = JsonRpcResponse {
+        jsonrpc: JsonRpcVersion::default(),
+        id,
+        result,
+        error,
+    };
+    tracing::trace!(target: "mcp", "To {}:\n{:#?}", client.server_name, resp);
+    if let Err(e) = client.transport.send(&JsonRpcMessage::Response(resp)).await {
+        tracing::error!("Failed to respond to {method} from {}: {:?}", client.server_name, e);
+    }
+}
+
+async fn fetch_list<T, R>(client: &Client<T>, method: &str) -> eyre::Result<R>
+where
+    T: Transport,
+    R: serde::de::DeserializeOwned,
+{
+    let resp = client.request(method, None).await?;
+    if let Some(error) = resp.error {
+        eyre::bail!("Failed to retrieve {method} for {}: {:?}", client.server_name, error);
+    }
+    let Some(result) = resp.result else {
+        eyre::bail!("Response for {method} from {} is missing result", client.server_name);
+    };
+    serde_json::from_value::<R>(result).map_err(|e| {
+        eyre::eyre!(
+            "Failed to deserialize {method} result from {}: {:?}",
+            client.server_name,
+            e
+        )
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::path::PathBuf;
@@ -776,6 +958,14 @@ mod tests {
         assert!(res_two.is_ok());
     }
 
+    #[test]
+    fn test_client_capabilities() {
+        let cap = serde_json::json!(ClientCapabilities::from(serde_json::json!({ "name": "TestClient" })));
+        assert_eq!(cap["capabilities"]["roots"]["listChanged"], serde_json::json!(true));
+        assert!(cap["capabilities"]["sampling"].is_object());
+        assert_eq!(cap["clientInfo"]["name"], serde_json::json!("TestClient"));
+    }
+
     #[allow(clippy::await_holding_lock)]
     async fn test_client_routine<T: Transport>(
         client: &mut Client<T>,
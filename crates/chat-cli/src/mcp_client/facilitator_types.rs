@@ -84,6 +84,60 @@ pub struct ResourceTemplatesListResult {
     pub next_cursor: Option<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A resource as advertised by a mcp server in response to `resources/list`
+pub struct ResourceGet {
+    /// Unique identifier for the resource
+    pub uri: String,
+    /// Human-readable name
+    pub name: String,
+    /// Optional description of what the resource represents
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Optional mime type of the resource
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A parameterized resource as advertised by a mcp server in response to
+/// `resources/templates/list`
+pub struct ResourceTemplateGet {
+    /// A uri template as per RFC 6570 that can be used to construct a resource uri
+    pub uri_template: String,
+    /// Human-readable name
+    pub name: String,
+    /// Optional description of what the resources constructed from this template represent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Optional mime type of all resources constructed from this template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// `result` field in [JsonRpcResponse] from a `resources/read` request
+pub struct ResourceReadResult {
+    pub contents: Vec<ResourceReadContents>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// Contents of a resource read. Exactly one of `text` or `blob` is expected to be present.
+pub struct ResourceReadContents {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// base64-encoded-data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 /// Result of prompt listing query
@@ -229,6 +283,61 @@ pub struct Resource {
     pub contents: ResourceContents,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A directory the client exposes to servers in response to `roots/list`
+pub struct Root {
+    /// Must be a `file://` uri
+    pub uri: String,
+    /// Optional human-readable name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// A single message in a `sampling/createMessage` request
+pub struct SamplingMessage {
+    pub role: Role,
+    pub content: MessageContent,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// `params` field of a `sampling/createMessage` request sent by a mcp server
+/// https://spec.modelcontextprotocol.io/specification/2024-11-05/client/sampling/
+pub struct CreateMessageParams {
+    pub messages: Vec<SamplingMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Upper bound requested by the server. This is advisory only since the backend does not
+    /// expose a way to cap the response length.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_preferences: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_context: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+/// `result` field in the response the client sends back for a `sampling/createMessage` request
+pub struct CreateMessageResult {
+    pub role: Role,
+    pub content: MessageContent,
+    /// Name of the model that generated the message
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+}
+
 /// Represents the capabilities supported by a Model Context Protocol server
 /// This is the "capabilities" field in the result of a response for init
 #[derive(Debug, Clone, Serialize, Deserialize)]
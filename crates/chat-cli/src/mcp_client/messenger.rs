@@ -1,9 +1,12 @@
 use thiserror::Error;
 
 use super::{
+    CreateMessageParams,
+    CreateMessageResult,
     PromptsListResult,
     ResourceTemplatesListResult,
     ResourcesListResult,
+    Root,
     ToolsListResult,
 };
 
@@ -37,6 +40,14 @@ pub trait Messenger: std::fmt::Debug + Send + Sync + 'static {
     /// Signals to the orchestrator that a server has started initializing
     async fn send_init_msg(&self) -> Result<(), MessengerError>;
 
+    /// Retrieves the roots the consumer is willing to expose to the server.
+    /// This function is used to answer `roots/list` requests initiated by the server
+    async fn list_roots(&self) -> Result<Vec<Root>, MessengerError>;
+
+    /// Asks the consumer to obtain a completion from the model on behalf of the server.
+    /// This function is used to answer `sampling/createMessage` requests initiated by the server
+    async fn create_message(&self, params: CreateMessageParams) -> Result<CreateMessageResult, MessengerError>;
+
     /// Creates a duplicate of the messenger object
     /// This function is used to create a new instance of the messenger with the same configuration
     fn duplicate(&self) -> Box<dyn Messenger>;
@@ -79,6 +90,14 @@ impl Messenger for NullMessenger {
         Ok(())
     }
 
+    async fn list_roots(&self) -> Result<Vec<Root>, MessengerError> {
+        Ok(Vec::new())
+    }
+
+    async fn create_message(&self, _params: CreateMessageParams) -> Result<CreateMessageResult, MessengerError> {
+        Err(MessengerError::Custom("Sampling is not supported".to_string()))
+    }
+
     fn duplicate(&self) -> Box<dyn Messenger> {
         Box::new(NullMessenger)
     }
@@ -104,5 +104,6 @@ pub struct JsonRpcError {
 pub enum TransportType {
     #[default]
     Stdio,
+    Http,
     Websocket,
 }
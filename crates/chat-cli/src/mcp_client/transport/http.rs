@@ -0,0 +1,406 @@
+//! Referencing https://modelcontextprotocol.io/specification/2025-03-26/basic/transports#streamable-http
+//! Every message from the client is a POST to the server's endpoint. The server either replies
+//! with a single json body or upgrades the response to an SSE stream over which it can send any
+//! number of messages (including requests and notifications of its own) before the response it
+//! owes. A separate GET stream is opened after initialization for messages the server wishes to
+//! send unprompted.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use reqwest::header::{
+    ACCEPT,
+    CONTENT_TYPE,
+    HeaderMap,
+    HeaderName,
+    HeaderValue,
+};
+use reqwest::{
+    Response,
+    StatusCode,
+};
+use tokio::sync::{
+    RwLock,
+    broadcast,
+};
+use url::Url;
+
+use super::base_protocol::JsonRpcMessage;
+use super::{
+    Listener,
+    LogListener,
+    Transport,
+    TransportError,
+};
+
+const MCP_SESSION_ID: &str = "mcp-session-id";
+const EVENT_STREAM_MIME: &str = "text/event-stream";
+
+#[derive(Debug, Clone)]
+pub struct JsonRpcHttpTransport {
+    url: Url,
+    headers: HeaderMap,
+    http_client: reqwest::Client,
+    session_id: Arc<RwLock<Option<String>>>,
+    sender: broadcast::Sender<Result<JsonRpcMessage, TransportError>>,
+    receiver: Arc<broadcast::Receiver<Result<JsonRpcMessage, TransportError>>>,
+    log_sender: broadcast::Sender<String>,
+    log_receiver: Arc<broadcast::Receiver<String>>,
+}
+
+impl JsonRpcHttpTransport {
+    pub fn client(url: &str, headers: &HashMap<String, String>) -> Result<Self, TransportError> {
+        let url = Url::parse(url).map_err(|e| TransportError::Http(format!("Invalid url {url}: {e}")))?;
+        let headers = to_header_map(headers)?;
+        let http_client = crate::request::new_client().map_err(|e| TransportError::Http(e.to_string()))?;
+        let (sender, receiver) = broadcast::channel::<Result<JsonRpcMessage, TransportError>>(100);
+        let (log_sender, log_receiver) = broadcast::channel::<String>(100);
+        Ok(Self {
+            url,
+            headers,
+            http_client,
+            session_id: Arc::new(RwLock::new(None)),
+            sender,
+            receiver: Arc::new(receiver),
+            log_sender,
+            log_receiver: Arc::new(log_receiver),
+        })
+    }
+
+    async fn request_headers(&self) -> HeaderMap {
+        let mut headers = self.headers.clone();
+        if let Some(session_id) = self.session_id.read().await.as_ref() {
+            if let Ok(value) = HeaderValue::from_str(session_id) {
+                headers.insert(MCP_SESSION_ID, value);
+            }
+        }
+        headers
+    }
+
+    /// Opens the long lived GET stream through which the server can send messages that are not
+    /// associated with any request of ours (e.g. list changed notifications). Servers that do not
+    /// offer this stream respond with 405, in which case there is nothing to do.
+    async fn listen_for_server_messages(self) {
+        let resp = self
+            .http_client
+            .get(self.url.clone())
+            .headers(self.request_headers().await)
+            .header(ACCEPT, EVENT_STREAM_MIME)
+            .send()
+            .await;
+        match resp {
+            Ok(resp) if resp.status() == StatusCode::METHOD_NOT_ALLOWED => {},
+            Ok(resp) if resp.status().is_success() => {
+                read_event_stream(resp, &self.sender, &self.log_sender).await;
+            },
+            Ok(resp) => {
+                let _ = self.log_sender.send(format!(
+                    "Server rejected request to open event stream with status {}",
+                    resp.status()
+                ));
+            },
+            Err(e) => {
+                let _ = self
+                    .log_sender
+                    .send(format!("Failed to open event stream with server: {e}"));
+            },
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for JsonRpcHttpTransport {
+    async fn send(&self, msg: &JsonRpcMessage) -> Result<(), TransportError> {
+        let resp = self
+            .http_client
+            .post(self.url.clone())
+            .headers(self.request_headers().await)
+            .header(ACCEPT, format!("application/json, {EVENT_STREAM_MIME}"))
+            .json(msg)
+            .send()
+            .await
+            .map_err(|e| TransportError::Http(format!("Error sending to server: {e}")))?;
+
+        let status = resp.status();
+        if status == StatusCode::NOT_FOUND && self.session_id.read().await.is_some() {
+            return Err(TransportError::Http(
+                "Session has been terminated by the server".to_owned(),
+            ));
+        }
+        if !status.is_success() {
+            let body = resp.text().await.unwrap_or_default();
+            return Err(TransportError::Http(format!("Server responded with {status}: {body}")));
+        }
+
+        if let Some(session_id) = resp.headers().get(MCP_SESSION_ID).and_then(|v| v.to_str().ok()) {
+            self.session_id.write().await.replace(session_id.to_owned());
+        }
+
+        if let JsonRpcMessage::Notification(notif) = msg {
+            if notif.method == "notifications/initialized" {
+                tokio::spawn(self.clone().listen_for_server_messages());
+            }
+        }
+
+        // Responses and notifications are acknowledged with 202 and carry no body
+        if status == StatusCode::ACCEPTED {
+            return Ok(());
+        }
+
+        let is_event_stream = resp
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with(EVENT_STREAM_MIME));
+        let sender = self.sender.clone();
+        let log_sender = self.log_sender.clone();
+        tokio::spawn(async move {
+            if is_event_stream {
+                read_event_stream(resp, &sender, &log_sender).await;
+            } else {
+                match resp.bytes().await {
+                    Ok(body) if body.is_empty() => {},
+                    Ok(body) => forward_messages(&body, &sender),
+                    Err(e) => {
+                        let _ = sender.send(Err(TransportError::Http(e.to_string())));
+                    },
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn get_listener(&self) -> impl Listener {
+        HttpListener {
+            receiver: self.receiver.resubscribe(),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<(), TransportError> {
+        // Servers are not obligated to allow clients to terminate sessions, so any failure here
+        // is of no consequence.
+        if self.session_id.read().await.is_some() {
+            let _ = self
+                .http_client
+                .delete(self.url.clone())
+                .headers(self.request_headers().await)
+                .send()
+                .await;
+        }
+        Ok(())
+    }
+
+    fn get_log_listener(&self) -> impl LogListener {
+        HttpLogListener {
+            receiver: self.log_receiver.resubscribe(),
+        }
+    }
+}
+
+fn to_header_map(headers: &HashMap<String, String>) -> Result<HeaderMap, TransportError> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| TransportError::Http(format!("Invalid header name {name}: {e}")))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| TransportError::Http(format!("Invalid value for header {name}: {e}")))?;
+        header_map.insert(name, value);
+    }
+    Ok(header_map)
+}
+
+/// Deserializes a body that is either a single message or a batch of messages and hands them
+/// over to the listeners.
+fn forward_messages(body: &[u8], sender: &broadcast::Sender<Result<JsonRpcMessage, TransportError>>) {
+    match serde_json::from_slice::<JsonRpcMessage>(body) {
+        Ok(msg) => {
+            let _ = sender.send(Ok(msg));
+        },
+        Err(e) => match serde_json::from_slice::<Vec<JsonRpcMessage>>(body) {
+            Ok(batch) => {
+                for msg in batch {
+                    let _ = sender.send(Ok(msg));
+                }
+            },
+            Err(_) => {
+                let _ = sender.send(Err(e.into()));
+            },
+        },
+    }
+}
+
+async fn read_event_stream(
+    mut resp: Response,
+    sender: &broadcast::Sender<Result<JsonRpcMessage, TransportError>>,
+    log_sender: &broadcast::Sender<String>,
+) {
+    let mut parser = SseParser::default();
+    loop {
+        match resp.chunk().await {
+            Ok(Some(chunk)) => {
+                for event in parser.push(&chunk) {
+                    if event.event == "message" {
+                        forward_messages(event.data.as_bytes(), sender);
+                    }
+                }
+            },
+            Ok(None) => break,
+            Err(e) => {
+                let _ = log_sender.send(format!("Event stream from server ended with error: {e}"));
+                break;
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    pub event: String,
+    pub data: String,
+}
+
+/// Incremental parser for `text/event-stream` bodies as per
+/// https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation
+///
+/// Only the `event` and `data` fields are of interest to us. Event ids and retry intervals are
+/// ignored since we never attempt to resume a stream.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseParser {
+    /// Feeds a chunk of the stream into the parser, returning any events it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line = self.buffer.drain(..=pos).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if line.is_empty() {
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: self.event.take().unwrap_or_else(|| "message".to_owned()),
+                        data: self.data.join("\n"),
+                    });
+                }
+                self.event = None;
+                self.data.clear();
+                continue;
+            }
+            // Lines starting with a colon are comments, typically used as keep alive
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line, ""),
+            };
+            match field {
+                "event" => self.event = Some(value.to_owned()),
+                "data" => self.data.push(value.to_owned()),
+                _ => {},
+            }
+        }
+        events
+    }
+}
+
+pub struct HttpListener {
+    pub receiver: broadcast::Receiver<Result<JsonRpcMessage, TransportError>>,
+}
+
+#[async_trait::async_trait]
+impl Listener for HttpListener {
+    async fn recv(&mut self) -> Result<JsonRpcMessage, TransportError> {
+        self.receiver.recv().await?
+    }
+}
+
+pub struct HttpLogListener {
+    pub receiver: broadcast::Receiver<String>,
+}
+
+#[async_trait::async_trait]
+impl LogListener for HttpLogListener {
+    async fn recv(&mut self) -> Result<String, TransportError> {
+        Ok(self.receiver.recv().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_sse_parser() {
+        let mut parser = SseParser::default();
+        assert!(parser.push(b": keep alive\n\ndata: {\"a\":").is_empty());
+        let events = parser.push(b" 1}\r\n\r\nevent: endpoint\ndata: /messages\n\n");
+        assert_eq!(events, vec![
+            SseEvent {
+                event: "message".to_owned(),
+                data: "{\"a\": 1}".to_owned(),
+            },
+            SseEvent {
+                event: "endpoint".to_owned(),
+                data: "/messages".to_owned(),
+            },
+        ]);
+
+        let events = parser.push(b"data: line one\ndata:line two\n\n");
+        assert_eq!(events[0].data, "line one\nline two");
+    }
+
+    #[tokio::test]
+    async fn test_http_transport() {
+        let mut server = mockito::Server::new_async().await;
+        let json_resp = server
+            .mock("POST", "/mcp")
+            .match_header("authorization", "Bearer token")
+            .match_body(mockito::Matcher::PartialJson(json!({ "id": 1 })))
+            .with_header("content-type", "application/json")
+            .with_header(MCP_SESSION_ID, "session")
+            .with_body(json!({ "jsonrpc": "2.0", "id": 1, "result": {} }).to_string())
+            .create_async()
+            .await;
+        let sse_resp = server
+            .mock("POST", "/mcp")
+            .match_header(MCP_SESSION_ID, "session")
+            .match_body(mockito::Matcher::PartialJson(json!({ "id": 2 })))
+            .with_header("content-type", EVENT_STREAM_MIME)
+            .with_body(format!(
+                "data: {}\n\ndata: {}\n\n",
+                json!({ "jsonrpc": "2.0", "method": "notifications/message" }),
+                json!({ "jsonrpc": "2.0", "id": 2, "result": {} })
+            ))
+            .create_async()
+            .await;
+
+        let headers = HashMap::from([("Authorization".to_owned(), "Bearer token".to_owned())]);
+        let transport = JsonRpcHttpTransport::client(&format!("{}/mcp", server.url()), &headers).unwrap();
+        let mut listener = transport.get_listener();
+
+        let request = |id: u64| -> JsonRpcMessage {
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": id, "method": "test_method" })).unwrap()
+        };
+
+        transport.send(&request(1)).await.unwrap();
+        assert_eq!(listener.recv().await.unwrap().id(), Some(1));
+
+        transport.send(&request(2)).await.unwrap();
+        assert!(matches!(
+            listener.recv().await.unwrap(),
+            JsonRpcMessage::Notification(_)
+        ));
+        assert_eq!(listener.recv().await.unwrap().id(), Some(2));
+
+        json_resp.assert_async().await;
+        sse_resp.assert_async().await;
+    }
+}
@@ -1,5 +1,7 @@
 pub mod base_protocol;
+pub mod http;
 pub mod stdio;
+pub mod websocket;
 
 use std::fmt::Debug;
 
@@ -13,6 +15,10 @@ pub enum TransportError {
     Serialization(String),
     #[error("IO error: {0}")]
     Stdio(String),
+    #[error("HTTP error: {0}")]
+    Http(String),
+    #[error("WebSocket error: {0}")]
+    WebSocket(String),
     #[error("{0}")]
     Custom(String),
     #[error(transparent)]
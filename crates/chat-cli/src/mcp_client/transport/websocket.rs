@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::stream::SplitSink;
+use futures::{
+    SinkExt,
+    StreamExt,
+};
+use tokio::net::TcpStream;
+use tokio::sync::{
+    Mutex,
+    OnceCell,
+    broadcast,
+};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::{
+    HeaderName,
+    HeaderValue,
+    Request,
+};
+use tokio_tungstenite::{
+    Connector,
+    MaybeTlsStream,
+    WebSocketStream,
+};
+
+use super::base_protocol::JsonRpcMessage;
+use super::{
+    Listener,
+    LogListener,
+    Transport,
+    TransportError,
+};
+
+type WebSocketSink = SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>;
+
+/// Sub protocol servers are expected to accept when speaking mcp over websocket.
+const MCP_SUBPROTOCOL: &str = "mcp";
+
+#[derive(Debug)]
+pub struct JsonRpcWebSocketTransport {
+    request: Request<()>,
+    /// The connection is established upon the first message sent (i.e. initialize) so that
+    /// constructing the transport need not be async and connection failures are surfaced
+    /// alongside the rest of the initialization errors.
+    sink: OnceCell<Mutex<WebSocketSink>>,
+    sender: broadcast::Sender<Result<JsonRpcMessage, TransportError>>,
+    receiver: broadcast::Receiver<Result<JsonRpcMessage, TransportError>>,
+    log_sender: broadcast::Sender<String>,
+    log_receiver: broadcast::Receiver<String>,
+}
+
+impl JsonRpcWebSocketTransport {
+    pub fn client(url: &str, headers: &HashMap<String, String>) -> Result<Self, TransportError> {
+        let mut request = url
+            .into_client_request()
+            .map_err(|e| TransportError::WebSocket(format!("Invalid url {url}: {e}")))?;
+        let request_headers = request.headers_mut();
+        request_headers.insert("sec-websocket-protocol", HeaderValue::from_static(MCP_SUBPROTOCOL));
+        for (name, value) in headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|e| TransportError::WebSocket(format!("Invalid header name {name}: {e}")))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|e| TransportError::WebSocket(format!("Invalid value for header {name}: {e}")))?;
+            request_headers.insert(name, value);
+        }
+        let (sender, receiver) = broadcast::channel::<Result<JsonRpcMessage, TransportError>>(100);
+        let (log_sender, log_receiver) = broadcast::channel::<String>(100);
+        Ok(Self {
+            request,
+            sink: OnceCell::new(),
+            sender,
+            receiver,
+            log_sender,
+            log_receiver,
+        })
+    }
+
+    async fn connect(&self) -> Result<Mutex<WebSocketSink>, TransportError> {
+        let connector = Connector::Rustls(Arc::new(crate::request::client_config()));
+        let (stream, _) =
+            tokio_tungstenite::connect_async_tls_with_config(self.request.clone(), None, false, Some(connector))
+                .await
+                .map_err(|e| TransportError::WebSocket(format!("Failed to connect to server: {e}")))?;
+        let (sink, mut stream) = stream.split();
+        let sender = self.sender.clone();
+        let log_sender = self.log_sender.clone();
+        tokio::spawn(async move {
+            while let Some(msg) = stream.next().await {
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        let _ = sender.send(serde_json::from_str::<JsonRpcMessage>(text.as_str()).map_err(Into::into));
+                    },
+                    Ok(Message::Binary(bytes)) => {
+                        let _ = sender.send(serde_json::from_slice::<JsonRpcMessage>(&bytes).map_err(Into::into));
+                    },
+                    Ok(Message::Close(frame)) => {
+                        let reason = frame.map_or("no reason given".to_owned(), |f| f.reason.to_string());
+                        let _ = log_sender.send(format!("Server closed the connection: {reason}"));
+                        break;
+                    },
+                    // Pings are answered by tungstenite on our behalf
+                    Ok(_) => {},
+                    Err(e) => {
+                        let _ = log_sender.send(format!("Connection to server ended with error: {e}"));
+                        break;
+                    },
+                }
+            }
+        });
+        Ok(Mutex::new(sink))
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for JsonRpcWebSocketTransport {
+    async fn send(&self, msg: &JsonRpcMessage) -> Result<(), TransportError> {
+        let sink = self.sink.get_or_try_init(|| self.connect()).await?;
+        let serialized = serde_json::to_string(msg)?;
+        sink.lock()
+            .await
+            .send(Message::text(serialized))
+            .await
+            .map_err(|e| TransportError::WebSocket(format!("Error writing to server: {e}")))
+    }
+
+    fn get_listener(&self) -> impl Listener {
+        WebSocketListener {
+            receiver: self.receiver.resubscribe(),
+        }
+    }
+
+    async fn shutdown(&self) -> Result<(), TransportError> {
+        if let Some(sink) = self.sink.get() {
+            sink.lock()
+                .await
+                .close()
+                .await
+                .map_err(|e| TransportError::WebSocket(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn get_log_listener(&self) -> impl LogListener {
+        WebSocketLogListener {
+            receiver: self.log_receiver.resubscribe(),
+        }
+    }
+}
+
+pub struct WebSocketListener {
+    pub receiver: broadcast::Receiver<Result<JsonRpcMessage, TransportError>>,
+}
+
+#[async_trait::async_trait]
+impl Listener for WebSocketListener {
+    async fn recv(&mut self) -> Result<JsonRpcMessage, TransportError> {
+        self.receiver.recv().await?
+    }
+}
+
+pub struct WebSocketLogListener {
+    pub receiver: broadcast::Receiver<String>,
+}
+
+#[async_trait::async_trait]
+impl LogListener for WebSocketLogListener {
+    async fn recv(&mut self) -> Result<String, TransportError> {
+        Ok(self.receiver.recv().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_websocket_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Echo server
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_hdr_async(
+                stream,
+                |req: &tokio_tungstenite::tungstenite::handshake::server::Request,
+                 mut resp: tokio_tungstenite::tungstenite::handshake::server::Response| {
+                    assert_eq!(req.headers().get("x-api-key").unwrap(), "key");
+                    resp.headers_mut()
+                        .insert("sec-websocket-protocol", HeaderValue::from_static(MCP_SUBPROTOCOL));
+                    Ok(resp)
+                },
+            )
+            .await
+            .unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                if msg.is_text() {
+                    ws.send(msg).await.unwrap();
+                }
+            }
+        });
+
+        let headers = HashMap::from([("X-Api-Key".to_owned(), "key".to_owned())]);
+        let transport = JsonRpcWebSocketTransport::client(&format!("ws://{addr}"), &headers).unwrap();
+        let mut listener = transport.get_listener();
+        let message: JsonRpcMessage =
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 1, "method": "test_method" })).unwrap();
+        transport.send(&message).await.unwrap();
+        assert_eq!(listener.recv().await.unwrap(), message);
+        transport.shutdown().await.unwrap();
+    }
+}
@@ -139,6 +139,27 @@ pub fn chat_profiles_dir(ctx: &Context) -> Result<PathBuf> {
     Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("profiles"))
 }
 
+/// The path to the global command policy consulted before `execute_bash` runs a command in `q
+/// chat`.
+pub fn chat_global_command_policy_path(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("command_policy.json"))
+}
+
+/// The directory containing the agents available in every workspace in `q chat`.
+pub fn chat_global_agents_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("agents"))
+}
+
+/// The directory containing the contents of the files snapshotted by checkpoints in `q chat`.
+pub fn chat_checkpoints_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("checkpoints"))
+}
+
+/// The directory containing the knowledge bases indexed by the `knowledge` tool in `q chat`.
+pub fn chat_knowledge_dir(ctx: &Context) -> Result<PathBuf> {
+    Ok(home_dir(ctx)?.join(".aws").join("amazonq").join("knowledge_bases"))
+}
+
 /// The path to the fig settings file
 pub fn settings_path() -> Result<PathBuf> {
     Ok(fig_data_dir()?.join("settings.json"))
@@ -13,6 +13,7 @@ use std::io::{
     ErrorKind,
     stdout,
 };
+use std::sync::OnceLock;
 
 use anstream::stream::IsTerminal;
 pub use cli_context::CliContext;
@@ -25,6 +26,7 @@ use eyre::{
     bail,
 };
 use thiserror::Error;
+use time::UtcOffset;
 use tracing::warn;
 
 #[derive(Debug, Error)]
@@ -98,3 +100,16 @@ pub fn dialoguer_theme() -> ColorfulTheme {
         ..ColorfulTheme::default()
     }
 }
+
+static LOCAL_OFFSET: OnceLock<UtcOffset> = OnceLock::new();
+
+/// Reads the offset of the local timezone. It can only be read while the process has a single
+/// thread, so this has to be called before the async runtime is started.
+pub fn init_local_offset() {
+    LOCAL_OFFSET.get_or_init(|| UtcOffset::current_local_offset().unwrap_or(UtcOffset::UTC));
+}
+
+/// The offset of the local timezone read by [init_local_offset], UTC if it was not read.
+pub fn local_offset() -> UtcOffset {
+    LOCAL_OFFSET.get().copied().unwrap_or(UtcOffset::UTC)
+}
@@ -19,6 +19,25 @@ const CODEX_FILES: &[&str] = &[
     "guard_end.zsh",
 ];
 
+/// Scripts in which `{{CLI_BINARY_NAME}}` is replaced with the name of the binary
+const TEMPLATED_FILES: &[&str] = &["inline.bash", "inline.fish"];
+
+/// The file defining `CLI_BINARY_NAME`, read instead of depending on `fig_util` when building
+const CONSTS_FILE: &str = "../fig_util/src/consts.rs";
+
+fn cli_binary_name() -> String {
+    println!("cargo:rerun-if-changed={CONSTS_FILE}");
+    std::fs::read_to_string(CONSTS_FILE)
+        .unwrap()
+        .lines()
+        .find_map(|line| {
+            line.strip_prefix("pub const CLI_BINARY_NAME: &str = \"")?
+                .strip_suffix("\";")
+                .map(ToOwned::to_owned)
+        })
+        .expect("CLI_BINARY_NAME is defined")
+}
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
 
@@ -32,4 +51,14 @@ fn main() {
         inline_shell_completion.push_str(&std::fs::read_to_string(path).unwrap());
     }
     std::fs::write(out_dir.join("inline_shell_completion.zsh"), inline_shell_completion).unwrap();
+
+    let cli_binary_name = cli_binary_name();
+    for file in TEMPLATED_FILES {
+        let path = std::path::Path::new(CODEX_FOLDER).join(file);
+        println!("cargo:rerun-if-changed={}", path.display());
+        let script = std::fs::read_to_string(path)
+            .unwrap()
+            .replace("{{CLI_BINARY_NAME}}", &cli_binary_name);
+        std::fs::write(out_dir.join(file), script).unwrap();
+    }
 }
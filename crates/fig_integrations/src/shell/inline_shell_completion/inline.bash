@@ -0,0 +1,200 @@
+#--------------------------------------------------------------------#
+# Inline Shell Completion for bash                                   #
+#--------------------------------------------------------------------#
+# Readline cannot display text past the end of the line, so printable
+# characters are bound to a function that inserts them and requests a
+# suggestion from a background worker. The worker waits until no key
+# was pressed for a moment, so that typing or pasting quickly does not
+# start a process per key, and then draws the suggestion after the
+# cursor. It is accepted with the right arrow or end of line keys.
+#
+# Only keys that still have their default functions are bound, so the
+# bindings of the user are kept.
+#
+# Requires bash 4.1+ for `bind -x` to be able to edit the line and for
+# file descriptors to be allocated with `{var}<>`.
+
+if [[ $- == *i* ]] && (( BASH_VERSINFO[0] > 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] >= 1) )); then
+
+export Q_INLINE_SHELL=bash
+
+# SGR parameters used to draw the suggestion
+: "${Q_AUTOSUGGEST_HIGHLIGHT_SGR:=90}"
+# Seconds without a key press before a suggestion is requested
+: "${Q_INLINE_DEBOUNCE:=0.1}"
+
+_q_inline_seq=0
+_q_inline_state="${TMPDIR:-/tmp}/q-inline-${USER:-$UID}-$$"
+_q_inline_fd=
+_q_inline_worker_pid=
+_q_inline_worker_failed=
+
+# Erases the suggestion drawn after the cursor
+_q_inline_clear() {
+	if (( READLINE_POINT == ${#READLINE_LINE} )); then
+		printf '\033[K' > /dev/tty
+	fi
+	if [[ -e "$_q_inline_state.suggestion" ]]; then
+		rm -f "$_q_inline_state.suggestion"
+	fi
+}
+
+# Reads requests from the queue, only fetching a suggestion for the last
+# one of a burst and drawing it unless another key was pressed since
+_q_inline_worker() {
+	local request next seq buffer suggestion current
+	while IFS= read -r -d '' request; do
+		while IFS= read -r -d '' -t "$Q_INLINE_DEBOUNCE" next; do
+			request="$next"
+		done
+		seq="${request%%$'\t'*}"
+		buffer="${request#*$'\t'}"
+
+		IFS= read -r current < "$_q_inline_state.seq"
+		[[ "$current" == "$seq" ]] || continue
+		suggestion="$(command {{CLI_BINARY_NAME}} _ inline-shell-completion --buffer "$buffer" 2> /dev/null)" || continue
+		[[ "$suggestion" == "$buffer"?* ]] || continue
+		IFS= read -r current < "$_q_inline_state.seq"
+		[[ "$current" == "$seq" ]] || continue
+
+		printf '%s' "$suggestion" > "$_q_inline_state.suggestion"
+		printf '\0337\033[%sm%s\033[0m\0338' "$Q_AUTOSUGGEST_HIGHLIGHT_SGR" "${suggestion#"$buffer"}" > /dev/tty
+	done
+}
+
+# Starts the worker unless it is running, it exits once the shell closes the queue
+_q_inline_start_worker() {
+	if [[ -n "$_q_inline_worker_pid" ]] && kill -0 "$_q_inline_worker_pid" 2> /dev/null; then
+		return 0
+	fi
+	[[ -z "$_q_inline_worker_failed" ]] || return 1
+
+	[[ -n "$_q_inline_fd" ]] && exec {_q_inline_fd}>&-
+	rm -f "$_q_inline_state.queue" "$_q_inline_state.pid"
+	if ! command mkfifo -m 600 "$_q_inline_state.queue" 2> /dev/null; then
+		_q_inline_worker_failed=1
+		return 1
+	fi
+	(
+		_q_inline_worker < "$_q_inline_state.queue" > /dev/null 2>&1 &
+		printf '%s\n' "$!" > "$_q_inline_state.pid"
+	)
+	# Opened for reading and writing so that opening it does not block
+	exec {_q_inline_fd}<> "$_q_inline_state.queue"
+	IFS= read -r _q_inline_worker_pid < "$_q_inline_state.pid"
+}
+
+# Requests a suggestion for the current line when the cursor is at its end
+_q_inline_fetch() {
+	(( _q_inline_seq++ ))
+	printf '%s' "$_q_inline_seq" > "$_q_inline_state.seq"
+	if [[ -n "${QTERM_SESSION_ID:-}" && -n "${READLINE_LINE// /}" ]] && (( READLINE_POINT == ${#READLINE_LINE} )); then
+		_q_inline_start_worker && printf '%s\t%s\0' "$_q_inline_seq" "$READLINE_LINE" >&"$_q_inline_fd"
+	fi
+}
+
+# Inserts the character with the given code, as self-insert would
+_q_inline_insert() {
+	local hex char
+	printf -v hex '%x' "$1"
+	printf -v char "\\x$hex"
+	_q_inline_clear
+	READLINE_LINE="${READLINE_LINE:0:READLINE_POINT}$char${READLINE_LINE:READLINE_POINT}"
+	(( READLINE_POINT++ ))
+	_q_inline_fetch
+}
+
+_q_inline_backward_delete_char() {
+	_q_inline_clear
+	if (( READLINE_POINT > 0 )); then
+		READLINE_LINE="${READLINE_LINE:0:READLINE_POINT-1}${READLINE_LINE:READLINE_POINT}"
+		(( READLINE_POINT-- ))
+	fi
+	_q_inline_fetch
+}
+
+# Accepts the suggestion, or moves the cursor as the original widget would
+_q_inline_accept() {
+	local suggestion
+	if (( READLINE_POINT == ${#READLINE_LINE} )) && [[ -f "$_q_inline_state.suggestion" ]]; then
+		suggestion="$(< "$_q_inline_state.suggestion")"
+		if [[ "$suggestion" == "$READLINE_LINE"?* ]]; then
+			(command {{CLI_BINARY_NAME}} _ inline-shell-completion-accept --buffer "$READLINE_LINE" --suggestion "${suggestion#"$READLINE_LINE"}" > /dev/null 2>&1 &)
+			_q_inline_clear
+			READLINE_LINE="$suggestion"
+			READLINE_POINT=${#READLINE_LINE}
+			return
+		fi
+	fi
+
+	case "$1" in
+		forward-char) (( READLINE_POINT < ${#READLINE_LINE} )) && (( READLINE_POINT++ )) ;;
+		end-of-line) READLINE_POINT=${#READLINE_LINE} ;;
+	esac
+}
+
+# Erases the suggestion before the line is accepted so it is not left on screen
+_q_inline_dismiss() {
+	(( _q_inline_seq++ ))
+	printf '%s' "$_q_inline_seq" > "$_q_inline_state.seq"
+	_q_inline_clear
+}
+
+_q_inline_cleanup() {
+	rm -f "$_q_inline_state.seq" "$_q_inline_state.suggestion" "$_q_inline_state.queue" "$_q_inline_state.pid"
+}
+
+if [[ -z "$(trap -p EXIT)" ]]; then
+	trap _q_inline_cleanup EXIT
+fi
+
+_q_inline_bind() {
+	local keymap="$1" line code hex key
+	local -A functions=()
+
+	# The readline function of every key, as in `"\C-m": accept-line`
+	while IFS= read -r line; do
+		[[ "$line" == '"'*'": '* ]] && functions["${line%%\": *}\""]="${line##*\": }"
+	done < <(bind -m "$keymap" -p 2> /dev/null)
+
+	# Binds `key` to `command` if it is still bound to the readline `function`
+	_q_inline_bind_key() {
+		[[ "${functions["\"$1\""]}" == "$2" ]] && bind -m "$keymap" -x "\"$1\": $3"
+	}
+
+	for code in {32..126}; do
+		printf -v hex '%x' "$code"
+		printf -v key "\\x$hex"
+		case "$key" in
+			'"' | '\') key="\\$key" ;;
+		esac
+		_q_inline_bind_key "$key" self-insert "_q_inline_insert $code"
+	done
+
+	_q_inline_bind_key '\C-?' backward-delete-char _q_inline_backward_delete_char
+	_q_inline_bind_key '\C-h' backward-delete-char _q_inline_backward_delete_char
+
+	_q_inline_bind_key '\e[C' forward-char '_q_inline_accept forward-char'
+	_q_inline_bind_key '\eOC' forward-char '_q_inline_accept forward-char'
+	_q_inline_bind_key '\e[F' end-of-line '_q_inline_accept end-of-line'
+	_q_inline_bind_key '\eOF' end-of-line '_q_inline_accept end-of-line'
+	_q_inline_bind_key '\C-e' end-of-line '_q_inline_accept end-of-line'
+
+	# bind -x cannot be followed by accept-line, so enter runs both through a macro
+	bind -m "$keymap" -x '"\e[9998~": _q_inline_dismiss'
+	bind -m "$keymap" '"\e[9999~": accept-line'
+	local enter
+	for enter in '\C-m' '\C-j'; do
+		if [[ "${functions["\"$enter\""]}" == accept-line ]]; then
+			bind -m "$keymap" "\"$enter\": \"\\e[9998~\\e[9999~\""
+		fi
+	done
+
+	unset -f _q_inline_bind_key
+}
+
+_q_inline_bind emacs
+_q_inline_bind vi-insert
+unset -f _q_inline_bind
+
+fi
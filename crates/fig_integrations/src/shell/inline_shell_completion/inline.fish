@@ -0,0 +1,122 @@
+#--------------------------------------------------------------------#
+# Inline Shell Completion for fish                                   #
+#--------------------------------------------------------------------#
+# Fish does not let other programs provide its autosuggestions, so
+# printable characters also fetch a suggestion in the background. Once
+# it is fetched fish is signaled to draw it after the cursor, unless its
+# own autosuggestion is shown, and it is accepted with the right arrow or
+# end of line keys.
+
+if status is-interactive
+
+set -gx Q_INLINE_SHELL fish
+
+# SGR parameters used to draw the suggestion
+set -q Q_AUTOSUGGEST_HIGHLIGHT_SGR; or set -g Q_AUTOSUGGEST_HIGHLIGHT_SGR 90
+
+set -g _q_inline_seq 0
+if set -q TMPDIR
+    set -g _q_inline_state $TMPDIR/q-inline-$USER-$fish_pid
+else
+    set -g _q_inline_state /tmp/q-inline-$USER-$fish_pid
+end
+
+# Runs in the background, signaling fish unless another key was pressed since
+set -g _q_inline_fetch_script '
+suggestion=$(command {{CLI_BINARY_NAME}} _ inline-shell-completion --buffer "$1" 2>/dev/null) || exit
+case $suggestion in "$1"?*) ;; *) exit ;; esac
+[ "$(cat "$3.seq" 2>/dev/null)" = "$2" ] || exit
+printf %s "$suggestion" > "$3.suggestion" && kill -USR1 "$4"'
+
+# Erases the suggestion drawn after the cursor
+function _q_inline_clear
+    if test (commandline -C) -eq (string length -- (commandline | string collect))
+        printf '\e[K' >/dev/tty
+    end
+    rm -f $_q_inline_state.suggestion
+end
+
+# Fetches a suggestion for the current line when the cursor is at its end
+function _q_inline_fetch
+    set -g _q_inline_seq (math $_q_inline_seq + 1)
+    printf %s $_q_inline_seq >$_q_inline_state.seq
+    _q_inline_clear
+
+    set -l buffer (commandline | string collect)
+    if set -q QTERM_SESSION_ID; and string match -qr '\S' -- $buffer; and test (commandline -C) -eq (string length -- $buffer)
+        command sh -c $_q_inline_fetch_script _q_inline $buffer $_q_inline_seq $_q_inline_state $fish_pid &
+        disown
+    end
+end
+
+# Draws the fetched suggestion if the line has not changed since
+function _q_inline_show --on-signal SIGUSR1
+    set -l suggestion (cat $_q_inline_state.suggestion 2>/dev/null | string collect)
+    set -l buffer (commandline | string collect)
+    test -n "$suggestion"; or return
+    test (commandline -C) -eq (string length -- $buffer); or return
+    test (string sub -l (string length -- $buffer) -- $suggestion) = "$buffer"; or return
+
+    # The autosuggestion of fish takes precedence
+    if commandline --showing-suggestion 2>/dev/null
+        rm -f $_q_inline_state.suggestion
+        return
+    end
+
+    set -l completion (string sub -s (math (string length -- $buffer) + 1) -- $suggestion)
+    printf '\e7\e[%sm%s\e[0m\e8' $Q_AUTOSUGGEST_HIGHLIGHT_SGR $completion >/dev/tty
+end
+
+# Accepts the suggestion, or runs the original input function
+function _q_inline_accept -a fallback
+    set -l buffer (commandline | string collect)
+    if test -f $_q_inline_state.suggestion; and test (commandline -C) -eq (string length -- $buffer)
+        set -l suggestion (cat $_q_inline_state.suggestion | string collect)
+        if test (string sub -l (string length -- $buffer) -- $suggestion) = "$buffer"
+            set -l completion (string sub -s (math (string length -- $buffer) + 1) -- $suggestion)
+            command {{CLI_BINARY_NAME}} _ inline-shell-completion-accept --buffer $buffer --suggestion $completion >/dev/null 2>&1 &
+            disown
+            _q_inline_clear
+            commandline -r -- $suggestion
+            commandline -C (string length -- $suggestion)
+            return
+        end
+    end
+
+    commandline -f $fallback
+end
+
+# Erases the suggestion before the line is executed so it is not left on screen
+function _q_inline_dismiss
+    set -g _q_inline_seq (math $_q_inline_seq + 1)
+    printf %s $_q_inline_seq >$_q_inline_state.seq
+    _q_inline_clear
+end
+
+function _q_inline_cleanup --on-event fish_exit
+    rm -f $_q_inline_state.seq $_q_inline_state.suggestion
+end
+
+# Key bindings are reset when fish_key_bindings changes, so they are set up before every prompt
+function _q_inline_bind --on-event fish_prompt
+    for mode in default insert
+        # Only bind the modes in which characters are inserted, i.e. not the vi normal mode
+        string match -q -- '*self-insert*' (bind -M $mode '' 2>/dev/null); or continue
+        string match -q -- '*_q_inline_fetch*' (bind -M $mode '' 2>/dev/null); and continue
+
+        bind -M $mode '' self-insert _q_inline_fetch
+        bind -M $mode \x7f backward-delete-char _q_inline_fetch
+        bind -M $mode \b backward-delete-char _q_inline_fetch
+
+        bind -M $mode \e\[C '_q_inline_accept forward-char'
+        bind -M $mode \eOC '_q_inline_accept forward-char'
+        bind -M $mode \e\[F '_q_inline_accept end-of-line'
+        bind -M $mode \eOF '_q_inline_accept end-of-line'
+        bind -M $mode \ce '_q_inline_accept end-of-line'
+
+        bind -M $mode \r _q_inline_dismiss execute
+        bind -M $mode \n _q_inline_dismiss execute
+    end
+end
+
+end
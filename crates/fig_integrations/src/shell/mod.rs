@@ -36,12 +36,28 @@ use crate::{
 };
 
 pub mod inline_shell_completion_plugin {
+    use super::Shell;
+
     pub const ZSH_SCRIPT: &str = concat!(
         "\n",
         include_str!(concat!(env!("OUT_DIR"), "/inline_shell_completion.zsh")),
         "\n"
     );
 
+    pub const BASH_SCRIPT: &str = concat!("\n", include_str!(concat!(env!("OUT_DIR"), "/inline.bash")), "\n");
+
+    pub const FISH_SCRIPT: &str = concat!("\n", include_str!(concat!(env!("OUT_DIR"), "/inline.fish")), "\n");
+
+    /// The inline shell completion integration for `shell`, if it is supported.
+    pub fn script(shell: Shell) -> Option<&'static str> {
+        match shell {
+            Shell::Zsh => Some(ZSH_SCRIPT),
+            Shell::Bash => Some(BASH_SCRIPT),
+            Shell::Fish => Some(FISH_SCRIPT),
+            Shell::Nu => None,
+        }
+    }
+
     #[cfg(test)]
     mod tests {
         use fig_util::CLI_BINARY_NAME;
@@ -60,6 +76,22 @@ pub mod inline_shell_completion_plugin {
 
             // Ensure script adds precmd hook
             assert!(ZSH_SCRIPT.contains(&format!("add-zsh-hook precmd _{CLI_BINARY_NAME}_autosuggest_start")));
+
+            // Ensure every script requests and accepts completions
+            for script in [ZSH_SCRIPT, BASH_SCRIPT, FISH_SCRIPT] {
+                assert!(script.contains(&format!("{CLI_BINARY_NAME} _ inline-shell-completion --buffer")));
+                assert!(script.contains(&format!("{CLI_BINARY_NAME} _ inline-shell-completion-accept --buffer")));
+            }
+
+            // Ensure the bash and fish scripts are templated
+            for script in [BASH_SCRIPT, FISH_SCRIPT] {
+                assert!(!script.contains("{{CLI_BINARY_NAME}}"));
+            }
+
+            // Ensure every script reports the shell it was loaded in
+            assert!(ZSH_SCRIPT.contains("export Q_INLINE_SHELL=zsh"));
+            assert!(BASH_SCRIPT.contains("export Q_INLINE_SHELL=bash"));
+            assert!(FISH_SCRIPT.contains("set -gx Q_INLINE_SHELL fish"));
         }
     }
 }
@@ -825,6 +857,11 @@ mod test {
         check_script(Shell::Bash, When::Post);
     }
 
+    #[test]
+    fn shellcheck_bash_inline_shell_completion() {
+        run_shellcheck(inline_shell_completion_plugin::BASH_SCRIPT.to_owned());
+    }
+
     #[test]
     fn test_legacy_codewhisperer_regex() {
         let re = regex::Regex::new(
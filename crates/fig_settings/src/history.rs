@@ -23,6 +23,8 @@ use crate::sqlite::{
 };
 
 const ALL_COLUMNS: &str = "id, command, shell, pid, session_id, cwd, start_time, duration, hostname, exit_code";
+/// Selected after [ALL_COLUMNS] only where the output is used, since it can be large
+const OUTPUT_COLUMN: &str = "output";
 
 fn escape_string(s: impl AsRef<str>) -> String {
     s.as_ref()
@@ -46,6 +48,8 @@ pub struct CommandInfo {
     pub end_time: Option<SystemTime>,
     pub hostname: Option<String>,
     pub exit_code: Option<i32>,
+    /// The end of what the command printed, only recorded when `history.captureOutput` is enabled
+    pub output: Option<String>,
 }
 
 #[derive(Debug, Default)]
@@ -92,8 +96,8 @@ impl History {
             if !command.is_empty() {
                 self.conn()?.execute(
                     "INSERT INTO history 
-                        (command, shell, pid, session_id, cwd, start_time, end_time, duration, hostname, exit_code)
-                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                        (command, shell, pid, session_id, cwd, start_time, end_time, duration, hostname, exit_code, output)
+                        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
                     params![
                         &command_info.command,
                         &command_info.shell,
@@ -123,6 +127,7 @@ impl History {
                             .and_then(|duration| i64::try_from(duration).ok()),
                         &command_info.hostname,
                         &command_info.exit_code,
+                        &command_info.output,
                     ],
                 )?;
             }
@@ -179,7 +184,9 @@ impl History {
 
     pub fn all_rows(&self) -> Result<Vec<CommandInfo>> {
         let conn = self.conn()?;
-        let mut stmt = conn.prepare(&format!("SELECT {ALL_COLUMNS} FROM history ORDER BY start_time ASC"))?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {ALL_COLUMNS}, {OUTPUT_COLUMN} FROM history ORDER BY start_time ASC"
+        ))?;
 
         let rows = stmt.query([])?;
 
@@ -188,7 +195,8 @@ impl History {
         Ok(rows_mapped)
     }
 
-    /// The Where expression is not escaped, so be careful!
+    /// The Where expression is not escaped, so be careful! The output of the commands is not
+    /// loaded.
     ///
     /// Ugh i should like use sqlx or something
     pub fn rows(
@@ -281,6 +289,10 @@ fn map_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<CommandInfo> {
         end_time,
         hostname: row.get(8)?,
         exit_code: row.get(9)?,
+        output: match row.as_ref().column_count() > 10 {
+            true => row.get(10)?,
+            false => None,
+        },
     })
 }
 
@@ -295,6 +307,7 @@ pub enum HistoryColumn {
     Duration,
     Hostname,
     ExitCode,
+    Output,
 }
 
 impl std::fmt::Display for HistoryColumn {
@@ -310,6 +323,7 @@ impl std::fmt::Display for HistoryColumn {
             HistoryColumn::Duration => f.write_str("duration"),
             HistoryColumn::Hostname => f.write_str("hostname"),
             HistoryColumn::ExitCode => f.write_str("exit_code"),
+            HistoryColumn::Output => f.write_str("output"),
         }
     }
 }
@@ -429,6 +443,7 @@ mod tests {
                     end_time: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(124)),
                     hostname: Some("laptop".into()),
                     exit_code: Some(0),
+                    output: None,
                 },
                 false,
             )
@@ -446,6 +461,7 @@ mod tests {
                     end_time: Some(std::time::UNIX_EPOCH + std::time::Duration::from_secs(125)),
                     hostname: Some("laptop".into()),
                     exit_code: Some(0),
+                    output: Some("test result: ok".into()),
                 },
                 false,
             )
@@ -463,6 +479,7 @@ mod tests {
                     end_time: None,
                     hostname: Some("laptop".into()),
                     exit_code: None,
+                    output: None,
                 },
                 false,
             )
@@ -486,6 +503,7 @@ mod tests {
         );
         assert_eq!(rows[0].hostname, Some("laptop".into()));
         assert_eq!(rows[0].exit_code, Some(0));
+        assert_eq!(rows[0].output, None);
 
         assert_eq!(rows[1].command, Some("cargo test".into()));
         assert_eq!(rows[1].shell, Some("zsh".into()));
@@ -502,6 +520,7 @@ mod tests {
         );
         assert_eq!(rows[1].hostname, Some("laptop".into()));
         assert_eq!(rows[1].exit_code, Some(0));
+        assert_eq!(rows[1].output, Some("test result: ok".into()));
 
         assert_eq!(rows[2].command, Some("cargo run".into()));
         assert_eq!(rows[2].shell, Some("zsh".into()));
@@ -532,6 +551,8 @@ mod tests {
             .unwrap();
 
         assert_eq!(row.len(), 2);
+        assert_eq!(row[0].command, Some("cargo test".into()));
+        assert_eq!(row[0].output, None);
 
         // while we're here, test the `query` method
         let row = history.query("SELECT * FROM history ORDER BY id ASC", ()).unwrap();
@@ -552,6 +573,7 @@ mod tests {
                 "hostname": "laptop",
                 "exit_code": 0,
                 "duration": 1000,
+                "output": null,
             })
             .as_object()
             .unwrap()
@@ -571,6 +593,7 @@ mod tests {
                 "hostname": "laptop",
                 "exit_code": 0,
                 "duration": 1000,
+                "output": "test result: ok",
             })
             .as_object()
             .unwrap()
@@ -590,6 +613,7 @@ mod tests {
                 "hostname": "laptop",
                 "exit_code": null,
                 "duration": null,
+                "output": null,
             })
             .as_object()
             .unwrap()
@@ -1 +1,40 @@
+use std::net::IpAddr;
+
+use url::Url;
+
 pub const UPDATE_AVAILABLE_KEY: &str = "update.new-version-available";
+
+/// The provider of inline suggestions, one of the `INLINE_PROVIDER_*` names
+pub const INLINE_PROVIDER_KEY: &str = "inline.provider";
+/// The endpoint inline suggestions are requested from with the [`INLINE_PROVIDER_HTTP`] provider
+pub const INLINE_PROVIDER_ENDPOINT_KEY: &str = "inline.provider.endpoint";
+pub const INLINE_PROVIDER_CODEWHISPERER: &str = "codewhisperer";
+pub const INLINE_PROVIDER_LOCAL: &str = "local";
+pub const INLINE_PROVIDER_HTTP: &str = "http";
+
+/// Checks that `endpoint` can be used with the [`INLINE_PROVIDER_HTTP`] provider. Only endpoints
+/// on this machine are allowed, so the history never leaves it.
+pub fn check_inline_provider_endpoint(endpoint: &Url) -> Result<(), String> {
+    if !matches!(endpoint.scheme(), "http" | "https") {
+        return Err(format!("{endpoint} must be an http or https url"));
+    }
+    if !is_loopback(endpoint) {
+        return Err(format!(
+            "{endpoint} is not on this machine, only localhost and loopback addresses are allowed"
+        ));
+    }
+    Ok(())
+}
+
+fn is_loopback(url: &Url) -> bool {
+    match url.host_str() {
+        Some(host) => {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            match host.parse::<IpAddr>() {
+                Ok(ip) => ip.is_loopback(),
+                Err(_) => host.eq_ignore_ascii_case("localhost"),
+            }
+        },
+        None => false,
+    }
+}
@@ -0,0 +1,4 @@
+CREATE TABLE conversations (
+    key TEXT PRIMARY KEY,
+    value TEXT
+);
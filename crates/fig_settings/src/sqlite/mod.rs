@@ -59,13 +59,19 @@ macro_rules! migrations {
     }};
 }
 
+// The database is shared with `q chat`, whose migrations are tracked in the same table, so both
+// lists must contain the same migrations in the same order.
 const MIGRATIONS: &[Migration] = migrations![
     "000_migration_table",
     "001_history_table",
     "002_drop_history_in_ssh_docker",
     "003_improved_history_timing",
     "004_state_table",
-    "005_auth_table"
+    "005_auth_table",
+    "006_make_state_blob",
+    "007_conversations_table",
+    "008_sessions_table",
+    "009_history_output"
 ];
 
 #[derive(Debug, Clone)]
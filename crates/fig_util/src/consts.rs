@@ -129,6 +129,9 @@ pub mod env_var {
         /// Indicates the user is using zsh autosuggestions which disables Inline
         Q_USING_ZSH_AUTOSUGGESTIONS = "Q_USING_ZSH_AUTOSUGGESTIONS",
 
+        /// The shell Inline was loaded in, set by its shell integration
+        Q_INLINE_SHELL = "Q_INLINE_SHELL",
+
         /// Overrides the path to the bundle metadata released with certain desktop builds.
         Q_BUNDLE_METADATA_PATH = "Q_BUNDLE_METADATA_PATH"
     }
@@ -0,0 +1,335 @@
+use std::fmt::Write;
+use std::sync::LazyLock;
+
+use async_trait::async_trait;
+use fig_api_client::Client;
+use fig_api_client::clients::FILE_CONTEXT_LEFT_FILE_CONTENT_MAX_LEN;
+use fig_api_client::model::{
+    FileContext,
+    LanguageName,
+    ProgrammingLanguage,
+    RecommendationsInput,
+};
+use fig_settings::history::CommandInfo;
+use regex::Regex;
+
+use super::context::PromptContext;
+use super::provider::{
+    InlineProvider,
+    ProviderError,
+    ProviderKind,
+    ServiceIds,
+    SuggestionRequest,
+    Suggestions,
+};
+
+const HISTORY_COUNT_DEFAULT: usize = 49;
+/// The commands of the prompt are picked from this many times more commands, preferring the
+/// relevant ones
+const HISTORY_QUERY_FACTOR: usize = 4;
+/// Maximum number of lines of a completion, when its lines are continued with `\`, `|` or `&&`
+const COMPLETION_MAX_LINES: usize = 5;
+
+static HISTORY_COUNT: LazyLock<usize> = LazyLock::new(|| {
+    std::env::var("Q_INLINE_SHELL_COMPLETION_HISTORY_COUNT")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(HISTORY_COUNT_DEFAULT)
+});
+
+/// Requests suggestions from CodeWhisperer, with the history formatted as a shell script
+#[derive(Debug)]
+pub struct CodeWhispererProvider {
+    client: Client,
+}
+
+impl CodeWhispererProvider {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl InlineProvider for CodeWhispererProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::CodeWhisperer
+    }
+
+    fn history_limit(&self) -> usize {
+        *HISTORY_COUNT * HISTORY_QUERY_FACTOR
+    }
+
+    async fn suggestions(&self, request: SuggestionRequest<'_>) -> Result<Suggestions, ProviderError> {
+        let Some((prompt, buffer_line)) = prompt(request.context, request.history, request.buffer) else {
+            return Ok(Suggestions::default());
+        };
+
+        let input = RecommendationsInput {
+            file_context: FileContext {
+                left_file_content: prompt,
+                right_file_content: "".into(),
+                filename: "history.sh".into(),
+                programming_language: ProgrammingLanguage {
+                    language_name: LanguageName::Shell,
+                },
+            },
+            max_results: 1,
+            next_token: None,
+        };
+
+        match self.client.generate_recommendations(input).await {
+            Ok(output) => Ok(Suggestions {
+                completions: output
+                    .recommendations
+                    .into_iter()
+                    .map(|choice| clean_completion(&choice.content, buffer_line))
+                    .collect(),
+                service_ids: Some(ServiceIds {
+                    request_id: output.request_id.unwrap_or_default(),
+                    session_id: output.session_id.unwrap_or_default(),
+                }),
+            }),
+            Err(err) if err.is_throttling_error() => Err(ProviderError::Throttled(err.into())),
+            Err(err) => Err(ProviderError::Other(err.into())),
+        }
+    }
+}
+
+/// Builds the prompt from the context and the history, which is expected from the most recent
+/// command, and returns it along with the number of the line of the buffer.
+///
+/// Commands run in the same directory or repository are preferred, and failed commands are
+/// annotated with their exit code. The oldest commands are left out to fit the maximum length.
+fn prompt(context: &PromptContext, history: &[CommandInfo], buffer: &str) -> Option<(String, usize)> {
+    let history = history.iter().filter(|info| info.command.is_some());
+    let mut commands = history
+        .clone()
+        .filter(|info| context.is_relevant(info))
+        .take(*HISTORY_COUNT)
+        .collect::<Vec<_>>();
+    if commands.is_empty() {
+        commands = history.take(*HISTORY_COUNT).collect();
+    }
+
+    let header = context.header();
+    for len in (1..=commands.len()).rev() {
+        let mut formatted_prompt = header.clone();
+        for (i, info) in commands[..len].iter().rev().enumerate() {
+            let _ = write!(
+                formatted_prompt,
+                "{:>5}  {}",
+                i + 1,
+                info.command.as_deref().unwrap_or_default()
+            );
+            if let Some(exit_code) = info.exit_code.filter(|exit_code| *exit_code != 0) {
+                let _ = write!(formatted_prompt, "  # exit {exit_code}");
+            }
+            formatted_prompt.push('\n');
+        }
+        let _ = write!(formatted_prompt, "{:>5}  {buffer}", len + 1);
+
+        if formatted_prompt.len() < FILE_CONTEXT_LEFT_FILE_CONTENT_MAX_LEN {
+            return Some((formatted_prompt, len + 1));
+        }
+    }
+    None
+}
+
+/// Exit code annotations of the prompt the model may have repeated
+static EXIT_CODE_COMMENT: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s+# exit \d+\s*$").unwrap());
+/// Lines of the history in the format of the prompt
+static HISTORY_LINE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\s*\d+\s{2}").unwrap());
+/// Line numbers in the format of the prompt, padded and followed by two spaces
+static LINE_NUMBER: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\s{2,}(\d+)\s{2}").unwrap());
+
+/// Whether the command continues on the next line
+fn continues(command: &str) -> bool {
+    let command = command.trim_end();
+    command.ends_with('\\') || command.ends_with('|') || command.ends_with("&&")
+}
+
+/// Where the model continued with the next lines of the prompt without a newline, if it did
+fn next_line_start(line: &str, buffer_line: usize) -> Option<usize> {
+    LINE_NUMBER
+        .captures_iter(line)
+        .find(|captures| {
+            captures[1]
+                .parse::<usize>()
+                .is_ok_and(|number| number == buffer_line + 1 || number == buffer_line + 2)
+        })
+        .and_then(|captures| captures.get(0))
+        .map(|m| m.start())
+}
+
+fn clean_completion(response: &str, buffer_line: usize) -> String {
+    // only keep the following lines of the response when the command continues on them
+    let mut completion = String::new();
+    for (i, line) in response.lines().take(COMPLETION_MAX_LINES).enumerate() {
+        if i > 0 {
+            if !continues(&completion) || line.trim().is_empty() || HISTORY_LINE.is_match(line) {
+                break;
+            }
+            completion.push('\n');
+        }
+
+        let next_line = next_line_start(line, buffer_line);
+        let line = next_line.map_or(line, |start| &line[..start]);
+        completion.push_str(EXIT_CODE_COMMENT.replace(line, "").trim_end());
+        if next_line.is_some() {
+            break;
+        }
+    }
+
+    // trim any remaining whitespace, and continuations of lines that were not kept
+    completion.trim_end().trim_end_matches('\\').trim_end().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use fig_settings::history::{
+        HistoryColumn,
+        Order,
+        OrderBy,
+        WhereExpression,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_prompt() {
+        let history = vec![
+            CommandInfo {
+                command: Some("echo world".into()),
+                ..Default::default()
+            },
+            CommandInfo {
+                command: Some("echo hello".into()),
+                ..Default::default()
+            },
+        ];
+
+        let (prompt, buffer_line) = prompt(&PromptContext::default(), &history, "echo ").unwrap();
+        println!("{prompt}");
+
+        assert_eq!(prompt, "    1  echo hello\n    2  echo world\n    3  echo ");
+        assert_eq!(buffer_line, 3);
+    }
+
+    #[test]
+    fn test_prompt_with_context() {
+        let command = |command: &str, cwd: &str, exit_code: i32| CommandInfo {
+            command: Some(command.into()),
+            cwd: Some(cwd.into()),
+            exit_code: Some(exit_code),
+            ..Default::default()
+        };
+        let history = vec![
+            command("cargo build", "/project", 101),
+            command("ls ~", "/home", 0),
+            command("cargo fmt", "/project", 0),
+        ];
+        let context = PromptContext {
+            cwd: Some("/project".into()),
+            git_branch: Some("main".into()),
+            project_types: vec!["rust"],
+            files: vec!["Cargo.toml".into(), "src/".into()],
+            ..Default::default()
+        };
+
+        let (prompt, buffer_line) = prompt(&context, &history, "cargo ").unwrap();
+
+        assert_eq!(
+            prompt,
+            "# Directory: /project\n# Git branch: main\n# Project: rust\n# Files: Cargo.toml src/\n    1  cargo fmt\n    2  cargo build  # exit 101\n    3  cargo "
+        );
+        assert_eq!(buffer_line, 3);
+    }
+
+    #[test]
+    fn test_clean_completion() {
+        let clean_completion_49 = |response| clean_completion(response, 49);
+
+        assert_eq!(clean_completion_49("echo hello"), "echo hello");
+        assert_eq!(clean_completion_49("echo hello\necho world"), "echo hello");
+        assert_eq!(clean_completion_49("echo hello   \necho world\n"), "echo hello");
+        assert_eq!(clean_completion_49("echo hello     "), "echo hello");
+
+        // Trim potential excess lines from the model
+        assert_eq!(clean_completion_49("cd           50      ls"), "cd");
+        assert_eq!(
+            clean_completion_49("git add     50  git commit -m \"initial commit\""),
+            "git add"
+        );
+        assert_eq!(clean_completion_49("cd           51      ls"), "cd");
+        assert_eq!(
+            clean_completion_49("git add     51  git commit -m \"initial commit\""),
+            "git add"
+        );
+
+        // Keep the lines the command continues on
+        assert_eq!(
+            clean_completion_49("build \\\n  --release\necho done"),
+            "build \\\n  --release"
+        );
+        assert_eq!(clean_completion_49("log |\n  grep fix\n   51  ls"), "log |\n  grep fix");
+        assert_eq!(clean_completion_49("build \\\n   51  ls"), "build");
+        assert_eq!(clean_completion_49("build &&\n\necho done"), "build &&");
+
+        // Remove exit code annotations
+        assert_eq!(clean_completion_49("test  # exit 101"), "test");
+
+        // Only line numbers in the format of the prompt are trimmed
+        assert_eq!(clean_completion("4 log.txt", 3), "4 log.txt");
+        assert_eq!(
+            clean_completion("head -n 5 log.txt | sort", 3),
+            "head -n 5 log.txt | sort"
+        );
+        assert_eq!(clean_completion("4 log.txt      4  ls", 3), "4 log.txt");
+        assert_eq!(clean_completion("4 log.txt      5  ls", 3), "4 log.txt");
+        assert_eq!(clean_completion("4 log.txt      6  ls", 3), "4 log.txt      6  ls");
+    }
+
+    #[test]
+    fn too_long_prompt() {
+        let history = vec![CommandInfo {
+            command: Some("a".repeat(FILE_CONTEXT_LEFT_FILE_CONTENT_MAX_LEN + 1)),
+            ..Default::default()
+        }];
+
+        assert!(prompt(&PromptContext::default(), &history, "echo ").is_none());
+    }
+
+    #[ignore = "not in CI"]
+    #[tokio::test]
+    async fn test_inline_suggestion_prompt() {
+        let history = fig_settings::history::History::new();
+        let commands = history
+            .rows(
+                Some(WhereExpression::NotNull(HistoryColumn::ExitCode)),
+                vec![OrderBy::new(HistoryColumn::Id, Order::Desc)],
+                *HISTORY_COUNT,
+                0,
+            )
+            .unwrap();
+        let (prompt, _) = prompt(&PromptContext::default(), &commands, "cd ").unwrap();
+
+        let client = fig_api_client::Client::new().await.unwrap();
+        let out = client
+            .generate_recommendations(RecommendationsInput {
+                file_context: FileContext {
+                    left_file_content: prompt,
+                    right_file_content: "".into(),
+                    filename: "history.sh".into(),
+                    programming_language: ProgrammingLanguage {
+                        language_name: LanguageName::Shell,
+                    },
+                },
+                max_results: 1,
+                next_token: None,
+            })
+            .await
+            .unwrap();
+
+        println!("out: {out:?}");
+    }
+}
@@ -3,9 +3,13 @@ use radix_trie::{
     TrieCommon,
 };
 
+use super::provider::ProviderKind;
+
 #[derive(Debug, Clone, Default)]
 pub struct CompletionCache {
     trie: Trie<String, f64>,
+    /// The provider of the cached completions
+    provider: ProviderKind,
 }
 
 impl CompletionCache {
@@ -17,6 +21,14 @@ impl CompletionCache {
         self.trie = Trie::new();
     }
 
+    /// Clears the cache when the provider changed, so completions of another provider are not used
+    pub fn set_provider(&mut self, provider: ProviderKind) {
+        if self.provider != provider {
+            self.clear();
+            self.provider = provider;
+        }
+    }
+
     pub fn get_insert_text(&self, buffer: &str) -> Option<&str> {
         self.trie.get_raw_descendant(buffer).and_then(|descendant| {
             descendant
@@ -71,4 +83,15 @@ mod tests {
 
         assert_eq!(cache.get_insert_text("other"), None);
     }
+
+    #[test]
+    fn test_set_provider() {
+        let mut cache = mock_cache();
+
+        cache.set_provider(ProviderKind::default());
+        assert_eq!(cache.get_insert_text("l"), Some("ls"));
+
+        cache.set_provider(ProviderKind::Local);
+        assert_eq!(cache.get_insert_text("l"), None);
+    }
 }
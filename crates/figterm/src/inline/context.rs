@@ -0,0 +1,182 @@
+use std::fmt::Write;
+use std::path::{
+    Path,
+    PathBuf,
+};
+
+use fig_settings::history::CommandInfo;
+
+/// Maximum number of entries of the directory listed in the prompt
+const FILES_MAX: usize = 30;
+
+/// Files that identify the type of a project, and the type they identify
+const PROJECT_MARKERS: &[(&str, &str)] = &[
+    ("Cargo.toml", "rust"),
+    ("package.json", "node"),
+    ("pyproject.toml", "python"),
+    ("requirements.txt", "python"),
+    ("setup.py", "python"),
+    ("go.mod", "go"),
+    ("pom.xml", "maven"),
+    ("build.gradle", "gradle"),
+    ("build.gradle.kts", "gradle"),
+    ("Gemfile", "ruby"),
+    ("composer.json", "php"),
+    ("Package.swift", "swift"),
+    ("CMakeLists.txt", "cmake"),
+    ("Makefile", "make"),
+    ("Dockerfile", "docker"),
+    ("docker-compose.yml", "docker compose"),
+    ("cdk.json", "aws cdk"),
+    ("main.tf", "terraform"),
+];
+
+/// What is known about the shell a completion is requested in, besides its history
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PromptContext {
+    /// The working directory of the shell
+    pub cwd: Option<PathBuf>,
+    /// The root of the git repository the working directory is in
+    pub repo_root: Option<PathBuf>,
+    /// The checked out branch, or the abbreviated commit when the HEAD is detached
+    pub git_branch: Option<String>,
+    /// The types of the project, detected in the working directory and the repository root
+    pub project_types: Vec<&'static str>,
+    /// Some of the entries of the working directory, directories ending with a `/`
+    pub files: Vec<String>,
+}
+
+impl PromptContext {
+    pub fn new(cwd: Option<PathBuf>) -> Self {
+        let Some(cwd) = cwd else {
+            return Self::default();
+        };
+
+        let repo_root = cwd
+            .ancestors()
+            .find(|dir| dir.join(".git").exists())
+            .map(Path::to_path_buf);
+        let git_branch = repo_root.as_deref().and_then(git_branch);
+
+        let mut project_types = Vec::new();
+        for dir in [Some(cwd.as_path()), repo_root.as_deref()].into_iter().flatten() {
+            for (marker, project_type) in PROJECT_MARKERS {
+                if dir.join(marker).is_file() && !project_types.contains(project_type) {
+                    project_types.push(*project_type);
+                }
+            }
+        }
+
+        let mut files = std::fs::read_dir(&cwd)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .filter_map(|entry| {
+                        let name = entry.file_name().into_string().ok()?;
+                        match entry.file_type() {
+                            Ok(file_type) if file_type.is_dir() => Some(format!("{name}/")),
+                            _ => Some(name),
+                        }
+                    })
+                    .filter(|name| !name.starts_with('.'))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files.truncate(FILES_MAX);
+
+        Self {
+            cwd: Some(cwd),
+            repo_root,
+            git_branch,
+            project_types,
+            files,
+        }
+    }
+
+    /// Whether the command was run in the working directory, or in the same repository
+    pub fn is_relevant(&self, command: &CommandInfo) -> bool {
+        let Some(cwd) = &self.cwd else {
+            return true;
+        };
+        let Some(command_cwd) = command.cwd.as_deref().map(Path::new) else {
+            return false;
+        };
+
+        command_cwd == cwd
+            || self
+                .repo_root
+                .as_deref()
+                .is_some_and(|root| command_cwd.starts_with(root))
+    }
+
+    /// The lines describing the context at the top of the prompt, as shell comments
+    pub fn header(&self) -> String {
+        let mut header = String::new();
+        if let Some(cwd) = &self.cwd {
+            let _ = writeln!(header, "# Directory: {}", cwd.display());
+        }
+        if let Some(git_branch) = &self.git_branch {
+            let _ = writeln!(header, "# Git branch: {git_branch}");
+        }
+        if !self.project_types.is_empty() {
+            let _ = writeln!(header, "# Project: {}", self.project_types.join(", "));
+        }
+        if !self.files.is_empty() {
+            let _ = writeln!(header, "# Files: {}", self.files.join(" "));
+        }
+        header
+    }
+}
+
+/// Reads the branch checked out in the repository, following the `.git` file of worktrees
+fn git_branch(repo_root: &Path) -> Option<String> {
+    let mut git_dir = repo_root.join(".git");
+    if git_dir.is_file() {
+        let content = std::fs::read_to_string(&git_dir).ok()?;
+        git_dir = repo_root.join(content.strip_prefix("gitdir:")?.trim());
+    }
+
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    match head.strip_prefix("ref: ") {
+        Some(reference) => Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_owned()),
+        None => head.get(..7).map(ToOwned::to_owned),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_context() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let repo = tempdir.path().join("repo");
+        let cwd = repo.join("crate");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(cwd.join("src")).unwrap();
+        std::fs::write(repo.join(".git").join("HEAD"), "ref: refs/heads/feature/inline\n").unwrap();
+        std::fs::write(repo.join("Makefile"), "").unwrap();
+        std::fs::write(cwd.join("Cargo.toml"), "").unwrap();
+        std::fs::write(cwd.join(".hidden"), "").unwrap();
+
+        let context = PromptContext::new(Some(cwd.clone()));
+        assert_eq!(context.repo_root.as_deref(), Some(repo.as_path()));
+        assert_eq!(context.git_branch.as_deref(), Some("feature/inline"));
+        assert_eq!(context.project_types, vec!["rust", "make"]);
+        assert_eq!(context.files, vec!["Cargo.toml", "src/"]);
+
+        let command = |cwd: &Path| CommandInfo {
+            command: Some("ls".into()),
+            cwd: Some(cwd.display().to_string()),
+            ..Default::default()
+        };
+        assert!(context.is_relevant(&command(&cwd)));
+        assert!(context.is_relevant(&command(&repo)));
+        assert!(!context.is_relevant(&command(tempdir.path())));
+
+        std::fs::write(repo.join(".git").join("HEAD"), "0123456789abcdef\n").unwrap();
+        assert_eq!(git_branch(&repo).as_deref(), Some("0123456"));
+    }
+}
@@ -0,0 +1,246 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use fig_request::reqwest::redirect::Policy;
+use fig_request::reqwest::{
+    Client,
+    StatusCode,
+    Url,
+};
+use fig_settings::keys::check_inline_provider_endpoint;
+use serde::{
+    Deserialize,
+    Serialize,
+};
+
+use super::provider::{
+    InlineProvider,
+    ProviderError,
+    ProviderKind,
+    SuggestionRequest,
+    Suggestions,
+};
+
+/// Suggestions arriving after this are not useful anymore
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// Maximum number of commands of the history sent to the endpoint
+const HISTORY_MAX: usize = 100;
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    buffer: &'a str,
+    cwd: Option<String>,
+    git_branch: Option<&'a str>,
+    project_types: &'a [&'static str],
+    files: &'a [String],
+    /// From the most recent command
+    history: Vec<Command<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Command<'a> {
+    command: &'a str,
+    cwd: Option<&'a str>,
+    exit_code: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    /// Full commands starting with the buffer, from the best one
+    suggestions: Vec<String>,
+}
+
+/// Requests suggestions from an endpoint on this machine, e.g. a model run locally.
+///
+/// The endpoint is sent a JSON `POST` request with the buffer, the context and the history, and
+/// responds with `{"suggestions": ["full command", ...]}`.
+#[derive(Debug)]
+pub struct HttpProvider {
+    endpoint: Url,
+    client: Client,
+}
+
+impl HttpProvider {
+    /// Fails unless the endpoint passes [`check_inline_provider_endpoint`]
+    pub fn new(endpoint: Url) -> anyhow::Result<Self> {
+        check_inline_provider_endpoint(&endpoint).map_err(anyhow::Error::msg)?;
+
+        // The system proxy is not used and redirects are not followed, as either could lead to
+        // another machine
+        let client = Client::builder()
+            .no_proxy()
+            .redirect(Policy::none())
+            .timeout(TIMEOUT)
+            .build()?;
+        Ok(Self { endpoint, client })
+    }
+}
+
+#[async_trait]
+impl InlineProvider for HttpProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Http
+    }
+
+    fn history_limit(&self) -> usize {
+        HISTORY_MAX
+    }
+
+    async fn suggestions(&self, request: SuggestionRequest<'_>) -> Result<Suggestions, ProviderError> {
+        let SuggestionRequest {
+            buffer,
+            history,
+            context,
+        } = request;
+
+        let body = Request {
+            buffer,
+            cwd: context.cwd.as_ref().map(|cwd| cwd.display().to_string()),
+            git_branch: context.git_branch.as_deref(),
+            project_types: &context.project_types,
+            files: &context.files,
+            history: history
+                .iter()
+                .filter_map(|info| {
+                    Some(Command {
+                        command: info.command.as_deref()?,
+                        cwd: info.cwd.as_deref(),
+                        exit_code: info.exit_code,
+                    })
+                })
+                .take(HISTORY_MAX)
+                .collect(),
+        };
+
+        let response = self
+            .client
+            .post(self.endpoint.clone())
+            .json(&body)
+            .send()
+            .await
+            .map_err(|err| ProviderError::Other(err.into()))?;
+
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            return Err(ProviderError::Throttled(anyhow::anyhow!(
+                "{} responded with 429",
+                self.endpoint
+            )));
+        }
+
+        let response: Response = response
+            .error_for_status()
+            .map_err(|err| ProviderError::Other(err.into()))?
+            .json()
+            .await
+            .context("invalid response")
+            .map_err(ProviderError::Other)?;
+
+        Ok(Suggestions {
+            completions: response
+                .suggestions
+                .iter()
+                .filter_map(|suggestion| suggestion.strip_prefix(buffer))
+                .map(|completion| completion.trim_end().to_owned())
+                .collect(),
+            service_ids: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::sync::atomic::{
+        AtomicBool,
+        Ordering,
+    };
+
+    use tokio::io::{
+        AsyncReadExt,
+        AsyncWriteExt,
+    };
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::inline::context::PromptContext;
+
+    /// Serves `response` to every request on a local port, and records whether it was requested
+    async fn serve(response: String) -> (SocketAddr, Arc<AtomicBool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requested = Arc::new(AtomicBool::new(false));
+        tokio::spawn({
+            let requested = Arc::clone(&requested);
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    requested.store(true, Ordering::SeqCst);
+                    // read the whole request before responding
+                    let mut request = Vec::new();
+                    let mut buf = [0; 4096];
+                    while let Ok(n @ 1..) = stream.read(&mut buf).await {
+                        request.extend_from_slice(&buf[..n]);
+                        let text = String::from_utf8_lossy(&request);
+                        if let Some((headers, body)) = text.split_once("\r\n\r\n") {
+                            let content_length = headers
+                                .lines()
+                                .filter_map(|line| line.split_once(':'))
+                                .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+                                .and_then(|(_, value)| value.trim().parse().ok())
+                                .unwrap_or(0);
+                            if body.len() >= content_length {
+                                break;
+                            }
+                        }
+                    }
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            }
+        });
+        (addr, requested)
+    }
+
+    #[test]
+    fn test_http_provider_is_local() {
+        let new = |endpoint: &str| HttpProvider::new(endpoint.parse().unwrap());
+
+        assert!(new("http://localhost:8080/suggest").is_ok());
+        assert!(new("http://127.0.0.1:8080").is_ok());
+        assert!(new("https://[::1]:8443/suggest").is_ok());
+
+        assert!(new("https://example.com/suggest").is_err());
+        assert!(new("http://10.0.0.1:8080").is_err());
+        assert!(new("http://localhost.example.com").is_err());
+        assert!(new("ftp://localhost").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_provider_does_not_follow_redirects() {
+        let body = r#"{"suggestions": ["git status"]}"#;
+        let (target, target_requested) = serve(format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ))
+        .await;
+        let (endpoint, _) = serve(format!(
+            "HTTP/1.1 307 Temporary Redirect\r\nLocation: http://{target}/\r\nContent-Length: 0\r\n\r\n"
+        ))
+        .await;
+
+        let request = SuggestionRequest {
+            buffer: "git ",
+            history: &[],
+            context: &PromptContext::default(),
+        };
+
+        let provider = HttpProvider::new(format!("http://{target}/").parse().unwrap()).unwrap();
+        let suggestions = provider.suggestions(request).await.unwrap();
+        assert_eq!(suggestions.completions, vec!["status"]);
+        target_requested.store(false, Ordering::SeqCst);
+
+        let provider = HttpProvider::new(format!("http://{endpoint}/").parse().unwrap()).unwrap();
+        assert!(provider.suggestions(request).await.is_err());
+        assert!(!target_requested.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use fig_settings::history::CommandInfo;
+
+use super::context::PromptContext;
+use super::provider::{
+    InlineProvider,
+    ProviderError,
+    ProviderKind,
+    SuggestionRequest,
+    Suggestions,
+};
+
+/// Number of commands after which a past run of a command counts half as much
+const RECENCY_HALF_LIFE: f64 = 100.0;
+/// How much more a command run in the same directory or repository counts
+const RELEVANT_WEIGHT: f64 = 2.0;
+/// How much more a command counts when it was run after the same command as the last one
+const SEQUENCE_WEIGHT: f64 = 4.0;
+/// Maximum number of suggestions, the ones after the first are cached
+const SUGGESTIONS_MAX: usize = 5;
+/// Number of commands of the history the suggestions are ranked from
+const HISTORY_MAX: usize = 1000;
+
+/// Suggests past commands from the history
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalProvider;
+
+#[async_trait]
+impl InlineProvider for LocalProvider {
+    fn kind(&self) -> ProviderKind {
+        ProviderKind::Local
+    }
+
+    fn history_limit(&self) -> usize {
+        HISTORY_MAX
+    }
+
+    async fn suggestions(&self, request: SuggestionRequest<'_>) -> Result<Suggestions, ProviderError> {
+        Ok(suggestions(request))
+    }
+}
+
+/// The suggestions of the [`LocalProvider`], which never fails
+pub(super) fn suggestions(request: SuggestionRequest<'_>) -> Suggestions {
+    let SuggestionRequest {
+        buffer,
+        history,
+        context,
+    } = request;
+
+    Suggestions {
+        completions: rank(history, buffer, context)
+            .into_iter()
+            .take(SUGGESTIONS_MAX)
+            .filter_map(|command| command.strip_prefix(buffer).map(ToOwned::to_owned))
+            .collect(),
+        service_ids: None,
+    }
+}
+
+/// Ranks the past successful commands starting with the buffer, from the best one.
+///
+/// The history is expected from the most recent command. Each run of a command adds to its score,
+/// decaying with how long ago it was run, so commands run often and recently rank first. Runs
+/// that followed the same command as the last one count more, so sequences such as `git add`
+/// then `git commit` are learned.
+fn rank<'a>(history: &'a [CommandInfo], buffer: &str, context: &PromptContext) -> Vec<&'a str> {
+    let last_command = history.first().and_then(|info| info.command.as_deref());
+    let mut scores: HashMap<&str, (f64, usize)> = HashMap::new();
+
+    for (index, info) in history.iter().enumerate() {
+        let Some(command) = info.command.as_deref().map(str::trim_end) else {
+            continue;
+        };
+        if info.exit_code != Some(0) || command.len() <= buffer.len() || !command.starts_with(buffer) {
+            continue;
+        }
+
+        let mut score = 0.5_f64.powf(index as f64 / RECENCY_HALF_LIFE);
+        if context.is_relevant(info) {
+            score *= RELEVANT_WEIGHT;
+        }
+        let previous_command = history.get(index + 1).and_then(|info| info.command.as_deref());
+        if last_command.is_some() && previous_command == last_command {
+            score *= SEQUENCE_WEIGHT;
+        }
+
+        scores.entry(command).or_insert((0.0, index)).0 += score;
+    }
+
+    let mut ranked = scores.into_iter().collect::<Vec<_>>();
+    ranked.sort_by(|(_, (a, a_index)), (_, (b, b_index))| b.total_cmp(a).then(a_index.cmp(b_index)));
+    ranked.into_iter().map(|(command, _)| command).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command: &str, cwd: &str, exit_code: i32) -> CommandInfo {
+        CommandInfo {
+            command: Some(command.into()),
+            cwd: Some(cwd.into()),
+            exit_code: Some(exit_code),
+            ..Default::default()
+        }
+    }
+
+    fn suggest<'a>(history: &'a [CommandInfo], buffer: &str, context: &PromptContext) -> Option<&'a str> {
+        rank(history, buffer, context).first().copied()
+    }
+
+    #[test]
+    fn test_rank() {
+        let context = PromptContext {
+            cwd: Some("/project".into()),
+            ..Default::default()
+        };
+        let history = vec![
+            command("git push --force", "/project", 1),
+            command("git status", "/other", 0),
+            command("git commit -m 'fix'", "/project", 0),
+            command("git log", "/project", 0),
+            command("git log", "/project", 0),
+            command("ls", "/project", 0),
+        ];
+
+        // Frequency wins over recency
+        assert_eq!(suggest(&history, "git ", &context), Some("git log"));
+        assert_eq!(suggest(&history, "git s", &context), Some("git status"));
+        // The same directory wins over recency
+        assert_eq!(suggest(&history[..3], "git ", &context), Some("git commit -m 'fix'"));
+        // Failed commands are never suggested
+        assert_eq!(suggest(&history, "git p", &context), None);
+        // Only longer commands complete the buffer
+        assert_eq!(suggest(&history, "ls", &context), None);
+        assert_eq!(suggest(&history, "cd", &context), None);
+
+        assert_eq!(rank(&history, "git ", &context), vec![
+            "git log",
+            "git commit -m 'fix'",
+            "git status"
+        ]);
+    }
+
+    #[test]
+    fn test_rank_sequence() {
+        let context = PromptContext::default();
+        let history = vec![
+            command("git add .", "/project", 0),
+            command("git push", "/project", 0),
+            command("git commit -m 'fix'", "/project", 0),
+            command("git add .", "/project", 0),
+            command("git status", "/project", 0),
+            command("git status", "/project", 0),
+            command("git status", "/project", 0),
+        ];
+
+        // The command run after the last one wins over more frequent ones
+        assert_eq!(suggest(&history, "git c", &context), Some("git commit -m 'fix'"));
+        assert_eq!(suggest(&history, "git s", &context), Some("git status"));
+        assert_eq!(suggest(&history[1..], "git ", &context), Some("git status"));
+        assert_eq!(suggest(&history, "git ", &context), Some("git commit -m 'fix'"));
+
+        let suggestions = suggestions(SuggestionRequest {
+            buffer: "git ",
+            history: &history,
+            context: &context,
+        });
+        assert_eq!(suggestions.completions, vec![
+            "commit -m 'fix'",
+            "status",
+            "add .",
+            "push"
+        ]);
+    }
+}
@@ -1,22 +1,22 @@
+mod codewhisperer;
 mod completion_cache;
+mod context;
+mod http;
+mod local;
+mod provider;
 mod validate;
 
-use std::fmt::Write;
-use std::sync::LazyLock;
+use std::path::PathBuf;
+use std::sync::{
+    Arc,
+    LazyLock,
+};
 use std::time::{
     Duration,
     Instant,
     SystemTime,
 };
 
-use fig_api_client::Client;
-use fig_api_client::clients::FILE_CONTEXT_LEFT_FILE_CONTENT_MAX_LEN;
-use fig_api_client::model::{
-    FileContext,
-    LanguageName,
-    ProgrammingLanguage,
-    RecommendationsInput,
-};
 use fig_proto::figterm::figterm_response_message::Response as FigtermResponse;
 use fig_proto::figterm::{
     FigtermResponseMessage,
@@ -36,7 +36,6 @@ use fig_util::terminal::{
     current_terminal_version,
 };
 use flume::Sender;
-use regex::Regex;
 use tokio::sync::Mutex;
 use tracing::{
     error,
@@ -46,13 +45,21 @@ use tracing::{
 use validate::validate;
 
 use self::completion_cache::CompletionCache;
+use self::context::PromptContext;
+use self::provider::{
+    InlineProvider,
+    ProviderError,
+    ProviderKind,
+    ServiceIds,
+    SuggestionRequest,
+    Suggestions,
+};
 use crate::history::{
     self,
     HistoryQueryParams,
     HistorySender,
 };
 
-const HISTORY_COUNT_DEFAULT: usize = 49;
 const DEBOUNCE_DURATION_DEFAULT: Duration = Duration::from_millis(300);
 
 static INLINE_ENABLED: Mutex<bool> = Mutex::const_new(true);
@@ -62,15 +69,15 @@ static LAST_RECEIVED: Mutex<Option<SystemTime>> = Mutex::const_new(None);
 static CACHE_ENABLED: LazyLock<bool> =
     LazyLock::new(|| std::env::var_os("Q_INLINE_SHELL_COMPLETION_CACHE_DISABLE").is_none());
 static COMPLETION_CACHE: LazyLock<Mutex<CompletionCache>> = LazyLock::new(|| Mutex::new(CompletionCache::new()));
+/// The context of the current prompt, only read again when the working directory changes or a
+/// command is run since reading it blocks on the file system
+static PROMPT_CONTEXT: Mutex<Option<PromptContext>> = Mutex::const_new(None);
+/// The provider of the previous request along with the kind and endpoint it was created for, so
+/// that its client and connections are reused until the settings change
+static PROVIDER: Mutex<Option<(ProviderKind, Option<String>, Arc<dyn InlineProvider>)>> = Mutex::const_new(None);
 
 static TELEMETRY_QUEUE: Mutex<TelemetryQueue> = Mutex::const_new(TelemetryQueue::new());
 
-static HISTORY_COUNT: LazyLock<usize> = LazyLock::new(|| {
-    std::env::var("Q_INLINE_SHELL_COMPLETION_HISTORY_COUNT")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(HISTORY_COUNT_DEFAULT)
-});
 static DEBOUNCE_DURATION: LazyLock<Duration> = LazyLock::new(|| {
     std::env::var("Q_INLINE_SHELL_COMPLETION_DEBOUNCE_MS")
         .ok()
@@ -80,6 +87,7 @@ static DEBOUNCE_DURATION: LazyLock<Duration> = LazyLock::new(|| {
 
 pub async fn on_prompt() {
     COMPLETION_CACHE.lock().await.clear();
+    PROMPT_CONTEXT.lock().await.take();
     TELEMETRY_QUEUE.lock().await.send_all_items(None).await;
 }
 
@@ -112,6 +120,7 @@ impl TelemetryQueue {
                 suggested_chars_len,
                 number_of_recommendations,
                 latency,
+                shell,
                 ..
             } = item;
 
@@ -129,8 +138,7 @@ impl TelemetryQueue {
                         latency,
                         terminal: current_terminal().map(|s| s.internal_id().into_owned()),
                         terminal_version: current_terminal_version().map(Into::into),
-                        // The only supported shell currently is Zsh
-                        shell: Some(Shell::Zsh.as_str().into()),
+                        shell: shell.map(|shell| shell.as_str().into()),
                         shell_version: None,
                     },
                 })
@@ -157,11 +165,14 @@ struct TelemetryQueueItem {
     suggested_chars_len: i32,
     number_of_recommendations: i32,
     latency: Duration,
+    shell: Option<Shell>,
 }
 
 pub async fn handle_request(
     figterm_request: InlineShellCompletionRequest,
     _session_id: String,
+    shell: Option<Shell>,
+    cwd: Option<PathBuf>,
     response_tx: Sender<FigtermResponseMessage>,
     history_sender: HistorySender,
 ) {
@@ -170,10 +181,14 @@ pub async fn handle_request(
     }
 
     let buffer = figterm_request.buffer.trim_start();
+    let provider_kind = ProviderKind::from_settings();
 
     if *CACHE_ENABLED {
+        let mut completion_cache = COMPLETION_CACHE.lock().await;
+        completion_cache.set_provider(provider_kind);
+
         // use cached completion if available
-        if let Some(insert_text) = COMPLETION_CACHE.lock().await.get_insert_text(buffer) {
+        if let Some(insert_text) = completion_cache.get_insert_text(buffer) {
             let trimmed_insert = insert_text.strip_prefix(buffer).unwrap_or(insert_text);
 
             if let Err(err) = response_tx
@@ -194,9 +209,7 @@ pub async fn handle_request(
     let now = SystemTime::now();
     LAST_RECEIVED.lock().await.replace(now);
 
-    let Ok(client) = Client::new().await else {
-        return;
-    };
+    let provider = provider(provider_kind).await;
 
     for _ in 0..3 {
         tokio::time::sleep(*DEBOUNCE_DURATION).await;
@@ -219,92 +232,65 @@ pub async fn handle_request(
             return;
         }
 
-        info!("Sending inline_shell_completion completion request");
-
-        let (history_query_tx, history_query_rx) = flume::bounded(1);
-        if let Err(err) = history_sender
-            .send_async(history::HistoryCommand::Query(
-                HistoryQueryParams { limit: *HISTORY_COUNT },
-                history_query_tx,
-            ))
-            .await
-        {
-            error!(%err, "Failed to send history query");
-        }
-
-        let history = match history_query_rx.recv_async().await {
-            Ok(Some(history)) => history,
-            err => {
-                error!(?err, "Failed to get history");
-                vec![]
-            },
-        };
+        info!(provider = %provider.kind(), "Sending inline_shell_completion completion request");
 
-        let Some(prompt) = prompt(&history, buffer) else {
-            return;
-        };
-
-        let input = RecommendationsInput {
-            file_context: FileContext {
-                left_file_content: prompt,
-                right_file_content: "".into(),
-                filename: "history.sh".into(),
-                programming_language: ProgrammingLanguage {
-                    language_name: LanguageName::Shell,
-                },
-            },
-            max_results: 1,
-            next_token: None,
+        let context = prompt_context(cwd.clone()).await;
+        let history = query_history(&history_sender, provider.history_limit()).await;
+        let request = SuggestionRequest {
+            buffer,
+            history: &history,
+            context: &context,
         };
 
         let start_instant = Instant::now();
 
-        let response = match client.generate_recommendations(input).await {
-            Err(err) if err.is_throttling_error() => {
+        let suggestions = match provider.suggestions(request).await {
+            Ok(suggestions) => suggestions,
+            Err(ProviderError::Throttled(err)) => {
                 warn!(%err, "Too many requests, trying again in 1 second");
                 tokio::time::sleep(Duration::from_secs(1).saturating_sub(*DEBOUNCE_DURATION)).await;
                 continue;
             },
-            other => other,
+            Err(err) => {
+                error!(%err, "Failed to get inline_shell_completion completion, falling back to the history");
+                local::suggestions(request)
+            },
         };
 
-        let insert_text = match response {
-            Ok(output) => {
-                let request_id = output.request_id.unwrap_or_default();
-                let session_id = output.session_id.unwrap_or_default();
-                let recommendations = output.recommendations;
-                let number_of_recommendations = recommendations.len() as i32;
-                let mut completion_cache = COMPLETION_CACHE.lock().await;
-
-                let mut completions = recommendations
-                    .into_iter()
-                    .map(|choice| clean_completion(&choice.content).clone())
-                    .collect::<Vec<_>>();
-
-                // skips the first one which we will recommend, we only cache the rest
-                for completion in completions.iter().skip(1) {
-                    let full_text = format!("{buffer}{completion}");
-                    if !completion.is_empty() && validate(&full_text) {
-                        completion_cache.insert(full_text, 1.0);
-                    }
+        let insert_text = {
+            let Suggestions {
+                mut completions,
+                service_ids,
+            } = suggestions;
+            let number_of_recommendations = completions.len() as i32;
+            let mut completion_cache = COMPLETION_CACHE.lock().await;
+
+            // skips the first one which we will recommend, we only cache the rest
+            for completion in completions.iter().skip(1) {
+                let full_text = format!("{buffer}{completion}");
+                if !completion.is_empty() && validate(&full_text) {
+                    completion_cache.insert(full_text, 1.0);
                 }
+            }
 
-                // now deals with the first recommendation
-                if let Some(completion) = completions.first_mut() {
-                    let full_text = format!("{buffer}{completion}");
-                    let valid = validate(&full_text);
-                    let is_empty = completion.is_empty();
+            // now deals with the first recommendation
+            if let Some(completion) = completions.first_mut() {
+                let full_text = format!("{buffer}{completion}");
+                let valid = validate(&full_text);
+                let is_empty = completion.is_empty();
 
-                    if valid && !is_empty {
-                        completion_cache.insert(full_text, 0.0);
-                    }
+                if valid && !is_empty {
+                    completion_cache.insert(full_text, 0.0);
+                }
 
-                    let suggestion_state = match (valid, completion.is_empty()) {
-                        (true, true) => SuggestionState::Empty,
-                        (true, false) => SuggestionState::Accept,
-                        (false, _) => SuggestionState::Discard,
-                    };
+                let suggestion_state = match (valid, completion.is_empty()) {
+                    (true, true) => SuggestionState::Empty,
+                    (true, false) => SuggestionState::Accept,
+                    (false, _) => SuggestionState::Discard,
+                };
 
+                // only the suggestions of the service are reported
+                if let Some(ServiceIds { request_id, session_id }) = service_ids {
                     tokio::spawn({
                         let completion = completion.clone();
                         let buffer = buffer.to_owned();
@@ -321,22 +307,19 @@ pub async fn handle_request(
                                 suggestion_state,
                                 edit_buffer_len: buffer.chars().count().try_into().ok(),
                                 buffer,
+                                shell,
                             });
                             // flush all but 4 messages, this is to retain messages that might have
                             // an accept waiting
                             queue.send_all_items(Some(4)).await;
                         }
                     });
-
-                    if valid { Some(std::mem::take(completion)) } else { None }
-                } else {
-                    None
                 }
-            },
-            Err(err) => {
-                error!(%err, "Failed to get inline_shell_completion completion");
+
+                if valid { Some(std::mem::take(completion)) } else { None }
+            } else {
                 None
-            },
+            }
         };
 
         info!(?insert_text, "Got inline_shell_completion completion");
@@ -376,139 +359,59 @@ pub async fn handle_set_enabled(figterm_request: InlineShellCompletionSetEnabled
     *INLINE_ENABLED.lock().await = figterm_request.enabled;
 }
 
-fn prompt(history: &[CommandInfo], buffer: &str) -> Option<String> {
-    for i in (0..history.len()).rev() {
-        let formatted_prompt = history
-            .iter()
-            .rev()
-            .take(i + 1)
-            .filter_map(|c| c.command.clone())
-            .chain([buffer.into()])
-            .enumerate()
-            .fold(String::new(), |mut acc, (i, c)| {
-                if i > 0 {
-                    acc.push('\n');
-                }
-                let _ = write!(acc, "{:>5}  {c}", i + 1);
-                acc
-            });
-
-        if formatted_prompt.len() < FILE_CONTEXT_LEFT_FILE_CONTENT_MAX_LEN {
-            return Some(formatted_prompt);
-        }
+/// The context of the prompt in the working directory, read on a blocking thread unless cached
+async fn prompt_context(cwd: Option<PathBuf>) -> PromptContext {
+    let mut cached = PROMPT_CONTEXT.lock().await;
+    if let Some(context) = cached.as_ref().filter(|context| context.cwd == cwd) {
+        return context.clone();
     }
-    None
-}
-
-static RE_1: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("{}\\s+.*", *HISTORY_COUNT + 1)).unwrap());
-static RE_2: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("{}\\s+.*", *HISTORY_COUNT + 2)).unwrap());
 
-fn clean_completion(response: &str) -> String {
-    // only return the first line of the response
-    let first_line = match response.split_once('\n') {
-        Some((left, _)) => left,
-        None => response,
+    let context = match tokio::task::spawn_blocking(move || PromptContext::new(cwd)).await {
+        Ok(context) => context,
+        Err(err) => {
+            error!(%err, "Failed to read the prompt context");
+            return PromptContext::default();
+        },
     };
-
-    // replace parts of the prompt that potentially are the next lines without a newline
-    let res = RE_1.replace(first_line, "");
-    let res = RE_2.replace(&res, "");
-
-    // trim any remaining whitespace
-    res.trim_end().to_owned()
+    *cached = Some(context.clone());
+    context
 }
 
-#[cfg(test)]
-mod tests {
-    use fig_settings::history::{
-        HistoryColumn,
-        Order,
-        OrderBy,
-        WhereExpression,
-    };
-
-    use super::*;
-
-    #[test]
-    fn test_prompt() {
-        let history = vec![
-            CommandInfo {
-                command: Some("echo world".into()),
-                ..Default::default()
-            },
-            CommandInfo {
-                command: Some("echo hello".into()),
-                ..Default::default()
-            },
-        ];
-
-        let prompt = prompt(&history, "echo ").unwrap();
-        println!("{prompt}");
-
-        assert_eq!(prompt, "    1  echo hello\n    2  echo world\n    3  echo ");
-    }
-
-    #[test]
-    fn test_clean_completion() {
-        assert_eq!(clean_completion("echo hello"), "echo hello");
-        assert_eq!(clean_completion("echo hello\necho world"), "echo hello");
-        assert_eq!(clean_completion("echo hello   \necho world\n"), "echo hello");
-        assert_eq!(clean_completion("echo hello     "), "echo hello");
-
-        // Trim potential excess lines from the model
-        assert_eq!(clean_completion("cd           50      ls"), "cd");
-        assert_eq!(
-            clean_completion("git add     50  git commit -m \"initial commit\""),
-            "git add"
-        );
-        assert_eq!(clean_completion("cd           51      ls"), "cd");
-        assert_eq!(
-            clean_completion("git add     51  git commit -m \"initial commit\""),
-            "git add"
-        );
+/// The provider of the kind, created unless it is cached
+async fn provider(kind: ProviderKind) -> Arc<dyn InlineProvider> {
+    let endpoint = provider::endpoint_from_settings(kind);
+    let mut cached = PROVIDER.lock().await;
+    if let Some((_, _, provider)) = cached
+        .as_ref()
+        .filter(|(cached_kind, cached_endpoint, _)| *cached_kind == kind && *cached_endpoint == endpoint)
+    {
+        return Arc::clone(provider);
     }
 
-    #[test]
-    fn too_long_prompt() {
-        let history = vec![CommandInfo {
-            command: Some("a".repeat(FILE_CONTEXT_LEFT_FILE_CONTENT_MAX_LEN + 1)),
-            ..Default::default()
-        }];
+    let provider = provider::create_provider(kind, endpoint.as_deref()).await;
+    // The local provider used in place of another one is not cached, so that creating the other
+    // one is tried again once it may succeed, e.g. after logging in
+    *cached = (provider.kind() == kind).then(|| (kind, endpoint, Arc::clone(&provider)));
+    provider
+}
 
-        assert!(prompt(&history, "echo ").is_none());
+async fn query_history(history_sender: &HistorySender, limit: usize) -> Vec<CommandInfo> {
+    let (history_query_tx, history_query_rx) = flume::bounded(1);
+    if let Err(err) = history_sender
+        .send_async(history::HistoryCommand::Query(
+            HistoryQueryParams { limit },
+            history_query_tx,
+        ))
+        .await
+    {
+        error!(%err, "Failed to send history query");
     }
 
-    #[ignore = "not in CI"]
-    #[tokio::test]
-    async fn test_inline_suggestion_prompt() {
-        let history = fig_settings::history::History::new();
-        let commands = history
-            .rows(
-                Some(WhereExpression::NotNull(HistoryColumn::ExitCode)),
-                vec![OrderBy::new(HistoryColumn::Id, Order::Desc)],
-                *HISTORY_COUNT,
-                0,
-            )
-            .unwrap();
-        let prompt = prompt(&commands, "cd ").unwrap();
-
-        let client = fig_api_client::Client::new().await.unwrap();
-        let out = client
-            .generate_recommendations(RecommendationsInput {
-                file_context: FileContext {
-                    left_file_content: prompt,
-                    right_file_content: "".into(),
-                    filename: "history.sh".into(),
-                    programming_language: ProgrammingLanguage {
-                        language_name: LanguageName::Shell,
-                    },
-                },
-                max_results: 1,
-                next_token: None,
-            })
-            .await
-            .unwrap();
-
-        println!("out: {out:?}");
+    match history_query_rx.recv_async().await {
+        Ok(Some(history)) => history,
+        err => {
+            error!(?err, "Failed to get history");
+            vec![]
+        },
     }
 }
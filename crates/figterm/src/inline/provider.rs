@@ -0,0 +1,159 @@
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fig_request::reqwest::Url;
+use fig_settings::history::CommandInfo;
+use fig_settings::keys::{
+    INLINE_PROVIDER_CODEWHISPERER,
+    INLINE_PROVIDER_ENDPOINT_KEY,
+    INLINE_PROVIDER_HTTP,
+    INLINE_PROVIDER_KEY,
+    INLINE_PROVIDER_LOCAL,
+};
+use tracing::warn;
+
+use super::codewhisperer::CodeWhispererProvider;
+use super::context::PromptContext;
+use super::http::HttpProvider;
+use super::local::LocalProvider;
+
+/// What a suggestion is requested for
+#[derive(Debug, Clone, Copy)]
+pub struct SuggestionRequest<'a> {
+    /// The command line typed so far
+    pub buffer: &'a str,
+    /// The recent commands, from the most recent one
+    pub history: &'a [CommandInfo],
+    pub context: &'a PromptContext,
+}
+
+/// Ids of a request to the service, reported in the telemetry of its suggestions
+#[derive(Debug, Clone, Default)]
+pub struct ServiceIds {
+    pub request_id: String,
+    pub session_id: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Suggestions {
+    /// The text to insert after the buffer, from the best suggestion
+    pub completions: Vec<String>,
+    /// Only set for the suggestions of the service
+    pub service_ids: Option<ServiceIds>,
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    /// Too many requests were sent, the request can be tried again later
+    Throttled(anyhow::Error),
+    Other(anyhow::Error),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::Throttled(err) => write!(f, "throttled: {err}"),
+            ProviderError::Other(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// A source of inline suggestions
+#[async_trait]
+pub trait InlineProvider: Send + Sync {
+    fn kind(&self) -> ProviderKind;
+
+    /// Number of commands of the history the provider uses
+    fn history_limit(&self) -> usize;
+
+    async fn suggestions(&self, request: SuggestionRequest<'_>) -> Result<Suggestions, ProviderError>;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProviderKind {
+    /// The CodeWhisperer service, falling back to the history when it is unavailable
+    #[default]
+    CodeWhisperer,
+    /// A model of the history
+    Local,
+    /// An endpoint on the machine set with [`INLINE_PROVIDER_ENDPOINT_KEY`]
+    Http,
+}
+
+impl ProviderKind {
+    pub const ALL: [ProviderKind; 3] = [ProviderKind::CodeWhisperer, ProviderKind::Local, ProviderKind::Http];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProviderKind::CodeWhisperer => INLINE_PROVIDER_CODEWHISPERER,
+            ProviderKind::Local => INLINE_PROVIDER_LOCAL,
+            ProviderKind::Http => INLINE_PROVIDER_HTTP,
+        }
+    }
+
+    /// The provider set with [`INLINE_PROVIDER_KEY`]. Unknown values fall back to the local
+    /// provider, so a typo never sends the history to the service.
+    pub fn from_settings() -> Self {
+        match fig_settings::settings::get_string_opt(INLINE_PROVIDER_KEY) {
+            Some(value) => Self::ALL
+                .into_iter()
+                .find(|kind| kind.as_str() == value)
+                .unwrap_or_else(|| {
+                    warn!(%value, "Unknown inline provider, using the local provider");
+                    Self::Local
+                }),
+            None => Self::default(),
+        }
+    }
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The endpoint set with [`INLINE_PROVIDER_ENDPOINT_KEY`], only read for the HTTP provider
+pub fn endpoint_from_settings(kind: ProviderKind) -> Option<String> {
+    match kind {
+        ProviderKind::Http => fig_settings::settings::get_string_opt(INLINE_PROVIDER_ENDPOINT_KEY),
+        ProviderKind::CodeWhisperer | ProviderKind::Local => None,
+    }
+}
+
+/// Creates the provider of the kind.
+///
+/// The local provider is used instead when the CodeWhisperer client cannot be created, or when
+/// the endpoint of the HTTP provider is missing or invalid.
+pub async fn create_provider(kind: ProviderKind, endpoint: Option<&str>) -> Arc<dyn InlineProvider> {
+    match kind {
+        ProviderKind::CodeWhisperer => match fig_api_client::Client::new().await {
+            Ok(client) => Arc::new(CodeWhispererProvider::new(client)),
+            Err(err) => {
+                warn!(%err, "Failed to create the CodeWhisperer client, using the local provider");
+                Arc::new(LocalProvider)
+            },
+        },
+        ProviderKind::Local => Arc::new(LocalProvider),
+        ProviderKind::Http => {
+            let Some(endpoint) = endpoint else {
+                warn!(
+                    "{INLINE_PROVIDER_ENDPOINT_KEY} must be set to use the http inline provider, using the local provider"
+                );
+                return Arc::new(LocalProvider);
+            };
+            match endpoint
+                .parse::<Url>()
+                .map_err(anyhow::Error::from)
+                .and_then(HttpProvider::new)
+            {
+                Ok(provider) => Arc::new(provider),
+                Err(err) => {
+                    warn!(%err, %endpoint, "Invalid endpoint of the http inline provider, using the local provider");
+                    Arc::new(LocalProvider)
+                },
+            }
+        },
+    }
+}
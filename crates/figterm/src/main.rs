@@ -580,6 +580,8 @@ fn figterm_main(command: Option<&[String]>) -> Result<()> {
         #[cfg(target_os = "windows")]
         term.set_windows_delay_end_prompt(true);
 
+        term.set_capture_command_output(history::capture_output_enabled());
+
         let mut write_buffer: Vec<u8> = vec![0; BUFFER_SIZE];
 
         let mut key_interceptor = KeyInterceptor::new();
@@ -609,6 +611,9 @@ fn figterm_main(command: Option<&[String]>) -> Result<()> {
             if let Err(err) = stdout.write(title_osc.as_bytes()).await {
                 error!("Failed to write title osc: {err}");
             }
+
+            // Used by shells that report their prompt with OSC 133 but not their name
+            term.set_default_shell(name.trim_start_matches('-'));
         }
 
         let mut csi_u_set = false;
@@ -34,6 +34,7 @@ use fig_proto::remote::{
     clientbound,
     hostbound,
 };
+use fig_util::Shell;
 use fig_util::env_var::PROCESS_LAUNCHED_BY_Q;
 use flume::Sender;
 use tokio::process::Command;
@@ -313,8 +314,22 @@ pub async fn process_figterm_message(
         Some(FigtermRequest::InlineShellCompletion(request)) => {
             let history_sender = history_sender.clone();
             let session_id = session_id.to_owned();
-
-            tokio::spawn(inline::handle_request(request, session_id, response_tx, history_sender));
+            let shell = term
+                .shell_state()
+                .local_context
+                .shell
+                .as_deref()
+                .and_then(Shell::try_find_shell);
+            let cwd = term.shell_state().local_context.current_working_directory.clone();
+
+            tokio::spawn(inline::handle_request(
+                request,
+                session_id,
+                shell,
+                cwd,
+                response_tx,
+                history_sender,
+            ));
         },
         Some(FigtermRequest::InlineShellCompletionAccept(request)) => {
             tokio::spawn(inline::handle_accept(request, session_id.to_owned()));
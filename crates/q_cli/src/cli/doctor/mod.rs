@@ -51,6 +51,7 @@ use fig_integrations::input_method::InputMethodError;
 use fig_integrations::shell::{
     ShellExt,
     ShellIntegration,
+    inline_shell_completion_plugin,
 };
 use fig_integrations::ssh::SshIntegration;
 use fig_integrations::{
@@ -75,6 +76,7 @@ use fig_util::directories::{
 };
 use fig_util::env_var::{
     PROCESS_LAUNCHED_BY_Q,
+    Q_INLINE_SHELL,
     Q_PARENT,
     Q_TERM,
     Q_USING_ZSH_AUTOSUGGESTIONS,
@@ -722,11 +724,11 @@ impl DoctorCheck for InlineCheck {
     async fn get_type(&self, _: &(), _: Platform) -> DoctorCheckType {
         let shell = get_shell_context().await;
         let inline_enabled = fig_settings::settings::get_bool_or("inline.enabled", true);
-        let is_zsh = matches!(shell, Ok(Some(Shell::Zsh)));
+        let is_supported = matches!(shell, Ok(Some(shell)) if inline_shell_completion_plugin::script(shell).is_some());
 
-        if is_zsh && inline_enabled {
+        if is_supported && inline_enabled {
             DoctorCheckType::NormalCheck
-        } else if !is_zsh {
+        } else if !is_supported {
             DoctorCheckType::NoCheck
         } else {
             DoctorCheckType::SoftCheck
@@ -744,7 +746,9 @@ impl DoctorCheck for InlineCheck {
             ));
         }
 
-        if std::env::var_os(Q_USING_ZSH_AUTOSUGGESTIONS).is_some() {
+        let shell = get_shell_context().await.ok().flatten();
+
+        if shell == Some(Shell::Zsh) && std::env::var_os(Q_USING_ZSH_AUTOSUGGESTIONS).is_some() {
             return Err(DoctorError::Error {
                 reason: "Using zsh-autosuggestions is not supported at the same time as Inline".into(),
                 info: vec![
@@ -765,6 +769,35 @@ impl DoctorCheck for InlineCheck {
             });
         }
 
+        if shell == Some(Shell::Bash) {
+            // `bind -x` can only edit the line and `{var}` file descriptors can only be used since
+            // bash 4.1, otherwise inline.bash does nothing
+            let (_, version) = Shell::current_shell_version()
+                .await
+                .context("Failed to get bash version")?;
+            if Version::parse(&version)
+                .is_ok_and(|version| version.major < 4 || (version.major == 4 && version.minor < 1))
+            {
+                return Err(DoctorError::Error {
+                    reason: format!("Inline requires bash 4.1 or newer, but bash {version} is used").into(),
+                    info: vec![
+                        format!("Install a newer bash with Brew: {}", "brew install bash".magenta()).into(),
+                        format!("Or switch to zsh: {}", "chsh -s /bin/zsh".magenta()).into(),
+                    ],
+                    fix: None,
+                    error: None,
+                });
+            }
+        }
+
+        if let Some(shell) = shell {
+            if std::env::var(Q_INLINE_SHELL).ok().as_deref() != Some(shell.as_str()) {
+                return Err(DoctorError::Warning(
+                    format!("Inline is not loaded in this {shell} session, restart your shell to load it").into(),
+                ));
+            }
+        }
+
         Ok(())
     }
 }
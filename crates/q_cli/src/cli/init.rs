@@ -155,22 +155,22 @@ async fn shell_init(shell: &Shell, when: &When, rcfile: &Option<String>) -> Resu
     let inline_enabled = fig_settings::settings::get_bool_or(INLINE_ENABLED_SETTINGS_KEY, true);
 
     if let When::Post = when {
-        if !matches!(
-            (shell, rcfile.as_deref()),
-            (Shell::Zsh, Some("zprofile")) | (Shell::Bash, Some("profile" | "bash_profile"))
-        ) && fig_settings::state::get_bool_or("dotfiles.enabled", true)
-            && shell == &Shell::Zsh
-            && when == &When::Post
-            && inline_enabled
-            && !*IS_SNAPSHOT_TEST
-        {
-            to_source.push(guard_source(
-                shell,
-                false,
-                "Q_DOTFILES_SOURCED",
-                GuardAssignment::AfterSourcing,
-                fig_integrations::shell::inline_shell_completion_plugin::ZSH_SCRIPT,
-            ));
+        if let Some(inline_script) = fig_integrations::shell::inline_shell_completion_plugin::script(*shell) {
+            if !matches!(
+                (shell, rcfile.as_deref()),
+                (Shell::Zsh, Some("zprofile")) | (Shell::Bash, Some("profile" | "bash_profile"))
+            ) && fig_settings::state::get_bool_or("dotfiles.enabled", true)
+                && inline_enabled
+                && !*IS_SNAPSHOT_TEST
+            {
+                to_source.push(guard_source(
+                    shell,
+                    false,
+                    "Q_DOTFILES_SOURCED",
+                    GuardAssignment::AfterSourcing,
+                    inline_script,
+                ));
+            }
         }
 
         // if stdin().is_tty() && env::var_os(PROCESS_LAUNCHED_BY_Q).is_none() {
@@ -267,7 +267,11 @@ async fn shell_init(shell: &Shell, when: &When, rcfile: &Option<String>) -> Resu
         }
     }
 
-    if inline_enabled && when == &When::Post && shell == &Shell::Zsh && !*IS_SNAPSHOT_TEST {
+    if inline_enabled
+        && when == &When::Post
+        && fig_integrations::shell::inline_shell_completion_plugin::script(*shell).is_some()
+        && !*IS_SNAPSHOT_TEST
+    {
         let key = "prompt.inline.count";
         if let Ok(prompt_count) = fig_settings::state::get_int(key) {
             let prompt_count = prompt_count.unwrap_or_default();
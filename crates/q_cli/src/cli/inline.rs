@@ -12,6 +12,7 @@ use fig_api_client::{
     Client,
     Customization,
 };
+use fig_integrations::shell::inline_shell_completion_plugin;
 use fig_ipc::{
     BufferedUnixStream,
     SendMessage,
@@ -21,8 +22,25 @@ use fig_proto::figterm::{
     FigtermRequestMessage,
     InlineShellCompletionSetEnabledRequest,
 };
-use fig_util::env_var::QTERM_SESSION_ID;
+use fig_settings::Settings;
+use fig_settings::keys::{
+    INLINE_PROVIDER_CODEWHISPERER,
+    INLINE_PROVIDER_ENDPOINT_KEY,
+    INLINE_PROVIDER_HTTP,
+    INLINE_PROVIDER_KEY,
+    INLINE_PROVIDER_LOCAL,
+    check_inline_provider_endpoint,
+};
+use fig_util::env_var::{
+    Q_INLINE_SHELL,
+    QTERM_SESSION_ID,
+};
+use fig_util::{
+    CLI_BINARY_NAME,
+    Shell,
+};
 use tracing::error;
+use url::Url;
 
 use super::OutputFormat;
 use crate::util::CliContext;
@@ -72,6 +90,10 @@ impl InlineSubcommand {
             InlineSubcommand::Status => {
                 let enabled = settings.get_bool(INLINE_ENABLED_SETTINGS_KEY)?.unwrap_or(true);
                 println!("Inline is {}", if enabled { "enabled" } else { "disabled" }.bold());
+                if enabled {
+                    println!("{}", provider_status(settings));
+                    print_shell_status();
+                }
             },
             InlineSubcommand::SetCustomization { arn } => {
                 let customizations = Client::new().await?.list_customizations().await?;
@@ -149,6 +171,68 @@ impl InlineSubcommand {
     }
 }
 
+/// Describes where the suggestions come from, set with [`INLINE_PROVIDER_KEY`].
+fn provider_status(settings: &Settings) -> String {
+    let provider = settings.get_string(INLINE_PROVIDER_KEY).ok().flatten();
+    match provider.as_deref() {
+        None | Some(INLINE_PROVIDER_CODEWHISPERER) => format!(
+            "Suggestions are provided by {}, or by your history when it is unavailable",
+            "CodeWhisperer".bold()
+        ),
+        Some(INLINE_PROVIDER_LOCAL) => format!(
+            "Suggestions are provided by the {} provider, your history does not leave this machine",
+            INLINE_PROVIDER_LOCAL.bold()
+        ),
+        Some(INLINE_PROVIDER_HTTP) => match settings.get_string(INLINE_PROVIDER_ENDPOINT_KEY).ok().flatten() {
+            Some(endpoint) => match endpoint
+                .parse::<Url>()
+                .map_err(|err| format!("{endpoint} is not a valid url: {err}"))
+                .and_then(|url| check_inline_provider_endpoint(&url))
+            {
+                Ok(()) => format!(
+                    "Suggestions are provided by the {} provider at {}",
+                    INLINE_PROVIDER_HTTP.bold(),
+                    endpoint.bold()
+                ),
+                Err(err) => format!(
+                    "The {} provider is selected but {err}, the {} provider is used instead",
+                    INLINE_PROVIDER_HTTP.bold(),
+                    INLINE_PROVIDER_LOCAL.bold()
+                ),
+            },
+            None => format!(
+                "The {} provider is selected but has no endpoint, the {} provider is used until it is set with {}",
+                INLINE_PROVIDER_HTTP.bold(),
+                INLINE_PROVIDER_LOCAL.bold(),
+                format!("{CLI_BINARY_NAME} settings {INLINE_PROVIDER_ENDPOINT_KEY} <url>").magenta()
+            ),
+        },
+        Some(provider) => format!(
+            "Unknown provider {}, the {} provider is used until {INLINE_PROVIDER_KEY} is set to {INLINE_PROVIDER_CODEWHISPERER}, {INLINE_PROVIDER_LOCAL} or {INLINE_PROVIDER_HTTP}",
+            provider.bold(),
+            INLINE_PROVIDER_LOCAL.bold()
+        ),
+    }
+}
+
+/// Prints whether Inline is loaded in the shell `q` was run from.
+fn print_shell_status() {
+    let Some(shell) = Shell::current_shell() else {
+        return;
+    };
+
+    if inline_shell_completion_plugin::script(shell).is_none() {
+        println!("Inline is not supported in {shell}, only in zsh, bash and fish");
+    } else if std::env::var(Q_INLINE_SHELL).is_ok_and(|loaded_shell| loaded_shell == shell.as_str()) {
+        println!("Inline is loaded in this {shell} session");
+    } else {
+        println!(
+            "Inline is not loaded in this {shell} session, restart your shell or run {} to find out why",
+            format!("{CLI_BINARY_NAME} doctor").magenta()
+        );
+    }
+}
+
 async fn send_set_enabled(enabled: bool) -> Result<()> {
     let session_id = std::env::var(QTERM_SESSION_ID)?;
     let figterm_socket_path = fig_util::directories::figterm_socket_path(&session_id)?;
@@ -176,8 +260,53 @@ mod tests {
         assert!(settings.get_bool(INLINE_ENABLED_SETTINGS_KEY).unwrap().unwrap());
         InlineSubcommand::Status.execute(&cli_context).await.unwrap();
 
+        settings.set_value(INLINE_PROVIDER_KEY, "local").unwrap();
+        InlineSubcommand::Status.execute(&cli_context).await.unwrap();
+
         InlineSubcommand::Disable.execute(&cli_context).await.unwrap();
         assert!(!settings.get_bool(INLINE_ENABLED_SETTINGS_KEY).unwrap().unwrap());
         InlineSubcommand::Status.execute(&cli_context).await.unwrap();
     }
+
+    #[test]
+    fn test_provider_status() {
+        let cli_context = CliContext::new_fake();
+        let settings = cli_context.settings();
+        let status = || anstream::adapter::strip_str(&provider_status(settings)).to_string();
+
+        assert_eq!(
+            status(),
+            "Suggestions are provided by CodeWhisperer, or by your history when it is unavailable"
+        );
+
+        settings.set_value(INLINE_PROVIDER_KEY, "local").unwrap();
+        assert_eq!(
+            status(),
+            "Suggestions are provided by the local provider, your history does not leave this machine"
+        );
+
+        settings.set_value(INLINE_PROVIDER_KEY, "http").unwrap();
+        assert!(status().starts_with("The http provider is selected but has no endpoint"));
+        settings
+            .set_value(INLINE_PROVIDER_ENDPOINT_KEY, "http://localhost:8080/suggest")
+            .unwrap();
+        assert_eq!(
+            status(),
+            "Suggestions are provided by the http provider at http://localhost:8080/suggest"
+        );
+
+        settings
+            .set_value(INLINE_PROVIDER_ENDPOINT_KEY, "https://example.com/suggest")
+            .unwrap();
+        assert_eq!(
+            status(),
+            "The http provider is selected but https://example.com/suggest is not on this machine, only localhost \
+             and loopback addresses are allowed, the local provider is used instead"
+        );
+        settings.set_value(INLINE_PROVIDER_ENDPOINT_KEY, "not a url").unwrap();
+        assert!(status().starts_with("The http provider is selected but not a url is not a valid url"));
+
+        settings.set_value(INLINE_PROVIDER_KEY, "remote").unwrap();
+        assert!(status().starts_with("Unknown provider remote, the local provider is used"));
+    }
 }
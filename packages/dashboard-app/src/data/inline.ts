@@ -12,6 +12,23 @@ const inlineShellCompletionSettings: PrefSection[] = [
         type: "boolean",
         default: true,
       },
+      {
+        id: "inline.provider",
+        title: "Suggestion provider",
+        description:
+          "Where suggestions come from. The local provider ranks commands from your history, and the http provider requests them from an endpoint on this machine.",
+        type: "select",
+        default: "codewhisperer",
+        options: ["codewhisperer", "local", "http"],
+      },
+      {
+        id: "inline.provider.endpoint",
+        title: "Provider endpoint",
+        description:
+          "The URL of the endpoint on this machine used by the http provider, e.g. http://localhost:8080/suggest.",
+        type: "text",
+        default: null,
+      },
     ],
   },
 ];